@@ -1,16 +1,247 @@
 use std::{
-    fs::File,
-    io::{self, Read},
-    path::PathBuf,
+    error::Error,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
 };
 
 use aes_gcm::{
     AeadCore, KeyInit, Nonce,
-    aead::{Aead, OsRng, consts::U12},
+    aead::{Aead, AeadInPlace, OsRng, Payload, consts::U12},
 };
 use aes_gcm::{Aes256Gcm, Key};
 
-use crate::api::{SecretReader, SecretUpdater};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::api::{SecretReader, SecretReaderMut, SecretUpdater};
+
+#[cfg(feature = "age")]
+mod age;
+#[cfg(any(unix, windows))]
+pub mod agent;
+#[cfg(feature = "aws")]
+pub mod aws;
+#[cfg(feature = "otp")]
+mod base32;
+mod base64;
+#[cfg(feature = "bip39")]
+mod bip39;
+#[cfg(feature = "cloud")]
+pub mod cloud;
+mod combine;
+#[cfg(feature = "hkdf")]
+mod committing;
+mod compare;
+#[cfg(windows)]
+mod credentials;
+mod dotenv;
+#[cfg(windows)]
+mod dpapi;
+#[cfg(feature = "ed25519-dalek")]
+mod ed25519;
+mod encoding;
+mod env;
+#[cfg(feature = "envelope")]
+pub mod envelope;
+#[cfg(unix)]
+mod fd;
+#[cfg(feature = "sha2")]
+mod fingerprint;
+mod hex;
+#[cfg(feature = "hpke")]
+mod hpke;
+#[cfg(any(feature = "aws", feature = "cloud", feature = "vault"))]
+mod http;
+#[cfg(feature = "https")]
+mod https;
+#[cfg(feature = "jwe")]
+mod jwe;
+#[cfg(any(feature = "ed25519-dalek", feature = "hmac"))]
+mod jwt;
+#[cfg(any(
+    feature = "argon2",
+    feature = "blake3",
+    feature = "hkdf",
+    feature = "pbkdf2",
+    feature = "scrypt"
+))]
+mod kdf;
+#[cfg(all(feature = "keychain", target_vendor = "apple"))]
+pub mod keychain;
+#[cfg(target_os = "linux")]
+pub mod keyring;
+#[cfg(feature = "aes-kw")]
+mod keywrap;
+#[cfg(feature = "legacy-crypto")]
+mod legacy;
+#[cfg(any(
+    feature = "blake3",
+    feature = "cmac",
+    feature = "hmac",
+    feature = "poly1305",
+    feature = "sha3"
+))]
+mod mac;
+#[cfg(target_os = "linux")]
+mod mapped;
+#[cfg(feature = "ml-kem")]
+mod ml_kem;
+#[cfg(all(
+    feature = "mobile",
+    any(target_os = "android", target_vendor = "apple")
+))]
+pub mod mobile;
+#[cfg(target_os = "linux")]
+pub mod mounted;
+#[cfg(feature = "noise")]
+pub mod noise;
+pub mod nonce;
+#[cfg(feature = "openpgp")]
+pub mod openpgp;
+#[cfg(feature = "otp")]
+mod otp;
+#[cfg(feature = "p256")]
+mod p256;
+#[cfg(feature = "pake")]
+pub mod pake;
+#[cfg(any(feature = "argon2", feature = "bcrypt", feature = "scrypt"))]
+mod password;
+mod pinentry;
+#[cfg(feature = "piv")]
+pub mod piv;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+#[cfg(feature = "pkcs8")]
+mod pkcs8;
+mod prompt;
+mod random;
+#[cfg(feature = "rsa")]
+mod rsa;
+mod sealed;
+#[cfg(all(feature = "secret-service", unix))]
+pub mod secret_service;
+mod shamir;
+#[cfg(feature = "aes-gcm-siv")]
+mod siv;
+#[cfg(feature = "slip10")]
+mod slip10;
+mod source;
+#[cfg(feature = "ssh")]
+mod ssh;
+#[cfg(any(unix, windows))]
+mod stdin;
+pub mod stream;
+#[cfg(target_os = "linux")]
+mod systemd;
+#[cfg(all(feature = "tpm", target_os = "linux"))]
+pub mod tpm;
+#[cfg(feature = "vault")]
+mod vault;
+#[cfg(feature = "x25519-dalek")]
+mod x25519;
+#[cfg(feature = "chacha20poly1305")]
+mod xchacha;
+
+#[cfg(feature = "age")]
+pub use age::{AgeKey, UpdateSecretFromAgeFile};
+#[cfg(feature = "otp")]
+pub use base32::UpdateSecretFromBase32;
+pub use base64::{Alphabet, UpdateSecretFromBase64};
+#[cfg(feature = "bip39")]
+pub use bip39::{Bip39Mnemonic, Bip39Phrase, Bip39Seed, UpdateSecretFromBip39};
+pub use combine::CombineParts;
+#[cfg(feature = "hkdf")]
+pub use committing::{CommittingCipher, CommittingDecipher};
+pub use compare::ConstantTimeEq;
+#[cfg(windows)]
+pub use credentials::{CredentialPersist, StoreInCredentialManager, UpdateSecretFromCredential};
+pub use dotenv::load_dotenv;
+#[cfg(windows)]
+pub use dpapi::{DpapiScope, ProtectWithDpapi, UpdateSecretFromDpapiBlob};
+#[cfg(feature = "ed25519-dalek")]
+pub use ed25519::{Ed25519PublicKey, Ed25519Sign};
+pub use encoding::Encoding;
+pub use env::UpdateSecretFromEnv;
+#[cfg(unix)]
+pub use fd::{UpdateSecretFromFd, UpdateSecretFromUnixSocket};
+#[cfg(feature = "sha2")]
+pub use fingerprint::Fingerprint;
+pub use hex::UpdateSecretFromHex;
+#[cfg(feature = "hpke")]
+pub use hpke::{HpkeAead, HpkeOpen, HpkeSeal};
+#[cfg(feature = "https")]
+pub use https::UpdateSecretFromHttps;
+#[cfg(feature = "jwe")]
+pub use jwe::{ExportJwe, JweAlgorithm, JweRecipient, UpdateSecretFromJwe};
+#[cfg(feature = "ed25519-dalek")]
+pub use jwt::SignJwtEdDsa;
+#[cfg(feature = "hmac")]
+pub use jwt::SignJwtHs256;
+#[cfg(feature = "pbkdf2")]
+pub use kdf::DeriveFromPassphrasePbkdf2;
+#[cfg(feature = "hkdf")]
+pub use kdf::DeriveSubkey;
+#[cfg(feature = "blake3")]
+pub use kdf::DeriveSubkeyBlake3;
+#[cfg(feature = "argon2")]
+pub use kdf::{Argon2Params, DeriveFromPassphrase, HashPassword, VerifyPassword};
+#[cfg(feature = "scrypt")]
+pub use kdf::{DeriveFromPassphraseScrypt, ScryptParams};
+#[cfg(feature = "aes-kw")]
+pub use keywrap::{KeyWrapMode, UnwrapKey, WrapKey};
+#[cfg(feature = "legacy-crypto")]
+pub use legacy::{AesCtr, CbcHmacCipher, CbcHmacDecipher};
+#[cfg(feature = "blake3")]
+pub use mac::{Blake3Mac, VerifyBlake3Mac};
+#[cfg(feature = "cmac")]
+pub use mac::{Cmac, VerifyCmac};
+#[cfg(feature = "hmac")]
+pub use mac::{Hmac, HmacHash, VerifyHmac};
+#[cfg(feature = "sha3")]
+pub use mac::{Kmac, KmacVariant, VerifyKmac};
+#[cfg(feature = "poly1305")]
+pub use mac::{Poly1305Tag, VerifyPoly1305Tag};
+#[cfg(target_os = "linux")]
+pub use mapped::UpdateSecretFromMappedFile;
+#[cfg(feature = "ml-kem")]
+pub use ml_kem::{MlKemDecapsulate, MlKemEncapsulate, MlKemPublicKey, MlKemSeed, MlKemVariant};
+#[cfg(feature = "otp")]
+pub use otp::{Hotp, OtpHash, Totp};
+#[cfg(feature = "p256")]
+pub use p256::{P256PublicKey, P256Sign};
+#[cfg(feature = "bcrypt")]
+pub use password::{
+    BCRYPT_MAX_PASSWORD_LEN, BcryptPasswordTooLong, BcryptTruncation, HashBcrypt, VerifyBcrypt,
+};
+#[cfg(any(feature = "argon2", feature = "bcrypt", feature = "scrypt"))]
+pub use password::{PasswordVerdict, VerifyAgainstPhc};
+pub use pinentry::UpdateSecretFromPinentry;
+#[cfg(feature = "pkcs8")]
+pub use pkcs8::{UpdateSecretFromEncryptedPkcs8, UpdateSecretFromPkcs8};
+pub use prompt::UpdateSecretFromPrompt;
+pub use random::GenerateRandom;
+#[cfg(feature = "rsa")]
+pub use rsa::{RSA_KEY_CAPACITY, RsaDecrypt, RsaKey, RsaPublicKey, RsaScheme, RsaSign};
+pub use sealed::{DecryptFile, EncryptToFile};
+pub use shamir::{ShamirCombine, ShamirSplit};
+#[cfg(feature = "aes-gcm-siv")]
+pub use siv::{SivCipher, SivDecipher};
+#[cfg(feature = "slip10")]
+pub use slip10::{DeriveSlip10Key, Slip10Curve};
+pub use source::Source;
+#[cfg(feature = "ssh")]
+pub use ssh::{UpdateSecretFromEncryptedOpenSsh, UpdateSecretFromOpenSsh};
+#[cfg(any(unix, windows))]
+pub use stdin::{StdinEnd, UpdateSecretFromStdin};
+#[cfg(target_os = "linux")]
+pub use systemd::{UpdateSecretFromEncryptedCredential, UpdateSecretFromSystemdCredential};
+#[cfg(feature = "vault")]
+pub use vault::{UpdateSecretFromVault, VaultAuth};
+#[cfg(feature = "x25519-dalek")]
+pub use x25519::{X25519Agree, X25519PublicKey};
+#[cfg(feature = "chacha20poly1305")]
+pub use xchacha::{XChaChaCipher, XChaChaDecipher};
 
 pub struct UpdateSecretFromFile(pub PathBuf);
 
@@ -21,6 +252,217 @@ impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretF
     }
 }
 
+/// Like [`UpdateSecretFromFile`], but the file must fill the secret exactly, so that a
+/// truncated key file is not silently completed with zeros.
+pub struct UpdateSecretFromFileExact(pub PathBuf);
+
+/// Returns the number of bytes consumed, always `N`.
+///
+/// Fails with [`io::ErrorKind::UnexpectedEof`] if the file is shorter than the secret, and
+/// with [`io::ErrorKind::InvalidInput`] if it is larger. On failure, the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromFileExact {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = read_exact(File::open(&self.0)?, sec);
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Fills `sec` from `reader`, which must then be at its end.
+fn read_exact(mut reader: impl Read, sec: &mut [u8]) -> io::Result<usize> {
+    reader.read_exact(sec)?;
+    let mut extra = [0u8];
+    let more = loop {
+        match reader.read(&mut extra) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            result => break result?,
+        }
+    };
+    extra.zeroize();
+    match more {
+        0 => Ok(sec.len()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "key file is larger than the secret",
+        )),
+    }
+}
+
+/// Reads `reader` until its end into the start of `sec`, zeroized on failure.
+#[cfg_attr(
+    not(any(unix, windows, feature = "age", feature = "openpgp")),
+    allow(dead_code)
+)]
+fn read_to_end(mut reader: impl Read, sec: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    let result = loop {
+        let mut extra = [0u8];
+        let buf = match sec.get_mut(len..) {
+            Some([]) | None => &mut extra[..],
+            Some(buf) => buf,
+        };
+        match reader.read(buf) {
+            Ok(0) => break Ok(len),
+            Ok(_) if buf.len() == 1 && len == sec.len() => {
+                extra.zeroize();
+                break Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "input is larger than the secret",
+                ));
+            }
+            Ok(read) => len += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => break Err(err),
+        }
+    };
+    if result.is_err() {
+        sec.zeroize();
+    }
+    result
+}
+
+/// Returns the bytes of `sec` up to its first NUL byte, as passphrases, passwords and PINs
+/// are held in secrets.
+#[cfg_attr(
+    not(any(
+        feature = "age",
+        feature = "argon2",
+        feature = "bcrypt",
+        feature = "bip39",
+        feature = "openpgp",
+        feature = "pake",
+        feature = "pbkdf2",
+        feature = "piv",
+        feature = "pkcs11",
+        feature = "pkcs8",
+        feature = "scrypt",
+        feature = "ssh"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn passphrase(sec: &[u8]) -> &[u8] {
+    let len = sec.iter().position(|&byte| byte == 0);
+    &sec[..len.unwrap_or(sec.len())]
+}
+
+/// Replaces the file at `path` with `data` atomically: `data` is written to a temporary
+/// file, synced, then renamed over `path`, so that `path` holds either its previous content
+/// or `data`, even after a crash.
+///
+/// The temporary file has a random name and is created exclusively, so that it is never a
+/// file planted (or being written) by someone else, and is only accessible to its owner on
+/// Unix, as is then `path`.
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut suffix = [0u8; 8];
+    getrandom::getrandom(&mut suffix)?;
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(format!(".{:016x}.tmp", u64::from_ne_bytes(suffix)));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&temporary)?;
+    let written = file.write_all(data).and_then(|()| file.sync_all());
+    if let Err(err) = written.and_then(|()| fs::rename(&temporary, path)) {
+        let _ = fs::remove_file(&temporary);
+        return Err(err);
+    }
+    // The rename itself is only durable once the directory is synced.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            ".".as_ref()
+        } else {
+            dir
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Like [`UpdateSecretFromFile`], but first checks that the key file is a regular file
+/// and, on Unix, that it is owned by the current user, and not accessible to its group
+/// nor to others (as OpenSSH does for private keys).
+///
+/// Checks are made on the opened file, so it cannot be swapped in between.
+pub struct UpdateSecretFromFileChecked(pub PathBuf);
+
+/// Why a key file was refused by [`UpdateSecretFromFileChecked`].
+///
+/// Returned as the inner error of an [`io::ErrorKind::PermissionDenied`] error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnsafeKeyFile {
+    /// Not a regular file (a directory, a FIFO, a device…).
+    NotRegular,
+    /// Owned by another user.
+    NotOwned { uid: u32 },
+    /// Accessible to the group or to others.
+    Permissive { mode: u32 },
+}
+
+impl fmt::Display for UnsafeKeyFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnsafeKeyFile::NotRegular => write!(f, "key file is not a regular file"),
+            UnsafeKeyFile::NotOwned { uid } => {
+                write!(f, "key file is owned by another user ({uid})")
+            }
+            UnsafeKeyFile::Permissive { mode } => {
+                write!(f, "key file permissions {mode:04o} are too open")
+            }
+        }
+    }
+}
+
+impl Error for UnsafeKeyFile {}
+
+impl UpdateSecretFromFileChecked {
+    fn open(&self) -> io::Result<File> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        // Do not block on a FIFO, rejected anyway.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NONBLOCK);
+        let file = options.open(&self.0)?;
+        let metadata = file.metadata()?;
+        let refuse = |why| io::Error::new(io::ErrorKind::PermissionDenied, why);
+        if !metadata.is_file() {
+            return Err(refuse(UnsafeKeyFile::NotRegular));
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            if metadata.uid() != unsafe { libc::geteuid() } {
+                return Err(refuse(UnsafeKeyFile::NotOwned {
+                    uid: metadata.uid(),
+                }));
+            }
+            if metadata.mode() & 0o077 != 0 {
+                return Err(refuse(UnsafeKeyFile::Permissive {
+                    mode: metadata.mode() & 0o7777,
+                }));
+            }
+        }
+        Ok(file)
+    }
+}
+
+/// Fails with [`io::ErrorKind::PermissionDenied`], holding an [`UnsafeKeyFile`], if the
+/// file is refused.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromFileChecked {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        self.open()?.read(sec)
+    }
+}
+
+/// Encrypts a message with AES-256-GCM, under a random nonce returned with the ciphertext.
+///
+/// Uses of the key are not counted: past 2³² messages, nonces may collide. Use
+/// [`CipherWithNonces`] to enforce the usage limits of the key.
 pub struct Cipher(pub Vec<u8>);
 
 impl<const N: usize> SecretReader<[u8; N], Result<(Vec<u8>, Nonce<U12>), aes_gcm::Error>>
@@ -28,7 +470,7 @@ impl<const N: usize> SecretReader<[u8; N], Result<(Vec<u8>, Nonce<U12>), aes_gcm
 {
     fn read(&self, sec: &[u8]) -> Result<(Vec<u8>, Nonce<U12>), aes_gcm::Error> {
         let key: &Key<Aes256Gcm> = sec.into(); // /!\ we must check that library aes_gcm does not copy secret, or properly erase the copy.
-        let cipher = Aes256Gcm::new(&key);
+        let cipher = Aes256Gcm::new(key);
         let nonce: Nonce<U12> = Aes256Gcm::generate_nonce(&mut OsRng);
         let enc = cipher.encrypt(&nonce, &*self.0)?;
         Ok((enc, nonce))
@@ -40,12 +482,145 @@ pub struct Decipher(pub (Vec<u8>, Nonce<U12>));
 impl<const N: usize> SecretReader<[u8; N], Result<Vec<u8>, aes_gcm::Error>> for Decipher {
     fn read(&self, sec: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
         let key: &Key<Aes256Gcm> = sec.into(); // /!\ we must check that library aes_gcm does not copy secret, or properly erase the copy.
-        let cipher = Aes256Gcm::new(&key);
+        let cipher = Aes256Gcm::new(key);
         let (enc, nonce) = &self.0;
         cipher.decrypt(nonce, enc as &[u8])
     }
 }
 
+/// Like [`Cipher`], but encrypts the message in place, in a buffer provided by the caller:
+/// the plaintext is overwritten by the ciphertext (followed by its tag), so that no copy of
+/// it is left behind. Returns the nonce.
+///
+/// If the buffer has no room for the tag, it is moved to a larger one, the previous one
+/// being zeroized.
+pub struct CipherInPlace<'a>(&'a mut Zeroizing<Vec<u8>>);
+
+/// Decrypts in place a ciphertext and its nonce, as produced by [`CipherInPlace`] (or
+/// [`Cipher`]): the ciphertext is overwritten by the plaintext.
+pub struct DecipherInPlace<'a> {
+    buffer: &'a mut Zeroizing<Vec<u8>>,
+    nonce: Nonce<U12>,
+}
+
+impl<'a> CipherInPlace<'a> {
+    pub fn new(message: &'a mut Zeroizing<Vec<u8>>) -> Self {
+        CipherInPlace(message)
+    }
+}
+
+impl<'a> DecipherInPlace<'a> {
+    pub fn new(ciphertext: &'a mut Zeroizing<Vec<u8>>, nonce: Nonce<U12>) -> Self {
+        DecipherInPlace {
+            buffer: ciphertext,
+            nonce,
+        }
+    }
+}
+
+/// Length of the tags of AES-GCM.
+const TAG_LEN: usize = 16;
+
+impl<const N: usize> SecretReaderMut<[u8; N], Result<Nonce<U12>, aes_gcm::Error>>
+    for CipherInPlace<'_>
+{
+    fn read_mut(&mut self, sec: &[u8]) -> Result<Nonce<U12>, aes_gcm::Error> {
+        let buffer = &mut self.0;
+        if buffer.capacity() - buffer.len() < TAG_LEN {
+            let mut larger = Zeroizing::new(Vec::with_capacity(buffer.len() + TAG_LEN));
+            larger.extend_from_slice(buffer);
+            **buffer = larger;
+        }
+        let cipher = Aes256Gcm::new(sec.into());
+        let nonce: Nonce<U12> = Aes256Gcm::generate_nonce(&mut OsRng);
+        cipher.encrypt_in_place(&nonce, b"", &mut ***buffer)?;
+        Ok(nonce)
+    }
+}
+
+/// On failure, the ciphertext is left as is.
+impl<const N: usize> SecretReaderMut<[u8; N], Result<(), aes_gcm::Error>> for DecipherInPlace<'_> {
+    fn read_mut(&mut self, sec: &[u8]) -> Result<(), aes_gcm::Error> {
+        let cipher = Aes256Gcm::new(sec.into());
+        cipher.decrypt_in_place(&self.nonce, b"", &mut **self.buffer)
+    }
+}
+
+/// Like [`Cipher`], but also authenticates associated data (not encrypted) binding the
+/// ciphertext to its context, such as a file name or a record identifier: the ciphertext
+/// cannot be spliced into another context, where it would not decrypt.
+pub struct CipherWithAad {
+    pub message: Vec<u8>,
+    pub aad: Vec<u8>,
+}
+
+/// Decrypts a ciphertext and its nonce, as returned by [`CipherWithAad`], with the same
+/// associated data.
+pub struct DecipherWithAad {
+    pub ciphertext: (Vec<u8>, Nonce<U12>),
+    pub aad: Vec<u8>,
+}
+
+impl<const N: usize> SecretReader<[u8; N], Result<(Vec<u8>, Nonce<U12>), aes_gcm::Error>>
+    for CipherWithAad
+{
+    fn read(&self, sec: &[u8]) -> Result<(Vec<u8>, Nonce<U12>), aes_gcm::Error> {
+        let cipher = Aes256Gcm::new(sec.into());
+        let nonce: Nonce<U12> = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: &self.message,
+            aad: &self.aad,
+        };
+        let enc = cipher.encrypt(&nonce, payload)?;
+        Ok((enc, nonce))
+    }
+}
+
+/// Like [`CipherWithAad`], but takes the nonce from a [`nonce::NonceSequence`], which
+/// enforces the usage limits of the key, rather than generating a random one.
+pub struct CipherWithNonces<'a> {
+    pub message: Vec<u8>,
+    pub aad: Vec<u8>,
+    pub nonces: &'a dyn nonce::NonceSequence,
+}
+
+/// Returns the ciphertext and its nonce, to be decrypted with [`DecipherWithAad`].
+///
+/// Fails with [`io::ErrorKind::QuotaExceeded`] once the nonces of the key are exhausted,
+/// and with [`io::ErrorKind::InvalidInput`] if the message is too long for AES-GCM.
+impl<const N: usize> SecretReader<[u8; N], io::Result<(Vec<u8>, Nonce<U12>)>>
+    for CipherWithNonces<'_>
+{
+    fn read(&self, sec: &[u8]) -> io::Result<(Vec<u8>, Nonce<U12>)> {
+        let nonce = self.nonces.next_nonce()?;
+        let cipher = Aes256Gcm::new(sec.into());
+        let payload = Payload {
+            msg: &self.message,
+            aad: &self.aad,
+        };
+        let enc = cipher.encrypt(&nonce, payload).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "message is too long for AES-GCM",
+            )
+        })?;
+        Ok((enc, nonce))
+    }
+}
+
+/// Fails if the associated data differs, as if the ciphertext was altered.
+impl<const N: usize> SecretReader<[u8; N], Result<Vec<u8>, aes_gcm::Error>> for DecipherWithAad {
+    fn read(&self, sec: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
+        let cipher = Aes256Gcm::new(sec.into());
+        let (enc, nonce) = &self.ciphertext;
+        let payload = Payload {
+            msg: enc,
+            aad: &self.aad,
+        };
+        cipher.decrypt(nonce, payload)
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -73,4 +648,146 @@ mod test {
             .unwrap();
         assert_eq!("secret message!!!", String::from_utf8_lossy(&decipher));
     }
+
+    #[test]
+    fn test_cipher_with_aad() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret
+            .as_mut()
+            .update_with(&UpdateSecretFromFile("./test/key".into()))
+            .unwrap();
+        let ciphertext = secret
+            .as_ref()
+            .read_with(&CipherWithAad {
+                message: b"secret message".to_vec(),
+                aad: b"record 1".to_vec(),
+            })
+            .unwrap();
+        let decipher = |aad: &[u8]| {
+            secret.as_ref().read_with(&DecipherWithAad {
+                ciphertext: ciphertext.clone(),
+                aad: aad.to_vec(),
+            })
+        };
+        assert_eq!(decipher(b"record 1").unwrap(), b"secret message");
+        assert!(decipher(b"record 2").is_err());
+        assert!(
+            secret
+                .as_ref()
+                .read_with(&Decipher(ciphertext.clone()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cipher_in_place() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret
+            .as_mut()
+            .update_with(&UpdateSecretFromFile("./test/key".into()))
+            .unwrap();
+        // Exactly as large as the message, so moved.
+        let mut buffer = Zeroizing::new(b"secret message".to_vec());
+        buffer.shrink_to_fit();
+        let nonce = secret
+            .as_ref()
+            .read_with_mut(&mut CipherInPlace::new(&mut buffer))
+            .unwrap();
+        assert_eq!(buffer.len(), 14 + TAG_LEN);
+        assert_ne!(buffer[..14], *b"secret message");
+        let ciphertext = (buffer.to_vec(), nonce);
+        assert_eq!(
+            secret.as_ref().read_with(&Decipher(ciphertext)).unwrap(),
+            b"secret message"
+        );
+        secret
+            .as_ref()
+            .read_with_mut(&mut DecipherInPlace::new(&mut buffer, nonce))
+            .unwrap();
+        assert_eq!(**buffer, *b"secret message");
+    }
+
+    #[test]
+    fn test_cipher_with_nonces() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret
+            .as_mut()
+            .update_with(&UpdateSecretFromFile("./test/key".into()))
+            .unwrap();
+        let nonces = nonce::RandomNonces::resume(nonce::RANDOM_LIMIT - 1);
+        let cipher = CipherWithNonces {
+            message: b"secret message".to_vec(),
+            aad: b"record 1".to_vec(),
+            nonces: &nonces,
+        };
+        let ciphertext = secret.as_ref().read_with(&cipher).unwrap();
+        let decipher = DecipherWithAad {
+            ciphertext,
+            aad: b"record 1".to_vec(),
+        };
+        assert_eq!(
+            secret.as_ref().read_with(&decipher).unwrap(),
+            b"secret message"
+        );
+        let err = secret.as_ref().read_with(&cipher).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::QuotaExceeded);
+    }
+
+    #[test]
+    fn test_update_secret_from_file_exact() {
+        let exact = || UpdateSecretFromFileExact("./test/key".into());
+        let mut secret = pin!(Secret::<[u8; 31]>::new());
+        assert_eq!(secret.as_mut().update_with(&exact()).unwrap(), 31);
+        let mut shorter = pin!(Secret::<[u8; 16]>::new());
+        let err = shorter.as_mut().update_with(&exact()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(shorter.as_ref().read_with(&|sec: &[u8]| sec == [0; 16]));
+        // The original key is one byte short of an AES-256 key.
+        let mut larger = pin!(Secret::<[u8; 32]>::new());
+        let err = larger.as_mut().update_with(&exact()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(larger.as_ref().read_with(&|sec: &[u8]| sec == [0; 32]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_update_secret_from_file_checked() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("secrust-key-{}", std::process::id()));
+        std::fs::write(&path, [0xa5; 32]).unwrap();
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        let checked = UpdateSecretFromFileChecked(path.clone());
+        for (mode, refused) in [(0o600, None), (0o644, Some(0o644)), (0o640, Some(0o640))] {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            let result = secret.as_mut().update_with(&checked);
+            match refused {
+                None => assert_eq!(result.unwrap(), 32),
+                Some(mode) => {
+                    let err = result.unwrap_err();
+                    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+                    let why = err.get_ref().unwrap().downcast_ref::<UnsafeKeyFile>();
+                    assert_eq!(why, Some(&UnsafeKeyFile::Permissive { mode }));
+                }
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+        let err = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromFileChecked(std::env::temp_dir()))
+            .unwrap_err();
+        let why = err.get_ref().unwrap().downcast_ref::<UnsafeKeyFile>();
+        assert_eq!(why, Some(&UnsafeKeyFile::NotRegular));
+    }
+
+    #[test]
+    fn test_read_to_end() {
+        let mut sec = [0xff; 8];
+        assert_eq!(read_to_end(&b"pass"[..], &mut sec).unwrap(), 4);
+        assert_eq!(sec[..4], *b"pass");
+        assert_eq!(read_to_end(&b"password"[..], &mut sec).unwrap(), 8);
+        let err = read_to_end(&b"passwords"[..], &mut sec).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(sec, [0; 8]);
+    }
 }
@@ -0,0 +1,181 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    iter,
+    path::PathBuf,
+    pin::Pin,
+};
+
+use ::age::{Decryptor, Identity, scrypt, secrecy::SecretString, x25519};
+use bech32::{ToBase32, Variant, u5};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::read_to_end;
+use crate::{
+    api::{Secret, SecretUpdater},
+    memory::{Global, SecureAlloc},
+};
+
+/// Human readable part of the Bech32 encoding of age X25519 identities.
+const SECRET_KEY_HRP: &str = "age-secret-key-";
+
+/// Key of an age-encrypted file, held in another [`Secret`].
+pub enum AgeKey<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    /// Passphrase (`age --passphrase`), up to its first NUL byte.
+    Passphrase(Pin<&'a Secret<[u8; K], Alloc>>),
+    /// Native X25519 identity, as its 32 raw bytes rather than its `AGE-SECRET-KEY-1…`
+    /// encoding.
+    X25519(Pin<&'a Secret<[u8; K], Alloc>>),
+}
+
+/// Decrypts an age-encrypted file (binary, not armored) directly into the secret.
+///
+/// # Security
+///
+/// The age identity is derived from the key only for the duration of the update, and the
+/// file key and decrypted chunks are zeroized by `age` once used. The Bech32 encoding of an
+/// X25519 identity, expected by `age`, is wiped too.
+pub struct UpdateSecretFromAgeFile<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub path: PathBuf,
+    pub key: AgeKey<'a, K, Alloc>,
+}
+
+impl<const K: usize, Alloc: SecureAlloc> AgeKey<'_, K, Alloc> {
+    fn identity(&self) -> io::Result<Box<dyn Identity>> {
+        match self {
+            AgeKey::Passphrase(secret) => secret.read_with(&|sec: &[u8]| {
+                let passphrase = std::str::from_utf8(super::passphrase(sec)).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "passphrase is not UTF-8")
+                })?;
+                let identity = scrypt::Identity::new(SecretString::from(passphrase.to_owned()));
+                Ok(Box::new(identity) as Box<dyn Identity>)
+            }),
+            AgeKey::X25519(secret) => secret.read_with(&|sec: &[u8]| {
+                x25519_identity(sec).map(|identity| Box::new(identity) as Box<dyn Identity>)
+            }),
+        }
+    }
+}
+
+/// Parses the raw bytes of an X25519 identity.
+fn x25519_identity(sec: &[u8]) -> io::Result<x25519::Identity> {
+    if sec.len() != 32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "X25519 identities are 32 bytes long",
+        ));
+    }
+    let mut base32 = sec.to_base32();
+    let encoded = bech32::encode(SECRET_KEY_HRP, &base32, Variant::Bech32).map(Zeroizing::new);
+    // `u5` does not implement `Zeroize`.
+    let zero = u5::try_from_u8(0).expect("0 fits in 5 bits");
+    for digit in base32.iter_mut() {
+        unsafe { std::ptr::write_volatile(digit, zero) };
+    }
+    encoded
+        .expect("HRP is valid")
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn decrypt_error(err: ::age::DecryptError) -> io::Error {
+    match err {
+        ::age::DecryptError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+/// Returns the length of the plaintext.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the file is not a valid age file or cannot
+/// be decrypted with the key, and with [`io::ErrorKind::InvalidInput`] if the plaintext is
+/// larger than the secret. On failure, the secret is zeroized.
+impl<const N: usize, const K: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], io::Result<usize>>
+    for UpdateSecretFromAgeFile<'_, K, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = File::open(&self.path).and_then(|file| {
+            let decryptor = Decryptor::new_buffered(BufReader::new(file)).map_err(decrypt_error)?;
+            let identity = self.key.identity()?;
+            let plaintext = decryptor
+                .decrypt(iter::once(&*identity))
+                .map_err(decrypt_error)?;
+            read_to_end(plaintext, sec)
+        });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{io::Write, pin::pin};
+
+    use ::age::{Encryptor, Recipient};
+
+    use super::*;
+    use crate::api::Secret;
+
+    fn encrypt(path: &PathBuf, recipient: &dyn Recipient, plaintext: &[u8]) {
+        let encryptor = Encryptor::with_recipients(iter::once(recipient)).unwrap();
+        let mut writer = encryptor.wrap_output(File::create(path).unwrap()).unwrap();
+        writer.write_all(plaintext).unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_update_secret_from_age_file() {
+        let path = std::env::temp_dir().join(format!("secrust-age-{}", std::process::id()));
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut().update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let recipient = key
+            .as_ref()
+            .read_with(&|sec: &[u8]| x25519_identity(sec).unwrap().to_public());
+        encrypt(&path, &recipient, b"age key");
+
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        let len = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromAgeFile {
+                path: path.clone(),
+                key: AgeKey::X25519(key.as_ref()),
+            })
+            .unwrap();
+        assert_eq!(len, 7);
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(&sec[..len], b"age key"));
+
+        let mut passphrase = pin!(Secret::<[u8; 32]>::new());
+        passphrase
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..4].copy_from_slice(b"pass"));
+        let mut recipient = scrypt::Recipient::new(SecretString::from("pass".to_owned()));
+        recipient.set_work_factor(2);
+        encrypt(&path, &recipient, b"age pass");
+        let len = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromAgeFile {
+                path: path.clone(),
+                key: AgeKey::Passphrase(passphrase.as_ref()),
+            })
+            .unwrap();
+        assert_eq!(len, 8);
+
+        let err = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromAgeFile {
+                path: path.clone(),
+                key: AgeKey::X25519(key.as_ref()),
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 16]));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,262 @@
+//! Operations delegated to an SSH agent (`ssh-agent`, `gpg-agent`, hardware token agents…),
+//! through the agent protocol, so that private keys never enter the process.
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::{
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+use zeroize::Zeroizing;
+
+use crate::api::SecretReader;
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+/// Asks for an `rsa-sha2-256` signature, rather than a legacy `ssh-rsa` (SHA-1) one, from
+/// an RSA key.
+pub const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+/// Asks for an `rsa-sha2-512` signature from an RSA key.
+pub const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+/// Longest reply accepted from the agent.
+const MAX_REPLY_LEN: usize = 256 * 1024;
+
+/// Where an agent listens.
+#[derive(Clone, Debug)]
+pub struct Agent {
+    /// Unix domain socket, or named pipe on Windows.
+    pub path: PathBuf,
+}
+
+/// A key held by an agent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AgentKey {
+    /// Public key, in the SSH wire format.
+    pub blob: Vec<u8>,
+    pub comment: String,
+}
+
+/// Signs the secret (a challenge, a token…) with a key of an agent, returning the signature
+/// in the SSH wire format.
+///
+/// # Security
+///
+/// The request holding the secret is zeroized once sent, but the secret is disclosed to the
+/// agent.
+pub struct SignWithAgent<'a> {
+    pub agent: &'a Agent,
+    pub key: &'a AgentKey,
+    /// `SSH_AGENT_RSA_SHA2_*` flags.
+    pub flags: u32,
+}
+
+#[cfg(unix)]
+type Stream = UnixStream;
+#[cfg(windows)]
+type Stream = std::fs::File;
+
+fn protocol_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+/// Parser of agent replies.
+struct Reply<'a>(&'a [u8]);
+
+impl<'a> Reply<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(protocol_error("truncated agent reply"));
+        }
+        let (value, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(value)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let value = self.take(4)?;
+        Ok(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+    }
+
+    fn string(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
+
+impl Agent {
+    /// The agent of the session, from `SSH_AUTH_SOCK`, or the OpenSSH agent named pipe on
+    /// Windows.
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if no agent is advertised.
+    pub fn from_env() -> io::Result<Self> {
+        match std::env::var_os("SSH_AUTH_SOCK") {
+            Some(path) if !path.is_empty() => Ok(Agent { path: path.into() }),
+            #[cfg(windows)]
+            _ => Ok(Agent {
+                path: PathBuf::from(r"\\.\pipe\openssh-ssh-agent"),
+            }),
+            #[cfg(unix)]
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "SSH_AUTH_SOCK is not set",
+            )),
+        }
+    }
+
+    fn connect(&self) -> io::Result<Stream> {
+        #[cfg(unix)]
+        return UnixStream::connect(&self.path);
+        #[cfg(windows)]
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.path)
+    }
+
+    /// Sends `request`, beginning with its type, and returns the payload of the reply if
+    /// it has the `expected` type.
+    fn call(&self, request: &[u8], expected: u8) -> io::Result<Vec<u8>> {
+        let mut stream = self.connect()?;
+        let mut frame = Zeroizing::new(Vec::with_capacity(4 + request.len()));
+        put_string(&mut frame, request);
+        stream.write_all(&frame)?;
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > MAX_REPLY_LEN {
+            return Err(protocol_error("invalid agent reply length"));
+        }
+        let mut reply = vec![0; len];
+        stream.read_exact(&mut reply)?;
+        match reply[0] {
+            kind if kind == expected => {
+                reply.remove(0);
+                Ok(reply)
+            }
+            SSH_AGENT_FAILURE => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the agent refused the request",
+            )),
+            _ => Err(protocol_error("unexpected agent reply")),
+        }
+    }
+
+    /// Lists the keys held by the agent.
+    pub fn keys(&self) -> io::Result<Vec<AgentKey>> {
+        let reply = self.call(
+            &[SSH_AGENTC_REQUEST_IDENTITIES],
+            SSH_AGENT_IDENTITIES_ANSWER,
+        )?;
+        let mut reply = Reply(&reply);
+        let count = reply.u32()?;
+        let mut keys = Vec::new();
+        for _ in 0..count {
+            let blob = reply.string()?.to_vec();
+            let comment = String::from_utf8_lossy(reply.string()?).into_owned();
+            keys.push(AgentKey { blob, comment });
+        }
+        Ok(keys)
+    }
+
+    /// Signs public `data` with `key`, see [`SignWithAgent`] to sign a secret.
+    pub fn sign(&self, key: &AgentKey, data: &[u8], flags: u32) -> io::Result<Vec<u8>> {
+        let mut request = Zeroizing::new(Vec::with_capacity(13 + key.blob.len() + data.len()));
+        request.push(SSH_AGENTC_SIGN_REQUEST);
+        put_string(&mut request, &key.blob);
+        put_string(&mut request, data);
+        request.extend_from_slice(&flags.to_be_bytes());
+        let reply = self.call(&request, SSH_AGENT_SIGN_RESPONSE)?;
+        Ok(Reply(&reply).string()?.to_vec())
+    }
+}
+
+/// Fails with [`io::ErrorKind::PermissionDenied`] if the agent refuses to sign (unknown key,
+/// denied confirmation).
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for SignWithAgent<'_> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        self.agent.sign(self.key, sec, self.flags)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+
+    use std::{os::unix::net::UnixListener, pin::pin};
+
+    use super::*;
+    use crate::api::Secret;
+
+    /// Answers one request with `reply`, returning the request.
+    fn serve(listener: UnixListener, reply: Vec<u8>) -> std::thread::JoinHandle<Vec<u8>> {
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut len = [0; 4];
+            stream.read_exact(&mut len).unwrap();
+            let mut request = vec![0; u32::from_be_bytes(len) as usize];
+            stream.read_exact(&mut request).unwrap();
+            let mut frame = Vec::new();
+            put_string(&mut frame, &reply);
+            stream.write_all(&frame).unwrap();
+            request
+        })
+    }
+
+    #[test]
+    fn test_sign_with_agent() {
+        let path = std::env::temp_dir().join(format!("secrust-agent-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let agent = Agent { path: path.clone() };
+
+        let mut reply = vec![SSH_AGENT_IDENTITIES_ANSWER, 0, 0, 0, 1];
+        put_string(&mut reply, b"key blob");
+        put_string(&mut reply, b"user@host");
+        let server = serve(UnixListener::bind(&path).unwrap(), reply);
+        let keys = agent.keys().unwrap();
+        assert_eq!(server.join().unwrap(), [SSH_AGENTC_REQUEST_IDENTITIES]);
+        assert_eq!(keys[0].comment, "user@host");
+
+        let mut reply = vec![SSH_AGENT_SIGN_RESPONSE];
+        put_string(&mut reply, b"signature");
+        std::fs::remove_file(&path).unwrap();
+        let server = serve(UnixListener::bind(&path).unwrap(), reply);
+        let mut secret = pin!(Secret::<[u8; 4]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"chal"));
+        let signature = secret
+            .as_ref()
+            .read_with(&SignWithAgent {
+                agent: &agent,
+                key: &keys[0],
+                flags: SSH_AGENT_RSA_SHA2_256,
+            })
+            .unwrap();
+        assert_eq!(signature, b"signature");
+        let request = server.join().unwrap();
+        let mut expected = vec![SSH_AGENTC_SIGN_REQUEST];
+        put_string(&mut expected, b"key blob");
+        put_string(&mut expected, b"chal");
+        expected.extend_from_slice(&SSH_AGENT_RSA_SHA2_256.to_be_bytes());
+        assert_eq!(request, expected);
+
+        let server = {
+            std::fs::remove_file(&path).unwrap();
+            serve(UnixListener::bind(&path).unwrap(), vec![SSH_AGENT_FAILURE])
+        };
+        let err = agent.sign(&keys[0], b"data", 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,414 @@
+//! Secrets held by AWS: values of Secrets Manager, and data keys wrapped by KMS for
+//! envelope encryption, the plaintext data key only living in a [`Secret`](crate::api::Secret).
+//!
+//! Requests are signed with AWS Signature Version 4, and sent over TLS.
+
+use std::{
+    borrow::Cow,
+    io,
+    time::{Duration, SystemTime},
+};
+
+use base64ct::{Base64, Encoding as _};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use ureq::http::Uri;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{
+    Encoding,
+    http::{agent, http_error, json_error, read_body},
+};
+use crate::api::{SecretReader, SecretUpdater};
+
+/// Credentials of an AWS identity.
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: Zeroizing<String>,
+    /// Set for temporary credentials.
+    pub session_token: Option<Zeroizing<String>>,
+}
+
+impl AwsCredentials {
+    /// Reads the credentials from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN` environment variables, which are left set.
+    pub fn from_env() -> io::Result<Self> {
+        let var = |name| {
+            std::env::var(name)
+                .map_err(|_| io::Error::new(io::ErrorKind::NotFound, format!("{name} is not set")))
+        };
+        Ok(AwsCredentials {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: Zeroizing::new(var("AWS_SECRET_ACCESS_KEY")?),
+            session_token: var("AWS_SESSION_TOKEN").ok().map(Zeroizing::new),
+        })
+    }
+}
+
+/// Where and how to reach AWS.
+pub struct Aws {
+    pub region: String,
+    pub credentials: AwsCredentials,
+    /// Replaces `https://<service>.<region>.amazonaws.com`, for VPC endpoints: a scheme and an
+    /// authority, without path.
+    pub endpoint: Option<String>,
+    pub timeout: Duration,
+}
+
+/// Fetches the value of a secret of Secrets Manager (`GetSecretValue`) into the secret.
+///
+/// Binary secrets are written as is, and string ones are decoded according to `encoding`.
+pub struct UpdateSecretFromSecretsManager<'a> {
+    pub aws: &'a Aws,
+    /// Name or ARN of the secret.
+    pub secret_id: String,
+    pub encoding: Encoding,
+}
+
+/// Wraps the data key held by the secret with a KMS key (`Encrypt`), returning the
+/// ciphertext to be stored alongside the data it protects.
+pub struct WrapWithKms<'a> {
+    pub aws: &'a Aws,
+    /// Identifier, ARN or alias of the KMS key.
+    pub key_id: String,
+}
+
+/// Unwraps a data key wrapped by [`WrapWithKms`] (`Decrypt`) into the secret.
+pub struct UnwrapWithKms<'a> {
+    pub aws: &'a Aws,
+    pub ciphertext: Vec<u8>,
+    /// KMS key expected to have wrapped the data key.
+    pub key_id: Option<String>,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], data: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    Zeroizing::new(mac.finalize().into_bytes().into())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    base16ct::lower::encode_string(bytes)
+}
+
+/// `YYYYMMDD'T'HHMMSS'Z'`, in UTC.
+fn amz_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Days since the epoch to civil date, over the proleptic Gregorian calendar.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Returns the `Host` header sent to `endpoint`: its host, and its port unless the default one
+/// of its scheme.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if `endpoint` is not an absolute URI of a host,
+/// or has a path or a query, requests being signed for the `/` path.
+fn endpoint_host(endpoint: &str) -> io::Result<String> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid AWS endpoint");
+    let uri: Uri = endpoint.parse().map_err(|_| invalid())?;
+    let default_port = match uri.scheme_str() {
+        Some("https") => 443,
+        Some("http") => 80,
+        _ => return Err(invalid()),
+    };
+    let host = uri.host().ok_or_else(invalid)?;
+    if uri.path() != "/" || uri.query().is_some() {
+        return Err(invalid());
+    }
+    Ok(match uri.port_u16() {
+        Some(port) if port != default_port => format!("{host}:{port}"),
+        _ => host.to_owned(),
+    })
+}
+
+/// Request to be signed with Signature Version 4.
+struct SignedRequest<'a> {
+    method: &'a str,
+    host: &'a str,
+    query: &'a str,
+    /// Lowercase names, sorted, not including `host` nor `x-amz-date`.
+    headers: &'a [(&'a str, &'a str)],
+    body: &'a [u8],
+}
+
+impl SignedRequest<'_> {
+    /// Returns the `Authorization` header.
+    fn authorization(
+        &self,
+        credentials: &AwsCredentials,
+        region: &str,
+        service: &str,
+        amz_date: &str,
+    ) -> String {
+        let mut headers: Vec<(&str, &str)> = self.headers.to_vec();
+        headers.extend([("host", self.host), ("x-amz-date", amz_date)]);
+        headers.sort();
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n/\n{}\n{canonical_headers}\n{signed_headers}\n{}",
+            self.method,
+            self.query,
+            hex(&Sha256::digest(self.body))
+        );
+        let date = &amz_date[..8];
+        let scope = format!("{date}/{region}/{service}/aws4_request");
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut secret = Zeroizing::new(format!("AWS4{}", *credentials.secret_access_key));
+        let key = hmac(secret.as_bytes(), date.as_bytes());
+        secret.zeroize();
+        let key = hmac(&*key, region.as_bytes());
+        let key = hmac(&*key, service.as_bytes());
+        let key = hmac(&*key, b"aws4_request");
+        let signature = hmac(&*key, string_to_sign.as_bytes());
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+            credentials.access_key_id,
+            hex(&*signature)
+        )
+    }
+}
+
+impl Aws {
+    /// Calls an action of a JSON API, returning the response body.
+    fn call(&self, service: &str, target: &str, body: &[u8]) -> io::Result<Zeroizing<Vec<u8>>> {
+        let default_endpoint;
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => endpoint.as_str(),
+            None => {
+                default_endpoint = format!("https://{service}.{}.amazonaws.com", self.region);
+                &default_endpoint
+            }
+        };
+        let host = endpoint_host(endpoint)?;
+        let amz_date = amz_date(SystemTime::now());
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1"),
+            ("x-amz-target", target),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.as_str()));
+        }
+        let authorization = SignedRequest {
+            method: "POST",
+            host: &host,
+            query: "",
+            headers: &headers,
+            body,
+        }
+        .authorization(&self.credentials, &self.region, service, &amz_date);
+        let mut request = agent(self.timeout)
+            .post(endpoint)
+            .header("x-amz-date", &amz_date)
+            .header("authorization", &authorization);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        read_body(request.send(body).map_err(http_error)?)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SecretValue<'a> {
+    #[serde(borrow)]
+    secret_string: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    secret_binary: Option<Cow<'a, str>>,
+}
+
+impl Drop for SecretValue<'_> {
+    fn drop(&mut self) {
+        // Values holding escapes have been unescaped in new buffers.
+        for value in [&mut self.secret_string, &mut self.secret_binary] {
+            if let Some(Cow::Owned(value)) = value {
+                value.zeroize();
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Encrypted<'a> {
+    #[serde(borrow)]
+    ciphertext_blob: Cow<'a, str>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Decrypted<'a> {
+    #[serde(borrow)]
+    plaintext: Cow<'a, str>,
+}
+
+impl Drop for Decrypted<'_> {
+    fn drop(&mut self) {
+        // A plaintext holding escapes has been unescaped in a new buffer.
+        if let Cow::Owned(plaintext) = &mut self.plaintext {
+            plaintext.zeroize();
+        }
+    }
+}
+
+/// Returns the length of the value.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the value is larger than the secret, and
+/// as [`Encoding`] does if it cannot be decoded.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>>
+    for UpdateSecretFromSecretsManager<'_>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let request = serde_json::json!({ "SecretId": self.secret_id }).to_string();
+        let body = self.aws.call(
+            "secretsmanager",
+            "secretsmanager.GetSecretValue",
+            request.as_bytes(),
+        )?;
+        let value: SecretValue = serde_json::from_slice(&body).map_err(json_error)?;
+        match (&value.secret_string, &value.secret_binary) {
+            (Some(string), _) => self.encoding.decode(string.as_bytes(), sec),
+            (None, Some(binary)) => Encoding::Base64.decode(binary.as_bytes(), sec),
+            (None, None) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "secret has no value",
+            )),
+        }
+    }
+}
+
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for WrapWithKms<'_> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let plaintext = Zeroizing::new(Base64::encode_string(sec));
+        let key_id = serde_json::to_string(&self.key_id).map_err(json_error)?;
+        let request = Zeroizing::new(format!(
+            r#"{{"KeyId":{key_id},"Plaintext":"{}"}}"#,
+            *plaintext
+        ));
+        let body = self
+            .aws
+            .call("kms", "TrentService.Encrypt", request.as_bytes())?;
+        let encrypted: Encrypted = serde_json::from_slice(&body).map_err(json_error)?;
+        Base64::decode_vec(&encrypted.ciphertext_blob)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Returns the length of the data key.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the data key is larger than the secret.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UnwrapWithKms<'_> {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let mut request = serde_json::json!({
+            "CiphertextBlob": Base64::encode_string(&self.ciphertext),
+        });
+        if let Some(key_id) = &self.key_id {
+            request["KeyId"] = key_id.as_str().into();
+        }
+        let body = self.aws.call(
+            "kms",
+            "TrentService.Decrypt",
+            request.to_string().as_bytes(),
+        )?;
+        let decrypted: Decrypted = serde_json::from_slice(&body).map_err(json_error)?;
+        Encoding::Base64.decode(decrypted.plaintext.as_bytes(), sec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // Example of the AWS documentation.
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: Zeroizing::new("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into()),
+            session_token: None,
+        };
+        let request = SignedRequest {
+            method: "GET",
+            host: "iam.amazonaws.com",
+            query: "Action=ListUsers&Version=2010-05-08",
+            headers: &[(
+                "content-type",
+                "application/x-www-form-urlencoded; charset=utf-8",
+            )],
+            body: b"",
+        };
+        let date = amz_date(SystemTime::UNIX_EPOCH + Duration::from_secs(1440938160));
+        assert_eq!(date, "20150830T123600Z");
+        assert_eq!(
+            request.authorization(&credentials, "us-east-1", "iam", &date),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn test_endpoint_host() {
+        for (endpoint, host) in [
+            (
+                "https://kms.eu-west-3.amazonaws.com",
+                "kms.eu-west-3.amazonaws.com",
+            ),
+            ("https://vpce.example.com/", "vpce.example.com"),
+            ("https://vpce.example.com:443", "vpce.example.com"),
+            (
+                "https://user@vpce.example.com:8443/",
+                "vpce.example.com:8443",
+            ),
+        ] {
+            assert_eq!(endpoint_host(endpoint).unwrap(), host);
+        }
+        for endpoint in [
+            "vpce.example.com",
+            "ftp://vpce.example.com",
+            "https://vpce.example.com/kms",
+            "https://vpce.example.com/?a=b",
+        ] {
+            let err = endpoint_host(endpoint).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_decrypted_with_escapes() {
+        let decrypted: Decrypted = serde_json::from_slice(br#"{"Plaintext":"ab\/c"}"#).unwrap();
+        assert!(matches!(decrypted.plaintext, Cow::Owned(_)));
+        assert_eq!(decrypted.plaintext, "ab/c");
+    }
+}
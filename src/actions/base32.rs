@@ -0,0 +1,80 @@
+use std::io;
+
+use base32ct::Encoding as _;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::Source;
+use crate::api::SecretUpdater;
+
+/// Decodes a Base32 key (RFC 4648) into the secret, such as the seed of an OTP
+/// authenticator, shown as `secret` in its `otpauth://` URI.
+///
+/// Letters may be lower or upper case, and padding and spaces (as in `JBSW Y3DP`) are
+/// ignored. The decoded key must fill the whole secret: a key of the wrong length is
+/// rejected, rather than silently truncated or padded.
+///
+/// # Security
+///
+/// Decoding is constant time, and writes directly into the secret. The encoded input, and
+/// its normalized copy, are zeroized once decoded.
+pub struct UpdateSecretFromBase32(pub Source);
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the input is not valid Base32, and with
+/// [`io::ErrorKind::InvalidInput`] if it does not decode to exactly `N` bytes. On failure,
+/// the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<()>> for UpdateSecretFromBase32 {
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let input = self.0.load()?;
+        let input: Zeroizing<Vec<u8>> = Zeroizing::new(
+            input
+                .iter()
+                .filter(|byte| !byte.is_ascii_whitespace() && **byte != b'=')
+                .map(u8::to_ascii_uppercase)
+                .collect(),
+        );
+        if input.len() != (8 * N).div_ceil(5) {
+            sec.zeroize();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Base32 key does not match the secret length",
+            ));
+        }
+        match base32ct::Base32UpperUnpadded::decode(&*input, sec) {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                sec.zeroize();
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid Base32 secret",
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_update_secret_from_base32() {
+        let base32 = |text: &str| UpdateSecretFromBase32(Source::Text(Zeroizing::new(text.into())));
+        let mut secret = pin!(Secret::<[u8; 5]>::new());
+        for text in ["MZXW6YTB", "mzxw 6ytb\n"] {
+            secret.as_mut().update_with(&base32(text)).unwrap();
+            assert!(secret.as_ref().read_with(&|sec: &[u8]| sec == b"fooba"));
+        }
+        let err = secret
+            .as_mut()
+            .update_with(&base32("MZXW6YT1"))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(secret.as_ref().read_with(&|sec: &[u8]| sec == [0; 5]));
+        let err = secret.as_mut().update_with(&base32("MZXW6")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,96 @@
+use std::io;
+
+use zeroize::Zeroize;
+
+use super::{Encoding, Source};
+use crate::api::SecretUpdater;
+
+/// Alphabet of a Base64 encoded key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alphabet {
+    /// Standard alphabet (`+` and `/`), with padding.
+    #[default]
+    Standard,
+    /// URL-safe alphabet (`-` and `_`), with or without padding.
+    UrlSafe,
+}
+
+/// Decodes a Base64 key into the secret.
+///
+/// The decoded key must fill the whole secret: a key of the wrong length is rejected,
+/// rather than silently truncated or padded.
+///
+/// # Security
+///
+/// Decoding is constant time, and writes directly into the secret. The encoded input is
+/// zeroized once decoded.
+pub struct UpdateSecretFromBase64 {
+    pub source: Source,
+    pub alphabet: Alphabet,
+}
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the input is not valid Base64, and with
+/// [`io::ErrorKind::InvalidInput`] if it does not decode to exactly `N` bytes. On failure,
+/// the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<()>> for UpdateSecretFromBase64 {
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let input = self.source.load()?;
+        let input = input.trim_ascii();
+        let (encoding, expected) = match self.alphabet {
+            Alphabet::Standard => (Encoding::Base64, N.div_ceil(3) * 4),
+            Alphabet::UrlSafe if input.ends_with(b"=") => (Encoding::Base64Url, N.div_ceil(3) * 4),
+            Alphabet::UrlSafe => (Encoding::Base64Url, (4 * N).div_ceil(3)),
+        };
+        if input.len() != expected {
+            sec.zeroize();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Base64 key does not match the secret length",
+            ));
+        }
+        encoding.decode(input, sec).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use zeroize::Zeroizing;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_update_secret_from_base64() {
+        let base64 = |text: &str, alphabet| UpdateSecretFromBase64 {
+            source: Source::Text(Zeroizing::new(text.into())),
+            alphabet,
+        };
+        let mut secret = pin!(Secret::<[u8; 4]>::new());
+        let expected = |sec: &[u8]| sec == [0xfb, 0xff, 0xbf, 0x01];
+        for (text, alphabet) in [
+            ("+/+/AQ==\n", Alphabet::Standard),
+            ("-_-_AQ==", Alphabet::UrlSafe),
+            ("-_-_AQ", Alphabet::UrlSafe),
+        ] {
+            secret
+                .as_mut()
+                .update_with(&base64(text, alphabet))
+                .unwrap();
+            assert!(secret.as_ref().read_with(&expected));
+        }
+        let err = secret
+            .as_mut()
+            .update_with(&base64("-_-_AQ==", Alphabet::Standard))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(secret.as_ref().read_with(&|sec: &[u8]| sec == [0; 4]));
+        let err = secret
+            .as_mut()
+            .update_with(&base64("+/+/", Alphabet::Standard))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,232 @@
+use std::{borrow::Cow, io, pin::Pin};
+
+use ::bip39::{Language, Mnemonic};
+use sha2::Sha512;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::UpdateSecretFromPrompt;
+use crate::{
+    api::{Secret, SecretReader, SecretUpdater, Unsizeable},
+    memory::{Global, SecureAlloc},
+};
+
+/// Data of a secret holding a BIP39 mnemonic phrase, UTF-8 encoded, followed by zeros.
+///
+/// It is typed with [`UpdateSecretFromPrompt`], as any passphrase.
+pub struct Bip39Phrase([u8; 256]);
+
+impl Default for Bip39Phrase {
+    fn default() -> Self {
+        Bip39Phrase([0; 256])
+    }
+}
+
+impl Zeroize for Bip39Phrase {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Unsizeable for Bip39Phrase {
+    type Unsized = [u8];
+
+    fn get_unsized(&self) -> &Self::Unsized {
+        &self.0
+    }
+
+    fn get_unsized_mut(&mut self) -> &mut Self::Unsized {
+        &mut self.0
+    }
+}
+
+/// Returns the length of the phrase, and fails as for byte arrays.
+impl SecretUpdater<Bip39Phrase, io::Result<usize>> for UpdateSecretFromPrompt {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        SecretUpdater::<[u8; 256], _>::update(self, sec)
+    }
+}
+
+/// Data of a secret holding a 64-byte BIP39 seed.
+pub struct Bip39Seed([u8; 64]);
+
+impl Default for Bip39Seed {
+    fn default() -> Self {
+        Bip39Seed([0; 64])
+    }
+}
+
+impl Zeroize for Bip39Seed {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Unsizeable for Bip39Seed {
+    type Unsized = [u8];
+
+    fn get_unsized(&self) -> &Self::Unsized {
+        &self.0
+    }
+
+    fn get_unsized_mut(&mut self) -> &mut Self::Unsized {
+        &mut self.0
+    }
+}
+
+/// Derives the seed of the English BIP39 mnemonic phrase held in a secret, and of the
+/// passphrase held in another secret, writing it directly into the secret.
+///
+/// The passphrase is the bytes of its secret up to its first NUL byte, so that a secret of
+/// zeros stands for no passphrase. The words of the phrase are separated by any whitespace.
+///
+/// # Security
+///
+/// The phrase is only parsed to the indexes of its words, zeroized on drop. The phrase,
+/// with its words separated by single spaces, and the passphrase, normalized, are hashed
+/// with PBKDF2 from buffers zeroized afterwards.
+pub struct UpdateSecretFromBip39<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub phrase: Pin<&'a Secret<Bip39Phrase, Alloc>>,
+    pub passphrase: Pin<&'a Secret<[u8; K], Alloc>>,
+}
+
+/// Renders the entropy held in the secret as an English BIP39 mnemonic phrase, such as to
+/// write it down as a backup.
+pub struct Bip39Mnemonic;
+
+/// Returns the words of the mnemonic, separated by single spaces.
+fn words(mnemonic: &Mnemonic) -> Zeroizing<String> {
+    // Large enough for 24 words of 8 letters, not to leave copies behind when growing.
+    let mut words = Zeroizing::new(String::with_capacity(24 * 9));
+    for (i, word) in mnemonic.words().enumerate() {
+        if i > 0 {
+            words.push(' ');
+        }
+        words.push_str(word);
+    }
+    words
+}
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the phrase has an unknown word, an invalid
+/// number of words or an invalid checksum, or if the phrase or the passphrase is not UTF-8.
+/// On failure, the secret is zeroized.
+impl<const K: usize, Alloc: SecureAlloc> SecretUpdater<Bip39Seed, io::Result<()>>
+    for UpdateSecretFromBip39<'_, K, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let invalid_data = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mnemonic = self.phrase.read_with(&|phrase: &[u8]| {
+            let phrase = std::str::from_utf8(super::passphrase(phrase))
+                .map_err(|_| invalid_data("mnemonic phrase is not UTF-8"))?;
+            Mnemonic::parse_in_normalized(Language::English, phrase)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+        });
+        let words = match mnemonic {
+            Ok(mnemonic) => words(&mnemonic),
+            Err(err) => {
+                sec.zeroize();
+                return Err(err);
+            }
+        };
+        let result = self.passphrase.read_with_mut(&mut |passphrase: &[u8]| {
+            let mut passphrase = Cow::Borrowed(
+                std::str::from_utf8(super::passphrase(passphrase))
+                    .map_err(|_| invalid_data("passphrase is not UTF-8"))?,
+            );
+            Mnemonic::normalize_utf8_cow(&mut passphrase);
+            let salt = Zeroizing::new([b"mnemonic", passphrase.as_bytes()].concat());
+            if let Cow::Owned(passphrase) = &mut passphrase {
+                passphrase.zeroize();
+            }
+            pbkdf2::pbkdf2_hmac::<Sha512>(words.as_bytes(), &salt, 2048, sec);
+            Ok(())
+        });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the entropy is not of 16, 20, 24, 28 or 32
+/// bytes.
+impl<const N: usize> SecretReader<[u8; N], io::Result<Zeroizing<String>>> for Bip39Mnemonic {
+    fn read(&self, sec: &[u8]) -> io::Result<Zeroizing<String>> {
+        let mnemonic = Mnemonic::from_entropy_in(Language::English, sec)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        Ok(words(&mnemonic))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+
+    /// Test vectors of the reference implementation of BIP39, with the passphrase `TREZOR`.
+    #[test]
+    fn test_bip39() {
+        let mut passphrase = pin!(Secret::<[u8; 8]>::new());
+        passphrase
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..6].copy_from_slice(b"TREZOR"));
+        let mut phrase = pin!(Secret::<Bip39Phrase>::new());
+        let mut seed = pin!(Secret::<Bip39Seed>::new());
+        let mut derive = |words: &str| {
+            phrase.as_mut().update_with(&|sec: &mut [u8]| {
+                sec.zeroize();
+                sec[..words.len()].copy_from_slice(words.as_bytes())
+            });
+            seed.as_mut().update_with(&UpdateSecretFromBip39 {
+                phrase: phrase.as_ref(),
+                passphrase: passphrase.as_ref(),
+            })?;
+            Ok::<_, io::Error>(seed.as_ref().read_with(&|sec: &[u8]| {
+                sec.iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<String>()
+            }))
+        };
+
+        for (entropy, words, expected) in [
+            (
+                [0x00; 16],
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264\
+                 c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                [0x7f; 16],
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3\
+                 c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            ),
+        ] {
+            let mut secret = pin!(Secret::<[u8; 16]>::new());
+            secret
+                .as_mut()
+                .update_with(&|sec: &mut [u8]| sec.copy_from_slice(&entropy));
+            assert_eq!(*secret.as_ref().read_with(&Bip39Mnemonic).unwrap(), words);
+            assert_eq!(derive(words).unwrap(), expected);
+            // Words may be separated by any whitespace.
+            assert_eq!(derive(&words.replace(' ', "  \n")).unwrap(), expected);
+        }
+
+        let err = derive(
+            "abandon abandon abandon abandon abandon abandon abandon abandon \
+             abandon abandon abandon abandon",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = derive("abandon secrust").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        seed.as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 64]));
+
+        let entropy = pin!(Secret::<[u8; 15]>::new());
+        let err = entropy.as_ref().read_with(&Bip39Mnemonic).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,374 @@
+//! Secrets held by cloud secret managers, behind a common [`CloudSecretSource`], so that the
+//! same loading code runs on any cloud.
+//!
+//! Sources authenticate with the identity of the workload (service account of the
+//! instance or of the Kubernetes pod), or with a given token.
+
+use std::{borrow::Cow, io, time::Duration};
+
+use base64ct::{Base64, Encoding as _};
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::http::{agent, http_error, is_transient, json_error, read_body};
+use crate::api::SecretUpdater;
+
+/// A cloud secret manager.
+pub trait CloudSecretSource {
+    /// Fetches the latest version of the named secret, writing its value into the start
+    /// of `sec` and returning its length.
+    ///
+    /// On failure, `sec` may have been partially written.
+    fn fetch(&self, name: &str, sec: &mut [u8]) -> io::Result<usize>;
+}
+
+/// Loads a secret from a [`CloudSecretSource`], retrying on transient failures
+/// (timeouts, connection failures, throttling and server errors) with an exponential
+/// backoff.
+pub struct UpdateSecretFromCloud<'a> {
+    pub source: &'a dyn CloudSecretSource,
+    pub name: String,
+    /// Number of retries, on top of the first attempt.
+    pub retries: u32,
+}
+
+/// Returns the length of the value.
+///
+/// Fails with [`io::ErrorKind::NotFound`] if there is no such secret, with
+/// [`io::ErrorKind::PermissionDenied`] if access is denied, with
+/// [`io::ErrorKind::InvalidInput`] if the name is not made of ASCII letters, digits, `-` and
+/// `_`, or if the value is larger than the secret, and with [`io::ErrorKind::InvalidData`]
+/// if the response is malformed. On failure, the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromCloud<'_> {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let mut backoff = Duration::from_millis(100);
+        let mut attempt = 0;
+        let result = loop {
+            match self.source.fetch(&self.name, sec) {
+                Err(err) if attempt < self.retries && is_transient(&err) => {
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Writes a value fetched in a response body into the secret.
+fn copy_value(value: &[u8], sec: &mut [u8]) -> io::Result<usize> {
+    let dst = sec.get_mut(..value.len()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "secret is larger than its buffer",
+        )
+    })?;
+    dst.copy_from_slice(value);
+    Ok(value.len())
+}
+
+#[derive(Deserialize)]
+struct AccessToken<'a> {
+    #[serde(borrow)]
+    access_token: Cow<'a, str>,
+}
+
+fn parse_token(body: &[u8]) -> io::Result<Zeroizing<String>> {
+    let token: AccessToken = serde_json::from_slice(body).map_err(json_error)?;
+    Ok(Zeroizing::new(token.access_token.into_owned()))
+}
+
+/// Checks that a secret name can be used as is as a segment of the path of a URL, as the
+/// names allowed by all secret managers can.
+fn path_segment(name: &str) -> io::Result<&str> {
+    match !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+    {
+        true => Ok(name),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid secret name",
+        )),
+    }
+}
+
+/// Percent-encodes a value of a form.
+fn form_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// How to authenticate to GCP.
+pub enum GcpAuth {
+    /// The service account of the instance (or of the GKE workload identity), from the
+    /// metadata server.
+    Metadata,
+    /// An OAuth access token.
+    Token(Zeroizing<String>),
+}
+
+/// GCP Secret Manager, for secrets of a project.
+pub struct GcpSecretManager {
+    pub project: String,
+    pub auth: GcpAuth,
+    pub timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct GcpSecretVersion<'a> {
+    #[serde(borrow)]
+    payload: GcpPayload<'a>,
+}
+
+#[derive(Deserialize)]
+struct GcpPayload<'a> {
+    /// Base64, never escaped.
+    data: &'a str,
+}
+
+/// Decodes the value of a secret version into the secret.
+fn decode_gcp_version(body: &[u8], sec: &mut [u8]) -> io::Result<usize> {
+    let version: GcpSecretVersion = serde_json::from_slice(body).map_err(json_error)?;
+    Base64::decode(version.payload.data, sec)
+        .map(<[u8]>::len)
+        .map_err(|err| match err {
+            base64ct::Error::InvalidLength => io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "secret is larger than its buffer",
+            ),
+            base64ct::Error::InvalidEncoding => {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid base64 secret payload")
+            }
+        })
+}
+
+impl CloudSecretSource for GcpSecretManager {
+    fn fetch(&self, name: &str, sec: &mut [u8]) -> io::Result<usize> {
+        let name = path_segment(name)?;
+        let agent = agent(self.timeout);
+        let token = match &self.auth {
+            GcpAuth::Token(token) => token.clone(),
+            GcpAuth::Metadata => {
+                let response = agent
+                    .get("http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token")
+                    .header("Metadata-Flavor", "Google")
+                    .call()
+                    .map_err(http_error)?;
+                parse_token(&read_body(response)?)?
+            }
+        };
+        let url = format!(
+            "https://secretmanager.googleapis.com/v1/projects/{}/secrets/{name}/versions/latest:access",
+            self.project
+        );
+        let response = agent
+            .get(url)
+            .header(
+                "Authorization",
+                &*Zeroizing::new(format!("Bearer {}", *token)),
+            )
+            .call()
+            .map_err(http_error)?;
+        decode_gcp_version(&read_body(response)?, sec)
+    }
+}
+
+/// How to authenticate to Azure.
+pub enum AzureAuth {
+    /// The managed identity of the instance, from the instance metadata service.
+    ManagedIdentity,
+    /// The AKS workload identity of the pod, set in the `AZURE_CLIENT_ID`,
+    /// `AZURE_TENANT_ID`, `AZURE_FEDERATED_TOKEN_FILE` (and `AZURE_AUTHORITY_HOST`)
+    /// environment variables.
+    WorkloadIdentity,
+    /// An OAuth access token for Key Vault.
+    Token(Zeroizing<String>),
+}
+
+/// Azure Key Vault.
+pub struct AzureKeyVault {
+    /// URL of the vault, such as `https://example.vault.azure.net`.
+    pub vault_url: String,
+    pub auth: AzureAuth,
+    pub timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct AzureSecret<'a> {
+    #[serde(borrow)]
+    value: Cow<'a, str>,
+}
+
+impl Drop for AzureSecret<'_> {
+    fn drop(&mut self) {
+        // A value holding escapes has been unescaped in a new buffer.
+        if let Cow::Owned(value) = &mut self.value {
+            value.zeroize();
+        }
+    }
+}
+
+impl AzureKeyVault {
+    fn token(&self, agent: &ureq::Agent) -> io::Result<Zeroizing<String>> {
+        let body = match &self.auth {
+            AzureAuth::Token(token) => return Ok(token.clone()),
+            AzureAuth::ManagedIdentity => agent
+                .get("http://169.254.169.254/metadata/identity/oauth2/token?api-version=2018-02-01&resource=https%3A%2F%2Fvault.azure.net")
+                .header("Metadata", "true")
+                .call(),
+            AzureAuth::WorkloadIdentity => {
+                let var = |name| {
+                    std::env::var(name).map_err(|_| {
+                        io::Error::new(io::ErrorKind::NotFound, format!("{name} is not set"))
+                    })
+                };
+                let authority = std::env::var("AZURE_AUTHORITY_HOST")
+                    .unwrap_or_else(|_| "https://login.microsoftonline.com/".into());
+                let assertion =
+                    Zeroizing::new(std::fs::read_to_string(var("AZURE_FEDERATED_TOKEN_FILE")?)?);
+                let form = Zeroizing::new(format!(
+                    "client_assertion_type={}&client_assertion={}&client_id={}&grant_type=client_credentials&scope={}",
+                    form_encode("urn:ietf:params:oauth:client-assertion-type:jwt-bearer"),
+                    form_encode(assertion.trim()),
+                    form_encode(&var("AZURE_CLIENT_ID")?),
+                    form_encode("https://vault.azure.net/.default"),
+                ));
+                let url = format!(
+                    "{}/{}/oauth2/v2.0/token",
+                    authority.trim_end_matches('/'),
+                    var("AZURE_TENANT_ID")?
+                );
+                agent
+                    .post(url)
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .send(form.as_bytes())
+            }
+        };
+        parse_token(&read_body(body.map_err(http_error)?)?)
+    }
+}
+
+impl CloudSecretSource for AzureKeyVault {
+    fn fetch(&self, name: &str, sec: &mut [u8]) -> io::Result<usize> {
+        let name = path_segment(name)?;
+        let agent = agent(self.timeout);
+        let token = self.token(&agent)?;
+        let url = format!(
+            "{}/secrets/{name}?api-version=7.4",
+            self.vault_url.trim_end_matches('/')
+        );
+        let response = agent
+            .get(url)
+            .header(
+                "Authorization",
+                &*Zeroizing::new(format!("Bearer {}", *token)),
+            )
+            .call()
+            .map_err(http_error)?;
+        let body = read_body(response)?;
+        let secret: AzureSecret = serde_json::from_slice(&body).map_err(json_error)?;
+        copy_value(secret.value.as_bytes(), sec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        pin::pin,
+    };
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_update_secret_from_azure_with_retry() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let vault_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            for (status, body) in [
+                ("503 Service Unavailable", ""),
+                ("200 OK", r#"{"value":"p\u00e4ss\nword","id":"x"}"#),
+            ] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut request = BufReader::new(stream);
+                let mut line = String::new();
+                request.read_line(&mut line).unwrap();
+                assert!(line.starts_with("GET /secrets/db-password?api-version="));
+                while request.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    request.get_mut(),
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        let vault = AzureKeyVault {
+            vault_url,
+            auth: AzureAuth::Token(Zeroizing::new("token".into())),
+            timeout: Duration::from_secs(5),
+        };
+        let update = UpdateSecretFromCloud {
+            source: &vault,
+            name: "db-password".into(),
+            retries: 2,
+        };
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        let len = secret.as_mut().update_with(&update).unwrap();
+        assert!(
+            secret
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec[..len] == *"päss\nword".as_bytes())
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_update_secret_from_gcp() {
+        let manager = GcpSecretManager {
+            project: "project".into(),
+            auth: GcpAuth::Token(Zeroizing::new("token".into())),
+            timeout: Duration::from_secs(5),
+        };
+        let mut secret = pin!(Secret::<[u8; 8]>::new());
+        for name in ["../other", "db?x", "db#x", "a/b", ""] {
+            let update = UpdateSecretFromCloud {
+                source: &manager,
+                name: name.into(),
+                retries: 0,
+            };
+            let err = secret.as_mut().update_with(&update).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let mut sec = [0u8; 8];
+        let body = br#"{"name":"x","payload":{"data":"c2VjcmV0","dataCrc32c":"1"}}"#;
+        assert_eq!(decode_gcp_version(body, &mut sec).unwrap(), 6);
+        assert_eq!(&sec[..6], b"secret");
+        let body = br#"{"payload":{"data":"bG9uZ2VyIHNlY3JldA=="}}"#;
+        let err = decode_gcp_version(body, &mut sec).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let body = br#"{"payload":{"data":"c2Vj!3JldA"}}"#;
+        let err = decode_gcp_version(body, &mut sec).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
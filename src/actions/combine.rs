@@ -0,0 +1,114 @@
+use std::{io, pin::pin};
+
+use zeroize::Zeroize;
+
+use crate::api::{Secret, SecretUpdater};
+
+/// Recombines a key split in parts (split knowledge, dual control): the secret is the XOR of
+/// the parts, each loaded by its own updater (files, environment, prompts…), so that no
+/// single channel carries the whole key.
+///
+/// Every part must fill the whole secret: the updaters must return `N`.
+///
+/// # Security
+///
+/// The first part is loaded directly into the secret, the other ones in a temporary
+/// [`Secret`], zeroized once combined.
+pub struct CombineParts<'a, const N: usize>(
+    pub Vec<&'a dyn SecretUpdater<[u8; N], io::Result<usize>>>,
+);
+
+fn check_len(len: usize, expected: usize) -> io::Result<()> {
+    match len == expected {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "key part is shorter than the secret",
+        )),
+    }
+}
+
+/// XORs the parts into `sec`.
+fn combine<const N: usize>(
+    parts: &[&dyn SecretUpdater<[u8; N], io::Result<usize>>],
+    sec: &mut [u8],
+) -> io::Result<usize>
+where
+    [u8; N]: Default,
+{
+    let Some((first, others)) = parts.split_first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no key part"));
+    };
+    check_len(first.update(sec)?, N)?;
+    let mut part = pin!(Secret::<[u8; N]>::new());
+    for updater in others {
+        let len = part
+            .as_mut()
+            .update_with(&|part: &mut [u8]| updater.update(part))?;
+        check_len(len, N)?;
+        part.as_ref().read_with_mut(&mut |part: &[u8]| {
+            for (byte, share) in sec.iter_mut().zip(part) {
+                *byte ^= share;
+            }
+        });
+    }
+    Ok(parts.len())
+}
+
+/// Returns the number of combined parts.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if there is no part, with
+/// [`io::ErrorKind::InvalidData`] if a part does not fill the secret, and otherwise as the
+/// updater of the failed part. On failure, the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for CombineParts<'_, N>
+where
+    [u8; N]: Default,
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = combine(&self.0, sec);
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_combine_parts() {
+        let first = |sec: &mut [u8]| {
+            sec.copy_from_slice(&[0x0f; 4]);
+            Ok(4)
+        };
+        let second = |sec: &mut [u8]| {
+            sec.copy_from_slice(&[0xf0, 0xf0, 0x0f, 0x00]);
+            Ok(4)
+        };
+        let short = |sec: &mut [u8]| {
+            sec[0] = 1;
+            Ok(1)
+        };
+        let mut secret = pin!(Secret::<[u8; 4]>::new());
+        let count = secret
+            .as_mut()
+            .update_with(&CombineParts(vec![&first, &second]))
+            .unwrap();
+        assert_eq!(count, 2);
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0xff, 0xff, 0x00, 0x0f]));
+
+        let err = secret
+            .as_mut()
+            .update_with(&CombineParts(vec![&first, &short]))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 4]));
+    }
+}
@@ -0,0 +1,145 @@
+use std::io;
+
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce, aead::Aead};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::api::{SecretReader, constant_time_eq};
+
+/// Length of the random salt prefixing the ciphertexts.
+const SALT_LEN: usize = 32;
+/// Length of the key commitment following the salt.
+const COMMITMENT_LEN: usize = 32;
+
+/// Encrypts a message with AES-256-GCM so that the ciphertext commits to the key: it only
+/// decrypts under the key it was encrypted with, even for an attacker choosing the keys.
+///
+/// Plain AES-GCM does not commit to its key, so that a ciphertext can be crafted to decrypt
+/// under many keys, which turns a decryption oracle into a partition oracle (as to guess a
+/// password-derived key), and lets a message be read differently by several recipients.
+///
+/// As the committing suites of the AWS Encryption SDK, a random salt derives with
+/// HKDF-SHA256 a fresh encryption key, under a fixed nonce, and a commitment to the key,
+/// checked before decrypting. The ciphertext is the salt, the commitment, then the AES-GCM
+/// ciphertext and its tag.
+pub struct CommittingCipher(pub Vec<u8>);
+
+/// Decrypts a ciphertext, as returned by [`CommittingCipher`].
+pub struct CommittingDecipher(pub Vec<u8>);
+
+/// Derives the encryption key and the commitment of a salt, under the key held in `sec`.
+fn derive(sec: &[u8], salt: &[u8]) -> (Zeroizing<[u8; 32]>, [u8; COMMITMENT_LEN]) {
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), sec);
+    let mut key = Zeroizing::new([0; 32]);
+    let mut commitment = [0; COMMITMENT_LEN];
+    hkdf.expand(b"secrust committing AES-256-GCM key", &mut *key)
+        .expect("32 bytes is a valid length");
+    hkdf.expand(
+        b"secrust committing AES-256-GCM commitment",
+        &mut commitment,
+    )
+    .expect("32 bytes is a valid length");
+    (key, commitment)
+}
+
+/// Returns the ciphertext.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the message is too large for AES-GCM, and
+/// with the errors of the OS CSPRNG.
+impl SecretReader<[u8; 32], io::Result<Vec<u8>>> for CommittingCipher {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let mut salt = [0; SALT_LEN];
+        getrandom::getrandom(&mut salt)?;
+        let (key, commitment) = derive(sec, &salt);
+        // Each key encrypts a single message, under the same nonce.
+        let enc = Aes256Gcm::new((&*key).into())
+            .encrypt(&Nonce::default(), &*self.0)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "message is too large for AES-GCM",
+                )
+            })?;
+        Ok([&salt[..], &commitment, &enc].concat())
+    }
+}
+
+/// Returns the message.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the ciphertext was not encrypted under
+/// this key, or was altered.
+impl SecretReader<[u8; 32], io::Result<Vec<u8>>> for CommittingDecipher {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let invalid_data = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ciphertext was not encrypted under this key, or was altered",
+            )
+        };
+        if self.0.len() < SALT_LEN + COMMITMENT_LEN {
+            return Err(invalid_data());
+        }
+        let (salt, rest) = self.0.split_at(SALT_LEN);
+        let (commitment, enc) = rest.split_at(COMMITMENT_LEN);
+        let (key, expected) = derive(sec, salt);
+        if !constant_time_eq(&expected, commitment) {
+            return Err(invalid_data());
+        }
+        Aes256Gcm::new((&*key).into())
+            .decrypt(&Nonce::default(), enc)
+            .map_err(|_| invalid_data())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_committing_cipher() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let enc = secret
+            .as_ref()
+            .read_with(&CommittingCipher(b"secret message".to_vec()))
+            .unwrap();
+        assert_eq!(enc.len(), SALT_LEN + COMMITMENT_LEN + 14 + 16);
+        let dec = secret
+            .as_ref()
+            .read_with(&CommittingDecipher(enc.clone()))
+            .unwrap();
+        assert_eq!(dec, b"secret message");
+
+        // Each message has its own salt.
+        let again = secret
+            .as_ref()
+            .read_with(&CommittingCipher(b"secret message".to_vec()))
+            .unwrap();
+        assert_ne!(again[..SALT_LEN], enc[..SALT_LEN]);
+
+        let mut other = pin!(Secret::<[u8; 32]>::new());
+        other.as_mut().update_with(&|sec: &mut [u8]| sec.fill(0x43));
+        for (key, enc) in [
+            (other.as_ref(), enc.clone()),
+            (
+                secret.as_ref(),
+                enc[..SALT_LEN + COMMITMENT_LEN - 1].to_vec(),
+            ),
+            (secret.as_ref(), {
+                let mut altered = enc.clone();
+                *altered.last_mut().unwrap() ^= 1;
+                altered
+            }),
+        ] {
+            let err = key.read_with(&CommittingDecipher(enc)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::api::{SecretReader, constant_time_eq};
+
+/// Compares the secret with a candidate (a token, a password…) in constant time, where `==`
+/// would exit on the first differing byte, revealing through timing how much of the
+/// candidate is right.
+///
+/// Only the lengths may leak: a candidate of another length is different. To compare two
+/// secrets, see [`Secret::ct_eq`](crate::api::Secret::ct_eq).
+pub struct ConstantTimeEq<'a>(pub &'a [u8]);
+
+/// Returns whether the secret equals the candidate.
+impl<const N: usize> SecretReader<[u8; N], bool> for ConstantTimeEq<'_> {
+    fn read(&self, sec: &[u8]) -> bool {
+        constant_time_eq(sec, self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_constant_time_eq() {
+        let mut secret = pin!(Secret::<[u8; 8]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"token123"));
+        assert!(secret.as_ref().read_with(&ConstantTimeEq(b"token123")));
+        assert!(!secret.as_ref().read_with(&ConstantTimeEq(b"token124")));
+        assert!(!secret.as_ref().read_with(&ConstantTimeEq(b"token")));
+
+        let mut other = pin!(Secret::<[u8; 8]>::new());
+        assert!(!secret.as_ref().ct_eq(other.as_ref()));
+        other
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"token123"));
+        assert!(secret.as_ref().ct_eq(other.as_ref()));
+        assert!(
+            !secret
+                .as_ref()
+                .ct_eq(pin!(Secret::<[u8; 4]>::new()).as_ref())
+        );
+    }
+}
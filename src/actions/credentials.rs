@@ -0,0 +1,193 @@
+use std::{ffi::OsStr, io, os::windows::ffi::OsStrExt};
+
+use windows_sys::Win32::{
+    Foundation::ERROR_NOT_FOUND,
+    Security::Credentials::{
+        CRED_MAX_CREDENTIAL_BLOB_SIZE, CRED_PERSIST, CRED_PERSIST_ENTERPRISE,
+        CRED_PERSIST_LOCAL_MACHINE, CRED_PERSIST_SESSION, CRED_TYPE_GENERIC, CREDENTIALW, CredFree,
+        CredReadW, CredWriteW,
+    },
+};
+use zeroize::Zeroize;
+
+use crate::api::{SecretReader, SecretUpdater};
+
+/// How long a credential stored in the Credential Manager lasts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CredentialPersist {
+    /// Until the end of the logon session.
+    Session,
+    /// Across logon sessions, on this machine only.
+    #[default]
+    LocalMachine,
+    /// Across logon sessions, and roaming with the user profile.
+    Enterprise,
+}
+
+impl CredentialPersist {
+    fn value(self) -> CRED_PERSIST {
+        match self {
+            CredentialPersist::Session => CRED_PERSIST_SESSION,
+            CredentialPersist::LocalMachine => CRED_PERSIST_LOCAL_MACHINE,
+            CredentialPersist::Enterprise => CRED_PERSIST_ENTERPRISE,
+        }
+    }
+}
+
+/// Loads the blob of a generic credential of the Windows Credential Manager (`CredReadW`).
+///
+/// # Security
+///
+/// The blob is returned by the Credential Manager in a buffer, zeroized before being freed.
+pub struct UpdateSecretFromCredential {
+    /// Name of the credential.
+    pub target: String,
+}
+
+/// Stores the secret as the blob of a generic credential of the Windows Credential Manager
+/// (`CredWriteW`), created or replaced.
+pub struct StoreInCredentialManager {
+    /// Name of the credential.
+    pub target: String,
+    pub user_name: Option<String>,
+    pub persist: CredentialPersist,
+}
+
+fn wide(text: &str) -> Vec<u16> {
+    OsStr::new(text).encode_wide().chain([0]).collect()
+}
+
+/// Returns the length of the blob.
+///
+/// Fails with [`io::ErrorKind::NotFound`] if there is no such credential, and with
+/// [`io::ErrorKind::InvalidInput`] if the blob is larger than the secret, which is then
+/// zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromCredential {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        /// Zeroizes and frees the credential.
+        struct Credential(*mut CREDENTIALW);
+        impl Drop for Credential {
+            fn drop(&mut self) {
+                unsafe {
+                    let credential = &*self.0;
+                    if !credential.CredentialBlob.is_null() {
+                        std::slice::from_raw_parts_mut(
+                            credential.CredentialBlob,
+                            credential.CredentialBlobSize as usize,
+                        )
+                        .zeroize();
+                    }
+                    CredFree(self.0.cast());
+                }
+            }
+        }
+
+        let target = wide(&self.target);
+        let mut credential = std::ptr::null_mut();
+        if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) } == 0 {
+            let err = io::Error::last_os_error();
+            return Err(match err.raw_os_error() {
+                Some(code) if code == ERROR_NOT_FOUND as i32 => {
+                    io::Error::new(io::ErrorKind::NotFound, err)
+                }
+                _ => err,
+            });
+        }
+        let credential = Credential(credential);
+        let blob = unsafe {
+            let credential = &*credential.0;
+            match credential.CredentialBlob.is_null() {
+                true => &[][..],
+                false => std::slice::from_raw_parts(
+                    credential.CredentialBlob,
+                    credential.CredentialBlobSize as usize,
+                ),
+            }
+        };
+        match sec.get_mut(..blob.len()) {
+            Some(dst) => {
+                dst.copy_from_slice(blob);
+                Ok(blob.len())
+            }
+            None => {
+                sec.zeroize();
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "credential is larger than its buffer",
+                ))
+            }
+        }
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the secret is larger than the credentials
+/// blobs can be (2560 bytes).
+impl<const N: usize> SecretReader<[u8; N], io::Result<()>> for StoreInCredentialManager {
+    fn read(&self, sec: &[u8]) -> io::Result<()> {
+        if sec.len() > CRED_MAX_CREDENTIAL_BLOB_SIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "secret is larger than a credential",
+            ));
+        }
+        let mut target = wide(&self.target);
+        let mut user_name = self.user_name.as_deref().map(wide);
+        let credential = CREDENTIALW {
+            Flags: 0,
+            Type: CRED_TYPE_GENERIC,
+            TargetName: target.as_mut_ptr(),
+            Comment: std::ptr::null_mut(),
+            LastWritten: unsafe { std::mem::zeroed() },
+            CredentialBlobSize: sec.len() as u32,
+            CredentialBlob: sec.as_ptr().cast_mut(),
+            Persist: self.persist.value(),
+            AttributeCount: 0,
+            Attributes: std::ptr::null_mut(),
+            TargetAlias: std::ptr::null_mut(),
+            UserName: user_name
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |user_name| user_name.as_mut_ptr()),
+        };
+        match unsafe { CredWriteW(&credential, 0) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use windows_sys::Win32::Security::Credentials::CredDeleteW;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_credential_round_trip() {
+        let target = format!("secrust-test-{}", std::process::id());
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(&[0xc3; 16]));
+        let store = StoreInCredentialManager {
+            target: target.clone(),
+            user_name: None,
+            persist: CredentialPersist::Session,
+        };
+        secret.as_ref().read_with(&store).unwrap();
+        let load = UpdateSecretFromCredential { target };
+        let mut loaded = pin!(Secret::<[u8; 32]>::new());
+        assert_eq!(loaded.as_mut().update_with(&load).unwrap(), 16);
+        assert!(
+            loaded
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec[..16] == [0xc3; 16])
+        );
+        unsafe { CredDeleteW(wide(&load.target).as_ptr(), CRED_TYPE_GENERIC, 0) };
+        let err = loaded.as_mut().update_with(&load).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,170 @@
+use std::{collections::HashMap, io, pin::Pin};
+
+use zeroize::Zeroize;
+
+use super::Source;
+use crate::api::Secret;
+
+/// Loads every entry of a dotenv file (`NAME=value` lines) into its own secret, keyed by its
+/// name, with the length of its value.
+///
+/// Lines may start with `export`, and blank lines and `#` comments are skipped. Values are
+/// either unquoted (up to a ` #` comment), single-quoted (literal), or double-quoted (with
+/// `\n`, `\r`, `\t`, `\\`, `\"` and `\$` escapes). A later entry replaces an earlier one with
+/// the same name.
+///
+/// As for [`super::UpdateSecretFromPrompt`], values are written (UTF-8 encoded) into byte
+/// arrays, a `String` not being able to grow through the `&mut str` lent to updaters.
+///
+/// # Security
+///
+/// Values are decoded directly into their secrets, and the content of the file is zeroized
+/// once parsed. Errors only report line numbers and names, never values.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if a line is not a valid entry, and with
+/// [`io::ErrorKind::InvalidInput`] if a value is larger than its secret.
+#[allow(clippy::type_complexity)]
+pub fn load_dotenv<const N: usize>(
+    source: &Source,
+) -> io::Result<HashMap<String, (Pin<Box<Secret<[u8; N]>>>, usize)>>
+where
+    [u8; N]: Default,
+{
+    let content = source.load()?;
+    let mut entries = HashMap::new();
+    for (number, line) in content.split(|&byte| byte == b'\n').enumerate() {
+        let invalid = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{what} on line {} of the dotenv file", number + 1),
+            )
+        };
+        let line = line.trim_ascii();
+        if line.is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+        let line = line.strip_prefix(b"export ").unwrap_or(line);
+        let equal = line
+            .iter()
+            .position(|&byte| byte == b'=')
+            .ok_or_else(|| invalid("missing `=`"))?;
+        let name = std::str::from_utf8(line[..equal].trim_ascii())
+            .ok()
+            .filter(|name| {
+                !name.is_empty()
+                    && name
+                        .bytes()
+                        .all(|byte| byte.is_ascii_alphanumeric() || b"_.-".contains(&byte))
+            })
+            .ok_or_else(|| invalid("invalid name"))?;
+        let (value, quoting) = split_value(line[equal + 1..].trim_ascii_start())
+            .ok_or_else(|| invalid("unterminated quoted value"))?;
+        let (secret, len) = Secret::boxed_with(&|sec: &mut [u8]| {
+            let result = decode(value, quoting, sec);
+            if result.is_err() {
+                sec.zeroize();
+            }
+            result
+        });
+        let len = len.map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("value of {name} is larger than the secret"),
+            )
+        })?;
+        entries.insert(name.to_owned(), (secret, len));
+    }
+    Ok(entries)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    None,
+    Single,
+    Double,
+}
+
+/// Splits the encoded value out of the rest of a line, after the `=`.
+fn split_value(rest: &[u8]) -> Option<(&[u8], Quoting)> {
+    match rest.first() {
+        Some(b'\'') => {
+            let end = rest[1..].iter().position(|&byte| byte == b'\'')?;
+            Some((&rest[1..1 + end], Quoting::Single))
+        }
+        Some(b'"') => {
+            let mut escaped = false;
+            let end = rest[1..].iter().position(|&byte| {
+                let end = byte == b'"' && !escaped;
+                escaped = byte == b'\\' && !escaped;
+                end
+            })?;
+            Some((&rest[1..1 + end], Quoting::Double))
+        }
+        _ => {
+            let comment = rest
+                .windows(2)
+                .position(|pair| pair[0].is_ascii_whitespace() && pair[1] == b'#');
+            Some((
+                rest[..comment.unwrap_or(rest.len())].trim_ascii_end(),
+                Quoting::None,
+            ))
+        }
+    }
+}
+
+/// Decodes `value` into the start of `sec`.
+fn decode(value: &[u8], quoting: Quoting, sec: &mut [u8]) -> Result<usize, ()> {
+    let mut len = 0;
+    let mut index = 0;
+    while index < value.len() {
+        // Unknown escapes are kept as is.
+        let (byte, size) = match (quoting, value[index], value.get(index + 1)) {
+            (Quoting::Double, b'\\', Some(b'n')) => (b'\n', 2),
+            (Quoting::Double, b'\\', Some(b'r')) => (b'\r', 2),
+            (Quoting::Double, b'\\', Some(b't')) => (b'\t', 2),
+            (Quoting::Double, b'\\', Some(&escaped @ (b'\\' | b'"' | b'$'))) => (escaped, 2),
+            (_, byte, _) => (byte, 1),
+        };
+        *sec.get_mut(len).ok_or(())? = byte;
+        len += 1;
+        index += size;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_load_dotenv() {
+        let source = Source::Text(
+            "# Database\nexport DB_PASSWORD='p@ss #1'\nAPI_KEY=abc123 # comment\n\n\
+             TOKEN=\"line\\nnext\\q\"\nAPI_KEY=def456\r\n"
+                .to_owned()
+                .into(),
+        );
+        let entries = load_dotenv::<16>(&source).unwrap();
+        assert_eq!(entries.len(), 3);
+        let value = |name: &str| {
+            let (secret, len) = &entries[name];
+            secret
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec[..*len].to_vec())
+        };
+        assert_eq!(value("DB_PASSWORD"), b"p@ss #1");
+        assert_eq!(value("API_KEY"), b"def456");
+        assert_eq!(value("TOKEN"), b"line\nnext\\q");
+
+        let err = load_dotenv::<16>(&Source::Text("KEY=\"open\n".to_owned().into()))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 1"));
+        let err = load_dotenv::<4>(&Source::Text("KEY=too long\n".to_owned().into()))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
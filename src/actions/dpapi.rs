@@ -0,0 +1,178 @@
+use std::io;
+
+use windows_sys::Win32::{
+    Foundation::LocalFree,
+    Security::Cryptography::{
+        CRYPT_INTEGER_BLOB, CRYPTPROTECT_LOCAL_MACHINE, CRYPTPROTECT_UI_FORBIDDEN,
+        CryptProtectData, CryptUnprotectData,
+    },
+};
+use zeroize::Zeroize;
+
+use crate::api::{SecretReader, SecretUpdater};
+
+/// Who can unprotect a DPAPI blob.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DpapiScope {
+    /// The current user, on any machine its profile roams to.
+    #[default]
+    CurrentUser,
+    /// Any user of the current machine.
+    LocalMachine,
+}
+
+impl DpapiScope {
+    fn flags(self) -> u32 {
+        CRYPTPROTECT_UI_FORBIDDEN
+            | match self {
+                DpapiScope::CurrentUser => 0,
+                DpapiScope::LocalMachine => CRYPTPROTECT_LOCAL_MACHINE,
+            }
+    }
+}
+
+/// Encrypts the secret with DPAPI (`CryptProtectData`), returning a blob that can be
+/// persisted as is.
+pub struct ProtectWithDpapi(pub DpapiScope);
+
+/// Decrypts a DPAPI blob (`CryptUnprotectData`) into the secret.
+///
+/// # Security
+///
+/// The plaintext is returned by DPAPI in a buffer, zeroized before being freed.
+pub struct UpdateSecretFromDpapiBlob {
+    pub blob: Vec<u8>,
+    pub scope: DpapiScope,
+}
+
+fn blob(data: &[u8]) -> io::Result<CRYPT_INTEGER_BLOB> {
+    Ok(CRYPT_INTEGER_BLOB {
+        cbData: data
+            .len()
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DPAPI data is too large"))?,
+        pbData: data.as_ptr().cast_mut(),
+    })
+}
+
+/// Frees a buffer allocated by DPAPI, after zeroizing it.
+struct Output(CRYPT_INTEGER_BLOB);
+
+impl Output {
+    fn as_slice(&mut self) -> &mut [u8] {
+        match self.0.pbData.is_null() {
+            true => &mut [],
+            false => unsafe {
+                std::slice::from_raw_parts_mut(self.0.pbData, self.0.cbData as usize)
+            },
+        }
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        self.as_slice().zeroize();
+        if !self.0.pbData.is_null() {
+            unsafe { LocalFree(self.0.pbData.cast()) };
+        }
+    }
+}
+
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for ProtectWithDpapi {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let input = blob(sec)?;
+        let mut output = Output(CRYPT_INTEGER_BLOB {
+            cbData: 0,
+            pbData: std::ptr::null_mut(),
+        });
+        let ok = unsafe {
+            CryptProtectData(
+                &input,
+                std::ptr::null(),
+                std::ptr::null(),
+                std::ptr::null(),
+                std::ptr::null(),
+                self.0.flags(),
+                &mut output.0,
+            )
+        };
+        match ok {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(output.as_slice().to_vec()),
+        }
+    }
+}
+
+/// Returns the length of the secret.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the secret is larger than its buffer, which
+/// is then zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromDpapiBlob {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let input = blob(&self.blob)?;
+        let mut output = Output(CRYPT_INTEGER_BLOB {
+            cbData: 0,
+            pbData: std::ptr::null_mut(),
+        });
+        let ok = unsafe {
+            CryptUnprotectData(
+                &input,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+                std::ptr::null(),
+                self.scope.flags(),
+                &mut output.0,
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let plaintext = output.as_slice();
+        match sec.get_mut(..plaintext.len()) {
+            Some(dst) => {
+                dst.copy_from_slice(plaintext);
+                Ok(plaintext.len())
+            }
+            None => {
+                sec.zeroize();
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "secret is larger than its buffer",
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_dpapi_round_trip() {
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(&[0x5a; 16]));
+        let blob = secret
+            .as_ref()
+            .read_with(&ProtectWithDpapi(DpapiScope::CurrentUser))
+            .unwrap();
+        assert!(!blob.windows(16).any(|window| window == [0x5a; 16]));
+        let mut loaded = pin!(Secret::<[u8; 16]>::new());
+        let len = loaded
+            .as_mut()
+            .update_with(&UpdateSecretFromDpapiBlob {
+                blob,
+                scope: DpapiScope::CurrentUser,
+            })
+            .unwrap();
+        assert_eq!(len, 16);
+        assert!(loaded.as_ref().read_with(&|sec: &[u8]| sec == [0x5a; 16]));
+    }
+}
@@ -0,0 +1,80 @@
+use ed25519_dalek::{Signer, SigningKey};
+
+use crate::api::SecretReader;
+
+/// Signs a message with Ed25519 (RFC 8032), the secret being the 32-byte seed of the key.
+///
+/// # Security
+///
+/// The signing key expanded from the seed is zeroized once the message is signed.
+pub struct Ed25519Sign(pub Vec<u8>);
+
+/// Returns the public key of the Ed25519 key whose seed is the secret, to verify the
+/// signatures of [`Ed25519Sign`].
+pub struct Ed25519PublicKey;
+
+/// Returns the signature.
+impl SecretReader<[u8; 32], [u8; 64]> for Ed25519Sign {
+    fn read(&self, sec: &[u8]) -> [u8; 64] {
+        let key = SigningKey::from_bytes(sec.try_into().expect("seeds are 32 bytes long"));
+        key.sign(&self.0).to_bytes()
+    }
+}
+
+impl SecretReader<[u8; 32], [u8; 32]> for Ed25519PublicKey {
+    fn read(&self, sec: &[u8]) -> [u8; 32] {
+        let key = SigningKey::from_bytes(sec.try_into().expect("seeds are 32 bytes long"));
+        key.verifying_key().to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use ed25519_dalek::{Signature, VerifyingKey};
+
+    use super::*;
+    use crate::api::Secret;
+
+    /// First test vector of RFC 8032, section 7.1.
+    #[test]
+    fn test_ed25519_sign() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(&[
+                0x9d, 0x61, 0xb1, 0x9d, 0xef, 0xfd, 0x5a, 0x60, 0xba, 0x84, 0x4a, 0xf4, 0x92, 0xec,
+                0x2c, 0xc4, 0x44, 0x49, 0xc5, 0x69, 0x7b, 0x32, 0x69, 0x19, 0x70, 0x3b, 0xac, 0x03,
+                0x1c, 0xae, 0x7f, 0x60,
+            ])
+        });
+        let public = secret.as_ref().read_with(&Ed25519PublicKey);
+        assert_eq!(
+            public,
+            [
+                0xd7, 0x5a, 0x98, 0x01, 0x82, 0xb1, 0x0a, 0xb7, 0xd5, 0x4b, 0xfe, 0xd3, 0xc9, 0x64,
+                0x07, 0x3a, 0x0e, 0xe1, 0x72, 0xf3, 0xda, 0xa6, 0x23, 0x25, 0xaf, 0x02, 0x1a, 0x68,
+                0xf7, 0x07, 0x51, 0x1a,
+            ]
+        );
+        let signature = secret.as_ref().read_with(&Ed25519Sign(Vec::new()));
+        assert_eq!(
+            signature[..],
+            [
+                0xe5, 0x56, 0x43, 0x00, 0xc3, 0x60, 0xac, 0x72, 0x90, 0x86, 0xe2, 0xcc, 0x80, 0x6e,
+                0x82, 0x8a, 0x84, 0x87, 0x7f, 0x1e, 0xb8, 0xe5, 0xd9, 0x74, 0xd8, 0x73, 0xe0, 0x65,
+                0x22, 0x49, 0x01, 0x55, 0x5f, 0xb8, 0x82, 0x15, 0x90, 0xa3, 0x3b, 0xac, 0xc6, 0x1e,
+                0x39, 0x70, 0x1c, 0xf9, 0xb4, 0x6b, 0xd2, 0x5b, 0xf5, 0xf0, 0x59, 0x5b, 0xbe, 0x24,
+                0x65, 0x51, 0x41, 0x43, 0x8e, 0x7a, 0x10, 0x0b,
+            ]
+        );
+        let signature = secret
+            .as_ref()
+            .read_with(&Ed25519Sign(b"secret message".to_vec()));
+        let public = VerifyingKey::from_bytes(&public).unwrap();
+        let signature = Signature::from_bytes(&signature);
+        assert!(public.verify_strict(b"secret message", &signature).is_ok());
+        assert!(public.verify_strict(b"other message", &signature).is_err());
+    }
+}
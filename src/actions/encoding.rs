@@ -0,0 +1,65 @@
+use std::io;
+
+use zeroize::Zeroize;
+
+/// Textual encoding of a secret read from a text source (environment variable, prompt…).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// The bytes of the text, as is.
+    #[default]
+    Raw,
+    /// Hexadecimal, either lower or upper case.
+    Hex,
+    /// Standard Base64, with padding.
+    Base64,
+    /// URL-safe Base64, with or without padding.
+    Base64Url,
+}
+
+impl Encoding {
+    /// Decodes `input` into the start of `sec`, returning the decoded length.
+    ///
+    /// Hexadecimal and Base64 decoding is constant time, and ignores surrounding ASCII
+    /// whitespaces (as a trailing newline). On failure, `sec` is zeroized.
+    pub(crate) fn decode(self, input: &[u8], sec: &mut [u8]) -> io::Result<usize> {
+        let result = match self {
+            Encoding::Raw => match sec.get_mut(..input.len()) {
+                Some(dst) => {
+                    dst.copy_from_slice(input);
+                    Ok(input.len())
+                }
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "secret is larger than its buffer",
+                )),
+            },
+            Encoding::Hex => base16ct::mixed::decode(input.trim_ascii(), sec)
+                .map(<[u8]>::len)
+                .map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "invalid hexadecimal secret")
+                }),
+            Encoding::Base64 => {
+                use base64ct::Encoding as _;
+                base64ct::Base64::decode(input.trim_ascii(), sec)
+                    .map(<[u8]>::len)
+                    .map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidData, "invalid Base64 secret")
+                    })
+            }
+            Encoding::Base64Url => {
+                use base64ct::Encoding as _;
+                let input = input.trim_ascii();
+                match input.ends_with(b"=") {
+                    true => base64ct::Base64Url::decode(input, sec),
+                    false => base64ct::Base64UrlUnpadded::decode(input, sec),
+                }
+                .map(<[u8]>::len)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid Base64 secret"))
+            }
+        };
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
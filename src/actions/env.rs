@@ -0,0 +1,110 @@
+use std::{ffi::OsString, io};
+
+use zeroize::Zeroizing;
+
+use super::Encoding;
+use crate::api::SecretUpdater;
+
+/// Loads a secret from an environment variable, then removes the variable.
+///
+/// # Security
+///
+/// The value is wiped from the environment block (on Unix, it is overwritten in place
+/// before being removed, so it does not linger in `/proc/self/environ` either), and its
+/// intermediate copy is zeroized.
+///
+/// Child processes spawned before the update still inherited it.
+///
+/// # Caveat
+///
+/// Modifying the environment is not thread-safe: no other thread may read or write the
+/// environment (including through libc functions such as `getaddrinfo`) during the update.
+/// Update secrets from the environment early, before spawning threads.
+pub struct UpdateSecretFromEnv {
+    pub name: OsString,
+    pub encoding: Encoding,
+}
+
+impl UpdateSecretFromEnv {
+    pub fn new(name: impl Into<OsString>, encoding: Encoding) -> Self {
+        UpdateSecretFromEnv {
+            name: name.into(),
+            encoding,
+        }
+    }
+
+    /// Returns the value of the variable, wiping and removing it from the environment.
+    pub(crate) fn take(&self) -> io::Result<Zeroizing<Vec<u8>>> {
+        let Some(value) = std::env::var_os(&self.name) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "secret environment variable not set",
+            ));
+        };
+        let value = Zeroizing::new(value.into_encoded_bytes());
+        #[cfg(unix)]
+        self.wipe();
+        unsafe { std::env::remove_var(&self.name) };
+        Ok(value)
+    }
+
+    /// Overwrites the value of the variable in the environment block itself.
+    #[cfg(unix)]
+    fn wipe(&self) {
+        use std::os::unix::ffi::OsStrExt;
+
+        let Ok(name) = std::ffi::CString::new(self.name.as_bytes()) else {
+            return;
+        };
+        let value = unsafe { libc::getenv(name.as_ptr()) };
+        if !value.is_null() {
+            let len = unsafe { libc::strlen(value) };
+            zeroize::Zeroize::zeroize(unsafe {
+                std::slice::from_raw_parts_mut(value.cast::<u8>(), len)
+            });
+        }
+    }
+}
+
+/// Returns the number of bytes written.
+///
+/// Fails with [`io::ErrorKind::NotFound`] if the variable is not set. It is removed even if
+/// its value cannot be decoded.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromEnv {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let value = self.take()?;
+        self.encoding.decode(&value, sec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_update_secret_from_env() {
+        // The only test in the crate touching the environment.
+        unsafe { std::env::set_var("SECRUST_TEST_KEY", "00ff10") };
+        let mut secret = pin!(Secret::<[u8; 4]>::new());
+        let len = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromEnv::new("SECRUST_TEST_KEY", Encoding::Hex))
+            .unwrap();
+        assert_eq!(len, 3);
+        assert!(
+            secret
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec == [0, 0xff, 0x10, 0])
+        );
+        assert!(std::env::var_os("SECRUST_TEST_KEY").is_none());
+        let err = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromEnv::new("SECRUST_TEST_KEY", Encoding::Raw))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
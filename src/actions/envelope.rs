@@ -0,0 +1,211 @@
+//! Envelope encryption: each object is encrypted with its own random data key (DEK), itself
+//! wrapped by a long-term key encryption key (KEK), and stored alongside the object.
+//!
+//! The KEK never encrypts data directly, so that it can live in a KMS or an HSM, and be
+//! rotated by rewrapping the data keys only. Data keys only ever live in pinned
+//! [`Secret`]s.
+
+use std::{io, pin::Pin};
+
+use aes_gcm::{
+    AeadCore, Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, OsRng, Payload, consts::U12},
+};
+use aes_kw::KekAes256;
+use zeroize::Zeroize;
+
+use super::GenerateRandom;
+#[cfg(feature = "aws")]
+use super::aws::{Aws, UnwrapWithKms, WrapWithKms};
+#[cfg(feature = "aws")]
+use crate::api::{SecretReader, SecretUpdater};
+use crate::{
+    api::Secret,
+    memory::{Global, SecureAlloc},
+};
+
+/// A key encryption key, wrapping 32-byte data keys.
+pub trait KeyEncryptionKey {
+    /// Wraps the data key `dek`.
+    fn wrap(&self, dek: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Unwraps `wrapped` into `dek`, which it must exactly fill.
+    ///
+    /// On failure, `dek` may have been partially written.
+    fn unwrap(&self, wrapped: &[u8], dek: &mut [u8]) -> io::Result<()>;
+}
+
+/// A KEK held in a secret, wrapping with AES-KW (RFC 3394).
+pub struct LocalKek<'a, Alloc: SecureAlloc = Global>(pub Pin<&'a Secret<[u8; 32], Alloc>>);
+
+impl<Alloc: SecureAlloc> KeyEncryptionKey for LocalKek<'_, Alloc> {
+    fn wrap(&self, dek: &[u8]) -> io::Result<Vec<u8>> {
+        self.0.read_with(&|kek: &[u8]| {
+            KekAes256::try_from(kek)
+                .expect("KEKs are 32 bytes long")
+                .wrap_vec(dek)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+        })
+    }
+
+    fn unwrap(&self, wrapped: &[u8], dek: &mut [u8]) -> io::Result<()> {
+        if wrapped.len() != dek.len() + aes_kw::IV_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "wrapped data key has an invalid length",
+            ));
+        }
+        self.0.read_with_mut(&mut |kek: &[u8]| {
+            KekAes256::try_from(kek)
+                .expect("KEKs are 32 bytes long")
+                .unwrap(wrapped, dek)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+        })
+    }
+}
+
+/// A KEK of AWS KMS, wrapping with `Encrypt` and unwrapping with `Decrypt`.
+#[cfg(feature = "aws")]
+pub struct KmsKek<'a> {
+    pub aws: &'a Aws,
+    /// Identifier, ARN or alias of the KMS key.
+    pub key_id: String,
+}
+
+#[cfg(feature = "aws")]
+impl KeyEncryptionKey for KmsKek<'_> {
+    fn wrap(&self, dek: &[u8]) -> io::Result<Vec<u8>> {
+        let wrap = WrapWithKms {
+            aws: self.aws,
+            key_id: self.key_id.clone(),
+        };
+        SecretReader::<[u8; 32], _>::read(&wrap, dek)
+    }
+
+    fn unwrap(&self, wrapped: &[u8], dek: &mut [u8]) -> io::Result<()> {
+        let unwrap = UnwrapWithKms {
+            aws: self.aws,
+            ciphertext: wrapped.to_vec(),
+            key_id: Some(self.key_id.clone()),
+        };
+        match SecretUpdater::<[u8; 32], _>::update(&unwrap, dek)? {
+            len if len == dek.len() => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "data key has an invalid length",
+            )),
+        }
+    }
+}
+
+/// An object encrypted with AES-256-GCM under its own data key, and the wrapped data key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    pub wrapped_key: Vec<u8>,
+    pub nonce: Nonce<U12>,
+    pub ciphertext: Vec<u8>,
+}
+
+fn aead_error(_: aes_gcm::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "object was not encrypted under this data key, or was altered",
+    )
+}
+
+/// Encrypts `plaintext` under a fresh data key, wrapped with `kek`. The associated data
+/// `aad` (not encrypted) is authenticated, and must be given again to [`open`].
+///
+/// Fails with the errors of the OS CSPRNG and of `kek`.
+pub fn seal(kek: &dyn KeyEncryptionKey, plaintext: &[u8], aad: &[u8]) -> io::Result<Envelope> {
+    let mut dek = Secret::<[u8; 32]>::boxed();
+    dek.as_mut().update_with(&GenerateRandom)?;
+    dek.as_ref().read_with(&|dek: &[u8]| {
+        let wrapped_key = kek.wrap(dek)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        let ciphertext = Aes256Gcm::new(dek.into())
+            .encrypt(&nonce, payload)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "object is too large for AES-GCM",
+                )
+            })?;
+        Ok(Envelope {
+            wrapped_key,
+            nonce,
+            ciphertext,
+        })
+    })
+}
+
+/// Unwraps the data key of an envelope with `kek` into a fresh secret, to decrypt objects
+/// sharing it without unwrapping it each time.
+///
+/// Fails with the errors of `kek`.
+pub fn unwrap_data_key(
+    kek: &dyn KeyEncryptionKey,
+    wrapped_key: &[u8],
+) -> io::Result<Pin<Box<Secret<[u8; 32]>>>> {
+    let (dek, result) = Secret::boxed_with(&|dek: &mut [u8]| {
+        let result = kek.unwrap(wrapped_key, dek);
+        if result.is_err() {
+            dek.zeroize();
+        }
+        result
+    });
+    result.map(|()| dek)
+}
+
+/// Decrypts an envelope sealed by [`seal`], with the same associated data.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the object was altered or the associated
+/// data differs, and with the errors of `kek`.
+pub fn open(kek: &dyn KeyEncryptionKey, envelope: &Envelope, aad: &[u8]) -> io::Result<Vec<u8>> {
+    let dek = unwrap_data_key(kek, &envelope.wrapped_key)?;
+    dek.as_ref().read_with(&|dek: &[u8]| {
+        let payload = Payload {
+            msg: &envelope.ciphertext,
+            aad,
+        };
+        Aes256Gcm::new(dek.into())
+            .decrypt(&envelope.nonce, payload)
+            .map_err(aead_error)
+    })
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+
+    #[test]
+    fn test_envelope() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret.as_mut().update_with(&GenerateRandom).unwrap();
+        let kek = LocalKek(secret.as_ref());
+        let envelope = seal(&kek, b"secret message", b"object 1").unwrap();
+        assert_eq!(envelope.wrapped_key.len(), 40);
+        assert_eq!(
+            open(&kek, &envelope, b"object 1").unwrap(),
+            b"secret message"
+        );
+        let err = open(&kek, &envelope, b"object 2").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Each object has its own data key.
+        let other = seal(&kek, b"secret message", b"object 1").unwrap();
+        assert_ne!(other.wrapped_key, envelope.wrapped_key);
+
+        let mut other = pin!(Secret::<[u8; 32]>::new());
+        other.as_mut().update_with(&GenerateRandom).unwrap();
+        let err = open(&LocalKek(other.as_ref()), &envelope, b"object 1").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
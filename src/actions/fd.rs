@@ -0,0 +1,138 @@
+use std::{
+    io::{self, Read},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::net::UnixStream,
+    },
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use zeroize::Zeroize;
+
+use crate::api::SecretUpdater;
+
+/// Receives a secret over a descriptor inherited or pre-opened by the process (a pipe, or
+/// one end of a `socketpair` set up by a supervisor), which is not closed.
+///
+/// The secret is framed by its length, as a 4-byte big-endian integer, and may be shorter
+/// than the secret buffer.
+///
+/// # Security
+///
+/// The secret is read directly into the secret buffer, without any intermediate copy.
+pub struct UpdateSecretFromFd {
+    pub fd: RawFd,
+    /// Maximum time to wait for the whole frame, forever if `None`.
+    pub timeout: Option<Duration>,
+}
+
+/// Receives a secret from a Unix domain socket listening at some path, framed as for
+/// [`UpdateSecretFromFd`].
+pub struct UpdateSecretFromUnixSocket {
+    pub path: PathBuf,
+    /// Maximum time to wait for the whole frame, forever if `None`.
+    pub timeout: Option<Duration>,
+}
+
+/// Returns the length of the received secret.
+///
+/// Fails with [`io::ErrorKind::TimedOut`] if the frame is not received in time, with
+/// [`io::ErrorKind::InvalidInput`] if the secret is larger than its buffer, and with
+/// [`io::ErrorKind::UnexpectedEof`] if the descriptor is closed before the end of the frame.
+/// On failure, the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromFd {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        read_frame(&mut FdReader(self.fd, deadline), sec)
+    }
+}
+
+/// Returns the length of the received secret, failing as [`UpdateSecretFromFd`] does.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromUnixSocket {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let stream = UnixStream::connect(&self.path)?;
+        read_frame(&mut FdReader(stream.as_raw_fd(), deadline), sec)
+    }
+}
+
+/// Reads a length-framed secret into the start of `sec`, zeroized on failure.
+fn read_frame(reader: &mut impl Read, sec: &mut [u8]) -> io::Result<usize> {
+    let mut len = [0u8; 4];
+    let result = reader.read_exact(&mut len).and_then(|()| {
+        let len = u32::from_be_bytes(len) as usize;
+        let dst = sec.get_mut(..len).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "secret is larger than its buffer",
+            )
+        })?;
+        reader.read_exact(dst).map(|()| len)
+    });
+    len.zeroize();
+    if result.is_err() {
+        sec.zeroize();
+    }
+    result
+}
+
+/// Reads from a borrowed descriptor, until a deadline.
+struct FdReader(RawFd, Option<Instant>);
+
+impl Read for FdReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let fd = self.0;
+        if let Some(deadline) = self.1 {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let mut poll = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+            match unsafe { libc::poll(&mut poll, 1, millis) } {
+                -1 => return Err(io::Error::last_os_error()),
+                0 => return Err(io::ErrorKind::TimedOut.into()),
+                _ => {}
+            }
+        }
+        match unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) } {
+            -1 => Err(io::Error::last_os_error()),
+            n => Ok(n as usize),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{io::Write, pin::pin};
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_update_secret_from_fd() {
+        let (mut supervisor, child) = UnixStream::pair().unwrap();
+        let from_fd = UpdateSecretFromFd {
+            fd: child.as_raw_fd(),
+            timeout: Some(Duration::from_millis(50)),
+        };
+        let mut secret = pin!(Secret::<[u8; 8]>::new());
+        supervisor.write_all(&[0, 0, 0, 3, 1, 2, 3]).unwrap();
+        assert_eq!(secret.as_mut().update_with(&from_fd).unwrap(), 3);
+        assert!(
+            secret
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec == [1, 2, 3, 0, 0, 0, 0, 0])
+        );
+        let err = secret.as_mut().update_with(&from_fd).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        supervisor.write_all(&[0, 0, 0, 9]).unwrap();
+        let err = secret.as_mut().update_with(&from_fd).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        // Still open.
+        assert_ne!(unsafe { libc::fcntl(child.as_raw_fd(), libc::F_GETFD) }, -1);
+    }
+}
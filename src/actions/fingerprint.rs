@@ -0,0 +1,70 @@
+use std::io;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::api::SecretReader;
+
+/// Returns a fingerprint of the secret, to identify it in logs and audit records: the given
+/// number of bytes (at most 32) of the start of its HMAC-SHA256 keyed with
+/// `secrust fingerprint v1`, in lower case hexadecimal.
+///
+/// This HMAC is the format of fingerprints, rather than a plain SHA-256 digest of the key:
+/// other protocols identify keys by such a digest, or derive values from it, and a
+/// fingerprint published in logs must not match (nor leak) any of them. Keying with a fixed
+/// context separates fingerprints from every other use of the key.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if more than 32 bytes are asked for.
+///
+/// # Security
+///
+/// A fingerprint only identifies high-entropy keys: the secret behind the fingerprint of a
+/// password can be recovered by brute force. Keeping 8 bytes is enough to tell keys apart,
+/// not to resist collisions crafted by an attacker.
+pub struct Fingerprint(pub usize);
+
+/// Key of the HMAC giving fingerprints.
+const CONTEXT: &[u8] = b"secrust fingerprint v1";
+
+impl<const N: usize> SecretReader<[u8; N], io::Result<String>> for Fingerprint {
+    fn read(&self, sec: &[u8]) -> io::Result<String> {
+        if self.0 > 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "fingerprints are at most 32 bytes",
+            ));
+        }
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(CONTEXT).expect("HMAC accepts keys of any length");
+        mac.update(sec);
+        let digest = mac.finalize().into_bytes();
+        Ok(base16ct::lower::encode_string(&digest[..self.0]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_fingerprint() {
+        let mut secret = pin!(Secret::<[u8; 3]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"abc"));
+        assert_eq!(
+            secret.as_ref().read_with(&Fingerprint(8)).unwrap(),
+            "16220eced0d35858"
+        );
+        assert_eq!(
+            secret.as_ref().read_with(&Fingerprint(32)).unwrap(),
+            "16220eced0d358586ebd07df2589b5856f7b080f7f6d9453bccfc248f082f606"
+        );
+        let err = secret.as_ref().read_with(&Fingerprint(33)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,62 @@
+use std::io;
+
+use zeroize::Zeroize;
+
+use super::{Encoding, Source};
+use crate::api::SecretUpdater;
+
+/// Decodes a hexadecimal key (either lower or upper case) into the secret.
+///
+/// The decoded key must fill the whole secret: a key of the wrong length is rejected,
+/// rather than silently truncated or padded.
+///
+/// # Security
+///
+/// Decoding is constant time, and writes directly into the secret. The encoded input is
+/// zeroized once decoded.
+pub struct UpdateSecretFromHex(pub Source);
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the input is not hexadecimal, and with
+/// [`io::ErrorKind::InvalidInput`] if it does not decode to exactly `N` bytes. On failure,
+/// the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<()>> for UpdateSecretFromHex {
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let input = self.0.load()?;
+        if input.trim_ascii().len() != 2 * N {
+            sec.zeroize();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "hexadecimal key does not match the secret length",
+            ));
+        }
+        Encoding::Hex.decode(&input, sec).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use zeroize::Zeroizing;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_update_secret_from_hex() {
+        let hex = |text: &str| UpdateSecretFromHex(Source::Text(Zeroizing::new(text.into())));
+        let mut secret = pin!(Secret::<[u8; 4]>::new());
+        secret.as_mut().update_with(&hex("00fF10a5\n")).unwrap();
+        assert!(
+            secret
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec == [0, 0xff, 0x10, 0xa5])
+        );
+        let err = secret.as_mut().update_with(&hex("00ff10")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(secret.as_ref().read_with(&|sec: &[u8]| sec == [0; 4]));
+        let err = secret.as_mut().update_with(&hex("00ff10zz")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
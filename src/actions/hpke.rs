@@ -0,0 +1,172 @@
+use std::io;
+
+use ::hpke::{
+    Deserializable, Kem, OpModeR, OpModeS, Serializable,
+    aead::{Aead, AesGcm128, AesGcm256, ChaCha20Poly1305},
+    kdf::HkdfSha256,
+    kem::X25519HkdfSha256,
+    single_shot_open, single_shot_seal,
+};
+use aes_gcm::aead::OsRng;
+use zeroize::Zeroizing;
+
+use crate::api::SecretReader;
+
+type PublicKey = <X25519HkdfSha256 as Kem>::PublicKey;
+type PrivateKey = <X25519HkdfSha256 as Kem>::PrivateKey;
+type EncappedKey = <X25519HkdfSha256 as Kem>::EncappedKey;
+
+/// AEADs of HPKE, all with DHKEM(X25519, HKDF-SHA256) and HKDF-SHA256.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HpkeAead {
+    /// AES-128-GCM, as in the mandatory cipher suite of MLS.
+    Aes128Gcm,
+    Aes256Gcm,
+    #[default]
+    ChaCha20Poly1305,
+}
+
+/// Encrypts the secret to the X25519 public key of a recipient, with HPKE (RFC 9180) in
+/// base mode, such as to send a key to the holder of a private key.
+pub struct HpkeSeal {
+    pub recipient: [u8; 32],
+    /// Application-specific information, bound to the encryption, empty if none.
+    pub info: Vec<u8>,
+    /// Associated data (not encrypted), authenticated with the secret.
+    pub aad: Vec<u8>,
+    pub aead: HpkeAead,
+}
+
+/// Decrypts a ciphertext sealed with HPKE (RFC 9180) in base mode, with the X25519 private
+/// key held in the secret, as [`HpkeSeal`] encrypts it.
+pub struct HpkeOpen {
+    /// Encapsulated key, sent with the ciphertext.
+    pub enc: [u8; 32],
+    pub ciphertext: Vec<u8>,
+    pub info: Vec<u8>,
+    pub aad: Vec<u8>,
+    pub aead: HpkeAead,
+}
+
+fn seal<A: Aead>(
+    this: &HpkeSeal,
+    plaintext: &[u8],
+) -> Result<([u8; 32], Vec<u8>), ::hpke::HpkeError> {
+    let recipient = PublicKey::from_bytes(&this.recipient)?;
+    let (enc, ciphertext) = single_shot_seal::<A, HkdfSha256, X25519HkdfSha256, _>(
+        &OpModeS::Base,
+        &recipient,
+        &this.info,
+        plaintext,
+        &this.aad,
+        &mut OsRng,
+    )?;
+    Ok((enc.to_bytes().into(), ciphertext))
+}
+
+fn open<A: Aead>(this: &HpkeOpen, sec: &[u8]) -> Result<Vec<u8>, ::hpke::HpkeError> {
+    // The private key is zeroized on drop.
+    let private_key = PrivateKey::from_bytes(sec)?;
+    single_shot_open::<A, HkdfSha256, X25519HkdfSha256>(
+        &OpModeR::Base,
+        &private_key,
+        &EncappedKey::from_bytes(&this.enc)?,
+        &this.info,
+        &this.ciphertext,
+        &this.aad,
+    )
+}
+
+/// Returns the encapsulated key and the ciphertext, to be sent to the recipient.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the public key of the recipient is of small
+/// order.
+impl<const N: usize> SecretReader<[u8; N], io::Result<([u8; 32], Vec<u8>)>> for HpkeSeal {
+    fn read(&self, sec: &[u8]) -> io::Result<([u8; 32], Vec<u8>)> {
+        match self.aead {
+            HpkeAead::Aes128Gcm => seal::<AesGcm128>(self, sec),
+            HpkeAead::Aes256Gcm => seal::<AesGcm256>(self, sec),
+            HpkeAead::ChaCha20Poly1305 => seal::<ChaCha20Poly1305>(self, sec),
+        }
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+    }
+}
+
+/// Returns the plaintext.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the ciphertext was not sealed to this key,
+/// with the same information and associated data, or was altered.
+impl SecretReader<[u8; 32], io::Result<Zeroizing<Vec<u8>>>> for HpkeOpen {
+    fn read(&self, sec: &[u8]) -> io::Result<Zeroizing<Vec<u8>>> {
+        match self.aead {
+            HpkeAead::Aes128Gcm => open::<AesGcm128>(self, sec),
+            HpkeAead::Aes256Gcm => open::<AesGcm256>(self, sec),
+            HpkeAead::ChaCha20Poly1305 => open::<ChaCha20Poly1305>(self, sec),
+        }
+        .map(Zeroizing::new)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::{actions::GenerateRandom, api::Secret};
+
+    #[test]
+    fn test_hpke() {
+        let mut private_key = pin!(Secret::<[u8; 32]>::new());
+        private_key.as_mut().update_with(&GenerateRandom).unwrap();
+        let recipient = private_key.as_ref().read_with(&|sec: &[u8]| {
+            X25519HkdfSha256::sk_to_pk(&PrivateKey::from_bytes(sec).unwrap())
+                .to_bytes()
+                .into()
+        });
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"master key bytes"));
+
+        for aead in [
+            HpkeAead::Aes128Gcm,
+            HpkeAead::Aes256Gcm,
+            HpkeAead::ChaCha20Poly1305,
+        ] {
+            let (enc, ciphertext) = secret
+                .as_ref()
+                .read_with(&HpkeSeal {
+                    recipient,
+                    info: b"key transport".to_vec(),
+                    aad: b"key 1".to_vec(),
+                    aead,
+                })
+                .unwrap();
+            let open = |aad: &[u8]| {
+                private_key.as_ref().read_with(&HpkeOpen {
+                    enc,
+                    ciphertext: ciphertext.clone(),
+                    info: b"key transport".to_vec(),
+                    aad: aad.to_vec(),
+                    aead,
+                })
+            };
+            assert_eq!(**open(b"key 1").unwrap(), *b"master key bytes");
+            let err = open(b"key 2").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        let err = secret
+            .as_ref()
+            .read_with(&HpkeSeal {
+                recipient: [0; 32],
+                info: Vec::new(),
+                aad: Vec::new(),
+                aead: HpkeAead::default(),
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
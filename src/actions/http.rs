@@ -0,0 +1,76 @@
+//! HTTP plumbing shared by the updaters fetching secrets from remote services.
+
+use std::{
+    io::{self, Read},
+    time::Duration,
+};
+
+use ureq::{Agent, Body, http::Response};
+use zeroize::Zeroizing;
+
+/// Largest response accepted, secrets responses being small.
+const BODY_LIMIT: u64 = 1 << 20;
+
+/// Capacity reserved when the response has no `Content-Length`.
+const DEFAULT_CAPACITY: usize = 16 << 10;
+
+pub(crate) fn agent(timeout: Duration) -> Agent {
+    Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .build()
+        .into()
+}
+
+pub(crate) fn http_error(err: ureq::Error) -> io::Error {
+    match err {
+        ureq::Error::StatusCode(404) => io::Error::new(io::ErrorKind::NotFound, err),
+        ureq::Error::StatusCode(401 | 403) => io::Error::new(io::ErrorKind::PermissionDenied, err),
+        err => err.into_io(),
+    }
+}
+
+/// Whether a request failing with this error may succeed if retried.
+#[cfg_attr(not(feature = "cloud"), allow(dead_code))]
+pub(crate) fn is_transient(err: &io::Error) -> bool {
+    match err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<ureq::Error>())
+    {
+        Some(ureq::Error::StatusCode(status)) => *status == 429 || *status >= 500,
+        Some(
+            ureq::Error::Timeout(_) | ureq::Error::ConnectionFailed | ureq::Error::HostNotFound,
+        ) => true,
+        _ => matches!(
+            err.kind(),
+            io::ErrorKind::TimedOut
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::UnexpectedEof
+        ),
+    }
+}
+
+/// Reads the whole body of a response, zeroized on drop.
+///
+/// The buffer is sized upfront from the `Content-Length`, so that no copy is left behind by
+/// reallocations (unless the header is missing or wrong). Buffers of the HTTP and TLS
+/// stacks are not wiped.
+pub(crate) fn read_body(mut response: Response<Body>) -> io::Result<Zeroizing<Vec<u8>>> {
+    let capacity = response
+        .body()
+        .content_length()
+        .map_or(DEFAULT_CAPACITY, |len| len.min(BODY_LIMIT) as usize);
+    let mut body = Zeroizing::new(Vec::with_capacity(capacity));
+    response
+        .body_mut()
+        .as_reader()
+        .take(BODY_LIMIT)
+        .read_to_end(&mut body)?;
+    Ok(body)
+}
+
+pub(crate) fn json_error(err: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,493 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    StreamOwned,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::{CryptoProvider, WebPkiSupportedAlgorithms, ring},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::read_to_end;
+use crate::api::SecretUpdater;
+
+/// Largest response head accepted.
+const HEAD_LIMIT: usize = 16 << 10;
+
+/// Fetches a secret with a `GET` over HTTPS, from an internal secret distribution service,
+/// pinning the public key of its certificate.
+///
+/// The certificate must be valid for the host, and either it or one of the intermediate
+/// certificates of its chain to a trust anchor must have one of the pinned public keys.
+/// Other certificates sent by the server are ignored, even if pinned.
+///
+/// # Security
+///
+/// The body of the response is read directly into the secret, rather than buffered: only
+/// the buffers of the TLS stack may keep a copy of it. The request, headers included, is
+/// zeroized once sent.
+pub struct UpdateSecretFromHttps {
+    /// `https://host[:port]/path` URL of the secret.
+    pub url: String,
+    /// SHA-256 digests of the DER `SubjectPublicKeyInfo` of the pinned keys, as in HPKP
+    /// (once base64 decoded). At least one is required.
+    pub pins: Vec<[u8; 32]>,
+    /// Trust anchors, as DER certificates, such as the internal CA of the service. The
+    /// Mozilla root certificates are trusted if empty.
+    pub roots: Vec<Vec<u8>>,
+    /// Additional request headers, such as credentials.
+    pub headers: Vec<(String, String)>,
+    /// Timeout of each network operation.
+    pub timeout: Duration,
+}
+
+/// Checks the certificate as usual, then its pins.
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    roots: Arc<RootCertStore>,
+    algorithms: WebPkiSupportedAlgorithms,
+    pins: Vec<[u8; 32]>,
+}
+
+impl PinnedVerifier {
+    fn new(
+        roots: RootCertStore,
+        pins: Vec<[u8; 32]>,
+        provider: Arc<CryptoProvider>,
+    ) -> io::Result<Self> {
+        let roots = Arc::new(roots);
+        let algorithms = provider.signature_verification_algorithms;
+        let inner = WebPkiServerVerifier::builder_with_provider(roots.clone(), provider)
+            .build()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        Ok(Self {
+            inner,
+            roots,
+            algorithms,
+            pins,
+        })
+    }
+
+    fn is_pinned(&self, cert: &webpki::Cert<'_>) -> bool {
+        let pin: [u8; 32] = Sha256::digest(cert.subject_public_key_info()).into();
+        self.pins.contains(&pin)
+    }
+
+    /// Checks that the end-entity certificate, or one of the intermediate certificates of a
+    /// chain to a trust anchor, is pinned. Only the certificates of validated chains count:
+    /// the server may send any other certificate along, pinned ones included.
+    fn verify_pins(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<(), webpki::Error> {
+        let cert = webpki::EndEntityCert::try_from(end_entity)?;
+        let pinned = |path: &webpki::VerifiedPath<'_>| {
+            let mut intermediates = path.intermediate_certificates();
+            match self.is_pinned(&cert) || intermediates.any(|cert| self.is_pinned(cert)) {
+                true => Ok(()),
+                false => Err(webpki::Error::UnknownIssuer),
+            }
+        };
+        cert.verify_for_usage(
+            self.algorithms.all,
+            &self.roots.roots,
+            intermediates,
+            now,
+            webpki::KeyUsage::server_auth(),
+            None,
+            Some(&pinned),
+        )
+        .map(|_| ())
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        self.verify_pins(end_entity, intermediates, now)
+            .map_err(|_| {
+                rustls::Error::InvalidCertificate(
+                    rustls::CertificateError::ApplicationVerificationFailure,
+                )
+            })?;
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Splits an `https` URL into its authority, host, port and path.
+fn split_url(url: &str) -> io::Result<(&str, &str, u16, &str)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid https URL");
+    let rest = url.strip_prefix("https://").ok_or_else(invalid)?;
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().map_err(|_| invalid())?),
+        _ => (authority, 443),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((
+        authority,
+        host,
+        port,
+        if path.is_empty() { "/" } else { path },
+    ))
+}
+
+fn connect(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last = io::Error::new(io::ErrorKind::NotFound, "host not found");
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(err) => last = err,
+        }
+    }
+    Err(last)
+}
+
+/// Reads the head of a response, up to the empty line.
+fn read_head(reader: &mut impl Read) -> io::Result<String> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        head.push(byte[0]);
+        if head.len() > HEAD_LIMIT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "response head is too large",
+            ));
+        }
+    }
+    String::from_utf8(head).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Reads the size of the next chunk of a chunked body.
+fn read_chunk_size(reader: &mut impl Read) -> io::Result<usize> {
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") && line.len() < 64 {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+    let size = line[..line.len() - 2].split(|&byte| byte == b';').next();
+    std::str::from_utf8(size.unwrap_or_default())
+        .ok()
+        .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))
+}
+
+/// Reads a response, its body into `sec`.
+fn read_response(mut reader: impl Read, sec: &mut [u8]) -> io::Result<usize> {
+    let head = read_head(&mut reader)?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.strip_prefix("HTTP/1."))
+        .and_then(|line| line.get(2..5))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid HTTP response"))?;
+    match status {
+        200 => {}
+        404 => return Err(io::Error::new(io::ErrorKind::NotFound, "secret not found")),
+        401 | 403 => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("secret access denied (HTTP {status})"),
+            ));
+        }
+        status => return Err(io::Error::other(format!("HTTP status {status}"))),
+    }
+    let mut content_length = None;
+    let mut chunked = false;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(value.parse::<usize>().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length")
+            })?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        }
+    }
+    let too_large = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "response is larger than the secret",
+        )
+    };
+    if chunked {
+        let mut len = 0;
+        loop {
+            let size = read_chunk_size(&mut reader)?;
+            if size == 0 {
+                return Ok(len);
+            }
+            let chunk = len
+                .checked_add(size)
+                .and_then(|end| sec.get_mut(len..end))
+                .ok_or_else(too_large)?;
+            reader.read_exact(chunk)?;
+            len += size;
+            reader.read_exact(&mut [0u8; 2])?;
+        }
+    }
+    match content_length {
+        Some(len) => {
+            reader.read_exact(sec.get_mut(..len).ok_or_else(too_large)?)?;
+            Ok(len)
+        }
+        // The connection is closed after the body.
+        None => read_to_end(reader, sec),
+    }
+}
+
+impl UpdateSecretFromHttps {
+    fn config(&self) -> io::Result<ClientConfig> {
+        if self.pins.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no pinned public key",
+            ));
+        }
+        let roots = match &self.roots[..] {
+            [] => RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            roots => {
+                let mut store = RootCertStore::empty();
+                for root in roots {
+                    store
+                        .add(CertificateDer::from(root.clone()))
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                }
+                store
+            }
+        };
+        let provider = Arc::new(ring::default_provider());
+        let verifier = PinnedVerifier::new(roots, self.pins.clone(), provider.clone())?;
+        Ok(ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth())
+    }
+
+    fn fetch(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let config = self.config()?;
+        let (authority, host, port, path) = split_url(&self.url)?;
+        let server = ServerName::try_from(host.to_owned())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let connection = ClientConnection::new(config.into(), server).map_err(io::Error::other)?;
+        let mut stream = StreamOwned::new(connection, connect(host, port, self.timeout)?);
+
+        let mut request = Zeroizing::new(String::new());
+        let _ = write!(
+            request,
+            "GET {path} HTTP/1.1\r\nHost: {authority}\r\nConnection: close\r\n"
+        );
+        for (name, value) in &self.headers {
+            if name.contains(['\r', '\n', ':']) || value.contains(['\r', '\n']) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid request header",
+                ));
+            }
+            let _ = write!(request, "{name}: {value}\r\n");
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+        stream.flush()?;
+        read_response(stream, sec)
+    }
+}
+
+/// Returns the length of the secret.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if there is no pin or if the response is
+/// larger than the secret, with [`io::ErrorKind::InvalidData`] if the certificate is not
+/// valid or not pinned, and with [`io::ErrorKind::NotFound`] or
+/// [`io::ErrorKind::PermissionDenied`] on the matching HTTP statuses. On failure, the secret
+/// is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromHttps {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = self.fetch(sec);
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use rustls::pki_types::pem::PemObject;
+
+    use super::*;
+
+    /// Trust anchor of the tests.
+    const ROOT: &str = "-----BEGIN CERTIFICATE-----
+MIIBZDCCAQqgAwIBAgIUfG43QUKvMjvpd902gqUDrizCZzIwCgYIKoZIzj0EAwIw
+DzENMAsGA1UEAwwEcm9vdDAgFw0yNjEwMTQxMjAyMjRaGA8yMTI2MDkyMDEyMDIy
+NFowDzENMAsGA1UEAwwEcm9vdDBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABIeC
+cbfmrpLp7X3lyfc8GYhxuqsYMmx6jLKeYPUkiWG4jGl3HZzlkq0R1rmU8ZF1uHd4
+L2D4OQ70O56pPTho23CjQjBAMA8GA1UdEwEB/wQFMAMBAf8wDgYDVR0PAQH/BAQD
+AgEGMB0GA1UdDgQWBBSY9zXuilbJjWGcyA6VhF7EWg0Q9zAKBggqhkjOPQQDAgNI
+ADBFAiEA1wo82FKuB9UWF0vmdqIng/R3EaCkBUdrkmf8ox2n4FECIEkvOFRi6teE
+fM8oY+iMGOwCVO+iZNc31kTIC1JTJmas
+-----END CERTIFICATE-----";
+    /// Issued by [`ROOT`].
+    const INTERMEDIATE: &str = "-----BEGIN CERTIFICATE-----
+MIIBezCCASCgAwIBAgIBAjAKBggqhkjOPQQDAjAPMQ0wCwYDVQQDDARyb290MCAX
+DTI2MTAxNDEyMDIyNFoYDzIxMjYwOTIwMTIwMjI0WjAXMRUwEwYDVQQDDAxpbnRl
+cm1lZGlhdGUwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAARBxPnhhgKBurgO4jqh
+V8N5nrFiPb48gY31qXxWiVQlaHtVBi5RyElUTJ9Ex7sTgIXEKqmi8k9LZCds0joM
+6Gj3o2MwYTAPBgNVHRMBAf8EBTADAQH/MA4GA1UdDwEB/wQEAwIBBjAdBgNVHQ4E
+FgQUhx9TYjoKvWIAXjuyQiWjr7PWC5gwHwYDVR0jBBgwFoAUmPc17opWyY1hnMgO
+lYRexFoNEPcwCgYIKoZIzj0EAwIDSQAwRgIhAOyGS/2u9cQ3WUYdleitHqJCBNLT
+eZjM0UCwP8/bq/QRAiEAvDgapTolbMADhpBXGavjFmpsE3lSYUe5EqcoP3wOluc=
+-----END CERTIFICATE-----";
+    /// Issued by [`INTERMEDIATE`], for `secrets.internal`.
+    const LEAF: &str = "-----BEGIN CERTIFICATE-----
+MIIBtzCCAV2gAwIBAgIBAzAKBggqhkjOPQQDAjAXMRUwEwYDVQQDDAxpbnRlcm1l
+ZGlhdGUwIBcNMjYxMDE0MTIwMjI0WhgPMjEyNjA5MjAxMjAyMjRaMBsxGTAXBgNV
+BAMMEHNlY3JldHMuaW50ZXJuYWwwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAQo
+GXiS3aV0gO4OQkX0Vl4OoLrGauO96XlziphzKDgtGw7ymXHcTxWmde2TnGGMGqOl
+h0o2cRFj+AzkCnZ3ApNLo4GTMIGQMAwGA1UdEwEB/wQCMAAwDgYDVR0PAQH/BAQD
+AgeAMBMGA1UdJQQMMAoGCCsGAQUFBwMBMBsGA1UdEQQUMBKCEHNlY3JldHMuaW50
+ZXJuYWwwHQYDVR0OBBYEFHQs1MVsuKi6R+Hmui0OsUpe38v8MB8GA1UdIwQYMBaA
+FIcfU2I6Cr1iAF47skIlo6+z1guYMAoGCCqGSM49BAMCA0gAMEUCICM7V35hoSTh
+b+o1gsbj7RfuWXJ65/gUnZLEeyq+6HCmAiEAg5NHq+xs/6jBy6YKUa+bJBb8CjB4
+2G3Tm6iuIHSlI5g=
+-----END CERTIFICATE-----";
+    /// Self-signed, unrelated to the other certificates.
+    const PINNED: &str = "-----BEGIN CERTIFICATE-----
+MIIBZzCCAQ6gAwIBAgIUH9MO8MQbHGawwhBaYSNFCo7tEKcwCgYIKoZIzj0EAwIw
+ETEPMA0GA1UEAwwGcGlubmVkMCAXDTI2MTAxNDEyMDIyNFoYDzIxMjYwOTIwMTIw
+MjI0WjARMQ8wDQYDVQQDDAZwaW5uZWQwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNC
+AASpepBwg1PnqwcHArBKulXXiXtD7eXneKeeVKeEZfrW1yjn2UL0DqcW5MYolweF
+02wpn7Dt9ldC5ZBgOrQdR7gAo0IwQDAPBgNVHRMBAf8EBTADAQH/MA4GA1UdDwEB
+/wQEAwIBBjAdBgNVHQ4EFgQUblx48VRNdLoeqy5LuKPCgKWozKgwCgYIKoZIzj0E
+AwIDRwAwRAIgPPOo+HZb/hprVmb1gCB4i6sSy74VAiLyRHygzZv+9dsCIC3UW45d
+1KTVn8oolnsoqclJGPoecu/H706hCATOFPf2
+-----END CERTIFICATE-----";
+
+    #[test]
+    fn test_pinned_verifier() {
+        let der = |pem: &str| CertificateDer::from_pem_slice(pem.as_bytes()).unwrap();
+        let pin = |pem: &str| -> [u8; 32] {
+            let cert = der(pem);
+            let cert = webpki::EndEntityCert::try_from(&cert).unwrap();
+            Sha256::digest(cert.subject_public_key_info()).into()
+        };
+        let verify = |pinned: &str, intermediates: &[&str]| {
+            let mut roots = RootCertStore::empty();
+            roots.add(der(ROOT)).unwrap();
+            let provider = Arc::new(ring::default_provider());
+            let verifier = PinnedVerifier::new(roots, vec![pin(pinned)], provider).unwrap();
+            let intermediates: Vec<_> = intermediates.iter().map(|pem| der(pem)).collect();
+            verifier.verify_server_cert(
+                &der(LEAF),
+                &intermediates,
+                &ServerName::try_from("secrets.internal").unwrap(),
+                &[],
+                // 2030-01-01, within the validity of the certificates.
+                UnixTime::since_unix_epoch(Duration::from_secs(1_893_456_000)),
+            )
+        };
+        assert!(verify(LEAF, &[INTERMEDIATE]).is_ok());
+        assert!(verify(INTERMEDIATE, &[INTERMEDIATE]).is_ok());
+        // A pinned certificate sent along a valid chain, but not part of it, does not count.
+        assert!(matches!(
+            verify(PINNED, &[INTERMEDIATE, PINNED]),
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure
+            ))
+        ));
+        assert!(verify(LEAF, &[]).is_err());
+    }
+
+    #[test]
+    fn test_read_response() {
+        let mut sec = [0u8; 16];
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                         4\r\nsecr\r\n5;ext=1\r\net ke\r\n1\r\ny\r\n0\r\n\r\n";
+        assert_eq!(read_response(&response[..], &mut sec).unwrap(), 10);
+        assert_eq!(&sec[..10], b"secret key");
+
+        let response = b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\nabcdef";
+        assert_eq!(read_response(&response[..], &mut sec).unwrap(), 3);
+        let response = b"HTTP/1.0 200 OK\r\n\r\nuntil the end";
+        assert_eq!(read_response(&response[..], &mut sec).unwrap(), 13);
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 17\r\n\r\n";
+        let err = read_response(&response[..], &mut sec).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let response = b"HTTP/1.1 403 Forbidden\r\n\r\n";
+        let err = read_response(&response[..], &mut sec).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        assert_eq!(
+            split_url("https://[::1]:8443/secret").unwrap(),
+            ("[::1]:8443", "::1", 8443, "/secret")
+        );
+        assert_eq!(
+            split_url("https://host").unwrap(),
+            ("host", "host", 443, "/")
+        );
+        assert!(split_url("http://host/secret").is_err());
+    }
+}
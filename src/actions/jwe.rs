@@ -0,0 +1,367 @@
+//! Keys exported as JSON Web Keys (RFC 7517) of type `oct`, encrypted in JSON Web Encryption
+//! (RFC 7516) compact serializations, so that keys move between services in a standard
+//! envelope, without ever being in clear.
+//!
+//! The content is encrypted with `A256GCM`, under a content encryption key managed with
+//! `A256KW`, wrapped with a shared key encryption key, or with `ECDH-ES` over X25519 (RFC
+//! 8037), agreed with the public key of the recipient.
+
+use std::{borrow::Cow, io, pin::Pin};
+
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce, Tag, aead::AeadInPlace};
+use aes_kw::KekAes256;
+use base64ct::{Base64UrlUnpadded, Encoding as _};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    api::{Secret, SecretReader, SecretUpdater},
+    memory::{Global, SecureAlloc},
+};
+
+/// Key management algorithms of JWE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JweAlgorithm {
+    /// `A256KW`: the content encryption key is wrapped with AES Key Wrap, under a 256-bit key
+    /// encryption key.
+    A256Kw,
+    /// `ECDH-ES`: the content encryption key is agreed between an ephemeral X25519 key and
+    /// the X25519 key of the recipient.
+    EcdhEsX25519,
+}
+
+/// Recipients of a JWE.
+pub enum JweRecipient<'a, Alloc: SecureAlloc = Global> {
+    /// Holder of the key encryption key held in a secret, for `A256KW`.
+    A256Kw(Pin<&'a Secret<[u8; 32], Alloc>>),
+    /// Holder of the private key of an X25519 public key, for `ECDH-ES`.
+    EcdhEsX25519([u8; 32]),
+}
+
+/// Exports the key held in the secret as a JWK, encrypted for a recipient in a JWE, returning
+/// its compact serialization.
+pub struct ExportJwe<'a, Alloc: SecureAlloc = Global>(pub JweRecipient<'a, Alloc>);
+
+/// Imports the key of a JWE, as returned by [`ExportJwe`] (or by any JOSE implementation,
+/// with `A256GCM` content encryption), writing it directly into the secret.
+///
+/// The algorithm is set by the recipient, not read from the JWE, so that a key encryption
+/// key is never used as an X25519 private key, nor the converse.
+pub struct UpdateSecretFromJwe<'a, Alloc: SecureAlloc = Global> {
+    /// Key encryption key, for `A256KW`, or X25519 private key, for `ECDH-ES`.
+    pub key: Pin<&'a Secret<[u8; 32], Alloc>>,
+    pub algorithm: JweAlgorithm,
+    pub jwe: String,
+}
+
+/// Length of the IVs of AES-GCM.
+const IV_LEN: usize = 12;
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    enc: String,
+    epk: Option<EphemeralKey>,
+    apu: Option<String>,
+    apv: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EphemeralKey {
+    kty: String,
+    crv: String,
+    x: String,
+}
+
+#[derive(Deserialize)]
+struct Jwk<'a> {
+    #[serde(borrow)]
+    kty: Cow<'a, str>,
+    #[serde(borrow)]
+    k: Cow<'a, str>,
+}
+
+impl Drop for Jwk<'_> {
+    fn drop(&mut self) {
+        // Values holding escapes have been unescaped in new buffers.
+        if let Cow::Owned(k) = &mut self.k {
+            k.zeroize();
+        }
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Derives the content encryption key of `A256GCM` from the secret agreed with `ECDH-ES`,
+/// with the Concat KDF of RFC 7518, section 4.6.2.
+fn concat_kdf(shared: &SharedSecret, apu: &[u8], apv: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut hash = Sha256::new();
+    hash.update(1u32.to_be_bytes());
+    hash.update(shared.as_bytes());
+    for part in [&b"A256GCM"[..], apu, apv] {
+        hash.update((part.len() as u32).to_be_bytes());
+        hash.update(part);
+    }
+    hash.update(256u32.to_be_bytes());
+    Zeroizing::new(hash.finalize().into())
+}
+
+/// Returns the compact JWE.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the X25519 public key of the recipient is of
+/// small order, and with the errors of the OS CSPRNG.
+impl<const N: usize, Alloc: SecureAlloc> SecretReader<[u8; N], io::Result<String>>
+    for ExportJwe<'_, Alloc>
+{
+    fn read(&self, sec: &[u8]) -> io::Result<String> {
+        let mut cek = Zeroizing::new([0; 32]);
+        let (header, encrypted_key) = match &self.0 {
+            JweRecipient::A256Kw(kek) => {
+                getrandom::getrandom(&mut *cek)?;
+                let wrapped = kek.read_with(&|kek: &[u8]| {
+                    let kek = KekAes256::try_from(kek).expect("KEKs are 32 bytes long");
+                    kek.wrap_vec(&*cek).expect("32-byte keys can be wrapped")
+                });
+                let header = r#"{"alg":"A256KW","enc":"A256GCM","cty":"jwk+json"}"#;
+                (header.to_string(), wrapped)
+            }
+            JweRecipient::EcdhEsX25519(recipient) => {
+                let mut ephemeral = Zeroizing::new([0; 32]);
+                getrandom::getrandom(&mut *ephemeral)?;
+                let ephemeral = StaticSecret::from(*ephemeral);
+                let shared = ephemeral.diffie_hellman(&PublicKey::from(*recipient));
+                if !shared.was_contributory() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "X25519 public key is of small order",
+                    ));
+                }
+                *cek = *concat_kdf(&shared, &[], &[]);
+                let epk = Base64UrlUnpadded::encode_string(PublicKey::from(&ephemeral).as_bytes());
+                let header = format!(
+                    r#"{{"alg":"ECDH-ES","enc":"A256GCM","cty":"jwk+json","epk":{{"kty":"OKP","crv":"X25519","x":"{epk}"}}}}"#
+                );
+                (header, Vec::new())
+            }
+        };
+        let header = Base64UrlUnpadded::encode_string(header.as_bytes());
+
+        let mut k = Zeroizing::new(vec![0; Base64UrlUnpadded::encoded_len(sec)]);
+        let k = Base64UrlUnpadded::encode(sec, &mut k).expect("buffer has the encoded length");
+        let mut content = Zeroizing::new(Vec::with_capacity(k.len() + 32));
+        content.extend_from_slice(br#"{"kty":"oct","k":""#);
+        content.extend_from_slice(k.as_bytes());
+        content.extend_from_slice(br#""}"#);
+        let mut iv = [0; IV_LEN];
+        getrandom::getrandom(&mut iv)?;
+        let tag = Aes256Gcm::new((&*cek).into())
+            .encrypt_in_place_detached(&iv.into(), header.as_bytes(), &mut content)
+            .expect("JWKs are not too large for AES-GCM");
+        Ok([
+            header,
+            Base64UrlUnpadded::encode_string(&encrypted_key),
+            Base64UrlUnpadded::encode_string(&iv),
+            Base64UrlUnpadded::encode_string(&content),
+            Base64UrlUnpadded::encode_string(&tag),
+        ]
+        .join("."))
+    }
+}
+
+impl<Alloc: SecureAlloc> UpdateSecretFromJwe<'_, Alloc> {
+    /// Returns the content encryption key of the JWE.
+    fn cek(&self, header: &Header, encrypted_key: &[u8]) -> io::Result<Zeroizing<[u8; 32]>> {
+        match self.algorithm {
+            JweAlgorithm::A256Kw => {
+                if header.alg != "A256KW" {
+                    return Err(invalid_data("JWE is not encrypted with A256KW"));
+                }
+                if encrypted_key.len() != 32 + aes_kw::IV_LEN {
+                    return Err(invalid_data("JWE encrypted key is not of 40 bytes"));
+                }
+                self.key.read_with(&|kek: &[u8]| {
+                    let kek = KekAes256::try_from(kek).expect("KEKs are 32 bytes long");
+                    let mut cek = Zeroizing::new([0; 32]);
+                    kek.unwrap(encrypted_key, &mut *cek)
+                        .map_err(|_| invalid_data("JWE was not encrypted under this key"))?;
+                    Ok(cek)
+                })
+            }
+            JweAlgorithm::EcdhEsX25519 => {
+                if header.alg != "ECDH-ES" {
+                    return Err(invalid_data("JWE is not encrypted with ECDH-ES"));
+                }
+                let invalid_header = || invalid_data("JWE header is not valid");
+                let epk = match &header.epk {
+                    Some(epk) if epk.kty == "OKP" && epk.crv == "X25519" => epk,
+                    _ => return Err(invalid_data("JWE has no ephemeral X25519 key")),
+                };
+                let mut ephemeral = [0; 32];
+                match Base64UrlUnpadded::decode(&epk.x, &mut ephemeral) {
+                    Ok(decoded) if decoded.len() == 32 && encrypted_key.is_empty() => {}
+                    _ => return Err(invalid_header()),
+                }
+                let decode = |part: &Option<String>| {
+                    Base64UrlUnpadded::decode_vec(part.as_deref().unwrap_or_default())
+                        .map_err(|_| invalid_header())
+                };
+                let (apu, apv) = (decode(&header.apu)?, decode(&header.apv)?);
+                self.key.read_with(&|private_key: &[u8]| {
+                    let private_key: [u8; 32] =
+                        private_key.try_into().expect("keys are 32 bytes long");
+                    let shared = StaticSecret::from(private_key).diffie_hellman(&ephemeral.into());
+                    if !shared.was_contributory() {
+                        return Err(invalid_data("JWE ephemeral key is of small order"));
+                    }
+                    Ok(concat_kdf(&shared, &apu, &apv))
+                })
+            }
+        }
+    }
+
+    fn import(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let parts: Vec<_> = self.jwe.split('.').collect();
+        let &[header_part, encrypted_key, iv, ciphertext, tag] = &parts[..] else {
+            return Err(invalid_data("JWE is not made of five parts"));
+        };
+        let decode = |part: &str| {
+            Base64UrlUnpadded::decode_vec(part).map_err(|_| invalid_data("JWE is not Base64url"))
+        };
+        let header: Header = serde_json::from_slice(&decode(header_part)?)
+            .map_err(|_| invalid_data("JWE header is not valid"))?;
+        if header.enc != "A256GCM" {
+            return Err(invalid_data("JWE content is not encrypted with A256GCM"));
+        }
+        let cek = self.cek(&header, &decode(encrypted_key)?)?;
+        let (iv, tag) = (decode(iv)?, decode(tag)?);
+        if iv.len() != IV_LEN || tag.len() != 16 {
+            return Err(invalid_data("JWE IV or tag is of invalid length"));
+        }
+        let mut content = Zeroizing::new(decode(ciphertext)?);
+        Aes256Gcm::new((&*cek).into())
+            .decrypt_in_place_detached(
+                Nonce::from_slice(&iv),
+                header_part.as_bytes(),
+                &mut content,
+                Tag::from_slice(&tag),
+            )
+            .map_err(|_| invalid_data("JWE was not encrypted under this key, or was altered"))?;
+        let jwk: Jwk = serde_json::from_slice(&content)
+            .map_err(|_| invalid_data("JWE content is not a JWK"))?;
+        if jwk.kty != "oct" {
+            return Err(invalid_data("JWK is not a symmetric key"));
+        }
+        let len = jwk.k.len() * 3 / 4;
+        if len > sec.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "key is larger than the secret",
+            ));
+        }
+        Base64UrlUnpadded::decode(jwk.k.as_bytes(), &mut sec[..len])
+            .map(<[u8]>::len)
+            .map_err(|_| invalid_data("JWK key is not Base64url"))
+    }
+}
+
+/// Returns the length of the key.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the JWE is not valid, is not encrypted with
+/// the algorithm of the recipient, was not encrypted under this key or was altered, or does
+/// not hold a symmetric JWK, and with [`io::ErrorKind::InvalidInput`] if the key is larger
+/// than the secret. On failure, the secret is zeroized.
+impl<const N: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], io::Result<usize>>
+    for UpdateSecretFromJwe<'_, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = self.import(sec);
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::actions::X25519PublicKey;
+
+    #[test]
+    fn test_jwe() {
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut()
+            .update_with(&|sec: &mut [u8]| sec.iter_mut().zip(0..).for_each(|(byte, i)| *byte = i));
+        let mut kek = pin!(Secret::<[u8; 32]>::new());
+        kek.as_mut().update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let mut private_key = pin!(Secret::<[u8; 32]>::new());
+        private_key
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x43));
+        let public_key = private_key.as_ref().read_with(&X25519PublicKey);
+
+        for (recipient, algorithm, wrong) in [
+            (
+                JweRecipient::A256Kw(kek.as_ref()),
+                JweAlgorithm::A256Kw,
+                JweAlgorithm::EcdhEsX25519,
+            ),
+            (
+                JweRecipient::EcdhEsX25519(public_key),
+                JweAlgorithm::EcdhEsX25519,
+                JweAlgorithm::A256Kw,
+            ),
+        ] {
+            let jwe = key.as_ref().read_with(&ExportJwe(recipient)).unwrap();
+            assert_eq!(jwe.split('.').count(), 5);
+            let recipient_key = match algorithm {
+                JweAlgorithm::A256Kw => kek.as_ref(),
+                JweAlgorithm::EcdhEsX25519 => private_key.as_ref(),
+            };
+            let import = |algorithm, jwe: &str| {
+                let mut imported = pin!(Secret::<[u8; 32]>::new());
+                let len = imported.as_mut().update_with(&UpdateSecretFromJwe {
+                    key: recipient_key,
+                    algorithm,
+                    jwe: jwe.to_string(),
+                })?;
+                key.as_ref().read_with(&|key: &[u8]| {
+                    imported
+                        .as_ref()
+                        .read_with(&|imported: &[u8]| assert_eq!(imported, key))
+                });
+                Ok::<_, io::Error>(len)
+            };
+            assert_eq!(import(algorithm, &jwe).unwrap(), 32);
+            let err = import(wrong, &jwe).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            let mut altered = jwe.clone().into_bytes();
+            let tag = jwe.rfind('.').unwrap() - 1;
+            altered[tag] = if altered[tag] == b'A' { b'B' } else { b'A' };
+            let err = import(algorithm, std::str::from_utf8(&altered).unwrap()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        let jwe = key
+            .as_ref()
+            .read_with(&ExportJwe(JweRecipient::A256Kw(kek.as_ref())))
+            .unwrap();
+        let mut short = pin!(Secret::<[u8; 16]>::new());
+        let err = short
+            .as_mut()
+            .update_with(&UpdateSecretFromJwe {
+                key: kek.as_ref(),
+                algorithm: JweAlgorithm::A256Kw,
+                jwe,
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,134 @@
+use std::io;
+
+use base64ct::{Base64UrlUnpadded, Encoding as _};
+
+#[cfg(feature = "ed25519-dalek")]
+use super::Ed25519Sign;
+#[cfg(feature = "hmac")]
+use super::{Hmac, HmacHash};
+use crate::api::SecretReader;
+
+/// Signs a JSON Web Token (RFC 7519) with HMAC-SHA256, keyed by the whole secret, returning
+/// its compact serialization.
+///
+/// The signing input (`BASE64URL(header) || '.' || BASE64URL(claims)`) is prepared by the
+/// application, with `"alg": "HS256"` in its header: only the signature is computed by the
+/// reader.
+#[cfg(feature = "hmac")]
+pub struct SignJwtHs256(pub String);
+
+/// Signs a JSON Web Token with Ed25519 (RFC 8037), the secret being the 32-byte seed of the
+/// key, as [`SignJwtHs256`] does with `"alg": "EdDSA"` in the header.
+#[cfg(feature = "ed25519-dalek")]
+pub struct SignJwtEdDsa(pub String);
+
+/// Checks that `input` is made of two Base64url segments, without padding.
+fn check_signing_input(input: &str) -> io::Result<()> {
+    let segments: Vec<_> = input.split('.').collect();
+    let valid = segments.len() == 2
+        && segments.iter().all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+        });
+    match valid {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "JWT signing input must be two Base64url segments separated by a dot",
+        )),
+    }
+}
+
+/// Appends the signature to the signing input.
+fn compact(input: &str, signature: &[u8]) -> String {
+    let mut buf = [0; 86];
+    let signature = Base64UrlUnpadded::encode(signature, &mut buf)
+        .expect("signatures are at most 64 bytes long");
+    format!("{input}.{signature}")
+}
+
+/// Returns the compact JWS.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the signing input is not made of two
+/// Base64url segments.
+#[cfg(feature = "hmac")]
+impl<const N: usize> SecretReader<[u8; N], io::Result<String>> for SignJwtHs256 {
+    fn read(&self, sec: &[u8]) -> io::Result<String> {
+        check_signing_input(&self.0)?;
+        let hmac = Hmac {
+            message: self.0.as_bytes().to_vec(),
+            hash: HmacHash::Sha256,
+        };
+        let tag = SecretReader::<[u8; N], _>::read(&hmac, sec);
+        Ok(compact(&self.0, &tag))
+    }
+}
+
+/// Returns the compact JWS.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the signing input is not made of two
+/// Base64url segments.
+#[cfg(feature = "ed25519-dalek")]
+impl SecretReader<[u8; 32], io::Result<String>> for SignJwtEdDsa {
+    fn read(&self, sec: &[u8]) -> io::Result<String> {
+        check_signing_input(&self.0)?;
+        let sign = Ed25519Sign(self.0.as_bytes().to_vec());
+        let signature = SecretReader::<[u8; 32], _>::read(&sign, sec);
+        Ok(compact(&self.0, &signature))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    /// Example of RFC 8037, section A.4, and the HS256 token of jwt.io.
+    #[test]
+    fn test_sign_jwt() {
+        #[cfg(feature = "hmac")]
+        {
+            let mut secret = pin!(Secret::<[u8; 19]>::new());
+            secret
+                .as_mut()
+                .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"your-256-bit-secret"));
+            let input = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                         eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiaWF0IjoxNTE2MjM5MDIyfQ";
+            let jws = secret.as_ref().read_with(&SignJwtHs256(input.into()));
+            assert_eq!(
+                jws.unwrap(),
+                format!("{input}.SflKxwRJSMeKKF2QT4fwpMeJf36POk6yJV_adQssw5c")
+            );
+            for input in ["eyJhbGciOiJIUzI1NiJ9", "eyJ9.e30=", "eyJ9.e30.e30"] {
+                let err = secret
+                    .as_ref()
+                    .read_with(&SignJwtHs256(input.into()))
+                    .unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            }
+        }
+        #[cfg(feature = "ed25519-dalek")]
+        {
+            let mut secret = pin!(Secret::<[u8; 32]>::new());
+            secret.as_mut().update_with(&|sec: &mut [u8]| {
+                Base64UrlUnpadded::decode("nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A", sec)
+                    .map(drop)
+                    .unwrap()
+            });
+            let input = "eyJhbGciOiJFZERTQSJ9.RXhhbXBsZSBvZiBFZDI1NTE5IHNpZ25pbmc";
+            let jws = secret.as_ref().read_with(&SignJwtEdDsa(input.into()));
+            assert_eq!(
+                jws.unwrap(),
+                format!(
+                    "{input}.hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5Bh\
+                     VsPt9g7sVvpAr_MuM0KAg"
+                )
+            );
+        }
+    }
+}
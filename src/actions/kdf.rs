@@ -0,0 +1,515 @@
+#[cfg(any(
+    feature = "argon2",
+    feature = "hkdf",
+    feature = "pbkdf2",
+    feature = "scrypt"
+))]
+use std::io;
+use std::pin::Pin;
+
+#[cfg(feature = "argon2")]
+use aes_gcm::aead::OsRng;
+#[cfg(feature = "argon2")]
+use argon2::{
+    Algorithm, Argon2, Version,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+};
+#[cfg(feature = "blake3")]
+use blake3::Hasher;
+#[cfg(feature = "hkdf")]
+use hkdf::Hkdf;
+#[cfg(any(feature = "hkdf", feature = "pbkdf2"))]
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+#[cfg(feature = "argon2")]
+use crate::api::SecretReader;
+use crate::{
+    api::{Secret, SecretUpdater},
+    memory::{Global, SecureAlloc},
+};
+
+/// Cost parameters of Argon2id.
+#[cfg(feature = "argon2")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Argon2Params {
+    /// Memory size, in KiB.
+    pub memory_kib: u32,
+    /// Number of passes over the memory.
+    pub iterations: u32,
+    /// Number of lanes.
+    pub parallelism: u32,
+}
+
+/// The minimal parameters recommended by OWASP: 19 MiB, 2 passes and 1 lane.
+#[cfg(feature = "argon2")]
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Argon2Params {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Derives a key filling the whole secret from a passphrase held in another secret, up to its
+/// first NUL byte, with Argon2id (version 0x13).
+///
+/// # Security
+///
+/// The key is written directly into the secret. The working memory of Argon2, which depends
+/// on the passphrase, is zeroized once the key is derived.
+#[cfg(feature = "argon2")]
+pub struct DeriveFromPassphrase<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub passphrase: Pin<&'a Secret<[u8; K], Alloc>>,
+    /// Salt, unique to the key, of at least 8 bytes.
+    pub salt: Vec<u8>,
+    pub params: Argon2Params,
+}
+
+/// Hashes the password held in the secret, up to its first NUL byte, with Argon2id under a
+/// random salt, returning the hash as a PHC string (`$argon2id$v=19$m=…`), to be stored as a
+/// password verifier.
+#[cfg(feature = "argon2")]
+pub struct HashPassword(pub Argon2Params);
+
+/// Checks the password held in the secret, up to its first NUL byte, against a PHC string,
+/// as returned by [`HashPassword`] (or by any Argon2 implementation), in constant time.
+#[cfg(feature = "argon2")]
+pub struct VerifyPassword(pub String);
+
+/// Cost parameters of scrypt.
+#[cfg(feature = "scrypt")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScryptParams {
+    /// Base 2 logarithm of the CPU/memory cost `N`.
+    pub log_n: u8,
+    /// Block size.
+    pub r: u32,
+    /// Parallelism.
+    pub p: u32,
+}
+
+/// The parameters recommended by the `scrypt` crate: `N = 2^17`, `r = 8` and `p = 1`, that is
+/// 128 MiB.
+#[cfg(feature = "scrypt")]
+impl Default for ScryptParams {
+    fn default() -> Self {
+        ScryptParams {
+            log_n: scrypt::Params::RECOMMENDED_LOG_N,
+            r: scrypt::Params::RECOMMENDED_R,
+            p: scrypt::Params::RECOMMENDED_P,
+        }
+    }
+}
+
+/// Derives a key filling the whole secret from a passphrase held in another secret, up to its
+/// first NUL byte, with scrypt (RFC 7914), as used by Tarsnap, Borg or age.
+///
+/// # Security
+///
+/// The key is written directly into the secret. The working memory of scrypt, which depends
+/// on the passphrase, is however not zeroized: prefer Argon2id (`argon2` feature) unless an
+/// existing format requires scrypt.
+#[cfg(feature = "scrypt")]
+pub struct DeriveFromPassphraseScrypt<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub passphrase: Pin<&'a Secret<[u8; K], Alloc>>,
+    pub salt: Vec<u8>,
+    pub params: ScryptParams,
+}
+
+/// Derives a key filling the whole secret from a passphrase held in another secret, up to its
+/// first NUL byte, with PBKDF2-HMAC-SHA256 (RFC 8018).
+///
+/// # Security
+///
+/// PBKDF2 is cheap to attack on dedicated hardware: prefer Argon2id (`argon2` feature)
+/// unless an existing format requires PBKDF2. The HMAC state keyed by the passphrase is not
+/// zeroized.
+#[cfg(feature = "pbkdf2")]
+pub struct DeriveFromPassphrasePbkdf2<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub passphrase: Pin<&'a Secret<[u8; K], Alloc>>,
+    pub salt: Vec<u8>,
+    /// Number of iterations, at least 600 000 as recommended by OWASP, unless an existing
+    /// format sets it.
+    pub iterations: u32,
+}
+
+/// Derives a subkey filling the whole secret from a master key held in another secret, with
+/// HKDF-SHA256 (RFC 5869), so that a master key fans out into keys for distinct purposes.
+///
+/// # Security
+///
+/// The subkey is written directly into the secret. The pseudorandom key extracted from the
+/// master key is however held by HMAC states which are not zeroized.
+#[cfg(feature = "hkdf")]
+pub struct DeriveSubkey<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub master: Pin<&'a Secret<[u8; K], Alloc>>,
+    /// Optional salt, empty if none.
+    pub salt: Vec<u8>,
+    /// Purpose of the subkey, such as `b"myapp 2024 file encryption"`: subkeys derived with
+    /// distinct purposes are independent.
+    pub info: Vec<u8>,
+}
+
+/// Derives a key filling the whole secret from key material held in another secret, with
+/// the `derive_key` mode of BLAKE3, much faster than [`DeriveSubkey`] on large inputs.
+///
+/// # Security
+///
+/// The key is written directly into the secret, and the hashing states are zeroized.
+#[cfg(feature = "blake3")]
+pub struct DeriveSubkeyBlake3<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub key_material: Pin<&'a Secret<[u8; K], Alloc>>,
+    /// Hardcoded, globally unique and application-specific context, such as
+    /// `"myapp 2024-06-01 12:00:00 file encryption"`.
+    pub context: String,
+}
+
+#[cfg(any(feature = "argon2", feature = "hkdf", feature = "scrypt"))]
+fn invalid_input(err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err.to_string())
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the parameters, the salt or the length of
+/// the secret are not supported by Argon2. On failure, the secret is zeroized.
+#[cfg(feature = "argon2")]
+impl<const N: usize, const K: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], io::Result<()>>
+    for DeriveFromPassphrase<'_, K, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let result = argon2::Params::new(
+            self.params.memory_kib,
+            self.params.iterations,
+            self.params.parallelism,
+            Some(sec.len()),
+        )
+        .map_err(invalid_input)
+        .and_then(|params| {
+            let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+            self.passphrase.read_with_mut(&mut |pass: &[u8]| {
+                argon2
+                    .hash_password_into(super::passphrase(pass), &self.salt, sec)
+                    .map_err(invalid_input)
+            })
+        });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the parameters are not supported by Argon2.
+#[cfg(feature = "argon2")]
+impl<const N: usize> SecretReader<[u8; N], io::Result<String>> for HashPassword {
+    fn read(&self, sec: &[u8]) -> io::Result<String> {
+        let params = argon2::Params::new(
+            self.0.memory_kib,
+            self.0.iterations,
+            self.0.parallelism,
+            None,
+        )
+        .map_err(invalid_input)?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = argon2
+            .hash_password(super::passphrase(sec), &salt)
+            .map_err(invalid_input)?;
+        Ok(hash.to_string())
+    }
+}
+
+/// Returns whether the password matches.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the hash is not a valid Argon2 PHC string.
+#[cfg(feature = "argon2")]
+impl<const N: usize> SecretReader<[u8; N], io::Result<bool>> for VerifyPassword {
+    fn read(&self, sec: &[u8]) -> io::Result<bool> {
+        let invalid_data = |err: argon2::password_hash::Error| {
+            io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+        };
+        let hash = PasswordHash::new(&self.0).map_err(invalid_data)?;
+        match Argon2::default().verify_password(super::passphrase(sec), &hash) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(err) => Err(invalid_data(err)),
+        }
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the parameters or the length of the secret
+/// are not supported by scrypt. On failure, the secret is zeroized.
+#[cfg(feature = "scrypt")]
+impl<const N: usize, const K: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], io::Result<()>>
+    for DeriveFromPassphraseScrypt<'_, K, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let params = self.params;
+        let result = scrypt::Params::new(params.log_n, params.r, params.p, sec.len())
+            .map_err(invalid_input)
+            .and_then(|params| {
+                self.passphrase.read_with_mut(&mut |pass: &[u8]| {
+                    scrypt::scrypt(super::passphrase(pass), &self.salt, &params, sec)
+                        .map_err(invalid_input)
+                })
+            });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the number of iterations is 0. On failure,
+/// the secret is zeroized.
+#[cfg(feature = "pbkdf2")]
+impl<const N: usize, const K: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], io::Result<()>>
+    for DeriveFromPassphrasePbkdf2<'_, K, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        if self.iterations == 0 {
+            sec.zeroize();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "PBKDF2 needs at least one iteration",
+            ));
+        }
+        self.passphrase.read_with_mut(&mut |pass: &[u8]| {
+            pbkdf2::pbkdf2_hmac::<Sha256>(super::passphrase(pass), &self.salt, self.iterations, sec)
+        });
+        Ok(())
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the secret is longer than 8160 bytes
+/// (255 blocks of SHA-256). On failure, the secret is zeroized.
+#[cfg(feature = "hkdf")]
+impl<const N: usize, const K: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], io::Result<()>>
+    for DeriveSubkey<'_, K, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let result = self.master.read_with_mut(&mut |master: &[u8]| {
+            Hkdf::<Sha256>::new(Some(&self.salt), master)
+                .expand(&self.info, sec)
+                .map_err(invalid_input)
+        });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl<const N: usize, const K: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], ()>
+    for DeriveSubkeyBlake3<'_, K, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) {
+        self.key_material.read_with_mut(&mut |material: &[u8]| {
+            let mut hasher = Hasher::new_derive_key(&self.context);
+            hasher.update(material);
+            let mut output = hasher.finalize_xof();
+            output.fill(sec);
+            hasher.zeroize();
+            output.zeroize();
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn test_derive_from_passphrase() {
+        let params = Argon2Params {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let mut passphrase = pin!(Secret::<[u8; 16]>::new());
+        passphrase
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..8].copy_from_slice(b"password"));
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut()
+            .update_with(&DeriveFromPassphrase {
+                passphrase: passphrase.as_ref(),
+                salt: b"somesalt".to_vec(),
+                params,
+            })
+            .unwrap();
+        let mut expected = [0u8; 32];
+        Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            argon2::Params::new(64, 1, 1, Some(32)).unwrap(),
+        )
+        .hash_password_into(b"password", b"somesalt", &mut expected)
+        .unwrap();
+        key.as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, expected));
+
+        let err = key
+            .as_mut()
+            .update_with(&DeriveFromPassphrase {
+                passphrase: passphrase.as_ref(),
+                salt: b"salt".to_vec(),
+                params,
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        key.as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 32]));
+    }
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn test_hash_password() {
+        let params = Argon2Params {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let mut password = pin!(Secret::<[u8; 16]>::new());
+        password
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..8].copy_from_slice(b"password"));
+        let hash = password.as_ref().read_with(&HashPassword(params)).unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+        assert!(
+            password
+                .as_ref()
+                .read_with(&VerifyPassword(hash.clone()))
+                .unwrap()
+        );
+
+        password
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..8].copy_from_slice(b"Password"));
+        assert!(!password.as_ref().read_with(&VerifyPassword(hash)).unwrap());
+        let err = password
+            .as_ref()
+            .read_with(&VerifyPassword("not a PHC string".into()))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// First test case of RFC 5869, truncated to 32 bytes.
+    #[cfg(feature = "hkdf")]
+    #[test]
+    fn test_derive_subkey() {
+        let mut master = pin!(Secret::<[u8; 22]>::new());
+        master
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x0b));
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut()
+            .update_with(&DeriveSubkey {
+                master: master.as_ref(),
+                salt: (0x00..=0x0c).collect(),
+                info: (0xf0..=0xf9).collect(),
+            })
+            .unwrap();
+        key.as_ref().read_with(&|sec: &[u8]| {
+            assert_eq!(
+                sec,
+                [
+                    0x3c, 0xb2, 0x5f, 0x25, 0xfa, 0xac, 0xd5, 0x7a, 0x90, 0x43, 0x4f, 0x64, 0xd0,
+                    0x36, 0x2f, 0x2a, 0x2d, 0x2d, 0x0a, 0x90, 0xcf, 0x1a, 0x5a, 0x4c, 0x5d, 0xb0,
+                    0x2d, 0x56, 0xec, 0xc4, 0xc5, 0xbf,
+                ]
+            )
+        });
+    }
+
+    /// Test vector of PBKDF2-HMAC-SHA256 with one iteration.
+    #[cfg(feature = "pbkdf2")]
+    #[test]
+    fn test_derive_from_passphrase_pbkdf2() {
+        let mut passphrase = pin!(Secret::<[u8; 16]>::new());
+        passphrase
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..8].copy_from_slice(b"password"));
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        let pbkdf2 = |iterations| DeriveFromPassphrasePbkdf2 {
+            passphrase: passphrase.as_ref(),
+            salt: b"salt".to_vec(),
+            iterations,
+        };
+        key.as_mut().update_with(&pbkdf2(1)).unwrap();
+        key.as_ref().read_with(&|sec: &[u8]| {
+            assert_eq!(
+                sec,
+                [
+                    0x12, 0x0f, 0xb6, 0xcf, 0xfc, 0xf8, 0xb3, 0x2c, 0x43, 0xe7, 0x22, 0x52, 0x56,
+                    0xc4, 0xf8, 0x37, 0xa8, 0x65, 0x48, 0xc9, 0x2c, 0xcc, 0x35, 0x48, 0x08, 0x05,
+                    0x98, 0x7c, 0xb7, 0x0b, 0xe1, 0x7b,
+                ]
+            )
+        });
+
+        let err = key.as_mut().update_with(&pbkdf2(0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        key.as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 32]));
+    }
+
+    /// Test vector of BLAKE3, with an empty input.
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_derive_subkey_blake3() {
+        let material = pin!(Secret::<[u8; 0]>::new());
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut().update_with(&DeriveSubkeyBlake3 {
+            key_material: material.as_ref(),
+            context: "BLAKE3 2019-12-27 16:29:52 test vectors context".into(),
+        });
+        key.as_ref().read_with(&|sec: &[u8]| {
+            assert_eq!(
+                sec,
+                [
+                    0x2c, 0xc3, 0x97, 0x83, 0xc2, 0x23, 0x15, 0x4f, 0xea, 0x8d, 0xfb, 0x7c, 0x1b,
+                    0x16, 0x60, 0xf2, 0xac, 0x2d, 0xcb, 0xd1, 0xc1, 0xde, 0x82, 0x77, 0xb0, 0xb0,
+                    0xdd, 0x39, 0xb7, 0xe5, 0x0d, 0x7d,
+                ]
+            )
+        });
+    }
+
+    /// First test vector of RFC 7914, section 12, truncated to 32 bytes.
+    #[cfg(feature = "scrypt")]
+    #[test]
+    fn test_derive_from_passphrase_scrypt() {
+        let passphrase = pin!(Secret::<[u8; 16]>::new());
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        let scrypt = |r| DeriveFromPassphraseScrypt {
+            passphrase: passphrase.as_ref(),
+            salt: Vec::new(),
+            params: ScryptParams { log_n: 4, r, p: 1 },
+        };
+        key.as_mut().update_with(&scrypt(1)).unwrap();
+        key.as_ref().read_with(&|sec: &[u8]| {
+            assert_eq!(
+                sec,
+                [
+                    0x77, 0xd6, 0x57, 0x62, 0x38, 0x65, 0x7b, 0x20, 0x3b, 0x19, 0xca, 0x42, 0xc1,
+                    0x8a, 0x04, 0x97, 0xf1, 0x6b, 0x48, 0x44, 0xe3, 0x07, 0x4a, 0xe8, 0xdf, 0xdf,
+                    0xfa, 0x3f, 0xed, 0xe2, 0x14, 0x42,
+                ]
+            )
+        });
+
+        let err = key.as_mut().update_with(&scrypt(0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        key.as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 32]));
+    }
+}
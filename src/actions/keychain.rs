@@ -0,0 +1,72 @@
+//! Secrets kept as generic password items of the macOS (or iOS) Keychain, so that services
+//! do not need plaintext key files.
+//!
+//! Items are identified by their service and account, and are encrypted at rest by the
+//! Keychain.
+
+use std::io;
+
+use security_framework::passwords::{PasswordOptions, generic_password, set_generic_password};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::api::{SecretReader, SecretUpdater};
+
+/// `errSecItemNotFound`
+const ITEM_NOT_FOUND: i32 = -25300;
+
+fn keychain_error(err: security_framework::base::Error) -> io::Error {
+    match err.code() {
+        ITEM_NOT_FOUND => io::Error::new(io::ErrorKind::NotFound, err),
+        _ => io::Error::other(err),
+    }
+}
+
+/// Loads the password of a generic password item.
+///
+/// # Security
+///
+/// The password is returned by the Security framework in a buffer, zeroized once copied.
+pub struct UpdateSecretFromKeychain {
+    pub service: String,
+    pub account: String,
+}
+
+/// Stores the secret as the password of a generic password item, created or updated.
+pub struct StoreInKeychain {
+    pub service: String,
+    pub account: String,
+}
+
+/// Returns the length of the password.
+///
+/// Fails with [`io::ErrorKind::NotFound`] if there is no such item, and with
+/// [`io::ErrorKind::InvalidInput`] if the password is larger than the secret. On failure,
+/// the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromKeychain {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let options = PasswordOptions::new_generic_password(&self.service, &self.account);
+        let result = generic_password(options)
+            .map(Zeroizing::new)
+            .map_err(keychain_error)
+            .and_then(|password| {
+                let dst = sec.get_mut(..password.len()).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "password is larger than its buffer",
+                    )
+                })?;
+                dst.copy_from_slice(&password);
+                Ok(password.len())
+            });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+impl<const N: usize> SecretReader<[u8; N], io::Result<()>> for StoreInKeychain {
+    fn read(&self, sec: &[u8]) -> io::Result<()> {
+        set_generic_password(&self.service, &self.account, sec).map_err(keychain_error)
+    }
+}
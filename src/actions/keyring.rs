@@ -0,0 +1,209 @@
+//! Secrets kept in the Linux kernel keyrings, between runs of a process.
+//!
+//! Keys are stored as `user` keys, whose payload lives in kernel memory: it is never
+//! swapped out in clear, nor written to disk.
+
+use std::{ffi::CString, io, time::Duration};
+
+use zeroize::Zeroize;
+
+use crate::api::{SecretReader, SecretUpdater};
+
+/// Keyring a key is searched in, or stored to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Keyring {
+    /// Keyring of the login session, shared by its processes.
+    #[default]
+    Session,
+    /// Keyring of the user, shared by all its processes.
+    User,
+    /// Keyring of the process, lost when it exits.
+    Process,
+}
+
+impl Keyring {
+    fn id(self) -> libc::c_long {
+        (match self {
+            Keyring::Session => libc::KEY_SPEC_SESSION_KEYRING,
+            Keyring::User => libc::KEY_SPEC_USER_KEYRING,
+            Keyring::Process => libc::KEY_SPEC_PROCESS_KEYRING,
+        }) as libc::c_long
+    }
+}
+
+/// Loads the payload of the `user` key with this description, searched in the keyring
+/// (and the keyrings linked to it).
+///
+/// # Security
+///
+/// The payload is read by the kernel directly into the secret.
+pub struct UpdateSecretFromKeyring {
+    pub description: String,
+    pub keyring: Keyring,
+}
+
+/// Stores the secret as the payload of a `user` key with this description, replacing any
+/// previous one, and returns its serial number, so that short-lived keys can be parked out
+/// of the memory of the process, then loaded with [`UpdateSecretFromKeyring`].
+///
+/// # Security
+///
+/// The key expires after the timeout, if any. It can be read by processes of the same
+/// user having the keyring, or a keyring it is linked into.
+pub struct StoreInKeyring {
+    pub description: String,
+    pub keyring: Keyring,
+    /// Lifetime of the key, rounded down to whole seconds, of at least one second.
+    pub timeout: Option<Duration>,
+}
+
+fn description(description: &str) -> io::Result<CString> {
+    CString::new(description)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "key description contains NUL"))
+}
+
+fn check(ret: libc::c_long) -> io::Result<libc::c_long> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        ret => Ok(ret),
+    }
+}
+
+/// Returns the length of the payload.
+///
+/// Fails with [`io::ErrorKind::NotFound`] if there is no such key (or it expired), and with
+/// [`io::ErrorKind::InvalidInput`] if the payload is larger than the secret, which is then
+/// zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromKeyring {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let description = description(&self.description)?;
+        let key = check(unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                libc::KEYCTL_SEARCH,
+                self.keyring.id(),
+                c"user".as_ptr(),
+                description.as_ptr(),
+                0,
+            )
+        })
+        .map_err(|err| match err.raw_os_error() {
+            Some(libc::ENOKEY | libc::EKEYEXPIRED | libc::EKEYREVOKED) => {
+                io::Error::new(io::ErrorKind::NotFound, err)
+            }
+            _ => err,
+        })?;
+        let len = check(unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                libc::KEYCTL_READ,
+                key,
+                sec.as_mut_ptr(),
+                N,
+            )
+        })? as usize;
+        if len > N {
+            // Only the start of the payload has been read.
+            sec.zeroize();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "key payload is larger than the secret",
+            ));
+        }
+        Ok(len)
+    }
+}
+
+/// Returns the serial number of the key.
+///
+/// Fails with the errors of the kernel, such as [`io::ErrorKind::PermissionDenied`] if the
+/// keyring cannot be written, or [`io::ErrorKind::QuotaExceeded`] if the quota of keys of
+/// the user is reached. If its timeout cannot be set, the key is invalidated, not to outlive
+/// it.
+impl<const N: usize> SecretReader<[u8; N], io::Result<i32>> for StoreInKeyring {
+    fn read(&self, sec: &[u8]) -> io::Result<i32> {
+        let description = description(&self.description)?;
+        let key = check(unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                c"user".as_ptr(),
+                description.as_ptr(),
+                sec.as_ptr(),
+                sec.len(),
+                self.keyring.id(),
+            )
+        })?;
+        if let Some(timeout) = self.timeout {
+            let secs = timeout.as_secs().clamp(1, libc::c_uint::MAX as u64);
+            let set = check(unsafe {
+                libc::syscall(libc::SYS_keyctl, libc::KEYCTL_SET_TIMEOUT, key, secs)
+            });
+            if let Err(err) = set {
+                unsafe { libc::syscall(libc::SYS_keyctl, libc::KEYCTL_INVALIDATE, key) };
+                return Err(err);
+            }
+        }
+        Ok(key as i32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_keyring_round_trip() {
+        let description = format!("secrust-test-{}", std::process::id());
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(&[0x3c; 16]));
+        let store = StoreInKeyring {
+            description: description.clone(),
+            keyring: Keyring::Process,
+            timeout: Some(Duration::from_secs(60)),
+        };
+        match secret.as_ref().read_with(&store) {
+            Ok(_) => {}
+            // Keyrings may be unavailable in containers.
+            Err(err) if err.raw_os_error() == Some(libc::ENOSYS) => return,
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => return,
+            Err(err) => panic!("failed to store the key: {err}"),
+        }
+        let load = UpdateSecretFromKeyring {
+            description,
+            keyring: Keyring::Process,
+        };
+        let mut loaded = pin!(Secret::<[u8; 32]>::new());
+        assert_eq!(loaded.as_mut().update_with(&load).unwrap(), 16);
+        assert!(
+            loaded
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec[..16] == [0x3c; 16])
+        );
+        let mut short = pin!(Secret::<[u8; 8]>::new());
+        let err = short.as_mut().update_with(&load).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let missing = UpdateSecretFromKeyring {
+            description: "secrust-test-missing".into(),
+            keyring: Keyring::Process,
+        };
+        let err = loaded.as_mut().update_with(&missing).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        // Expired keys are not found anymore.
+        let store = StoreInKeyring {
+            timeout: Some(Duration::from_millis(1)),
+            ..store
+        };
+        secret.as_ref().read_with(&store).unwrap();
+        loaded.as_mut().update_with(&load).unwrap();
+        std::thread::sleep(Duration::from_millis(1100));
+        let err = loaded.as_mut().update_with(&load).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{io, pin::Pin};
+
+use aes_kw::KekAes256;
+use zeroize::Zeroize;
+
+use crate::{
+    api::{Secret, SecretReader, SecretUpdater},
+    memory::{Global, SecureAlloc},
+};
+
+/// Key wrapping algorithms of AES, with a 256-bit key encryption key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyWrapMode {
+    /// AES Key Wrap (RFC 3394), for keys of a multiple of 8 bytes, of at least 16 bytes.
+    Kw,
+    /// AES Key Wrap with Padding (RFC 5649), for keys of any length.
+    Kwp,
+}
+
+/// Wraps the key held in another secret with the key encryption key held in the secret,
+/// returning the wrapped key, to be stored or transported.
+pub struct WrapKey<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub key: Pin<&'a Secret<[u8; K], Alloc>>,
+    pub mode: KeyWrapMode,
+}
+
+/// Unwraps a wrapped key (as returned by [`WrapKey`]) with the key encryption key held in
+/// another secret, writing the key directly into the secret.
+pub struct UnwrapKey<'a, Alloc: SecureAlloc = Global> {
+    pub kek: Pin<&'a Secret<[u8; 32], Alloc>>,
+    pub wrapped: Vec<u8>,
+    pub mode: KeyWrapMode,
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the key cannot be wrapped with AES-KW, as it
+/// is shorter than 16 bytes or not a multiple of 8 bytes.
+impl<const K: usize, Alloc: SecureAlloc> SecretReader<[u8; 32], io::Result<Vec<u8>>>
+    for WrapKey<'_, K, Alloc>
+{
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let kek = KekAes256::try_from(sec).expect("KEKs are 32 bytes long");
+        let wrapped = self.key.read_with(&|key: &[u8]| match self.mode {
+            KeyWrapMode::Kw if key.len() < 16 => Err(aes_kw::Error::InvalidDataSize),
+            KeyWrapMode::Kw => kek.wrap_vec(key),
+            KeyWrapMode::Kwp => kek.wrap_with_padding_vec(key),
+        });
+        wrapped.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+    }
+}
+
+/// Returns the length of the key.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the key was not wrapped with this key
+/// encryption key, or was altered, and with [`io::ErrorKind::InvalidInput`] if the key is
+/// larger than the secret (with AES-KWP, once padded to a multiple of 8 bytes). On failure,
+/// the secret is zeroized.
+impl<const N: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], io::Result<usize>>
+    for UnwrapKey<'_, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = match self.wrapped.len().checked_sub(aes_kw::IV_LEN) {
+            Some(len) if len <= sec.len() => {
+                let key = &mut sec[..len];
+                self.kek.read_with_mut(&mut |kek: &[u8]| {
+                    let kek = KekAes256::try_from(kek).expect("KEKs are 32 bytes long");
+                    match self.mode {
+                        KeyWrapMode::Kw => kek.unwrap(&self.wrapped, key).map(|()| len),
+                        KeyWrapMode::Kwp => kek
+                            .unwrap_with_padding(&self.wrapped, key)
+                            .map(|key| key.len()),
+                    }
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+                })
+            }
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "wrapped key is larger than the secret",
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "wrapped key is too short",
+            )),
+        };
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+
+    /// Test vector of RFC 3394, section 4.6.
+    #[test]
+    fn test_wrap_key() {
+        let mut kek = pin!(Secret::<[u8; 32]>::new());
+        kek.as_mut()
+            .update_with(&|sec: &mut [u8]| sec.iter_mut().zip(0..).for_each(|(byte, i)| *byte = i));
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut().update_with(&|sec: &mut [u8]| {
+            sec[..16]
+                .iter_mut()
+                .zip(0..)
+                .for_each(|(byte, i)| *byte = i * 0x11);
+            sec[16..]
+                .iter_mut()
+                .zip(0..)
+                .for_each(|(byte, i)| *byte = i);
+        });
+        let wrap = |mode| {
+            kek.as_ref().read_with(&WrapKey {
+                key: key.as_ref(),
+                mode,
+            })
+        };
+        let wrapped = wrap(KeyWrapMode::Kw).unwrap();
+        assert_eq!(
+            wrapped,
+            [
+                0x28, 0xc9, 0xf4, 0x04, 0xc4, 0xb8, 0x10, 0xf4, 0xcb, 0xcc, 0xb3, 0x5c, 0xfb, 0x87,
+                0xf8, 0x26, 0x3f, 0x57, 0x86, 0xe2, 0xd8, 0x0e, 0xd3, 0x26, 0xcb, 0xc7, 0xf0, 0xe7,
+                0x1a, 0x99, 0xf4, 0x3b, 0xfb, 0x98, 0x8b, 0x9b, 0x7a, 0x02, 0xdd, 0x21,
+            ]
+        );
+
+        let mut unwrapped = pin!(Secret::<[u8; 32]>::new());
+        for mode in [KeyWrapMode::Kw, KeyWrapMode::Kwp] {
+            let unwrap = |wrapped| UnwrapKey {
+                kek: kek.as_ref(),
+                wrapped,
+                mode,
+            };
+            let wrapped = wrap(mode).unwrap();
+            assert_eq!(
+                unwrapped
+                    .as_mut()
+                    .update_with(&unwrap(wrapped.clone()))
+                    .unwrap(),
+                32
+            );
+            key.as_ref().read_with(&|key: &[u8]| {
+                unwrapped
+                    .as_ref()
+                    .read_with(&|sec: &[u8]| assert_eq!(sec, key))
+            });
+
+            let mut altered = wrapped;
+            altered[0] ^= 1;
+            let err = unwrapped
+                .as_mut()
+                .update_with(&unwrap(altered))
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            unwrapped
+                .as_ref()
+                .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 32]));
+        }
+    }
+}
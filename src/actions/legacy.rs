@@ -0,0 +1,191 @@
+//! Ciphers predating AEADs, only to interoperate with legacy file formats: AES-256-CBC with
+//! PKCS#7 padding and HMAC-SHA256 (encrypt-then-MAC), and unauthenticated AES-256-CTR.
+//!
+//! New formats should use [`super::Cipher`], or `CommittingCipher`, instead.
+
+use std::{io, pin::Pin};
+
+use aes::{
+    Aes256,
+    cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, StreamCipher, block_padding::Pkcs7},
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    api::{Secret, SecretReader, constant_time_eq},
+    memory::{Global, SecureAlloc},
+};
+
+/// Encrypts a message with AES-256-CBC, under a random IV, with the key held in the secret,
+/// then authenticates the IV and the ciphertext with HMAC-SHA256, with the key held in
+/// another secret.
+///
+/// The returned ciphertext is the IV, the CBC ciphertext, then the 32-byte tag.
+pub struct CbcHmacCipher<'a, Alloc: SecureAlloc = Global> {
+    pub mac_key: Pin<&'a Secret<[u8; 32], Alloc>>,
+    pub message: Vec<u8>,
+}
+
+/// Decrypts a ciphertext, as returned by [`CbcHmacCipher`], once its tag is checked.
+pub struct CbcHmacDecipher<'a, Alloc: SecureAlloc = Global> {
+    pub mac_key: Pin<&'a Secret<[u8; 32], Alloc>>,
+    pub ciphertext: Vec<u8>,
+}
+
+/// Encrypts or decrypts data with AES-256-CTR, with the key held in the secret, and a
+/// 128-bit big-endian counter starting at `iv`, as OpenSSL does.
+///
+/// # Security
+///
+/// The data is not authenticated, and a key must never be used twice with the same `iv`.
+pub struct AesCtr {
+    pub iv: [u8; 16],
+    pub data: Vec<u8>,
+}
+
+/// Length of the IV of CBC.
+const IV_LEN: usize = 16;
+/// Length of the tag of HMAC-SHA256.
+const TAG_LEN: usize = 32;
+
+/// Computes the tag of `data` under the key held in `mac_key`.
+fn tag<Alloc: SecureAlloc>(mac_key: Pin<&Secret<[u8; 32], Alloc>>, data: &[u8]) -> [u8; 32] {
+    mac_key.read_with(&|key: &[u8]| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac.finalize().into_bytes().into()
+    })
+}
+
+/// Fails with the errors of the OS CSPRNG.
+impl<Alloc: SecureAlloc> SecretReader<[u8; 32], io::Result<Vec<u8>>> for CbcHmacCipher<'_, Alloc> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let mut iv = [0; IV_LEN];
+        getrandom::getrandom(&mut iv)?;
+        let mut ciphertext = iv.to_vec();
+        ciphertext.extend(
+            cbc::Encryptor::<Aes256>::new(sec.into(), &iv.into())
+                .encrypt_padded_vec_mut::<Pkcs7>(&self.message),
+        );
+        let tag = tag(self.mac_key, &ciphertext);
+        ciphertext.extend_from_slice(&tag);
+        Ok(ciphertext)
+    }
+}
+
+/// Returns the message.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the ciphertext was not encrypted and
+/// authenticated under these keys, or was altered.
+impl<Alloc: SecureAlloc> SecretReader<[u8; 32], io::Result<Vec<u8>>>
+    for CbcHmacDecipher<'_, Alloc>
+{
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let invalid_data = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ciphertext was not encrypted under these keys, or was altered",
+            )
+        };
+        let authenticated_len = self
+            .ciphertext
+            .len()
+            .checked_sub(TAG_LEN)
+            .filter(|&len| len >= IV_LEN)
+            .ok_or_else(invalid_data)?;
+        let (authenticated, expected) = self.ciphertext.split_at(authenticated_len);
+        // The tag is checked first, not to be a padding oracle.
+        if !constant_time_eq(&tag(self.mac_key, authenticated), expected) {
+            return Err(invalid_data());
+        }
+        let (iv, ciphertext) = authenticated.split_at(IV_LEN);
+        let iv: [u8; IV_LEN] = iv.try_into().expect("IVs are 16 bytes long");
+        cbc::Decryptor::<Aes256>::new(sec.into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+            .map_err(|_| invalid_data())
+    }
+}
+
+/// Returns the encrypted or decrypted data.
+impl SecretReader<[u8; 32], Vec<u8>> for AesCtr {
+    fn read(&self, sec: &[u8]) -> Vec<u8> {
+        let mut data = self.data.clone();
+        ctr::Ctr128BE::<Aes256>::new(sec.into(), &self.iv.into()).apply_keystream(&mut data);
+        data
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+
+    /// Test vector of NIST SP 800-38A, F.5.5, for AES-CTR.
+    #[test]
+    fn test_legacy() {
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(&[
+                0x60, 0x3d, 0xeb, 0x10, 0x15, 0xca, 0x71, 0xbe, 0x2b, 0x73, 0xae, 0xf0, 0x85, 0x7d,
+                0x77, 0x81, 0x1f, 0x35, 0x2c, 0x07, 0x3b, 0x61, 0x08, 0xd7, 0x2d, 0x98, 0x10, 0xa3,
+                0x09, 0x14, 0xdf, 0xf4,
+            ])
+        });
+        let ctr = |data: &[u8]| {
+            key.as_ref().read_with(&AesCtr {
+                iv: [
+                    0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc,
+                    0xfd, 0xfe, 0xff,
+                ],
+                data: data.to_vec(),
+            })
+        };
+        let plaintext = [
+            0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93,
+            0x17, 0x2a,
+        ];
+        let ciphertext = ctr(&plaintext);
+        assert_eq!(
+            ciphertext,
+            [
+                0x60, 0x1e, 0xc3, 0x13, 0x77, 0x57, 0x89, 0xa5, 0xb7, 0xa7, 0xf5, 0x04, 0xbb, 0xf3,
+                0xd2, 0x28,
+            ]
+        );
+        assert_eq!(ctr(&ciphertext), plaintext);
+
+        let mut mac_key = pin!(Secret::<[u8; 32]>::new());
+        mac_key
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let ciphertext = key
+            .as_ref()
+            .read_with(&CbcHmacCipher {
+                mac_key: mac_key.as_ref(),
+                message: b"secret message".to_vec(),
+            })
+            .unwrap();
+        assert_eq!(ciphertext.len(), IV_LEN + 16 + TAG_LEN);
+        let decipher = |ciphertext| {
+            key.as_ref().read_with(&CbcHmacDecipher {
+                mac_key: mac_key.as_ref(),
+                ciphertext,
+            })
+        };
+        assert_eq!(decipher(ciphertext.clone()).unwrap(), b"secret message");
+        for altered in [
+            {
+                let mut altered = ciphertext.clone();
+                altered[IV_LEN] ^= 1;
+                altered
+            },
+            ciphertext[..IV_LEN + TAG_LEN - 1].to_vec(),
+        ] {
+            let err = decipher(altered).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,541 @@
+#[cfg(feature = "cmac")]
+use aes::{Aes128, Aes256};
+#[cfg(feature = "cmac")]
+use cmac::digest::{KeyInit, Mac};
+#[cfg(all(feature = "hmac", not(feature = "cmac")))]
+use hmac::digest::{KeyInit, Mac};
+#[cfg(feature = "poly1305")]
+use poly1305::{Key, Poly1305, universal_hash};
+#[cfg(feature = "hmac")]
+use sha2::{Sha256, Sha512};
+#[cfg(feature = "sha3")]
+use sha3::{
+    CShake128, CShake128Core, CShake256, CShake256Core,
+    digest::{ExtendableOutput, XofReader},
+};
+
+use crate::api::SecretReader;
+#[cfg(any(feature = "poly1305", feature = "sha3"))]
+use crate::api::constant_time_eq;
+
+/// Hash functions of HMAC.
+#[cfg(feature = "hmac")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HmacHash {
+    /// HMAC-SHA256, with 32-byte tags.
+    Sha256,
+    /// HMAC-SHA512, with 64-byte tags.
+    Sha512,
+}
+
+/// Computes the HMAC (RFC 2104) of a message, keyed by the whole secret, such as the
+/// signature of an API request or of a webhook payload.
+#[cfg(feature = "hmac")]
+pub struct Hmac {
+    pub message: Vec<u8>,
+    pub hash: HmacHash,
+}
+
+/// Checks the HMAC of a message, as computed by [`Hmac`], in constant time.
+#[cfg(feature = "hmac")]
+pub struct VerifyHmac {
+    pub message: Vec<u8>,
+    pub hash: HmacHash,
+    pub tag: Vec<u8>,
+}
+
+/// Computes the BLAKE3 keyed hash of a message, keyed by the 32-byte key held in the secret:
+/// a MAC much faster than [`Hmac`] on large messages.
+#[cfg(feature = "blake3")]
+pub struct Blake3Mac(pub Vec<u8>);
+
+/// Checks the BLAKE3 keyed hash of a message, as computed by [`Blake3Mac`], in constant
+/// time.
+#[cfg(feature = "blake3")]
+pub struct VerifyBlake3Mac {
+    pub message: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+/// Variants of KMAC, with the security strength of their name.
+#[cfg(feature = "sha3")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KmacVariant {
+    Kmac128,
+    Kmac256,
+}
+
+/// Computes the KMAC (NIST SP 800-185) of a message, keyed by the whole secret, for
+/// regulated environments mandating SHA-3 based MACs over HMAC.
+///
+/// The key is absorbed directly from the secret, without being copied.
+#[cfg(feature = "sha3")]
+pub struct Kmac {
+    pub message: Vec<u8>,
+    /// Customization string, empty if none: tags computed with distinct customizations are
+    /// independent.
+    pub customization: Vec<u8>,
+    pub variant: KmacVariant,
+    /// Length of the tag, in bytes.
+    pub len: usize,
+}
+
+/// Checks the KMAC of a message, as computed by [`Kmac`], in constant time, the length of
+/// the tag being part of the KMAC.
+#[cfg(feature = "sha3")]
+pub struct VerifyKmac {
+    pub message: Vec<u8>,
+    pub customization: Vec<u8>,
+    pub variant: KmacVariant,
+    pub tag: Vec<u8>,
+}
+
+/// Computes the AES-CMAC (RFC 4493, NIST SP 800-38B) of a message, keyed by the AES-128 or
+/// AES-256 key held in the secret, as required by SCP03 or automotive protocols.
+#[cfg(feature = "cmac")]
+pub struct Cmac(pub Vec<u8>);
+
+/// Checks the AES-CMAC of a message, as computed by [`Cmac`], in constant time.
+#[cfg(feature = "cmac")]
+pub struct VerifyCmac {
+    pub message: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+/// Computes the Poly1305 tag (RFC 8439) of a message, keyed by the 32-byte one-time key
+/// held in the secret.
+///
+/// # Security
+///
+/// A Poly1305 key authenticates a single message: a key used twice lets an attacker forge
+/// tags. Derive a fresh key for each message, as ChaCha20-Poly1305 and Noise do.
+#[cfg(feature = "poly1305")]
+pub struct Poly1305Tag(pub Vec<u8>);
+
+/// Checks the Poly1305 tag of a message, as computed by [`Poly1305Tag`], in constant time.
+#[cfg(feature = "poly1305")]
+pub struct VerifyPoly1305Tag {
+    pub message: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+#[cfg(any(feature = "cmac", feature = "hmac"))]
+fn mac<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> M {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac
+}
+
+/// Returns the tag.
+#[cfg(feature = "hmac")]
+impl<const N: usize> SecretReader<[u8; N], Vec<u8>> for Hmac {
+    fn read(&self, sec: &[u8]) -> Vec<u8> {
+        match self.hash {
+            HmacHash::Sha256 => mac::<hmac::Hmac<Sha256>>(sec, &self.message)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            HmacHash::Sha512 => mac::<hmac::Hmac<Sha512>>(sec, &self.message)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+        }
+    }
+}
+
+/// Returns whether the tag is valid. Truncated tags are refused.
+#[cfg(feature = "hmac")]
+impl<const N: usize> SecretReader<[u8; N], bool> for VerifyHmac {
+    fn read(&self, sec: &[u8]) -> bool {
+        match self.hash {
+            HmacHash::Sha256 => mac::<hmac::Hmac<Sha256>>(sec, &self.message)
+                .verify_slice(&self.tag)
+                .is_ok(),
+            HmacHash::Sha512 => mac::<hmac::Hmac<Sha512>>(sec, &self.message)
+                .verify_slice(&self.tag)
+                .is_ok(),
+        }
+    }
+}
+
+#[cfg(feature = "blake3")]
+fn blake3_mac(key: &[u8], message: &[u8]) -> blake3::Hash {
+    blake3::keyed_hash(
+        key.try_into().expect("BLAKE3 keys are 32 bytes long"),
+        message,
+    )
+}
+
+/// Returns the tag.
+#[cfg(feature = "blake3")]
+impl SecretReader<[u8; 32], [u8; 32]> for Blake3Mac {
+    fn read(&self, sec: &[u8]) -> [u8; 32] {
+        blake3_mac(sec, &self.0).into()
+    }
+}
+
+/// Returns whether the tag is valid. Truncated tags are refused.
+#[cfg(feature = "blake3")]
+impl SecretReader<[u8; 32], bool> for VerifyBlake3Mac {
+    fn read(&self, sec: &[u8]) -> bool {
+        blake3_mac(sec, &self.message) == *self.tag
+    }
+}
+
+/// Encodes `x` in big endian after (`left_encode`) or before (`right_encode`) its length,
+/// as in SP 800-185, section 2.3.1.
+#[cfg(feature = "sha3")]
+fn encode(x: usize, left: bool) -> Vec<u8> {
+    let bytes = (x as u64).to_be_bytes();
+    let start = bytes.iter().position(|&byte| byte != 0).unwrap_or(7);
+    let len = (8 - start) as u8;
+    match left {
+        true => [&[len], &bytes[start..]].concat(),
+        false => [&bytes[start..], &[len]].concat(),
+    }
+}
+
+/// Computes the KMAC of `message`, with `rate` the rate of cSHAKE in bytes.
+#[cfg(feature = "sha3")]
+fn kmac(mut xof: impl ExtendableOutput, rate: usize, key: &[u8], message: &[u8], tag: &mut [u8]) {
+    // bytepad(encode_string(K), rate), absorbed piecewise.
+    let prefix = [encode(rate, true), encode(8 * key.len(), true)].concat();
+    xof.update(&prefix);
+    xof.update(key);
+    let padding = (rate - (prefix.len() + key.len()) % rate) % rate;
+    xof.update(&vec![0; padding]);
+    xof.update(message);
+    xof.update(&encode(8 * tag.len(), false));
+    xof.finalize_xof().read(tag);
+}
+
+#[cfg(feature = "sha3")]
+fn kmac_tag(
+    variant: KmacVariant,
+    customization: &[u8],
+    key: &[u8],
+    message: &[u8],
+    tag: &mut [u8],
+) {
+    match variant {
+        KmacVariant::Kmac128 => {
+            let core = CShake128Core::new_with_function_name(b"KMAC", customization);
+            kmac(CShake128::from_core(core), 168, key, message, tag)
+        }
+        KmacVariant::Kmac256 => {
+            let core = CShake256Core::new_with_function_name(b"KMAC", customization);
+            kmac(CShake256::from_core(core), 136, key, message, tag)
+        }
+    }
+}
+
+/// Returns the tag.
+#[cfg(feature = "sha3")]
+impl<const N: usize> SecretReader<[u8; N], Vec<u8>> for Kmac {
+    fn read(&self, sec: &[u8]) -> Vec<u8> {
+        let mut tag = vec![0; self.len];
+        kmac_tag(
+            self.variant,
+            &self.customization,
+            sec,
+            &self.message,
+            &mut tag,
+        );
+        tag
+    }
+}
+
+/// Returns whether the tag is valid. Truncated tags are refused, as they are the tags of
+/// another length.
+#[cfg(feature = "sha3")]
+impl<const N: usize> SecretReader<[u8; N], bool> for VerifyKmac {
+    fn read(&self, sec: &[u8]) -> bool {
+        let mut tag = vec![0; self.tag.len()];
+        kmac_tag(
+            self.variant,
+            &self.customization,
+            sec,
+            &self.message,
+            &mut tag,
+        );
+        !self.tag.is_empty() && constant_time_eq(&tag, &self.tag)
+    }
+}
+
+/// Returns the tag.
+#[cfg(feature = "cmac")]
+impl SecretReader<[u8; 16], [u8; 16]> for Cmac {
+    fn read(&self, sec: &[u8]) -> [u8; 16] {
+        mac::<cmac::Cmac<Aes128>>(sec, &self.0)
+            .finalize()
+            .into_bytes()
+            .into()
+    }
+}
+
+/// Returns the tag.
+#[cfg(feature = "cmac")]
+impl SecretReader<[u8; 32], [u8; 16]> for Cmac {
+    fn read(&self, sec: &[u8]) -> [u8; 16] {
+        mac::<cmac::Cmac<Aes256>>(sec, &self.0)
+            .finalize()
+            .into_bytes()
+            .into()
+    }
+}
+
+/// Returns whether the tag is valid. Truncated tags are refused.
+#[cfg(feature = "cmac")]
+impl SecretReader<[u8; 16], bool> for VerifyCmac {
+    fn read(&self, sec: &[u8]) -> bool {
+        mac::<cmac::Cmac<Aes128>>(sec, &self.message)
+            .verify_slice(&self.tag)
+            .is_ok()
+    }
+}
+
+/// Returns whether the tag is valid. Truncated tags are refused.
+#[cfg(feature = "cmac")]
+impl SecretReader<[u8; 32], bool> for VerifyCmac {
+    fn read(&self, sec: &[u8]) -> bool {
+        mac::<cmac::Cmac<Aes256>>(sec, &self.message)
+            .verify_slice(&self.tag)
+            .is_ok()
+    }
+}
+
+#[cfg(feature = "poly1305")]
+fn poly1305(key: &[u8], message: &[u8]) -> [u8; 16] {
+    <Poly1305 as universal_hash::KeyInit>::new(Key::from_slice(key))
+        .compute_unpadded(message)
+        .into()
+}
+
+/// Returns the tag.
+#[cfg(feature = "poly1305")]
+impl SecretReader<[u8; 32], [u8; 16]> for Poly1305Tag {
+    fn read(&self, sec: &[u8]) -> [u8; 16] {
+        poly1305(sec, &self.0)
+    }
+}
+
+/// Returns whether the tag is valid. Truncated tags are refused.
+#[cfg(feature = "poly1305")]
+impl SecretReader<[u8; 32], bool> for VerifyPoly1305Tag {
+    fn read(&self, sec: &[u8]) -> bool {
+        constant_time_eq(&poly1305(sec, &self.message), &self.tag)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    /// Second test case of RFC 4231.
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn test_hmac() {
+        let mut secret = pin!(Secret::<[u8; 4]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"Jefe"));
+        let message = b"what do ya want for nothing?".to_vec();
+        for (hash, expected) in [
+            (
+                HmacHash::Sha256,
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                HmacHash::Sha512,
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+                 9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            ),
+        ] {
+            let tag = secret.as_ref().read_with(&Hmac {
+                message: message.clone(),
+                hash,
+            });
+            let hex: String = tag.iter().map(|byte| format!("{byte:02x}")).collect();
+            assert_eq!(hex, expected);
+
+            let verify = |tag: &[u8]| {
+                secret.as_ref().read_with(&VerifyHmac {
+                    message: message.clone(),
+                    hash,
+                    tag: tag.to_vec(),
+                })
+            };
+            assert!(verify(&tag));
+            assert!(!verify(&tag[..16]));
+            let mut altered = tag.clone();
+            altered[0] ^= 1;
+            assert!(!verify(&altered));
+        }
+    }
+
+    /// Test vector of BLAKE3, with an empty message.
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3_mac() {
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(b"whats the Elvish word for friend")
+        });
+        let tag = key.as_ref().read_with(&Blake3Mac(Vec::new()));
+        assert_eq!(
+            tag,
+            [
+                0x92, 0xb2, 0xb7, 0x56, 0x04, 0xed, 0x3c, 0x76, 0x1f, 0x9d, 0x6f, 0x62, 0x39, 0x2c,
+                0x8a, 0x92, 0x27, 0xad, 0x0e, 0xa3, 0xf0, 0x95, 0x73, 0xe7, 0x83, 0xf1, 0x49, 0x8a,
+                0x4e, 0xd6, 0x0d, 0x26,
+            ]
+        );
+        let verify = |tag: &[u8]| {
+            key.as_ref().read_with(&VerifyBlake3Mac {
+                message: Vec::new(),
+                tag: tag.to_vec(),
+            })
+        };
+        assert!(verify(&tag));
+        assert!(!verify(&tag[..16]));
+    }
+
+    /// Samples #1 and #6 of NIST for KMAC.
+    #[cfg(feature = "sha3")]
+    #[test]
+    fn test_kmac() {
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.iter_mut().zip(0x40..).for_each(|(byte, i)| *byte = i)
+        });
+        let kmac = |variant, customization: &[u8], len| {
+            key.as_ref().read_with(&Kmac {
+                message: vec![0, 1, 2, 3],
+                customization: customization.to_vec(),
+                variant,
+                len,
+            })
+        };
+        let tag = kmac(KmacVariant::Kmac128, b"", 32);
+        assert_eq!(
+            tag,
+            [
+                0xe5, 0x78, 0x0b, 0x0d, 0x3e, 0xa6, 0xf7, 0xd3, 0xa4, 0x29, 0xc5, 0x70, 0x6a, 0xa4,
+                0x3a, 0x00, 0xfa, 0xdb, 0xd7, 0xd4, 0x96, 0x28, 0x83, 0x9e, 0x31, 0x87, 0x24, 0x3f,
+                0x45, 0x6e, 0xe1, 0x4e,
+            ]
+        );
+        assert_eq!(
+            kmac(KmacVariant::Kmac256, b"My Tagged Application", 64),
+            [
+                0x20, 0xc5, 0x70, 0xc3, 0x13, 0x46, 0xf7, 0x03, 0xc9, 0xac, 0x36, 0xc6, 0x1c, 0x03,
+                0xcb, 0x64, 0xc3, 0x97, 0x0d, 0x0c, 0xfc, 0x78, 0x7e, 0x9b, 0x79, 0x59, 0x9d, 0x27,
+                0x3a, 0x68, 0xd2, 0xf7, 0xf6, 0x9d, 0x4c, 0xc3, 0xde, 0x9d, 0x10, 0x4a, 0x35, 0x16,
+                0x89, 0xf2, 0x7c, 0xf6, 0xf5, 0x95, 0x1f, 0x01, 0x03, 0xf3, 0x3f, 0x4f, 0x24, 0x87,
+                0x10, 0x24, 0xd9, 0xc2, 0x77, 0x73, 0xa8, 0xdd,
+            ]
+        );
+
+        let verify = |tag: &[u8]| {
+            key.as_ref().read_with(&VerifyKmac {
+                message: vec![0, 1, 2, 3],
+                customization: Vec::new(),
+                variant: KmacVariant::Kmac128,
+                tag: tag.to_vec(),
+            })
+        };
+        assert!(verify(&tag));
+        assert!(!verify(&tag[..16]));
+        assert!(!verify(&[]));
+    }
+
+    /// Examples of RFC 4493, section 4, and of NIST SP 800-38B, section D.3.
+    #[cfg(feature = "cmac")]
+    #[test]
+    fn test_cmac() {
+        let message = [
+            0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93,
+            0x17, 0x2a,
+        ];
+        let mut key128 = pin!(Secret::<[u8; 16]>::new());
+        key128.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(&[
+                0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+                0x4f, 0x3c,
+            ])
+        });
+        let tag = key128.as_ref().read_with(&Cmac(message.to_vec()));
+        assert_eq!(
+            tag,
+            [
+                0x07, 0x0a, 0x16, 0xb4, 0x6b, 0x4d, 0x41, 0x44, 0xf7, 0x9b, 0xdd, 0x9d, 0xd0, 0x4a,
+                0x28, 0x7c,
+            ]
+        );
+        let mut key256 = pin!(Secret::<[u8; 32]>::new());
+        key256.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(&[
+                0x60, 0x3d, 0xeb, 0x10, 0x15, 0xca, 0x71, 0xbe, 0x2b, 0x73, 0xae, 0xf0, 0x85, 0x7d,
+                0x77, 0x81, 0x1f, 0x35, 0x2c, 0x07, 0x3b, 0x61, 0x08, 0xd7, 0x2d, 0x98, 0x10, 0xa3,
+                0x09, 0x14, 0xdf, 0xf4,
+            ])
+        });
+        let tag = key256.as_ref().read_with(&Cmac(message.to_vec()));
+        assert_eq!(
+            tag,
+            [
+                0x28, 0xa7, 0x02, 0x3f, 0x45, 0x2e, 0x8f, 0x82, 0xbd, 0x4b, 0xf2, 0x8d, 0x8c, 0x37,
+                0xc3, 0x5c,
+            ]
+        );
+
+        let verify = |tag: &[u8]| {
+            key256.as_ref().read_with(&VerifyCmac {
+                message: message.to_vec(),
+                tag: tag.to_vec(),
+            })
+        };
+        assert!(verify(&tag));
+        assert!(!verify(&tag[..8]));
+        assert!(!key128.as_ref().read_with(&VerifyCmac {
+            message: message.to_vec(),
+            tag: tag.to_vec(),
+        }));
+    }
+
+    /// Test vector of RFC 8439, section 2.5.2.
+    #[cfg(feature = "poly1305")]
+    #[test]
+    fn test_poly1305() {
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        key.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(&[
+                0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+                0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+                0x41, 0x49, 0xf5, 0x1b,
+            ])
+        });
+        let message = b"Cryptographic Forum Research Group".to_vec();
+        let tag = key.as_ref().read_with(&Poly1305Tag(message.clone()));
+        assert_eq!(
+            tag,
+            [
+                0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6, 0xc2, 0x2b, 0x8b, 0xaf, 0x0c, 0x01,
+                0x27, 0xa9,
+            ]
+        );
+        let verify = |message: &[u8], tag: &[u8]| {
+            key.as_ref().read_with(&VerifyPoly1305Tag {
+                message: message.to_vec(),
+                tag: tag.to_vec(),
+            })
+        };
+        assert!(verify(&message, &tag));
+        assert!(!verify(b"Cryptographic Forum Research Grou", &tag));
+        assert!(!verify(&message, &tag[..15]));
+    }
+}
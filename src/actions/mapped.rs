@@ -0,0 +1,193 @@
+use std::{
+    ffi::CString,
+    fs::File,
+    io,
+    os::{fd::AsRawFd, unix::ffi::OsStrExt, unix::fs::MetadataExt},
+    path::PathBuf,
+    ptr,
+};
+
+use zeroize::Zeroize;
+
+use crate::api::SecretUpdater;
+
+/// Loads a key file through a locked memory mapping (`MAP_LOCKED | MAP_POPULATE`) rather than
+/// `read`, then evicts it from the page cache.
+///
+/// A key file in the page cache is potentially readable from elsewhere (swapped out with a
+/// hibernation image, or dumped with the kernel memory): the updater tells whether the key was
+/// already cached before being loaded, and may refuse it then.
+///
+/// # Security
+///
+/// The mapping is read only and shares the pages of the page cache, so there is no private
+/// copy of the key to zeroize: the pages are locked while mapped, and evicted from the page
+/// cache (`POSIX_FADV_DONTNEED`) once unmapped, which the kernel may not honour if the file
+/// is mapped elsewhere or dirty.
+///
+/// The file is locked (a shared `flock`) while loaded, as truncating it then would kill the
+/// process with `SIGBUS`. The lock is only advisory: the key file must not be writable by
+/// processes that do not take it.
+///
+/// Since Linux 5.0, whether a file is in the page cache is only reported for files the caller
+/// owns or may write to (`mincore`, as `cachestat`, reports nothing otherwise).
+pub struct UpdateSecretFromMappedFile {
+    pub path: PathBuf,
+    /// Fails rather than loading a key which was already in the page cache, or whose caching
+    /// cannot be checked.
+    pub refuse_cached: bool,
+    /// Disables readahead, so that no more than the key file is read in the page cache.
+    pub no_readahead: bool,
+}
+
+/// Memory mapping of a file, unmapped on drop.
+struct Mapping {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(file: &File, len: usize, flags: libc::c_int) -> io::Result<Self> {
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE | flags,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        match addr {
+            libc::MAP_FAILED => Err(io::Error::last_os_error()),
+            addr => Ok(Mapping { addr, len }),
+        }
+    }
+
+    /// Returns whether some pages of the mapping are in the page cache.
+    fn is_cached(&self) -> io::Result<bool> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let mut pages = vec![0u8; self.len.div_ceil(page)];
+        if unsafe { libc::mincore(self.addr, self.len, pages.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(pages.iter().any(|&page| page & 1 != 0))
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.addr.cast(), self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.len) };
+    }
+}
+
+impl UpdateSecretFromMappedFile {
+    /// Returns whether the kernel reports the caching of the file to the caller.
+    fn is_cache_observable(&self, file: &File) -> io::Result<bool> {
+        if file.metadata()?.uid() == unsafe { libc::geteuid() } {
+            return Ok(true);
+        }
+        let path = CString::new(self.path.as_os_str().as_bytes())?;
+        Ok(
+            unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), libc::W_OK, libc::AT_EACCESS) }
+                == 0,
+        )
+    }
+
+    fn load(&self, file: &File, sec: &mut [u8]) -> io::Result<(usize, bool)> {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = file.metadata()?.len() as usize;
+        let Some(dst) = sec.get_mut(..len) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "key file is larger than the secret",
+            ));
+        };
+        if len == 0 {
+            return Ok((0, false));
+        }
+        if self.no_readahead {
+            unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_RANDOM) };
+        }
+        // Checked before populating the mapping.
+        let cached = Mapping::new(file, len, 0)?.is_cached()?;
+        if cached && self.refuse_cached {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "key file is already in the page cache",
+            ));
+        }
+        if self.refuse_cached && !self.is_cache_observable(file)? {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "page cache residency of a key file not owned nor writable cannot be checked",
+            ));
+        }
+        let mapping = Mapping::new(file, len, libc::MAP_LOCKED | libc::MAP_POPULATE)?;
+        dst.copy_from_slice(mapping.as_slice());
+        Ok((len, cached))
+    }
+}
+
+/// Returns the length of the key, and whether it was already in the page cache (`false` if
+/// this cannot be observed).
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the file is larger than the secret, with
+/// [`io::ErrorKind::PermissionDenied`] if it was cached, or its caching cannot be checked,
+/// and `refuse_cached` is set, and with
+/// [`io::ErrorKind::WouldBlock`] if the pages cannot be locked (`RLIMIT_MEMLOCK`). On failure,
+/// the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<(usize, bool)>>
+    for UpdateSecretFromMappedFile
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<(usize, bool)> {
+        let file = File::open(&self.path)?;
+        let result = self.load(&file, sec);
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_update_secret_from_mapped_file() {
+        let path = std::env::temp_dir().join(format!("secrust-mapped-{}", std::process::id()));
+        std::fs::write(&path, b"mapped key").unwrap();
+        let mapped = |refuse_cached| UpdateSecretFromMappedFile {
+            path: path.clone(),
+            refuse_cached,
+            no_readahead: true,
+        };
+
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        let (len, _) = secret.as_mut().update_with(&mapped(false)).unwrap();
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(&sec[..len], b"mapped key"));
+
+        // Just written, the file is still in the page cache.
+        std::fs::write(&path, b"cached key").unwrap();
+        let err = secret.as_mut().update_with(&mapped(true)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 16]));
+        std::fs::remove_file(path).unwrap();
+    }
+}
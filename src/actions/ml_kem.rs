@@ -0,0 +1,263 @@
+//! Post-quantum key encapsulation with ML-KEM (FIPS 203).
+//!
+//! The decapsulation key is held in a secret as its 64-byte seed `d ‖ z`, from which the
+//! expanded key is derived each time it is used, and zeroized after it. The shared secrets
+//! are written directly into a secret, on both sides.
+
+use std::{io, pin::Pin};
+
+use ::ml_kem::{
+    Decapsulate, Encapsulate, FromSeed, KeyExport, MlKem512, MlKem768, MlKem1024, TryKeyInit,
+};
+use zeroize::Zeroize;
+
+use super::GenerateRandom;
+use crate::{
+    api::{Secret, SecretReader, SecretUpdater, Unsizeable},
+    memory::{Global, SecureAlloc},
+};
+
+/// Data of a secret holding the seed of an ML-KEM decapsulation key, as `d ‖ z`, the same
+/// for all the parameter sets.
+///
+/// It is generated with [`GenerateRandom`].
+pub struct MlKemSeed([u8; 64]);
+
+impl Default for MlKemSeed {
+    fn default() -> Self {
+        MlKemSeed([0; 64])
+    }
+}
+
+impl Zeroize for MlKemSeed {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Unsizeable for MlKemSeed {
+    type Unsized = [u8];
+
+    fn get_unsized(&self) -> &Self::Unsized {
+        &self.0
+    }
+
+    fn get_unsized_mut(&mut self) -> &mut Self::Unsized {
+        &mut self.0
+    }
+}
+
+/// Fails if the OS CSPRNG cannot be used, in which case the secret is left unchanged.
+impl SecretUpdater<MlKemSeed, io::Result<()>> for GenerateRandom {
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        getrandom::getrandom(sec).map_err(io::Error::from)
+    }
+}
+
+/// Parameter sets of ML-KEM.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MlKemVariant {
+    /// ML-KEM-512, of security category 1.
+    MlKem512,
+    /// ML-KEM-768, of security category 3, as recommended by FIPS 203.
+    #[default]
+    MlKem768,
+    /// ML-KEM-1024, of security category 5.
+    MlKem1024,
+}
+
+/// Returns the encapsulation (public) key of the decapsulation key whose seed is held in
+/// the secret.
+pub struct MlKemPublicKey(pub MlKemVariant);
+
+/// Encapsulates a fresh shared secret to an encapsulation key, writing it directly into
+/// the secret.
+pub struct MlKemEncapsulate {
+    pub encapsulation_key: Vec<u8>,
+    pub variant: MlKemVariant,
+}
+
+/// Decapsulates a shared secret (as encapsulated by [`MlKemEncapsulate`]) with the
+/// decapsulation key whose seed is held in another secret, writing it directly into the
+/// secret.
+///
+/// # Security
+///
+/// ML-KEM rejects altered ciphertexts implicitly: they decapsulate without any error, to a
+/// shared secret unrelated to the one of the sender. Confirm the shared secret, for
+/// instance by decrypting with it, before trusting it.
+pub struct MlKemDecapsulate<'a, Alloc: SecureAlloc = Global> {
+    pub decapsulation_key: Pin<&'a Secret<MlKemSeed, Alloc>>,
+    pub ciphertext: Vec<u8>,
+    pub variant: MlKemVariant,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Derives the key pair of a seed. The decapsulation key is zeroized on drop.
+fn key_pair<K: FromSeed>(seed: &[u8]) -> (K::DecapsulationKey, K::EncapsulationKey) {
+    K::from_seed(seed.try_into().expect("seeds are 64 bytes long"))
+}
+
+fn public_key<K: FromSeed>(seed: &[u8]) -> Vec<u8> {
+    key_pair::<K>(seed).1.to_bytes().to_vec()
+}
+
+fn encapsulate<K: FromSeed>(encapsulation_key: &[u8], shared: &mut [u8]) -> io::Result<Vec<u8>> {
+    let (ciphertext, mut key) = K::EncapsulationKey::new_from_slice(encapsulation_key)
+        .map_err(|_| invalid_data("invalid ML-KEM encapsulation key"))?
+        .encapsulate();
+    shared.copy_from_slice(&key);
+    key.as_mut_slice().zeroize();
+    Ok(ciphertext.to_vec())
+}
+
+fn decapsulate<K: FromSeed>(seed: &[u8], ciphertext: &[u8], shared: &mut [u8]) -> io::Result<()>
+where
+    K::DecapsulationKey: Decapsulate,
+{
+    let mut key = key_pair::<K>(seed)
+        .0
+        .decapsulate_slice(ciphertext)
+        .map_err(|_| invalid_data("ML-KEM ciphertext has an invalid length"))?;
+    shared.copy_from_slice(&key);
+    key.as_mut_slice().zeroize();
+    Ok(())
+}
+
+impl SecretReader<MlKemSeed, Vec<u8>> for MlKemPublicKey {
+    fn read(&self, sec: &[u8]) -> Vec<u8> {
+        match self.0 {
+            MlKemVariant::MlKem512 => public_key::<MlKem512>(sec),
+            MlKemVariant::MlKem768 => public_key::<MlKem768>(sec),
+            MlKemVariant::MlKem1024 => public_key::<MlKem1024>(sec),
+        }
+    }
+}
+
+/// Returns the ciphertext, to be sent to the holder of the decapsulation key.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the encapsulation key is invalid. On
+/// failure, the secret is zeroized.
+impl SecretUpdater<[u8; 32], io::Result<Vec<u8>>> for MlKemEncapsulate {
+    fn update(&self, sec: &mut [u8]) -> io::Result<Vec<u8>> {
+        let key = &self.encapsulation_key;
+        let result = match self.variant {
+            MlKemVariant::MlKem512 => encapsulate::<MlKem512>(key, sec),
+            MlKemVariant::MlKem768 => encapsulate::<MlKem768>(key, sec),
+            MlKemVariant::MlKem1024 => encapsulate::<MlKem1024>(key, sec),
+        };
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the ciphertext has an invalid length. On
+/// failure, the secret is zeroized.
+impl<Alloc: SecureAlloc> SecretUpdater<[u8; 32], io::Result<()>> for MlKemDecapsulate<'_, Alloc> {
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let result = self.decapsulation_key.read_with_mut(&mut |seed: &[u8]| {
+            let ciphertext = &self.ciphertext;
+            match self.variant {
+                MlKemVariant::MlKem512 => decapsulate::<MlKem512>(seed, ciphertext, sec),
+                MlKemVariant::MlKem768 => decapsulate::<MlKem768>(seed, ciphertext, sec),
+                MlKemVariant::MlKem1024 => decapsulate::<MlKem1024>(seed, ciphertext, sec),
+            }
+        });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+
+    #[test]
+    fn test_ml_kem() {
+        let mut seed = pin!(Secret::<MlKemSeed>::new());
+        seed.as_mut().update_with(&GenerateRandom).unwrap();
+        let mut sent = pin!(Secret::<[u8; 32]>::new());
+        let mut received = pin!(Secret::<[u8; 32]>::new());
+        let read = |sec: Pin<&Secret<[u8; 32]>>| sec.read_with(&|sec: &[u8]| sec.to_vec());
+
+        for (variant, len) in [
+            (MlKemVariant::MlKem512, 800),
+            (MlKemVariant::MlKem768, 1184),
+            (MlKemVariant::MlKem1024, 1568),
+        ] {
+            let encapsulation_key = seed.as_ref().read_with(&MlKemPublicKey(variant));
+            assert_eq!(encapsulation_key.len(), len);
+            let ciphertext = sent
+                .as_mut()
+                .update_with(&MlKemEncapsulate {
+                    encapsulation_key: encapsulation_key.clone(),
+                    variant,
+                })
+                .unwrap();
+            let decapsulate = |ciphertext| MlKemDecapsulate {
+                decapsulation_key: seed.as_ref(),
+                ciphertext,
+                variant,
+            };
+            received
+                .as_mut()
+                .update_with(&decapsulate(ciphertext.clone()))
+                .unwrap();
+            assert_ne!(read(sent.as_ref()), [0; 32]);
+            assert_eq!(read(sent.as_ref()), read(received.as_ref()));
+
+            // An altered ciphertext decapsulates to another shared secret.
+            let mut altered = ciphertext.clone();
+            altered[0] ^= 1;
+            received
+                .as_mut()
+                .update_with(&decapsulate(altered))
+                .unwrap();
+            assert_ne!(read(sent.as_ref()), read(received.as_ref()));
+
+            let err = received
+                .as_mut()
+                .update_with(&decapsulate(ciphertext[1..].to_vec()))
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(read(received.as_ref()), [0; 32]);
+            let err = sent
+                .as_mut()
+                .update_with(&MlKemEncapsulate {
+                    encapsulation_key: encapsulation_key[1..].to_vec(),
+                    variant,
+                })
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        // Against the `ml-kem` crate, with the same seed.
+        let bytes = seed.as_ref().read_with(&|sec: &[u8]| sec.to_vec());
+        let (_, encapsulation_key) = MlKem768::from_seed(bytes[..].try_into().unwrap());
+        assert_eq!(
+            seed.as_ref()
+                .read_with(&MlKemPublicKey(MlKemVariant::MlKem768)),
+            encapsulation_key.to_bytes().to_vec()
+        );
+        let (ciphertext, key) = encapsulation_key.encapsulate();
+        received
+            .as_mut()
+            .update_with(&MlKemDecapsulate {
+                decapsulation_key: seed.as_ref(),
+                ciphertext: ciphertext.to_vec(),
+                variant: MlKemVariant::MlKem768,
+            })
+            .unwrap();
+        assert_eq!(read(received.as_ref()), key.to_vec());
+    }
+}
@@ -0,0 +1,104 @@
+//! Secrets sealed by a key of the platform keystore of mobile devices: the Secure Enclave on
+//! iOS (and macOS), the Android Keystore on Android. Keys are generated in the keystore and
+//! never leave it, so that a sealed blob can only be unsealed on the device which sealed it.
+//!
+//! The API is the same on both platforms, so that code shared by mobile applications does
+//! not depend on it. Keys are identified by a label, unique within the application.
+//!
+//! On iOS and macOS, keys are P-256 keys of the Secure Enclave, the secret being encrypted
+//! with ECIES (`kSecKeyAlgorithmECIESEncryptionCofactorVariableIVX963SHA256AESGCM`). The
+//! binary must be code signed with a keychain access group entitlement.
+//!
+//! On Android, keys are AES-256 keys of the Android Keystore (in its TEE), with GCM. The
+//! Java VM is found through [`ndk_context`], which must have been initialized, as it is by
+//! `android-activity`.
+
+use std::io;
+
+use zeroize::Zeroize;
+
+use crate::api::{SecretReader, SecretUpdater};
+
+#[cfg(target_os = "android")]
+mod android;
+#[cfg(target_vendor = "apple")]
+mod enclave;
+
+#[cfg(target_os = "android")]
+use android::Key;
+#[cfg(target_vendor = "apple")]
+use enclave::Key;
+
+/// A key of the platform keystore.
+pub struct KeystoreKey(Key);
+
+impl KeystoreKey {
+    /// Generates a key under this label.
+    ///
+    /// Fails with [`io::ErrorKind::AlreadyExists`] if there is already one.
+    pub fn generate(label: &str) -> io::Result<Self> {
+        match Key::open(label) {
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a keystore key has this label",
+            )),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Key::generate(label).map(Self),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Finds the key with this label.
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if there is none.
+    pub fn open(label: &str) -> io::Result<Self> {
+        Key::open(label).map(Self)
+    }
+
+    /// Deletes the key from the keystore: the blobs it sealed can no longer be unsealed.
+    pub fn delete(self) -> io::Result<()> {
+        self.0.delete()
+    }
+}
+
+/// Seals the secret with a keystore key, returning the sealed blob.
+pub struct SealToKeystore<'a>(pub &'a KeystoreKey);
+
+/// Unseals a blob sealed by [`SealToKeystore`] into the secret.
+pub struct UnsealFromKeystore<'a> {
+    pub key: &'a KeystoreKey,
+    pub blob: &'a [u8],
+}
+
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for SealToKeystore<'_> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        self.0.0.encrypt(sec)
+    }
+}
+
+/// Returns the length of the secret.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the blob was not sealed by this key or was
+/// altered, and with [`io::ErrorKind::InvalidInput`] if the secret is too small. On failure,
+/// the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UnsealFromKeystore<'_> {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = self.key.0.decrypt(self.blob, sec);
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Copies the plaintext into the start of `sec`.
+fn copy_plaintext(plaintext: &[u8], sec: &mut [u8]) -> io::Result<usize> {
+    sec.get_mut(..plaintext.len())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sealed secret is larger than the secret",
+            )
+        })?
+        .copy_from_slice(plaintext);
+    Ok(plaintext.len())
+}
@@ -0,0 +1,304 @@
+use std::io;
+
+use jni::{
+    JNIEnv, JavaVM,
+    objects::{GlobalRef, JByteArray, JObject, JValue},
+};
+use zeroize::Zeroizing;
+
+use super::copy_plaintext;
+
+const PROVIDER: &str = "AndroidKeyStore";
+
+const TRANSFORMATION: &str = "AES/GCM/NoPadding";
+
+/// Length of the IVs generated by the keystore, prepended to the ciphertexts.
+const IV_LEN: usize = 12;
+
+/// `Cipher.ENCRYPT_MODE` and `Cipher.DECRYPT_MODE`
+const ENCRYPT_MODE: i32 = 1;
+const DECRYPT_MODE: i32 = 2;
+
+/// `KeyProperties.PURPOSE_ENCRYPT | KeyProperties.PURPOSE_DECRYPT`
+const PURPOSES: i32 = 3;
+
+/// Local references created by each call.
+const FRAME_CAPACITY: i32 = 16;
+
+/// Maps the pending Java exception, if any, to an error, by its class.
+fn java_error(env: &mut JNIEnv, err: jni::errors::Error) -> io::Error {
+    let jni::errors::Error::JavaException = err else {
+        return io::Error::other(err);
+    };
+    let Ok(exception) = env.exception_occurred() else {
+        return io::Error::other(err);
+    };
+    let _ = env.exception_clear();
+    let class = (|| {
+        let class = env
+            .call_method(&exception, "getClass", "()Ljava/lang/Class;", &[])?
+            .l()?;
+        let name = env
+            .call_method(&class, "getName", "()Ljava/lang/String;", &[])?
+            .l()?;
+        env.get_string(&name.into()).map(String::from)
+    })();
+    let _ = env.exception_clear();
+    let class = class.unwrap_or_default();
+    let kind = match class.rsplit('.').next().unwrap_or_default() {
+        "AEADBadTagException" => io::ErrorKind::InvalidData,
+        "UserNotAuthenticatedException" | "KeyPermanentlyInvalidatedException" => {
+            io::ErrorKind::PermissionDenied
+        }
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, format!("Android Keystore error ({class})"))
+}
+
+/// Runs `f` with the JNI environment of the current thread, in its own frame of local
+/// references.
+fn with_env<T>(
+    vm: &JavaVM,
+    f: impl FnOnce(&mut JNIEnv) -> jni::errors::Result<T>,
+) -> io::Result<T> {
+    let mut env = vm.attach_current_thread().map_err(io::Error::other)?;
+    let result = env.with_local_frame(FRAME_CAPACITY, |env| f(env));
+    result.map_err(|err| java_error(&mut env, err))
+}
+
+fn vm() -> io::Result<JavaVM> {
+    let context = ndk_context::android_context();
+    unsafe { JavaVM::from_raw(context.vm().cast()) }.map_err(io::Error::other)
+}
+
+/// Zeroizes a Java byte array, in case it is not collected (and overwritten) soon.
+fn wipe(env: &mut JNIEnv, array: &JByteArray) -> jni::errors::Result<()> {
+    env.call_static_method(
+        "java/util/Arrays",
+        "fill",
+        "([BB)V",
+        &[JValue::Object(array), JValue::Byte(0)],
+    )
+    .map(drop)
+}
+
+/// Runs `cipher.doFinal(input)`, and wipes `input`.
+fn do_final<'local>(
+    env: &mut JNIEnv<'local>,
+    cipher: &JObject,
+    input: &[u8],
+) -> jni::errors::Result<JByteArray<'local>> {
+    let input = env.byte_array_from_slice(input)?;
+    let output = env
+        .call_method(cipher, "doFinal", "([B)[B", &[JValue::Object(&input)])
+        .and_then(|output| output.l());
+    // A pending exception is set aside while wiping, as no Java method can be called then.
+    let exception = match output {
+        Err(jni::errors::Error::JavaException) => {
+            let exception = env.exception_occurred()?;
+            env.exception_clear()?;
+            Some(exception)
+        }
+        _ => None,
+    };
+    wipe(env, &input)?;
+    if let Some(exception) = exception {
+        env.throw(exception)?;
+    }
+    Ok(output?.into())
+}
+
+/// Returns the loaded `AndroidKeyStore`.
+fn keystore<'local>(env: &mut JNIEnv<'local>) -> jni::errors::Result<JObject<'local>> {
+    let provider = env.new_string(PROVIDER)?;
+    let store = env
+        .call_static_method(
+            "java/security/KeyStore",
+            "getInstance",
+            "(Ljava/lang/String;)Ljava/security/KeyStore;",
+            &[JValue::Object(&provider)],
+        )?
+        .l()?;
+    env.call_method(
+        &store,
+        "load",
+        "(Ljava/security/KeyStore$LoadStoreParameter;)V",
+        &[JValue::Object(&JObject::null())],
+    )?;
+    Ok(store)
+}
+
+fn cipher<'local>(env: &mut JNIEnv<'local>) -> jni::errors::Result<JObject<'local>> {
+    let transformation = env.new_string(TRANSFORMATION)?;
+    env.call_static_method(
+        "javax/crypto/Cipher",
+        "getInstance",
+        "(Ljava/lang/String;)Ljavax/crypto/Cipher;",
+        &[JValue::Object(&transformation)],
+    )?
+    .l()
+}
+
+/// A secret key of the Android Keystore.
+pub(super) struct Key {
+    vm: JavaVM,
+    key: GlobalRef,
+    label: String,
+}
+
+impl Key {
+    pub(super) fn generate(label: &str) -> io::Result<Self> {
+        let vm = vm()?;
+        let key = with_env(&vm, |env| {
+            let algorithm = env.new_string("AES")?;
+            let provider = env.new_string(PROVIDER)?;
+            let generator = env
+                .call_static_method(
+                    "javax/crypto/KeyGenerator",
+                    "getInstance",
+                    "(Ljava/lang/String;Ljava/lang/String;)Ljavax/crypto/KeyGenerator;",
+                    &[JValue::Object(&algorithm), JValue::Object(&provider)],
+                )?
+                .l()?;
+            let alias = env.new_string(label)?;
+            let builder = env.new_object(
+                "android/security/keystore/KeyGenParameterSpec$Builder",
+                "(Ljava/lang/String;I)V",
+                &[JValue::Object(&alias), JValue::Int(PURPOSES)],
+            )?;
+            let returns_builder = "Landroid/security/keystore/KeyGenParameterSpec$Builder;";
+            for (method, value) in [
+                ("setBlockModes", "GCM"),
+                ("setEncryptionPaddings", "NoPadding"),
+            ] {
+                let value = env.new_string(value)?;
+                let values = env.new_object_array(1, "java/lang/String", &value)?;
+                env.call_method(
+                    &builder,
+                    method,
+                    format!("([Ljava/lang/String;){returns_builder}"),
+                    &[JValue::Object(&values)],
+                )?;
+            }
+            env.call_method(
+                &builder,
+                "setKeySize",
+                format!("(I){returns_builder}"),
+                &[JValue::Int(256)],
+            )?;
+            let spec = env
+                .call_method(
+                    &builder,
+                    "build",
+                    "()Landroid/security/keystore/KeyGenParameterSpec;",
+                    &[],
+                )?
+                .l()?;
+            env.call_method(
+                &generator,
+                "init",
+                "(Ljava/security/spec/AlgorithmParameterSpec;)V",
+                &[JValue::Object(&spec)],
+            )?;
+            let key = env
+                .call_method(&generator, "generateKey", "()Ljavax/crypto/SecretKey;", &[])?
+                .l()?;
+            env.new_global_ref(key)
+        })?;
+        Ok(Key {
+            vm,
+            key,
+            label: label.to_owned(),
+        })
+    }
+
+    pub(super) fn open(label: &str) -> io::Result<Self> {
+        let vm = vm()?;
+        let key = with_env(&vm, |env| {
+            let store = keystore(env)?;
+            let alias = env.new_string(label)?;
+            let key = env
+                .call_method(
+                    &store,
+                    "getKey",
+                    "(Ljava/lang/String;[C)Ljava/security/Key;",
+                    &[JValue::Object(&alias), JValue::Object(&JObject::null())],
+                )?
+                .l()?;
+            match key.is_null() {
+                true => Ok(None),
+                false => env.new_global_ref(key).map(Some),
+            }
+        })?;
+        let key =
+            key.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such keystore key"))?;
+        Ok(Key {
+            vm,
+            key,
+            label: label.to_owned(),
+        })
+    }
+
+    pub(super) fn delete(self) -> io::Result<()> {
+        with_env(&self.vm, |env| {
+            let store = keystore(env)?;
+            let alias = env.new_string(&self.label)?;
+            env.call_method(
+                &store,
+                "deleteEntry",
+                "(Ljava/lang/String;)V",
+                &[JValue::Object(&alias)],
+            )
+            .map(drop)
+        })
+    }
+
+    /// Encrypts with an IV generated by the keystore, which refuses IVs chosen by the
+    /// caller.
+    pub(super) fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        with_env(&self.vm, |env| {
+            let cipher = cipher(env)?;
+            env.call_method(
+                &cipher,
+                "init",
+                "(ILjava/security/Key;)V",
+                &[JValue::Int(ENCRYPT_MODE), JValue::Object(self.key.as_obj())],
+            )?;
+            let iv: JByteArray = env.call_method(&cipher, "getIV", "()[B", &[])?.l()?.into();
+            let ciphertext = do_final(env, &cipher, plaintext)?;
+            let mut blob = env.convert_byte_array(&iv)?;
+            blob.extend(env.convert_byte_array(&ciphertext)?);
+            Ok(blob)
+        })
+    }
+
+    pub(super) fn decrypt(&self, blob: &[u8], sec: &mut [u8]) -> io::Result<usize> {
+        let (iv, ciphertext) = blob.split_at_checked(IV_LEN).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "sealed blob is too short")
+        })?;
+        let plaintext = with_env(&self.vm, |env| {
+            let cipher = cipher(env)?;
+            let iv = env.byte_array_from_slice(iv)?;
+            let spec = env.new_object(
+                "javax/crypto/spec/GCMParameterSpec",
+                "(I[B)V",
+                &[JValue::Int(128), JValue::Object(&iv)],
+            )?;
+            env.call_method(
+                &cipher,
+                "init",
+                "(ILjava/security/Key;Ljava/security/spec/AlgorithmParameterSpec;)V",
+                &[
+                    JValue::Int(DECRYPT_MODE),
+                    JValue::Object(self.key.as_obj()),
+                    JValue::Object(&spec),
+                ],
+            )?;
+            let output = do_final(env, &cipher, ciphertext)?;
+            let plaintext = env.convert_byte_array(&output).map(Zeroizing::new);
+            wipe(env, &output)?;
+            plaintext
+        })?;
+        copy_plaintext(&plaintext, sec)
+    }
+}
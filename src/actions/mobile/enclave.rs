@@ -0,0 +1,77 @@
+use std::io;
+
+use security_framework::{
+    item::{ItemClass, ItemSearchOptions, KeyClass, Location, Reference, SearchResult},
+    key::{Algorithm, GenerateKeyOptions, KeyType, SecKey, Token},
+};
+use zeroize::Zeroizing;
+
+use super::copy_plaintext;
+
+/// `errSecItemNotFound`
+const ITEM_NOT_FOUND: i32 = -25300;
+
+const ALGORITHM: Algorithm = Algorithm::ECIESEncryptionCofactorVariableIVX963SHA256AESGCM;
+
+/// `CFError` is neither `Send` nor `Sync`, only its description is kept.
+fn cf_error(err: impl std::fmt::Display) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+/// A private key of the Secure Enclave.
+pub(super) struct Key(SecKey);
+
+impl Key {
+    pub(super) fn generate(label: &str) -> io::Result<Self> {
+        let mut options = GenerateKeyOptions::default();
+        options
+            .set_key_type(KeyType::ec_sec_prime_random())
+            .set_size_in_bits(256)
+            .set_label(label)
+            .set_token(Token::SecureEnclave)
+            .set_location(Location::DataProtectionKeychain);
+        SecKey::new(&options).map(Key).map_err(cf_error)
+    }
+
+    pub(super) fn open(label: &str) -> io::Result<Self> {
+        let results = ItemSearchOptions::new()
+            .class(ItemClass::key())
+            .key_class(KeyClass::private())
+            .label(label)
+            .load_refs(true)
+            .search()
+            .map_err(|err| match err.code() {
+                ITEM_NOT_FOUND => io::Error::new(io::ErrorKind::NotFound, err),
+                _ => io::Error::other(err),
+            })?;
+        results
+            .into_iter()
+            .find_map(|result| match result {
+                SearchResult::Ref(Reference::Key(key)) => Some(Key(key)),
+                _ => None,
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such keystore key"))
+    }
+
+    pub(super) fn delete(self) -> io::Result<()> {
+        self.0.delete().map_err(cf_error)
+    }
+
+    pub(super) fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let public = self
+            .0
+            .public_key()
+            .ok_or_else(|| io::Error::other("no public key for this Secure Enclave key"))?;
+        public.encrypt_data(ALGORITHM, plaintext).map_err(cf_error)
+    }
+
+    pub(super) fn decrypt(&self, blob: &[u8], sec: &mut [u8]) -> io::Result<usize> {
+        // The blob is not authentic, or not sealed by this key.
+        let plaintext = self
+            .0
+            .decrypt_data(ALGORITHM, blob)
+            .map(Zeroizing::new)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        copy_plaintext(&plaintext, sec)
+    }
+}
@@ -0,0 +1,213 @@
+//! Secrets mounted as files in a directory, by Docker (`/run/secrets`) or Kubernetes (secret
+//! and projected volumes).
+//!
+//! Kubernetes updates volumes atomically: entries are symbolic links through `..data`, itself
+//! a link to a timestamped directory, replaced at once by a rename. Entries are therefore
+//! always read in a consistent version, and a [`Watcher`] can notice the rotation of secrets.
+
+use std::{
+    fs::File,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use super::read_to_end;
+use crate::api::SecretUpdater;
+
+/// Name of the link to the current version of a Kubernetes volume.
+const DATA: &str = "..data";
+
+/// Directory of mounted secrets.
+#[derive(Clone, Debug)]
+pub struct MountedSecrets {
+    pub dir: PathBuf,
+}
+
+/// Loads an entry of a directory of mounted secrets.
+pub struct UpdateSecretFromMountedSecret {
+    pub dir: PathBuf,
+    pub name: String,
+}
+
+/// Changes of a directory of mounted secrets, through inotify.
+pub struct Watcher {
+    fd: OwnedFd,
+}
+
+impl MountedSecrets {
+    /// Secrets of a Docker service or container, in `/run/secrets`.
+    pub fn docker() -> Self {
+        MountedSecrets {
+            dir: PathBuf::from("/run/secrets"),
+        }
+    }
+
+    /// Lists the names of the entries, leaving out hidden files, including the internal
+    /// versions of Kubernetes volumes.
+    pub fn names(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            if let Some(name) = name.to_str().filter(|name| !name.starts_with('.')) {
+                names.push(name.to_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Returns the updater loading the entry `name`.
+    pub fn entry(&self, name: impl Into<String>) -> UpdateSecretFromMountedSecret {
+        UpdateSecretFromMountedSecret {
+            dir: self.dir.clone(),
+            name: name.into(),
+        }
+    }
+
+    /// Watches the directory, to reload its entries when they are updated.
+    pub fn watch(&self) -> io::Result<Watcher> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let dir = std::ffi::CString::new(self.dir.as_os_str().as_encoded_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains NUL"))?;
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Watcher { fd })
+    }
+}
+
+impl Watcher {
+    /// Waits until an entry is updated, for at most `timeout` if any, and returns whether
+    /// one was. Entries should then all be reloaded, as Kubernetes updates them at once.
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        loop {
+            let timeout = deadline.map_or(-1, |deadline| {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                timeout.as_millis().try_into().unwrap_or(libc::c_int::MAX)
+            });
+            match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+                -1 => match io::Error::last_os_error() {
+                    err if err.kind() == io::ErrorKind::Interrupted => continue,
+                    err => return Err(err),
+                },
+                0 => return Ok(false),
+                _ => {}
+            }
+            if self.read_events()? {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Reads the pending events, returning whether an entry was updated.
+    fn read_events(&self) -> io::Result<bool> {
+        // Aligned for `inotify_event`, and large enough for at least one event.
+        let mut buffer = [0u32; 1024];
+        let len = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                buffer.as_mut_ptr().cast(),
+                size_of_val(&buffer),
+            )
+        };
+        if len == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let bytes =
+            unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), len as usize) };
+        let header = size_of::<libc::inotify_event>();
+        let mut updated = false;
+        let mut offset = 0;
+        while offset + header <= bytes.len() {
+            let event = unsafe {
+                bytes
+                    .as_ptr()
+                    .add(offset)
+                    .cast::<libc::inotify_event>()
+                    .read_unaligned()
+            };
+            let name = &bytes[offset + header..][..event.len as usize];
+            let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+            updated |= name == DATA.as_bytes() || !name.starts_with(b".");
+            offset += header + event.len as usize;
+        }
+        Ok(updated)
+    }
+}
+
+/// Returns the length of the entry.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the name is not the one of an entry (empty,
+/// hidden or with a `/`), and if the entry is larger than the secret, which is then zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromMountedSecret {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        if self.name.is_empty() || self.name.contains('/') || self.name.starts_with('.') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid secret entry name",
+            ));
+        }
+        read_to_end(File::open(self.dir.join(&self.name))?, sec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{os::unix::fs::symlink, pin::pin};
+
+    use super::*;
+    use crate::api::Secret;
+
+    /// Writes a version of a Kubernetes volume with one `key` entry, and makes it current.
+    fn write_version(dir: &std::path::Path, version: &str, key: &[u8]) {
+        std::fs::create_dir(dir.join(version)).unwrap();
+        std::fs::write(dir.join(version).join("key"), key).unwrap();
+        symlink(version, dir.join("..data_tmp")).unwrap();
+        std::fs::rename(dir.join("..data_tmp"), dir.join(DATA)).unwrap();
+    }
+
+    #[test]
+    fn test_mounted_secrets() {
+        let dir = std::env::temp_dir().join(format!("secrust-mounted-{}", std::process::id()));
+        std::fs::create_dir(&dir).unwrap();
+        write_version(&dir, "..2024_01_01", b"first");
+        symlink("..data/key", dir.join("key")).unwrap();
+        let secrets = MountedSecrets { dir: dir.clone() };
+        assert_eq!(secrets.names().unwrap(), ["key"]);
+
+        let mut secret = pin!(Secret::<[u8; 8]>::new());
+        let len = secret.as_mut().update_with(&secrets.entry("key")).unwrap();
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(&sec[..len], b"first"));
+        let err = secret
+            .as_mut()
+            .update_with(&secrets.entry(DATA))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let watcher = secrets.watch().unwrap();
+        assert!(!watcher.wait(Some(Duration::ZERO)).unwrap());
+        write_version(&dir, "..2024_01_02", b"second");
+        assert!(watcher.wait(Some(Duration::from_secs(5))).unwrap());
+        let len = secret.as_mut().update_with(&secrets.entry("key")).unwrap();
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(&sec[..len], b"second"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
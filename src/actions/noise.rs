@@ -0,0 +1,447 @@
+//! Noise handshakes (`Noise_XX_25519_ChaChaPoly_SHA256`), with the X25519 static key `s`
+//! held in a secret, interoperable with other implementations of the Noise Protocol
+//! Framework, such as `snow`.
+//!
+//! With the XX pattern, both parties send their static public key during the handshake,
+//! encrypted: check the one of the peer (with [`NoiseHandshake::remote_static`]) against
+//! the expected one before trusting it. Once the three messages are exchanged, the
+//! handshake turns into a [`NoiseTransport`], whose session keys live in fresh secrets.
+
+use std::{io, pin::Pin};
+
+use chacha20poly1305::{
+    ChaCha20Poly1305, KeyInit, Nonce,
+    aead::{Aead, Payload},
+};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+use zeroize::Zeroizing;
+
+use super::X25519PublicKey;
+use crate::{
+    api::Secret,
+    memory::{Global, SecureAlloc},
+};
+
+/// Name of the protocol, of exactly 32 bytes, the length of the hash.
+const PROTOCOL_NAME: &[u8; 32] = b"Noise_XX_25519_ChaChaPoly_SHA256";
+
+/// Maximum length of a Noise message.
+const MAX_MESSAGE_LEN: usize = 65535;
+
+const TAG_LEN: usize = 16;
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Nonce of ChaChaPoly, the counter in little endian after 32 zero bits.
+fn nonce(n: u64) -> io::Result<Nonce> {
+    // The last nonce is reserved by the specification.
+    if n == u64::MAX {
+        return Err(invalid_input("Noise nonces are exhausted"));
+    }
+    let mut nonce = Nonce::default();
+    nonce[4..].copy_from_slice(&n.to_le_bytes());
+    Ok(nonce)
+}
+
+fn encrypt(key: &[u8], n: u64, aad: &[u8], msg: &[u8]) -> io::Result<Vec<u8>> {
+    if msg.len() + TAG_LEN > MAX_MESSAGE_LEN {
+        return Err(invalid_input("Noise message is too large"));
+    }
+    ChaCha20Poly1305::new(key.into())
+        .encrypt(&nonce(n)?, Payload { msg, aad })
+        .map_err(|_| invalid_input("Noise message is too large"))
+}
+
+fn decrypt(key: &[u8], n: u64, aad: &[u8], msg: &[u8]) -> io::Result<Vec<u8>> {
+    ChaCha20Poly1305::new(key.into())
+        .decrypt(&nonce(n)?, Payload { msg, aad })
+        .map_err(|_| invalid_data("Noise message was altered, or comes from another peer"))
+}
+
+/// Chaining key, handshake hash and cipher state of a handshake.
+struct SymmetricState {
+    ck: Zeroizing<[u8; 32]>,
+    h: [u8; 32],
+    k: Option<Zeroizing<[u8; 32]>>,
+    n: u64,
+}
+
+impl SymmetricState {
+    fn new(prologue: &[u8]) -> Self {
+        let mut state = SymmetricState {
+            ck: Zeroizing::new(*PROTOCOL_NAME),
+            h: *PROTOCOL_NAME,
+            k: None,
+            n: 0,
+        };
+        state.mix_hash(prologue);
+        state
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.h = Sha256::new()
+            .chain_update(self.h)
+            .chain_update(data)
+            .finalize()
+            .into();
+    }
+
+    /// Returns the two outputs of HKDF, as `(ck, k)` when mixing a key, or as the keys of
+    /// the initiator and of the responder when splitting.
+    fn hkdf(&self, ikm: &[u8]) -> Zeroizing<[u8; 64]> {
+        let mut okm = Zeroizing::new([0; 64]);
+        Hkdf::<Sha256>::new(Some(&*self.ck), ikm)
+            .expand(&[], &mut *okm)
+            .expect("64 bytes is a valid length");
+        okm
+    }
+
+    fn mix_key(&mut self, shared: &SharedSecret) {
+        let okm = self.hkdf(shared.as_bytes());
+        self.ck.copy_from_slice(&okm[..32]);
+        let mut k = Zeroizing::new([0; 32]);
+        k.copy_from_slice(&okm[32..]);
+        self.k = Some(k);
+        self.n = 0;
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let ciphertext = match &self.k {
+            Some(k) => encrypt(&**k, self.n, &self.h, plaintext)?,
+            None => plaintext.to_vec(),
+        };
+        self.n += u64::from(self.k.is_some());
+        self.mix_hash(&ciphertext);
+        Ok(ciphertext)
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        let plaintext = match &self.k {
+            Some(k) => decrypt(&**k, self.n, &self.h, ciphertext)?,
+            None => ciphertext.to_vec(),
+        };
+        self.n += u64::from(self.k.is_some());
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+}
+
+/// A Noise XX handshake, with the X25519 private key held in a secret as static key.
+///
+/// The initiator writes the first and third messages, and reads the second; the responder
+/// does the opposite. Each message may carry a payload, encrypted from the second message
+/// on, but only authenticated once the handshake is finished.
+///
+/// # Security
+///
+/// The static key is only copied for the Diffie-Hellman operations, and zeroized right
+/// after, as are the ephemeral key, the chaining key and the handshake keys on drop. On
+/// failure, the handshake must be abandoned.
+pub struct NoiseHandshake<'a, Alloc: SecureAlloc = Global> {
+    static_key: Pin<&'a Secret<[u8; 32], Alloc>>,
+    initiator: bool,
+    symmetric: SymmetricState,
+    e: Option<StaticSecret>,
+    re: Option<PublicKey>,
+    rs: Option<PublicKey>,
+    /// Number of messages written or read.
+    step: u8,
+}
+
+impl<'a, Alloc: SecureAlloc> NoiseHandshake<'a, Alloc> {
+    /// Starts a handshake as initiator. The prologue, such as a protocol version, is data
+    /// both parties must agree on, without sending it.
+    pub fn initiator(static_key: Pin<&'a Secret<[u8; 32], Alloc>>, prologue: &[u8]) -> Self {
+        Self::new(static_key, prologue, true)
+    }
+
+    /// Starts a handshake as responder.
+    pub fn responder(static_key: Pin<&'a Secret<[u8; 32], Alloc>>, prologue: &[u8]) -> Self {
+        Self::new(static_key, prologue, false)
+    }
+
+    fn new(static_key: Pin<&'a Secret<[u8; 32], Alloc>>, prologue: &[u8], initiator: bool) -> Self {
+        NoiseHandshake {
+            static_key,
+            initiator,
+            symmetric: SymmetricState::new(prologue),
+            e: None,
+            re: None,
+            rs: None,
+            step: 0,
+        }
+    }
+
+    /// Returns the static public key of the peer, once received.
+    pub fn remote_static(&self) -> Option<[u8; 32]> {
+        self.rs.map(|rs| rs.to_bytes())
+    }
+
+    /// Returns whether the three messages of the handshake were exchanged.
+    pub fn is_finished(&self) -> bool {
+        self.step == 3
+    }
+
+    /// Whether it is the turn of this party to write.
+    fn writes(&self) -> bool {
+        self.initiator == self.step.is_multiple_of(2)
+    }
+
+    fn dh_ephemeral(&self, public: Option<PublicKey>) -> io::Result<SharedSecret> {
+        let e = self.e.as_ref().expect("ephemeral key is set");
+        let shared = e.diffie_hellman(&public.expect("remote key is set"));
+        match shared.was_contributory() {
+            true => Ok(shared),
+            false => Err(invalid_data("Noise public key is of small order")),
+        }
+    }
+
+    fn dh_static(&self, public: Option<PublicKey>) -> io::Result<SharedSecret> {
+        let public = public.expect("remote key is set");
+        let shared = self.static_key.read_with(&|sec: &[u8]| {
+            let sec: [u8; 32] = sec.try_into().expect("keys are 32 bytes long");
+            StaticSecret::from(sec).diffie_hellman(&public)
+        });
+        match shared.was_contributory() {
+            true => Ok(shared),
+            false => Err(invalid_data("Noise public key is of small order")),
+        }
+    }
+
+    /// Writes the next message of the handshake, carrying `payload`.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if it is not the turn of this party to
+    /// write, or if the message would be larger than 65535 bytes, with
+    /// [`io::ErrorKind::InvalidData`] if the ephemeral key of the peer is of small order, and
+    /// with the errors of the OS CSPRNG.
+    pub fn write_message(&mut self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        if self.is_finished() || !self.writes() {
+            return Err(invalid_input("not the turn of this party to write"));
+        }
+        let mut message = Vec::new();
+        if self.step < 2 {
+            // -> e, or <- e, ee, s, es.
+            let mut e = Zeroizing::new([0; 32]);
+            getrandom::getrandom(&mut *e)?;
+            let e = StaticSecret::from(*e);
+            let public = PublicKey::from(&e);
+            self.symmetric.mix_hash(public.as_bytes());
+            message.extend_from_slice(public.as_bytes());
+            self.e = Some(e);
+        }
+        if self.step == 1 {
+            let ee = self.dh_ephemeral(self.re)?;
+            self.symmetric.mix_key(&ee);
+        }
+        if self.step > 0 {
+            // s, then es (as responder) or se (as initiator), both DH(s, re).
+            let s = self.static_key.read_with(&X25519PublicKey);
+            message.extend(self.symmetric.encrypt_and_hash(&s)?);
+            let dh = self.dh_static(self.re)?;
+            self.symmetric.mix_key(&dh);
+        }
+        message.extend(self.symmetric.encrypt_and_hash(payload)?);
+        if message.len() > MAX_MESSAGE_LEN {
+            return Err(invalid_input("Noise message is too large"));
+        }
+        self.step += 1;
+        Ok(message)
+    }
+
+    /// Reads the next message of the handshake, returning its payload.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if it is not the turn of this party to
+    /// read, and with [`io::ErrorKind::InvalidData`] if the message is malformed, was
+    /// altered, or carries a public key of small order.
+    pub fn read_message(&mut self, message: &[u8]) -> io::Result<Vec<u8>> {
+        if self.is_finished() || self.writes() {
+            return Err(invalid_input("not the turn of this party to read"));
+        }
+        let too_short = || invalid_data("Noise message is too short");
+        let mut message = message;
+        if self.step < 2 {
+            // -> e, or <- e, ee, s, es.
+            let (re, rest) = message.split_first_chunk::<32>().ok_or_else(too_short)?;
+            self.symmetric.mix_hash(re);
+            self.re = Some(PublicKey::from(*re));
+            message = rest;
+        }
+        if self.step == 1 {
+            let ee = self.dh_ephemeral(self.re)?;
+            self.symmetric.mix_key(&ee);
+        }
+        if self.step > 0 {
+            // s, then es (as initiator) or se (as responder), both DH(e, rs).
+            let (rs, rest) = message
+                .split_at_checked(32 + TAG_LEN)
+                .ok_or_else(too_short)?;
+            let rs: [u8; 32] = self.symmetric.decrypt_and_hash(rs)?[..]
+                .try_into()
+                .expect("public keys are 32 bytes long");
+            self.rs = Some(PublicKey::from(rs));
+            let dh = self.dh_ephemeral(self.rs)?;
+            self.symmetric.mix_key(&dh);
+            message = rest;
+        }
+        let payload = self.symmetric.decrypt_and_hash(message)?;
+        self.step += 1;
+        Ok(payload)
+    }
+
+    /// Turns the finished handshake into a transport, splitting the chaining key into the
+    /// session keys.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the handshake is not finished.
+    pub fn into_transport(self) -> io::Result<NoiseTransport> {
+        if !self.is_finished() {
+            return Err(invalid_input("Noise handshake is not finished"));
+        }
+        let okm = self.symmetric.hkdf(&[]);
+        let key = |range: std::ops::Range<usize>| {
+            Secret::boxed_with(&|key: &mut [u8]| key.copy_from_slice(&okm[range.clone()])).0
+        };
+        let (initiator, responder) = (key(0..32), key(32..64));
+        let (send, receive) = match self.initiator {
+            true => (initiator, responder),
+            false => (responder, initiator),
+        };
+        Ok(NoiseTransport {
+            send,
+            receive,
+            send_nonce: 0,
+            receive_nonce: 0,
+            handshake_hash: self.symmetric.h,
+            remote_static: self.remote_static().expect("remote key is set"),
+        })
+    }
+}
+
+/// Encrypted channel of a finished Noise handshake, with its sending and receiving keys
+/// held in secrets.
+///
+/// Messages must be decrypted in the order they were encrypted, as their nonces are
+/// counters.
+pub struct NoiseTransport {
+    send: Pin<Box<Secret<[u8; 32]>>>,
+    receive: Pin<Box<Secret<[u8; 32]>>>,
+    send_nonce: u64,
+    receive_nonce: u64,
+    handshake_hash: [u8; 32],
+    remote_static: [u8; 32],
+}
+
+impl NoiseTransport {
+    /// Encrypts a message to the peer.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the message would be larger than
+    /// 65535 bytes, or if the nonces are exhausted.
+    pub fn encrypt(&mut self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let n = self.send_nonce;
+        let message = self
+            .send
+            .as_ref()
+            .read_with(&|key: &[u8]| encrypt(key, n, &[], payload))?;
+        self.send_nonce += 1;
+        Ok(message)
+    }
+
+    /// Decrypts the next message of the peer.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the message was altered, replayed or
+    /// reordered.
+    pub fn decrypt(&mut self, message: &[u8]) -> io::Result<Vec<u8>> {
+        let n = self.receive_nonce;
+        let payload = self
+            .receive
+            .as_ref()
+            .read_with(&|key: &[u8]| decrypt(key, n, &[], message))?;
+        self.receive_nonce += 1;
+        Ok(payload)
+    }
+
+    /// Returns the hash of the handshake, identifying the session, to bind it to a higher
+    /// level authentication (channel binding).
+    pub fn handshake_hash(&self) -> [u8; 32] {
+        self.handshake_hash
+    }
+
+    /// Returns the static public key of the peer.
+    pub fn remote_static(&self) -> [u8; 32] {
+        self.remote_static
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::actions::GenerateRandom;
+
+    #[test]
+    fn test_noise_xx() {
+        let mut static_key = pin!(Secret::<[u8; 32]>::new());
+        static_key.as_mut().update_with(&GenerateRandom).unwrap();
+        let static_public = static_key.as_ref().read_with(&X25519PublicKey);
+        let builder = || snow::Builder::new("Noise_XX_25519_ChaChaPoly_SHA256".parse().unwrap());
+        let peer_keys = builder().generate_keypair().unwrap();
+        let mut peer = builder()
+            .local_private_key(&peer_keys.private)
+            .prologue(b"secrust test")
+            .build_responder()
+            .unwrap();
+        let mut buf = [0; MAX_MESSAGE_LEN];
+
+        let mut handshake = NoiseHandshake::initiator(static_key.as_ref(), b"secrust test");
+        let err = handshake.read_message(&[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let message = handshake.write_message(b"hello").unwrap();
+        let len = peer.read_message(&message, &mut buf).unwrap();
+        assert_eq!(buf[..len], *b"hello");
+        let len = peer.write_message(b"from the responder", &mut buf).unwrap();
+        let payload = handshake.read_message(&buf[..len]).unwrap();
+        assert_eq!(payload, b"from the responder");
+        assert_eq!(handshake.remote_static().unwrap()[..], peer_keys.public);
+        let message = handshake.write_message(b"").unwrap();
+        peer.read_message(&message, &mut buf).unwrap();
+        assert_eq!(peer.get_remote_static().unwrap(), static_public);
+
+        let handshake_hash = peer.get_handshake_hash().to_vec();
+        let mut peer = peer.into_transport_mode().unwrap();
+        let mut transport = handshake.into_transport().unwrap();
+        assert_eq!(transport.handshake_hash()[..], handshake_hash);
+        let message = transport.encrypt(b"secret message").unwrap();
+        let len = peer.read_message(&message, &mut buf).unwrap();
+        assert_eq!(buf[..len], *b"secret message");
+        let len = peer.write_message(b"secret reply", &mut buf).unwrap();
+        assert_eq!(transport.decrypt(&buf[..len]).unwrap(), b"secret reply");
+        // Replayed messages are refused.
+        let err = transport.decrypt(&buf[..len]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // As responder, against itself.
+        let mut responder = NoiseHandshake::responder(static_key.as_ref(), b"secrust test");
+        let mut handshake = NoiseHandshake::initiator(static_key.as_ref(), b"secrust test");
+        let message = handshake.write_message(b"").unwrap();
+        responder.read_message(&message).unwrap();
+        let message = responder.write_message(b"").unwrap();
+        handshake.read_message(&message).unwrap();
+        let message = handshake.write_message(b"").unwrap();
+        responder.read_message(&message).unwrap();
+        let (mut initiator, mut responder) = (
+            handshake.into_transport().unwrap(),
+            responder.into_transport().unwrap(),
+        );
+        let message = responder.encrypt(b"secret message").unwrap();
+        assert_eq!(initiator.decrypt(&message).unwrap(), b"secret message");
+    }
+}
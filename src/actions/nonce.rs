@@ -0,0 +1,186 @@
+//! Generation of the 96-bit nonces of AES-GCM, enforcing the usage limits of NIST SP
+//! 800-38D (section 8) for each key.
+//!
+//! Nonces are either random ([`RandomNonces`]), or made of a fixed field followed by a
+//! counter ([`CounterNonces`]), persisted so that no value is used twice, even across
+//! restarts. Deterministic nonces are required when a key encrypts more than 2³² messages.
+//!
+//! A sequence must only be used with a single key, and a key with a single sequence.
+
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use aes_gcm::{
+    Nonce,
+    aead::{OsRng, consts::U12, rand_core::RngCore},
+};
+
+/// Most invocations allowed with random nonces (section 8.3).
+pub const RANDOM_LIMIT: u64 = 1 << 32;
+
+/// A sequence of nonces, for a single key.
+pub trait NonceSequence: Sync {
+    /// Returns the next nonce.
+    ///
+    /// Fails with [`io::ErrorKind::QuotaExceeded`] once the key has been used as many times
+    /// as allowed: it must then be replaced.
+    fn next_nonce(&self) -> io::Result<Nonce<U12>>;
+}
+
+fn exhausted() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::QuotaExceeded,
+        "nonces of this key are exhausted",
+    )
+}
+
+/// Random nonces, at most [`RANDOM_LIMIT`] of them, so that the probability of a collision
+/// stays below 2⁻³².
+///
+/// Uses are only counted by this instance: a key used by several processes, or after a
+/// restart, should rather use a [`CounterNonces`].
+#[derive(Debug, Default)]
+pub struct RandomNonces {
+    used: AtomicU64,
+}
+
+impl RandomNonces {
+    /// Starts counting from `used` previous uses of the key.
+    pub fn resume(used: u64) -> Self {
+        RandomNonces {
+            used: AtomicU64::new(used),
+        }
+    }
+
+    /// Returns how many nonces were generated.
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+}
+
+impl NonceSequence for RandomNonces {
+    fn next_nonce(&self) -> io::Result<Nonce<U12>> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < RANDOM_LIMIT).then_some(used + 1)
+            })
+            .map_err(|_| exhausted())?;
+        let mut nonce = Nonce::default();
+        OsRng.fill_bytes(&mut nonce);
+        Ok(nonce)
+    }
+}
+
+/// Deterministic nonces (section 8.2.1): a 32-bit fixed field, such as the identifier of
+/// the device (so that devices sharing a key never use the same nonces), followed by a
+/// 64-bit counter.
+///
+/// The counter is persisted in a file, replaced atomically (written to a temporary file,
+/// synced, then renamed). To spare a write for each nonce, counters are reserved by blocks:
+/// the unused ones are skipped after a restart.
+#[derive(Debug)]
+pub struct CounterNonces {
+    fixed: [u8; 4],
+    path: PathBuf,
+    block: u64,
+    state: Mutex<Counter>,
+}
+
+#[derive(Debug)]
+struct Counter {
+    next: u64,
+    reserved: u64,
+}
+
+impl CounterNonces {
+    /// Resumes the counter persisted in `path` (starting from 0 if the file does not exist),
+    /// with a fixed field of zeros.
+    pub fn open(path: impl Into<PathBuf>, block: u64) -> io::Result<Self> {
+        Self::with_device_id(path, block, [0; 4])
+    }
+
+    /// Resumes the counter persisted in `path`, with `device_id` as the fixed field.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the file does not hold a counter, and
+    /// with [`io::ErrorKind::InvalidInput`] if `block` is 0.
+    pub fn with_device_id(
+        path: impl Into<PathBuf>,
+        block: u64,
+        device_id: [u8; 4],
+    ) -> io::Result<Self> {
+        if block == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "nonce counters must be reserved by blocks of at least one",
+            ));
+        }
+        let path = path.into();
+        let next = match fs::read(&path) {
+            Ok(content) => u64::from_be_bytes(content.try_into().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid nonce counter file")
+            })?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        Ok(CounterNonces {
+            fixed: device_id,
+            path,
+            block,
+            state: Mutex::new(Counter {
+                next,
+                reserved: next,
+            }),
+        })
+    }
+
+    /// Persists `value` as the next counter to use after a restart.
+    fn persist(&self, value: u64) -> io::Result<()> {
+        super::write_atomically(&self.path, &value.to_be_bytes())
+    }
+}
+
+impl NonceSequence for CounterNonces {
+    fn next_nonce(&self) -> io::Result<Nonce<U12>> {
+        let mut counter = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        if counter.next == u64::MAX {
+            return Err(exhausted());
+        }
+        if counter.next == counter.reserved {
+            let reserved = counter.next.saturating_add(self.block);
+            self.persist(reserved)?;
+            counter.reserved = reserved;
+        }
+        let mut nonce = Nonce::default();
+        nonce[..4].copy_from_slice(&self.fixed);
+        nonce[4..].copy_from_slice(&counter.next.to_be_bytes());
+        counter.next += 1;
+        Ok(nonce)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_counter_nonces() {
+        let path = std::env::temp_dir().join(format!("secrust-nonces-{}", std::process::id()));
+        let nonces = CounterNonces::with_device_id(&path, 2, *b"dev1").unwrap();
+        let first = nonces.next_nonce().unwrap();
+        assert_eq!(first[..], *b"dev1\0\0\0\0\0\0\0\0");
+        assert_eq!(nonces.next_nonce().unwrap()[11], 1);
+        assert_eq!(nonces.next_nonce().unwrap()[11], 2);
+
+        // Restarts after the reserved block.
+        let nonces = CounterNonces::with_device_id(&path, 2, *b"dev1").unwrap();
+        assert_eq!(nonces.next_nonce().unwrap()[11], 4);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+//! Decryption of OpenPGP-encrypted secret files (as produced by `gpg --encrypt` or
+//! `gpg --symmetric`), with [Sequoia](https://sequoia-pgp.org/).
+//!
+//! As for any library depending on Sequoia, the cryptographic backend is left to the
+//! application, which must enable one of the `crypto-*` features of `sequoia-openpgp`
+//! (`crypto-nettle` on most platforms, `crypto-cng` on Windows).
+
+use std::{io, path::PathBuf, pin::Pin};
+
+use sequoia_openpgp::{
+    KeyHandle,
+    crypto::{Password, SessionKey, mem::Protected, mpi},
+    packet::{
+        Key, PKESK, SKESK,
+        key::{PublicParts, SecretParts, UnspecifiedRole},
+    },
+    parse::{
+        Parse,
+        stream::{DecryptionHelper, DecryptorBuilder, MessageStructure, VerificationHelper},
+    },
+    policy::StandardPolicy,
+    types::{Curve, SymmetricAlgorithm},
+};
+use zeroize::Zeroize;
+
+pub use sequoia_openpgp::Cert;
+
+use super::read_to_end;
+use crate::{
+    api::{Secret, SecretUpdater},
+    memory::{Global, SecureAlloc},
+};
+
+/// Key of an OpenPGP-encrypted file, held in another [`Secret`].
+pub enum PgpKey<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    /// Passphrase of a symmetrically encrypted file, up to its first NUL byte.
+    Passphrase(Pin<&'a Secret<[u8; K], Alloc>>),
+    /// Secret of a Curve25519 encryption subkey of `cert` (`cv25519` or `X25519`), as its 32
+    /// raw bytes in X25519 (little-endian) order.
+    ///
+    /// `cert` only provides the public parts of the key, its secret key material, if any, is
+    /// ignored.
+    X25519 {
+        cert: &'a Cert,
+        secret: Pin<&'a Secret<[u8; K], Alloc>>,
+    },
+}
+
+/// Decrypts an OpenPGP-encrypted file (armored or binary) directly into the secret.
+///
+/// Signatures of the message, if any, are not verified.
+///
+/// # Security
+///
+/// The secret key material is only handed to Sequoia, which keeps it in its own protected
+/// memory, for the duration of the update. The plaintext may however be buffered by Sequoia
+/// before reaching the secret, and these buffers are not wiped.
+pub struct UpdateSecretFromPgpFile<'a, const K: usize, Alloc: SecureAlloc = Global> {
+    pub path: PathBuf,
+    pub key: PgpKey<'a, K, Alloc>,
+}
+
+/// Attaches the secret `sec` to `key`, a Curve25519 encryption key.
+fn with_secret(
+    key: &Key<PublicParts, UnspecifiedRole>,
+    sec: &[u8],
+) -> Option<Key<SecretParts, UnspecifiedRole>> {
+    if sec.len() != 32 {
+        return None;
+    }
+    let material = match key.mpis() {
+        // OpenPGP stores ECDH scalars in big-endian order.
+        mpi::PublicKey::ECDH {
+            curve: Curve::Cv25519,
+            ..
+        } => {
+            let mut scalar = Protected::from(sec);
+            scalar.reverse();
+            mpi::SecretKeyMaterial::ECDH {
+                scalar: scalar.into(),
+            }
+        }
+        mpi::PublicKey::X25519 { .. } => mpi::SecretKeyMaterial::X25519 { x: sec.into() },
+        _ => return None,
+    };
+    Some(key.clone().add_secret(material.into()).0)
+}
+
+struct Helper<'a, 'k, const K: usize, Alloc: SecureAlloc>(&'a PgpKey<'k, K, Alloc>);
+
+impl<const K: usize, Alloc: SecureAlloc> VerificationHelper for Helper<'_, '_, K, Alloc> {
+    fn get_certs(&mut self, _ids: &[KeyHandle]) -> sequoia_openpgp::Result<Vec<Cert>> {
+        Ok(Vec::new())
+    }
+
+    fn check(&mut self, _structure: MessageStructure) -> sequoia_openpgp::Result<()> {
+        Ok(())
+    }
+}
+
+impl<const K: usize, Alloc: SecureAlloc> DecryptionHelper for Helper<'_, '_, K, Alloc> {
+    fn decrypt(
+        &mut self,
+        pkesks: &[PKESK],
+        skesks: &[SKESK],
+        sym_algo: Option<SymmetricAlgorithm>,
+        decrypt: &mut dyn FnMut(Option<SymmetricAlgorithm>, &SessionKey) -> bool,
+    ) -> sequoia_openpgp::Result<Option<Cert>> {
+        match self.0 {
+            PgpKey::Passphrase(secret) => {
+                let password =
+                    secret.read_with(&|sec: &[u8]| Password::from(super::passphrase(sec)));
+                for skesk in skesks {
+                    if let Ok((algo, session_key)) = skesk.decrypt(&password)
+                        && decrypt(algo, &session_key)
+                    {
+                        return Ok(None);
+                    }
+                }
+            }
+            PgpKey::X25519 { cert, secret } => {
+                for pkesk in pkesks {
+                    // Anonymous recipients are tried against every subkey.
+                    let keys = cert.keys().filter(|key| match pkesk.recipient() {
+                        Some(handle) => handle.aliases(key.key().key_handle()),
+                        None => true,
+                    });
+                    for key in keys {
+                        let public = key.key().parts_as_public().role_as_unspecified();
+                        let Some(key) = secret.read_with(&|sec: &[u8]| with_secret(public, sec))
+                        else {
+                            continue;
+                        };
+                        let mut keypair = key.into_keypair()?;
+                        if let Some((algo, session_key)) = pkesk.decrypt(&mut keypair, sym_algo)
+                            && decrypt(algo, &session_key)
+                        {
+                            return Ok(Some((*cert).clone()));
+                        }
+                    }
+                }
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::InvalidData, "no key to decrypt the file").into())
+    }
+}
+
+fn pgp_error(err: sequoia_openpgp::anyhow::Error) -> io::Error {
+    match err.downcast::<io::Error>() {
+        Ok(err) => err,
+        Err(err) => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+/// Returns the length of the plaintext.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the file is not a valid OpenPGP message or
+/// cannot be decrypted with the key, and with [`io::ErrorKind::InvalidInput`] if the
+/// plaintext is larger than the secret. On failure, the secret is zeroized.
+impl<const N: usize, const K: usize, Alloc: SecureAlloc> SecretUpdater<[u8; N], io::Result<usize>>
+    for UpdateSecretFromPgpFile<'_, K, Alloc>
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let policy = StandardPolicy::new();
+        let result = DecryptorBuilder::from_file(&self.path)
+            .and_then(|builder| builder.with_policy(&policy, None, Helper(&self.key)))
+            .map_err(pgp_error)
+            .and_then(|decryptor| read_to_end(decryptor, sec));
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{fs::File, io::Write, pin::pin};
+
+    use sequoia_openpgp::{
+        cert::CertBuilder,
+        packet::key::SecretKeyMaterial,
+        serialize::stream::{Encryptor, LiteralWriter, Message, Recipient},
+    };
+
+    use super::*;
+    use crate::api::Secret;
+
+    fn encrypt<'a, 'b: 'a>(
+        file: &'a mut File,
+        encryptor: impl FnOnce(Message<'a>) -> Encryptor<'a, 'b>,
+        plaintext: &[u8],
+    ) {
+        let message = encryptor(Message::new(file)).build().unwrap();
+        let mut message = LiteralWriter::new(message).build().unwrap();
+        message.write_all(plaintext).unwrap();
+        message.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_update_secret_from_pgp_file() {
+        let path = std::env::temp_dir().join(format!("secrust-pgp-{}", std::process::id()));
+        let (cert, _) = CertBuilder::new()
+            .add_storage_encryption_subkey()
+            .generate()
+            .unwrap();
+        let subkey = cert.keys().subkeys().secret().next().unwrap();
+        let SecretKeyMaterial::Unencrypted(material) = subkey.key().secret() else {
+            panic!("unencrypted key expected");
+        };
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        material.map(|material| {
+            let mpi::SecretKeyMaterial::ECDH { scalar } = material else {
+                panic!("cv25519 key expected");
+            };
+            key.as_mut().update_with(&|sec: &mut [u8]| {
+                sec.copy_from_slice(scalar.value());
+                sec.reverse();
+            });
+        });
+        let recipient = Recipient::new(None, None, subkey.key());
+        let mut file = File::create(&path).unwrap();
+        encrypt(
+            &mut file,
+            |message| Encryptor::for_recipients(message, [recipient]),
+            b"pgp key",
+        );
+        let cert = cert.clone().strip_secret_key_material();
+
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        let len = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromPgpFile {
+                path: path.clone(),
+                key: PgpKey::X25519 {
+                    cert: &cert,
+                    secret: key.as_ref(),
+                },
+            })
+            .unwrap();
+        assert_eq!(len, 7);
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(&sec[..len], b"pgp key"));
+
+        let mut passphrase = pin!(Secret::<[u8; 32]>::new());
+        passphrase
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..4].copy_from_slice(b"pass"));
+        let mut file = File::create(&path).unwrap();
+        encrypt(
+            &mut file,
+            |message| Encryptor::with_passwords(message, ["pass"]),
+            b"pgp pass",
+        );
+        let len = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromPgpFile {
+                path: path.clone(),
+                key: PgpKey::Passphrase(passphrase.as_ref()),
+            })
+            .unwrap();
+        assert_eq!(len, 8);
+
+        let err = secret
+            .as_mut()
+            .update_with(&UpdateSecretFromPgpFile {
+                path: path.clone(),
+                key: PgpKey::X25519 {
+                    cert: &cert,
+                    secret: key.as_ref(),
+                },
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 16]));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    io,
+    time::{Duration, SystemTime},
+};
+
+use hmac::{Mac, digest::KeyInit};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::api::SecretReader;
+
+/// Hash functions of HOTP and TOTP.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OtpHash {
+    /// HMAC-SHA1, of RFC 4226, and the only one most authenticators support.
+    #[default]
+    Sha1,
+    /// HMAC-SHA256, of RFC 6238.
+    Sha256,
+    /// HMAC-SHA512, of RFC 6238.
+    Sha512,
+}
+
+/// Computes the HOTP code (RFC 4226) of a counter, keyed by the seed held in the secret.
+pub struct Hotp {
+    pub counter: u64,
+    /// Number of decimal digits of the code, from 6 to 8.
+    pub digits: u32,
+    pub hash: OtpHash,
+}
+
+/// Computes the TOTP code (RFC 6238) of a time, keyed by the seed held in the secret, as
+/// the HOTP code of the number of periods elapsed since the Unix epoch.
+///
+/// Authenticators use periods of 30 seconds and 6-digit codes with SHA-1, unless their
+/// `otpauth://` URI tells otherwise.
+pub struct Totp {
+    pub time: SystemTime,
+    pub period: Duration,
+    /// Number of decimal digits of the code, from 6 to 8.
+    pub digits: u32,
+    pub hash: OtpHash,
+}
+
+fn mac<M: Mac + KeyInit>(key: &[u8], counter: u64) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Computes the code of `counter`, with the dynamic truncation of RFC 4226, section 5.3.
+fn hotp(key: &[u8], counter: u64, digits: u32, hash: OtpHash) -> io::Result<String> {
+    if !(6..=8).contains(&digits) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "codes must have between 6 and 8 digits",
+        ));
+    }
+    let mac = match hash {
+        OtpHash::Sha1 => mac::<hmac::Hmac<Sha1>>(key, counter),
+        OtpHash::Sha256 => mac::<hmac::Hmac<Sha256>>(key, counter),
+        OtpHash::Sha512 => mac::<hmac::Hmac<Sha512>>(key, counter),
+    };
+    let offset = usize::from(mac[mac.len() - 1] & 0x0f);
+    let bytes = mac[offset..offset + 4].try_into().expect("4 bytes");
+    let code = (u32::from_be_bytes(bytes) & 0x7fff_ffff) % 10u32.pow(digits);
+    Ok(format!("{code:0width$}", width = digits as usize))
+}
+
+/// Returns the code.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the number of digits is not supported.
+impl<const N: usize> SecretReader<[u8; N], io::Result<String>> for Hotp {
+    fn read(&self, sec: &[u8]) -> io::Result<String> {
+        hotp(sec, self.counter, self.digits, self.hash)
+    }
+}
+
+/// Returns the code.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the number of digits is not supported, if
+/// the period is shorter than a second, or if the time is before the Unix epoch.
+impl<const N: usize> SecretReader<[u8; N], io::Result<String>> for Totp {
+    fn read(&self, sec: &[u8]) -> io::Result<String> {
+        let invalid_input = |msg| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        if self.period.as_secs() == 0 {
+            return Err(invalid_input("period must be at least a second"));
+        }
+        let elapsed = self
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_| invalid_input("time is before the Unix epoch"))?;
+        let counter = elapsed.as_secs() / self.period.as_secs();
+        hotp(sec, counter, self.digits, self.hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    /// Test vectors of RFC 4226, appendix D, and of RFC 6238, appendix B.
+    #[test]
+    fn test_otp() {
+        let mut seed = pin!(Secret::<[u8; 20]>::new());
+        seed.as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"12345678901234567890"));
+        for (counter, expected) in [(0, "755224"), (1, "287082"), (9, "520489")] {
+            let code = seed.as_ref().read_with(&Hotp {
+                counter,
+                digits: 6,
+                hash: OtpHash::Sha1,
+            });
+            assert_eq!(code.unwrap(), expected);
+        }
+
+        let mut seed256 = pin!(Secret::<[u8; 32]>::new());
+        seed256.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(b"12345678901234567890123456789012")
+        });
+        let totp = |secs, hash| Totp {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            period: Duration::from_secs(30),
+            digits: 8,
+            hash,
+        };
+        for (secs, sha1, sha256) in [
+            (59, "94287082", "46119246"),
+            (1111111109, "07081804", "68084774"),
+            (20000000000, "65353130", "77737706"),
+        ] {
+            let code = seed.as_ref().read_with(&totp(secs, OtpHash::Sha1));
+            assert_eq!(code.unwrap(), sha1);
+            let code = seed256.as_ref().read_with(&totp(secs, OtpHash::Sha256));
+            assert_eq!(code.unwrap(), sha256);
+        }
+
+        let err = seed
+            .as_ref()
+            .read_with(&Hotp {
+                counter: 0,
+                digits: 10,
+                hash: OtpHash::Sha1,
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let mut zero_period = totp(59, OtpHash::Sha1);
+        zero_period.period = Duration::from_millis(500);
+        let err = seed.as_ref().read_with(&zero_period).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
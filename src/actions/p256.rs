@@ -0,0 +1,108 @@
+use std::io;
+
+use p256::ecdsa::{Signature, SigningKey, signature::Signer};
+
+use crate::api::SecretReader;
+
+/// Signs a message with ECDSA over P-256 and SHA-256 (ES256 of JOSE), the secret being the
+/// 32-byte big-endian private scalar.
+///
+/// # Security
+///
+/// Nonces are derived deterministically from the key and the message (RFC 6979), so that a
+/// failing random number generator cannot leak the key. The copy of the scalar is zeroized
+/// once the message is signed.
+pub struct P256Sign(pub Vec<u8>);
+
+/// Returns the uncompressed SEC1 encoding (`04 || x || y`) of the public key of the P-256
+/// private scalar held in the secret, to verify the signatures of [`P256Sign`].
+pub struct P256PublicKey;
+
+fn signing_key(sec: &[u8]) -> io::Result<SigningKey> {
+    SigningKey::from_slice(sec).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "secret is not a valid P-256 scalar",
+        )
+    })
+}
+
+/// Returns the signature, as `r || s` (use [`Signature::to_der`] for the DER encoding of
+/// X.509).
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the secret is 0 or not below the order of
+/// the curve.
+impl SecretReader<[u8; 32], io::Result<[u8; 64]>> for P256Sign {
+    fn read(&self, sec: &[u8]) -> io::Result<[u8; 64]> {
+        let signature: Signature = signing_key(sec)?.sign(&self.0);
+        Ok(signature.to_bytes().into())
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the secret is 0 or not below the order of
+/// the curve.
+impl SecretReader<[u8; 32], io::Result<[u8; 65]>> for P256PublicKey {
+    fn read(&self, sec: &[u8]) -> io::Result<[u8; 65]> {
+        let point = signing_key(sec)?.verifying_key().to_encoded_point(false);
+        Ok(point
+            .as_bytes()
+            .try_into()
+            .expect("uncompressed points are 65 bytes long"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    /// Test vector of RFC 6979, section A.2.5, with SHA-256 and the message "sample".
+    #[test]
+    fn test_p256_sign() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(&[
+                0xc9, 0xaf, 0xa9, 0xd8, 0x45, 0xba, 0x75, 0x16, 0x6b, 0x5c, 0x21, 0x57, 0x67, 0xb1,
+                0xd6, 0x93, 0x4e, 0x50, 0xc3, 0xdb, 0x36, 0xe8, 0x9b, 0x12, 0x7b, 0x8a, 0x62, 0x2b,
+                0x12, 0x0f, 0x67, 0x21,
+            ])
+        });
+        let public = secret.as_ref().read_with(&P256PublicKey).unwrap();
+        assert_eq!(
+            public[1..],
+            [
+                0x60, 0xfe, 0xd4, 0xba, 0x25, 0x5a, 0x9d, 0x31, 0xc9, 0x61, 0xeb, 0x74, 0xc6, 0x35,
+                0x6d, 0x68, 0xc0, 0x49, 0xb8, 0x92, 0x3b, 0x61, 0xfa, 0x6c, 0xe6, 0x69, 0x62, 0x2e,
+                0x60, 0xf2, 0x9f, 0xb6, 0x79, 0x03, 0xfe, 0x10, 0x08, 0xb8, 0xbc, 0x99, 0xa4, 0x1a,
+                0xe9, 0xe9, 0x56, 0x28, 0xbc, 0x64, 0xf2, 0xf1, 0xb2, 0x0c, 0x2d, 0x7e, 0x9f, 0x51,
+                0x77, 0xa3, 0xc2, 0x94, 0xd4, 0x46, 0x22, 0x99,
+            ]
+        );
+        let signature = secret
+            .as_ref()
+            .read_with(&P256Sign(b"sample".to_vec()))
+            .unwrap();
+        assert_eq!(
+            signature,
+            [
+                0xef, 0xd4, 0x8b, 0x2a, 0xac, 0xb6, 0xa8, 0xfd, 0x11, 0x40, 0xdd, 0x9c, 0xd4, 0x5e,
+                0x81, 0xd6, 0x9d, 0x2c, 0x87, 0x7b, 0x56, 0xaa, 0xf9, 0x91, 0xc3, 0x4d, 0x0e, 0xa8,
+                0x4e, 0xaf, 0x37, 0x16, 0xf7, 0xcb, 0x1c, 0x94, 0x2d, 0x65, 0x7c, 0x41, 0xd4, 0x36,
+                0xc7, 0xa1, 0xb6, 0xe2, 0x9f, 0x65, 0xf3, 0xe9, 0x00, 0xdb, 0xb9, 0xaf, 0xf4, 0x06,
+                0x4d, 0xc4, 0xab, 0x2f, 0x84, 0x3a, 0xcd, 0xa8,
+            ]
+        );
+
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0xff));
+        let err = secret
+            .as_ref()
+            .read_with(&P256Sign(b"sample".to_vec()))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,284 @@
+//! Password-authenticated key exchange with SPAKE2, over the Ed25519 group, compatible with
+//! the `spake2` crate and with `python-spake2` (as used by Magic Wormhole).
+//!
+//! Both sides read the password from their secret to start the exchange, send the returned
+//! message to their peer, then read it again to finish the exchange with the message of
+//! the peer: the password is never copied out of its secret, and the agreed key lands in a
+//! fresh one. Passwords are the bytes of the secret up to its first NUL byte.
+//!
+//! The agreed key is only the same on both sides if they used the same password: confirm
+//! it, for instance by exchanging MACs of the transcript, before trusting the peer.
+
+use std::{io, pin::Pin};
+
+use curve25519_dalek::{EdwardsPoint, Scalar, edwards::CompressedEdwardsY};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::api::{Secret, SecretReader};
+
+/// Side of a SPAKE2 exchange, and the identities of the parties.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Spake2Side {
+    /// The first party, such as a client, talking to [`Spake2Side::B`].
+    A { id_a: Vec<u8>, id_b: Vec<u8> },
+    /// The second party, such as a server, talking to [`Spake2Side::A`].
+    B { id_a: Vec<u8>, id_b: Vec<u8> },
+    /// Either party, when both play the same role, talking to another symmetric party.
+    Symmetric { id: Vec<u8> },
+}
+
+/// Starts a SPAKE2 exchange with the password held in the secret.
+pub struct Spake2Start(pub Spake2Side);
+
+/// Ephemeral state of a SPAKE2 exchange, between [`Spake2Start`] and [`Spake2Finish`].
+///
+/// # Security
+///
+/// The state does not hold the password, but an ephemeral scalar, zeroized on drop.
+pub struct Spake2State {
+    side: Spake2Side,
+    scalar: Scalar,
+    message: [u8; 32],
+}
+
+impl Drop for Spake2State {
+    fn drop(&mut self) {
+        self.scalar.zeroize();
+    }
+}
+
+/// Finishes a SPAKE2 exchange with the password held in the secret, and the message of the
+/// peer.
+///
+/// # Security
+///
+/// The Diffie-Hellman element and the transcript are zeroized, but not the SHA-256 states
+/// hashing them.
+pub struct Spake2Finish {
+    pub state: Spake2State,
+    pub message: Vec<u8>,
+}
+
+/// Decompresses a constant of the Ed25519 group of `python-spake2`.
+fn constant(bytes: [u8; 32]) -> EdwardsPoint {
+    CompressedEdwardsY(bytes)
+        .decompress()
+        .expect("constants are valid points")
+}
+
+/// Blinding constants of the sides, as `(M, N, S)` of `python-spake2`.
+fn constants() -> (EdwardsPoint, EdwardsPoint, EdwardsPoint) {
+    (
+        constant([
+            0x15, 0xcf, 0xd1, 0x8e, 0x38, 0x59, 0x52, 0x98, 0x2b, 0x6a, 0x8f, 0x8c, 0x78, 0x54,
+            0x96, 0x3b, 0x58, 0xe3, 0x43, 0x88, 0xc8, 0xe6, 0xda, 0xe8, 0x91, 0xdb, 0x75, 0x64,
+            0x81, 0xa0, 0x23, 0x12,
+        ]),
+        constant([
+            0xf0, 0x4f, 0x2e, 0x7e, 0xb7, 0x34, 0xb2, 0xa8, 0xf8, 0xb4, 0x72, 0xea, 0xf9, 0xc3,
+            0xc6, 0x32, 0x57, 0x6a, 0xc6, 0x4a, 0xea, 0x65, 0x0b, 0x49, 0x6a, 0x8a, 0x20, 0xff,
+            0x00, 0xe5, 0x83, 0xc3,
+        ]),
+        constant([
+            0x6f, 0x00, 0xda, 0xe8, 0x7c, 0x1b, 0xe1, 0xa7, 0x3b, 0x59, 0x22, 0xef, 0x43, 0x1c,
+            0xd8, 0xf5, 0x78, 0x79, 0x56, 0x9c, 0x22, 0x2d, 0x22, 0xb1, 0xcd, 0x71, 0xe8, 0x54,
+            0x6a, 0xb8, 0xe6, 0xf1,
+        ]),
+    )
+}
+
+impl Spake2Side {
+    /// Returns the byte prefixing the messages of the side, and the constants blinding the
+    /// messages of the side and of its peer.
+    fn params(&self) -> (u8, EdwardsPoint, EdwardsPoint) {
+        let (m, n, s) = constants();
+        match self {
+            Spake2Side::A { .. } => (b'A', m, n),
+            Spake2Side::B { .. } => (b'B', n, m),
+            Spake2Side::Symmetric { .. } => (b'S', s, s),
+        }
+    }
+}
+
+/// Hashes the password to a scalar, as `python-spake2` does: HKDF-SHA256 with the info
+/// `"SPAKE2 pw"`, read as a big-endian integer reduced modulo the order of the group.
+fn password_scalar(password: &[u8]) -> Scalar {
+    let mut okm = Zeroizing::new([0; 48]);
+    Hkdf::<Sha256>::new(Some(b""), password)
+        .expand(b"SPAKE2 pw", &mut *okm)
+        .expect("48 bytes is a valid length");
+    let mut wide = Zeroizing::new([0; 64]);
+    wide[..48].copy_from_slice(&*okm);
+    wide[..48].reverse();
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+/// Returns the state, and the message to send to the peer.
+///
+/// Fails with the errors of the OS CSPRNG.
+impl<const N: usize> SecretReader<[u8; N], io::Result<(Spake2State, Vec<u8>)>> for Spake2Start {
+    fn read(&self, sec: &[u8]) -> io::Result<(Spake2State, Vec<u8>)> {
+        let mut wide = Zeroizing::new([0; 64]);
+        getrandom::getrandom(&mut *wide)?;
+        let scalar = Scalar::from_bytes_mod_order_wide(&wide);
+        let (prefix, blinding, _) = self.0.params();
+        let mut password = password_scalar(super::passphrase(sec));
+        let element = EdwardsPoint::mul_base(&scalar) + blinding * password;
+        password.zeroize();
+        let state = Spake2State {
+            side: self.0.clone(),
+            scalar,
+            message: element.compress().to_bytes(),
+        };
+        let message = [&[prefix], &state.message[..]].concat();
+        Ok((state, message))
+    }
+}
+
+/// Returns a fresh secret holding the agreed key.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the message of the peer is malformed, or
+/// comes from a peer of the wrong side.
+impl<const N: usize> SecretReader<[u8; N], io::Result<Pin<Box<Secret<[u8; 32]>>>>>
+    for Spake2Finish
+{
+    fn read(&self, sec: &[u8]) -> io::Result<Pin<Box<Secret<[u8; 32]>>>> {
+        let invalid_data = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let side = &self.state.side;
+        let (prefix, _, unblinding) = side.params();
+        let expected = match side {
+            Spake2Side::A { .. } => b'B',
+            Spake2Side::B { .. } => b'A',
+            Spake2Side::Symmetric { .. } => prefix,
+        };
+        let peer: [u8; 32] = match self.message.split_first() {
+            Some((&side, peer)) if side == expected => peer
+                .try_into()
+                .map_err(|_| invalid_data("SPAKE2 message has an invalid length"))?,
+            _ => return Err(invalid_data("SPAKE2 message comes from the wrong side")),
+        };
+        let element = CompressedEdwardsY(peer)
+            .decompress()
+            .ok_or_else(|| invalid_data("SPAKE2 message is not a valid point"))?;
+
+        let password = super::passphrase(sec);
+        let mut password_scalar = password_scalar(password);
+        let shared = Zeroizing::new(
+            ((element - unblinding * password_scalar) * self.state.scalar)
+                .compress()
+                .to_bytes(),
+        );
+        password_scalar.zeroize();
+        // The transcript hashes the password, the identities, the messages of A and B (or
+        // sorted, when symmetric) and the shared element.
+        let mut transcript = Zeroizing::new(Vec::with_capacity(6 * 32));
+        transcript.extend_from_slice(&Sha256::digest(password));
+        let ours = &self.state.message;
+        let (first, second) = match side {
+            Spake2Side::A { id_a, id_b } | Spake2Side::B { id_a, id_b } => {
+                transcript.extend_from_slice(&Sha256::digest(id_a));
+                transcript.extend_from_slice(&Sha256::digest(id_b));
+                match side {
+                    Spake2Side::A { .. } => (ours, &peer),
+                    _ => (&peer, ours),
+                }
+            }
+            Spake2Side::Symmetric { id } => {
+                transcript.extend_from_slice(&Sha256::digest(id));
+                (ours.min(&peer), ours.max(&peer))
+            }
+        };
+        transcript.extend_from_slice(first);
+        transcript.extend_from_slice(second);
+        transcript.extend_from_slice(&*shared);
+        let (key, ()) = Secret::boxed_with(&|key: &mut [u8]| {
+            key.copy_from_slice(&Sha256::digest(&*transcript))
+        });
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use spake2::{Ed25519Group, Identity, Password, Spake2};
+
+    use super::*;
+
+    #[test]
+    fn test_spake2() {
+        let mut password = pin!(Secret::<[u8; 16]>::new());
+        password
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..8].copy_from_slice(b"password"));
+        let read_key =
+            |key: Pin<Box<Secret<[u8; 32]>>>| key.as_ref().read_with(&|sec: &[u8]| sec.to_vec());
+        let ids = (b"client".to_vec(), b"server".to_vec());
+
+        // Against the `spake2` crate, as A.
+        let (state, message) = password
+            .as_ref()
+            .read_with(&Spake2Start(Spake2Side::A {
+                id_a: ids.0.clone(),
+                id_b: ids.1.clone(),
+            }))
+            .unwrap();
+        let (peer, peer_message) = Spake2::<Ed25519Group>::start_b(
+            &Password::new(b"password"),
+            &Identity::new(&ids.0),
+            &Identity::new(&ids.1),
+        );
+        let key = password
+            .as_ref()
+            .read_with(&Spake2Finish {
+                state,
+                message: peer_message,
+            })
+            .unwrap();
+        assert_eq!(read_key(key), peer.finish(&message).unwrap());
+
+        // Against itself, symmetric, with the wrong password.
+        let start = || {
+            password
+                .as_ref()
+                .read_with(&Spake2Start(Spake2Side::Symmetric { id: ids.0.clone() }))
+                .unwrap()
+        };
+        let ((state, message), (peer, peer_message)) = (start(), start());
+        let mut wrong = pin!(Secret::<[u8; 16]>::new());
+        wrong
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..8].copy_from_slice(b"passw0rd"));
+        let key = password.as_ref().read_with(&Spake2Finish {
+            state,
+            message: peer_message.clone(),
+        });
+        let peer_key = wrong.as_ref().read_with(&Spake2Finish {
+            state: peer,
+            message,
+        });
+        assert_ne!(read_key(key.unwrap()), read_key(peer_key.unwrap()));
+
+        // A message of the wrong side is refused.
+        let (state, _) = password
+            .as_ref()
+            .read_with(&Spake2Start(Spake2Side::B {
+                id_a: ids.0.clone(),
+                id_b: ids.1,
+            }))
+            .unwrap();
+        let err = password
+            .as_ref()
+            .read_with(&Spake2Finish {
+                state,
+                message: peer_message,
+            })
+            .map(drop)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,390 @@
+//! Password hashes, and verification of passwords against stored hashes, whichever
+//! algorithm hashed them.
+//!
+//! Passwords are held in any secret of bytes, up to its first NUL byte: an array, or a
+//! larger buffer (such as 256 bytes, for passwords of legacy user databases).
+
+use std::io;
+#[cfg(feature = "bcrypt")]
+use std::{error::Error, fmt};
+
+#[cfg(feature = "argon2")]
+use argon2::{Argon2, password_hash};
+#[cfg(any(feature = "argon2", feature = "scrypt"))]
+use password_hash::{PasswordHash, PasswordVerifier};
+#[cfg(all(feature = "scrypt", not(feature = "argon2")))]
+use scrypt::password_hash;
+
+use zeroize::Zeroize;
+
+use crate::api::{SecretReader, Unsizeable};
+
+/// Whether a password matches a stored hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordVerdict {
+    Match,
+    Mismatch,
+}
+
+/// Checks the password held in the secret, up to its first NUL byte, against a stored hash,
+/// in constant time, so that login paths never copy the password out of its secret.
+///
+/// The algorithm is detected from the hash: a PHC string of Argon2 (`$argon2id$…`, as
+/// returned by `HashPassword`, `$argon2i$…` or `$argon2d$…`) or of scrypt (`$scrypt$…`),
+/// or a bcrypt hash (`$2b$…`, `$2a$…`, `$2y$…` or `$2x$…`). Each algorithm is only supported
+/// with its feature.
+///
+/// bcrypt hashes are checked as `BcryptTruncation::Truncate` does, against the first 72
+/// bytes of the password: use `VerifyBcrypt` to refuse longer passwords.
+pub struct VerifyAgainstPhc(pub String);
+
+/// Longest password hashed by bcrypt as a whole, in bytes.
+#[cfg(feature = "bcrypt")]
+pub const BCRYPT_MAX_PASSWORD_LEN: usize = 72;
+
+/// How bcrypt treats passwords longer than [`BCRYPT_MAX_PASSWORD_LEN`] bytes, of which it
+/// only hashes the first 72.
+#[cfg(feature = "bcrypt")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BcryptTruncation {
+    /// Refuses them with a [`BcryptPasswordTooLong`] error, so that two passwords sharing
+    /// their first 72 bytes are never taken to be the same.
+    #[default]
+    Reject,
+    /// Hashes their first 72 bytes, as most bcrypt implementations silently do, to check
+    /// the hashes of a legacy user database.
+    Truncate,
+}
+
+/// Hashes the password held in the secret, up to its first NUL byte, with bcrypt under a
+/// random salt, returning the hash in the modular crypt format (`$2b$12$…`), for systems
+/// which can only store bcrypt hashes.
+///
+/// New systems should use `HashPassword`, with Argon2id, instead.
+#[cfg(feature = "bcrypt")]
+pub struct HashBcrypt {
+    /// Base 2 logarithm of the number of rounds, from 4 to 31, 12 being the usual cost.
+    pub cost: u32,
+    pub truncation: BcryptTruncation,
+}
+
+/// Checks the password held in the secret, up to its first NUL byte, against a bcrypt hash,
+/// in constant time.
+#[cfg(feature = "bcrypt")]
+pub struct VerifyBcrypt {
+    pub hash: String,
+    pub truncation: BcryptTruncation,
+}
+
+/// Why bcrypt refused a password with [`BcryptTruncation::Reject`].
+///
+/// Returned as the inner error of an [`io::ErrorKind::InvalidInput`] error.
+#[cfg(feature = "bcrypt")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BcryptPasswordTooLong {
+    /// Length of the password, longer than [`BCRYPT_MAX_PASSWORD_LEN`].
+    pub len: usize,
+}
+
+#[cfg(feature = "bcrypt")]
+impl fmt::Display for BcryptPasswordTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "password of {} bytes is longer than the {BCRYPT_MAX_PASSWORD_LEN} bytes hashed by \
+             bcrypt",
+            self.len
+        )
+    }
+}
+
+#[cfg(feature = "bcrypt")]
+impl Error for BcryptPasswordTooLong {}
+
+/// Algorithms of stored hashes.
+enum Algorithm {
+    Argon2,
+    Scrypt,
+    Bcrypt,
+}
+
+impl Algorithm {
+    fn detect(hash: &str) -> Option<Algorithm> {
+        let id = hash.strip_prefix('$')?.split('$').next()?;
+        match id {
+            "argon2id" | "argon2i" | "argon2d" => Some(Algorithm::Argon2),
+            "scrypt" => Some(Algorithm::Scrypt),
+            "2a" | "2b" | "2x" | "2y" => Some(Algorithm::Bcrypt),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_data(err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// Checks a PHC string with a verifier of the `password-hash` crate.
+#[cfg(any(feature = "argon2", feature = "scrypt"))]
+fn verify_phc(
+    verifier: &dyn PasswordVerifier,
+    password: &[u8],
+    hash: &str,
+) -> io::Result<PasswordVerdict> {
+    let hash = PasswordHash::new(hash).map_err(invalid_data)?;
+    match verifier.verify_password(password, &hash) {
+        Ok(()) => Ok(PasswordVerdict::Match),
+        Err(password_hash::Error::Password) => Ok(PasswordVerdict::Mismatch),
+        Err(err) => Err(invalid_data(err)),
+    }
+}
+
+/// Returns the password, if bcrypt may hash it with this truncation.
+#[cfg(feature = "bcrypt")]
+fn bcrypt_password(password: &[u8], truncation: BcryptTruncation) -> io::Result<&[u8]> {
+    if password.len() > BCRYPT_MAX_PASSWORD_LEN && truncation == BcryptTruncation::Reject {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            BcryptPasswordTooLong {
+                len: password.len(),
+            },
+        ));
+    }
+    Ok(password)
+}
+
+/// Checks a bcrypt hash, that only holds the first 72 bytes of the password.
+#[cfg(feature = "bcrypt")]
+fn verify_bcrypt(password: &[u8], hash: &str) -> io::Result<PasswordVerdict> {
+    match bcrypt::verify(password, hash) {
+        Ok(true) => Ok(PasswordVerdict::Match),
+        Ok(false) => Ok(PasswordVerdict::Mismatch),
+        Err(err) => Err(invalid_data(err)),
+    }
+}
+
+/// Fails with [`io::ErrorKind::Unsupported`] if the algorithm of the hash is unknown, or if
+/// its feature is not enabled, and with [`io::ErrorKind::InvalidData`] if the hash is not
+/// valid.
+impl<Data: Zeroize + Unsizeable<Unsized = [u8]>> SecretReader<Data, io::Result<PasswordVerdict>>
+    for VerifyAgainstPhc
+{
+    fn read(&self, sec: &[u8]) -> io::Result<PasswordVerdict> {
+        let unsupported = |msg| io::Error::new(io::ErrorKind::Unsupported, msg);
+        let password = super::passphrase(sec);
+        match Algorithm::detect(&self.0) {
+            #[cfg(feature = "argon2")]
+            Some(Algorithm::Argon2) => verify_phc(&Argon2::default(), password, &self.0),
+            #[cfg(feature = "scrypt")]
+            Some(Algorithm::Scrypt) => verify_phc(&scrypt::Scrypt, password, &self.0),
+            #[cfg(feature = "bcrypt")]
+            Some(Algorithm::Bcrypt) => verify_bcrypt(password, &self.0),
+            #[cfg(not(feature = "argon2"))]
+            Some(Algorithm::Argon2) => Err(unsupported("Argon2 needs the `argon2` feature")),
+            #[cfg(not(feature = "scrypt"))]
+            Some(Algorithm::Scrypt) => Err(unsupported("scrypt needs the `scrypt` feature")),
+            #[cfg(not(feature = "bcrypt"))]
+            Some(Algorithm::Bcrypt) => Err(unsupported("bcrypt needs the `bcrypt` feature")),
+            None => Err(unsupported("unknown password hash algorithm")),
+        }
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the cost is not supported, or, holding a
+/// [`BcryptPasswordTooLong`], if the password is refused, and with the errors of the OS
+/// CSPRNG.
+#[cfg(feature = "bcrypt")]
+impl<Data: Zeroize + Unsizeable<Unsized = [u8]>> SecretReader<Data, io::Result<String>>
+    for HashBcrypt
+{
+    fn read(&self, sec: &[u8]) -> io::Result<String> {
+        let password = bcrypt_password(super::passphrase(sec), self.truncation)?;
+        bcrypt::hash(password, self.cost).map_err(|err| match err {
+            bcrypt::BcryptError::Rand(err) => io::Error::other(err.to_string()),
+            err => io::Error::new(io::ErrorKind::InvalidInput, err.to_string()),
+        })
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`], holding a [`BcryptPasswordTooLong`], if the
+/// password is refused, and with [`io::ErrorKind::InvalidData`] if the hash is not a valid
+/// bcrypt hash.
+#[cfg(feature = "bcrypt")]
+impl<Data: Zeroize + Unsizeable<Unsized = [u8]>> SecretReader<Data, io::Result<PasswordVerdict>>
+    for VerifyBcrypt
+{
+    fn read(&self, sec: &[u8]) -> io::Result<PasswordVerdict> {
+        let password = bcrypt_password(super::passphrase(sec), self.truncation)?;
+        verify_bcrypt(password, &self.hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    #[cfg(feature = "argon2")]
+    use crate::actions::{Argon2Params, HashPassword};
+    use crate::api::Secret;
+
+    #[test]
+    fn test_verify_against_phc() {
+        let mut password = pin!(Secret::<[u8; 16]>::new());
+        password
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..8].copy_from_slice(b"password"));
+        let verify = |hash: &str| {
+            password
+                .as_ref()
+                .read_with(&VerifyAgainstPhc(hash.to_string()))
+        };
+        // Valid hashes, and malformed hashes of the same algorithm.
+        let hashes = [
+            #[cfg(feature = "argon2")]
+            (
+                password
+                    .as_ref()
+                    .read_with(&HashPassword(Argon2Params {
+                        memory_kib: 64,
+                        iterations: 1,
+                        parallelism: 1,
+                    }))
+                    .unwrap(),
+                "$argon2id$v=19$m=64,t=1,p=1$c29tZXNhbHQ$!",
+            ),
+            #[cfg(feature = "scrypt")]
+            (
+                {
+                    use scrypt::password_hash::{PasswordHasher, SaltString};
+                    let salt = SaltString::from_b64("c29tZXNhbHQ").unwrap();
+                    let params = scrypt::Params::new(10, 8, 1, 32).unwrap();
+                    scrypt::Scrypt
+                        .hash_password_customized(b"password", None, None, params, &salt)
+                        .unwrap()
+                        .to_string()
+                },
+                "$scrypt$ln=10,r=8,p=1$c29tZXNhbHQ$!",
+            ),
+            #[cfg(feature = "bcrypt")]
+            (bcrypt::hash("password", 4).unwrap(), "$2b$xx$"),
+        ];
+        for (hash, malformed) in hashes {
+            assert_eq!(verify(&hash).unwrap(), PasswordVerdict::Match);
+            assert_eq!(
+                verify(&hash.replacen("$", "$$", 1)).unwrap_err().kind(),
+                io::ErrorKind::Unsupported
+            );
+            let other = pin!(Secret::<[u8; 16]>::new());
+            let verdict = other
+                .as_ref()
+                .read_with(&VerifyAgainstPhc(hash.clone()))
+                .unwrap();
+            assert_eq!(verdict, PasswordVerdict::Mismatch);
+            let err = verify(malformed).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        let err = verify("$1$salt$hash").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[cfg(feature = "bcrypt")]
+    struct LongPassword([u8; 128]);
+
+    #[cfg(feature = "bcrypt")]
+    impl Default for LongPassword {
+        fn default() -> Self {
+            LongPassword([0; 128])
+        }
+    }
+
+    #[cfg(feature = "bcrypt")]
+    impl Zeroize for LongPassword {
+        fn zeroize(&mut self) {
+            self.0.zeroize();
+        }
+    }
+
+    #[cfg(feature = "bcrypt")]
+    impl Unsizeable for LongPassword {
+        type Unsized = [u8];
+
+        fn get_unsized(&self) -> &Self::Unsized {
+            &self.0
+        }
+
+        fn get_unsized_mut(&mut self) -> &mut Self::Unsized {
+            &mut self.0
+        }
+    }
+
+    #[cfg(feature = "bcrypt")]
+    #[test]
+    fn test_bcrypt() {
+        let mut password = pin!(Secret::<LongPassword>::new());
+        password
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[..100].fill(b'x'));
+        // Hashed with another implementation, from 100 bytes `x`.
+        let legacy = "$2a$05$......................YgIDy4hFBdVlc/6LHnD9mX488r9cLd2";
+        let verify = |truncation| {
+            password.as_ref().read_with(&VerifyBcrypt {
+                hash: legacy.to_string(),
+                truncation,
+            })
+        };
+        assert_eq!(
+            verify(BcryptTruncation::Truncate).unwrap(),
+            PasswordVerdict::Match
+        );
+        let err = verify(BcryptTruncation::Reject).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let why = err
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<BcryptPasswordTooLong>();
+        assert_eq!(why, Some(&BcryptPasswordTooLong { len: 100 }));
+        let hash = |cost, truncation| {
+            password
+                .as_ref()
+                .read_with(&HashBcrypt { cost, truncation })
+        };
+        let err = hash(4, BcryptTruncation::Reject).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = hash(3, BcryptTruncation::Truncate).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let truncated = hash(4, BcryptTruncation::Truncate).unwrap();
+        assert!(truncated.starts_with("$2b$04$"));
+
+        // Up to 72 bytes, passwords are hashed as a whole.
+        password
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[BCRYPT_MAX_PASSWORD_LEN..].fill(0));
+        let whole = password
+            .as_ref()
+            .read_with(&HashBcrypt {
+                cost: 4,
+                truncation: BcryptTruncation::Reject,
+            })
+            .unwrap();
+        assert_eq!(
+            password
+                .as_ref()
+                .read_with(&VerifyAgainstPhc(truncated))
+                .unwrap(),
+            PasswordVerdict::Match
+        );
+        password
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec[BCRYPT_MAX_PASSWORD_LEN - 1] = 0);
+        let verdict = password
+            .as_ref()
+            .read_with(&VerifyBcrypt {
+                hash: whole,
+                truncation: BcryptTruncation::Reject,
+            })
+            .unwrap();
+        assert_eq!(verdict, PasswordVerdict::Mismatch);
+    }
+}
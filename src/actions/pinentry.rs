@@ -0,0 +1,255 @@
+use std::{
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::{ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use zeroize::{Zeroize, Zeroizing};
+
+use super::prompt::Line;
+use crate::api::SecretUpdater;
+
+/// `GPG_ERR_CANCELED`, in the low bits of the error codes of pinentry.
+const ERR_CANCELED: u32 = 99;
+
+/// Reads a passphrase typed in a pinentry dialog (`pinentry-gnome3`, `pinentry-qt`,
+/// `pinentry-curses`…), driven through the Assuan protocol.
+///
+/// The pinentry chooses its interface from the environment (`DISPLAY`, `WAYLAND_DISPLAY`,
+/// `GPG_TTY`…), as when launched by `gpg-agent`.
+///
+/// As for [`super::UpdateSecretFromPrompt`], the passphrase is written (UTF-8 encoded) into a
+/// byte array, a `String` not being able to grow through the `&mut str` lent to updaters.
+///
+/// # Security
+///
+/// The passphrase is decoded from the pipe of the pinentry byte by byte, directly into the
+/// secret. Pinentries protect their own copy (locked memory, grabbed keyboard).
+pub struct UpdateSecretFromPinentry {
+    /// Program to launch, searched in `PATH` if relative, usually `pinentry`.
+    pub program: PathBuf,
+    /// Text explaining what the passphrase is for.
+    pub description: String,
+    /// Label of the input field, such as `Passphrase:`.
+    pub prompt: String,
+}
+
+/// Percent-escapes `value`, for an Assuan command.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' | '\r' | '\n' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+/// Assuan connection to a pinentry.
+struct Pinentry {
+    input: ChildStdin,
+    output: ChildStdout,
+}
+
+impl Pinentry {
+    /// Reads one byte, the pipe being unbuffered so that no copy of the passphrase is left.
+    fn byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.output.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Reads the rest of a line which is not data.
+    fn line(&mut self) -> io::Result<Zeroizing<Vec<u8>>> {
+        let mut line = Zeroizing::new(Vec::new());
+        loop {
+            match self.byte()? {
+                b'\n' => return Ok(line),
+                byte => line.push(byte),
+            }
+        }
+    }
+
+    /// Decodes the rest of a data (`D`) line into `data`.
+    fn data(&mut self, data: &mut Line) -> io::Result<()> {
+        loop {
+            let mut byte = self.byte()?;
+            match byte {
+                b'\n' => return Ok(()),
+                b'%' => {
+                    let mut digits = [self.byte()?, self.byte()?];
+                    let decoded = hex_digit(digits[0]).zip(hex_digit(digits[1]));
+                    digits.zeroize();
+                    let (high, low) = decoded.ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "invalid Assuan escape")
+                    })?;
+                    let mut decoded = [high << 4 | low];
+                    data.push(&decoded);
+                    decoded.zeroize();
+                }
+                _ => data.push(&[byte]),
+            }
+            byte.zeroize();
+        }
+    }
+
+    /// Waits for the `OK` ending a response, decoding its data lines into `data`, if any.
+    fn response(&mut self, mut data: Option<&mut Line>) -> io::Result<()> {
+        loop {
+            let kind = [self.byte()?, self.byte()?];
+            match (&kind, data.as_deref_mut()) {
+                (b"D ", Some(data)) => {
+                    self.data(data)?;
+                    continue;
+                }
+                (b"OK", _) => {
+                    self.line()?;
+                    return Ok(());
+                }
+                (b"ER", _) => {
+                    let line = self.line()?;
+                    return Err(error(&line));
+                }
+                // Status and comment lines, or data of commands expecting none.
+                _ if kind[1] != b'\n' => {
+                    self.line()?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str, data: Option<&mut Line>) -> io::Result<()> {
+        self.input.write_all(command.as_bytes())?;
+        self.input.write_all(b"\n")?;
+        self.input.flush()?;
+        self.response(data)
+    }
+}
+
+/// Converts the rest of an `ERR` line, `R <code> <description>`.
+fn error(line: &[u8]) -> io::Error {
+    let line = String::from_utf8_lossy(line);
+    let mut words = line.trim_start_matches('R').trim().splitn(2, ' ');
+    let code = words.next().and_then(|code| code.parse::<u32>().ok());
+    let description = words.next().unwrap_or("unknown error");
+    match code {
+        Some(code) if code & 0xffff == ERR_CANCELED => io::Error::new(
+            io::ErrorKind::Interrupted,
+            "the passphrase entry was cancelled",
+        ),
+        _ => io::Error::other(format!("pinentry failed: {description}")),
+    }
+}
+
+/// Returns the length of the passphrase.
+///
+/// Fails with [`io::ErrorKind::Interrupted`] if the dialog is cancelled, and with
+/// [`io::ErrorKind::InvalidInput`] if the passphrase is larger than the secret. On failure, the
+/// secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromPinentry {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let mut child = Command::new(&self.program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut pinentry = Pinentry {
+            input: child.stdin.take().expect("stdin is piped"),
+            output: child.stdout.take().expect("stdout is piped"),
+        };
+        let mut line = Line::new(sec);
+        let result = pinentry
+            .response(None)
+            .and_then(|()| {
+                pinentry.command(&format!("SETDESC {}", escape(&self.description)), None)
+            })
+            .and_then(|()| pinentry.command(&format!("SETPROMPT {}", escape(&self.prompt)), None))
+            .and_then(|()| pinentry.command("GETPIN", Some(&mut line)))
+            .and_then(|()| line.finish());
+        let _ = pinentry.command("BYE", None);
+        drop(pinentry);
+        if result.is_err() {
+            let _ = child.kill();
+        }
+        child.wait()?;
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+
+    use std::{os::unix::fs::PermissionsExt, pin::pin};
+
+    use super::*;
+    use crate::api::Secret;
+
+    /// Writes a fake pinentry, answering `GETPIN` with `pin`, or cancelling without one.
+    fn fake_pinentry(name: &str, pin: Option<&str>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("secrust-{name}-{}", std::process::id()));
+        let getpin = match pin {
+            Some(pin) => {
+                format!("echo 'S PASSWORD_FROM_CACHE'; echo 'D {pin}'; echo 'D %25'; echo OK")
+            }
+            None => "echo 'ERR 83886179 Operation cancelled <Pinentry>'".to_owned(),
+        };
+        std::fs::write(
+            &path,
+            format!(
+                "#!/bin/sh
+echo 'OK Pleased to meet you'
+while read -r command args; do
+    case \"$command\" in
+        GETPIN) {getpin} ;;
+        BYE) echo 'OK closing connection'; exit ;;
+        *) echo OK ;;
+    esac
+done
+"
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_update_secret_from_pinentry() {
+        let pinentry = |program| UpdateSecretFromPinentry {
+            program,
+            description: "Unlock the 100% secret\nkey".to_owned(),
+            prompt: "Passphrase:".to_owned(),
+        };
+
+        let program = fake_pinentry("pinentry", Some("p%C3%A4ss"));
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        let len = secret
+            .as_mut()
+            .update_with(&pinentry(program.clone()))
+            .unwrap();
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(&sec[..len], "päss%".as_bytes()));
+        std::fs::remove_file(program).unwrap();
+
+        let program = fake_pinentry("pinentry-cancel", None);
+        let err = secret
+            .as_mut()
+            .update_with(&pinentry(program.clone()))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        secret
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 16]));
+        std::fs::remove_file(program).unwrap();
+        assert_eq!(escape("100%\n"), "100%25%0A");
+    }
+}
@@ -0,0 +1,330 @@
+//! Operations delegated to the PIV application of a smart card (NIST SP 800-73-4), through
+//! PC/SC, so that keys never leave the card.
+//!
+//! As for [`super::pkcs11`], the secret is the PIN of the card, made of the bytes of the
+//! secret until the first NUL byte (or all of them), and only exposed for the duration of
+//! each operation.
+
+use std::io;
+
+use pcsc::{Context, Protocols, Scope, ShareMode};
+use zeroize::Zeroizing;
+
+use crate::api::SecretReader;
+
+/// Identifier of the PIV application.
+const PIV_AID: [u8; 5] = [0xa0, 0x00, 0x00, 0x03, 0x08];
+
+/// Status word of a successful command.
+const SW_OK: u16 = 0x9000;
+
+fn pcsc_error(err: pcsc::Error) -> io::Error {
+    match err {
+        pcsc::Error::NoSmartcard | pcsc::Error::RemovedCard => {
+            io::Error::new(io::ErrorKind::NotFound, err)
+        }
+        err => io::Error::other(err),
+    }
+}
+
+/// Maps a status word other than [`SW_OK`] to an error.
+fn status_error(sw: u16) -> io::Error {
+    match sw {
+        0x63c0..=0x63cf => io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("wrong PIN, {} tries left", sw & 0xf),
+        ),
+        0x6983 => io::Error::new(io::ErrorKind::PermissionDenied, "PIN is blocked"),
+        0x6982 => io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "security status not satisfied",
+        ),
+        0x6a80 | 0x6700 => io::Error::new(io::ErrorKind::InvalidInput, "data rejected by the card"),
+        0x6a81 | 0x6a82 | 0x6a88 => {
+            io::Error::new(io::ErrorKind::NotFound, "no such PIV application or key")
+        }
+        sw => io::Error::other(format!("smart card error {sw:04X}")),
+    }
+}
+
+/// A smart card with a PIV application.
+///
+/// The card is opened exclusively, so that no other application can make use of the PIN
+/// once verified.
+pub struct Card {
+    card: pcsc::Card,
+}
+
+/// Slot (key reference) of a PIV key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Slot {
+    /// PIV authentication (9A).
+    Authentication = 0x9a,
+    /// Digital signature (9C).
+    Signature = 0x9c,
+    /// Key management (9D), for decryption and key agreement.
+    KeyManagement = 0x9d,
+    /// Card authentication (9E).
+    CardAuthentication = 0x9e,
+}
+
+/// Algorithm of a PIV key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Algorithm {
+    Rsa1024 = 0x06,
+    Rsa2048 = 0x07,
+    EccP256 = 0x11,
+    EccP384 = 0x14,
+}
+
+impl Algorithm {
+    fn is_rsa(self) -> bool {
+        matches!(self, Algorithm::Rsa1024 | Algorithm::Rsa2048)
+    }
+}
+
+/// Splits the status word out of a response.
+fn split_status(response: &mut Zeroizing<Vec<u8>>) -> io::Result<u16> {
+    let Some(len) = response.len().checked_sub(2) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "truncated smart card response",
+        ));
+    };
+    let sw = u16::from_be_bytes([response[len], response[len + 1]]);
+    response.truncate(len);
+    Ok(sw)
+}
+
+/// Sends a command (`header` being its CLA, INS, P1 and P2 bytes) through `transmit`,
+/// chaining it if `data` does not fit in one short APDU, and gathers the response.
+fn exchange(
+    mut transmit: impl FnMut(&[u8]) -> io::Result<Zeroizing<Vec<u8>>>,
+    header: [u8; 4],
+    data: &[u8],
+) -> io::Result<Zeroizing<Vec<u8>>> {
+    let mut chunks = data.chunks(255).peekable();
+    let (mut response, mut sw) = loop {
+        // Commands without data are sent as a single empty chunk.
+        let chunk = chunks.next().unwrap_or_default();
+        let last = chunks.peek().is_none();
+        let mut apdu = Zeroizing::new(Vec::with_capacity(5 + chunk.len()));
+        apdu.push(if last { header[0] } else { header[0] | 0x10 });
+        apdu.extend_from_slice(&header[1..]);
+        if !chunk.is_empty() {
+            apdu.push(chunk.len() as u8);
+            apdu.extend_from_slice(chunk);
+        }
+        let mut response = transmit(&apdu)?;
+        let sw = split_status(&mut response)?;
+        if last {
+            break (response, sw);
+        }
+        if sw != SW_OK {
+            return Err(status_error(sw));
+        }
+    };
+    // More data is available.
+    while sw >> 8 == 0x61 {
+        let mut next = transmit(&[0x00, 0xc0, 0x00, 0x00, sw as u8])?;
+        sw = split_status(&mut next)?;
+        response.extend_from_slice(&next);
+    }
+    match sw {
+        SW_OK => Ok(response),
+        sw => Err(status_error(sw)),
+    }
+}
+
+/// Appends a BER-TLV with `tag` and `value` to `out`.
+fn push_tlv(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    match value.len() {
+        len @ ..0x80 => out.push(len as u8),
+        len @ ..0x100 => out.extend_from_slice(&[0x81, len as u8]),
+        len => out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]),
+    }
+    out.extend_from_slice(value);
+}
+
+/// Returns the value of the BER-TLV with `tag` at the start of `data`.
+fn parse_tlv(data: &[u8], tag: u8) -> io::Result<&[u8]> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed smart card response");
+    let (value, len) = match data {
+        [t, len @ ..=0x7f, value @ ..] if *t == tag => (value, *len as usize),
+        [t, 0x81, len, value @ ..] if *t == tag => (value, *len as usize),
+        [t, 0x82, high, low, value @ ..] if *t == tag => {
+            (value, u16::from_be_bytes([*high, *low]) as usize)
+        }
+        _ => return Err(invalid()),
+    };
+    value.get(..len).ok_or_else(invalid)
+}
+
+impl Card {
+    /// Connects to the card in the first reader whose name contains `reader` (or in the
+    /// first reader with a card, if `None`), and selects its PIV application.
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if there is no such card.
+    pub fn open(reader: Option<&str>) -> io::Result<Self> {
+        let context = Context::establish(Scope::User).map_err(pcsc_error)?;
+        let readers = context.list_readers_owned().map_err(pcsc_error)?;
+        let readers = readers
+            .iter()
+            .filter(|name| reader.is_none_or(|reader| name.to_string_lossy().contains(reader)));
+        for name in readers {
+            match context.connect(name, ShareMode::Exclusive, Protocols::ANY) {
+                Ok(card) => {
+                    let card = Card { card };
+                    card.exchange([0x00, 0xa4, 0x04, 0x00], &PIV_AID)?;
+                    return Ok(card);
+                }
+                Err(pcsc::Error::NoSmartcard | pcsc::Error::RemovedCard) => continue,
+                Err(err) => return Err(pcsc_error(err)),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no PIV smart card found",
+        ))
+    }
+
+    fn exchange(&self, header: [u8; 4], data: &[u8]) -> io::Result<Zeroizing<Vec<u8>>> {
+        exchange(
+            |apdu| {
+                let mut buffer = Zeroizing::new(vec![0; pcsc::MAX_BUFFER_SIZE]);
+                let len = self
+                    .card
+                    .transmit(apdu, &mut buffer)
+                    .map_err(pcsc_error)?
+                    .len();
+                buffer.truncate(len);
+                Ok(buffer)
+            },
+            header,
+            data,
+        )
+    }
+
+    /// Verifies the PIN in `sec`, padded with `FF` bytes as PIV requires.
+    fn verify(&self, sec: &[u8]) -> io::Result<()> {
+        let sec = super::passphrase(sec);
+        let mut pin = Zeroizing::new([0xff; 8]);
+        pin.get_mut(..sec.len())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "PIN is longer than 8 bytes")
+            })?
+            .copy_from_slice(sec);
+        self.exchange([0x00, 0x20, 0x00, 0x80], &*pin).map(drop)
+    }
+
+    /// Runs GENERAL AUTHENTICATE with the key in `slot` on `data`, given with `tag`, once
+    /// the PIN in `sec` is verified.
+    fn authenticate(
+        &self,
+        sec: &[u8],
+        slot: Slot,
+        algorithm: Algorithm,
+        tag: u8,
+        data: &[u8],
+    ) -> io::Result<Vec<u8>> {
+        self.verify(sec)?;
+        let mut template = Zeroizing::new(vec![0x82, 0x00]);
+        push_tlv(&mut template, tag, data);
+        let mut command = Zeroizing::new(Vec::new());
+        push_tlv(&mut command, 0x7c, &template);
+        let response = self.exchange([0x00, 0x87, algorithm as u8, slot as u8], &command)?;
+        Ok(parse_tlv(parse_tlv(&response, 0x7c)?, 0x82)?.to_vec())
+    }
+}
+
+/// Signs data with a key of the card, returning the signature.
+///
+/// For RSA keys, the data is the whole padded block (as long as the modulus), the card
+/// only performing the raw RSA operation. For ECC keys, it is the digest, and the
+/// signature is DER encoded.
+pub struct Sign<'a> {
+    pub card: &'a Card,
+    pub slot: Slot,
+    pub algorithm: Algorithm,
+    pub data: &'a [u8],
+}
+
+/// Decrypts data with a key of the card, returning the plaintext.
+///
+/// For RSA keys, the data is the ciphertext, and the plaintext is the whole padded block,
+/// to be unpadded by the caller. For ECC keys, the data is the public key of the peer (an
+/// uncompressed point), and the plaintext the shared secret of ECDH.
+pub struct Decrypt<'a> {
+    pub card: &'a Card,
+    pub slot: Slot,
+    pub algorithm: Algorithm,
+    pub data: &'a [u8],
+}
+
+/// Fails with [`io::ErrorKind::PermissionDenied`] if the PIN is wrong (or blocked), and
+/// with [`io::ErrorKind::NotFound`] if there is no key in the slot.
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for Sign<'_> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        self.card
+            .authenticate(sec, self.slot, self.algorithm, 0x81, self.data)
+    }
+}
+
+/// The plaintext is returned in clear: decrypt secrets with
+/// [`Secret::read_with_scratch`](crate::api::Secret::read_with_scratch) or store them
+/// in another secret right away.
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for Decrypt<'_> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let tag = if self.algorithm.is_rsa() { 0x81 } else { 0x85 };
+        self.card
+            .authenticate(sec, self.slot, self.algorithm, tag, self.data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_exchange() {
+        let data = [0x5a; 300];
+        let mut sent = Vec::new();
+        let response = exchange(
+            |apdu| {
+                sent.push(apdu.to_vec());
+                let response: &[u8] = match sent.len() {
+                    1 => &[0x90, 0x00],
+                    2 => &[0x7c, 0x61, 0x02],
+                    _ => &[0x01, 0x90, 0x00],
+                };
+                Ok(Zeroizing::new(response.to_vec()))
+            },
+            [0x00, 0x87, 0x11, 0x9a],
+            &data,
+        )
+        .unwrap();
+        assert_eq!(*response, [0x7c, 0x01]);
+        assert_eq!(sent[0][..5], [0x10, 0x87, 0x11, 0x9a, 0xff]);
+        assert_eq!(sent[0].len(), 5 + 255);
+        assert_eq!(sent[1][..5], [0x00, 0x87, 0x11, 0x9a, 45]);
+        assert_eq!(sent[2], [0x00, 0xc0, 0x00, 0x00, 0x02]);
+
+        let err = exchange(
+            |_| Ok(Zeroizing::new(vec![0x63, 0xc2])),
+            [0x00, 0x20, 0x00, 0x80],
+            &[],
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        let mut tlv = Vec::new();
+        push_tlv(&mut tlv, 0x82, &data);
+        assert_eq!(tlv[..4], [0x82, 0x82, 0x01, 0x2c]);
+        assert_eq!(parse_tlv(&tlv, 0x82).unwrap(), data);
+        assert!(parse_tlv(&tlv[..100], 0x82).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Operations delegated to a PKCS#11 token (an HSM, a smart card…), so that keys never
+//! leave it.
+//!
+//! The secret is the PIN of the token user, so it is only exposed for the duration of
+//! each operation. As PINs are shorter than their buffer, the PIN is made of the bytes
+//! of the secret until the first NUL byte (or all of them), as written by updaters such as
+//! [`UpdateSecretFromPrompt`](super::UpdateSecretFromPrompt).
+
+use std::{io, path::Path};
+
+pub use cryptoki::mechanism::Mechanism;
+use cryptoki::{
+    context::{CInitializeArgs, CInitializeFlags, Pkcs11},
+    object::{Attribute, ObjectHandle},
+    session::{Session, UserType},
+    slot::Slot,
+    types::RawAuthPin,
+};
+
+use crate::api::SecretReader;
+
+fn pkcs11_error(err: cryptoki::error::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+/// A token, found by its label among the slots of a PKCS#11 module.
+pub struct Token {
+    pkcs11: Pkcs11,
+    slot: Slot,
+}
+
+impl Token {
+    /// Loads the module (a shared library) and finds the token.
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if no token has this label.
+    pub fn open(module: impl AsRef<Path>, label: &str) -> io::Result<Self> {
+        let pkcs11 = Pkcs11::new(module.as_ref()).map_err(pkcs11_error)?;
+        pkcs11
+            .initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK))
+            .map_err(pkcs11_error)?;
+        for slot in pkcs11.get_slots_with_token().map_err(pkcs11_error)? {
+            let info = pkcs11.get_token_info(slot).map_err(pkcs11_error)?;
+            if info.label() == label {
+                return Ok(Token { pkcs11, slot });
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no such PKCS#11 token",
+        ))
+    }
+
+    /// Opens a session logged in as the user, with the PIN in `sec`.
+    fn login(&self, sec: &[u8]) -> io::Result<Session> {
+        let pin = RawAuthPin::new(Box::new(super::passphrase(sec).to_vec()));
+        let session = self
+            .pkcs11
+            .open_ro_session(self.slot)
+            .map_err(pkcs11_error)?;
+        session
+            .login_with_raw(UserType::User, &pin)
+            .map_err(pkcs11_error)?;
+        Ok(session)
+    }
+}
+
+/// Finds the only key with this label allowing `usage`.
+fn find_key(session: &Session, label: &str, usage: Attribute) -> io::Result<ObjectHandle> {
+    let keys = session
+        .find_objects(&[Attribute::Label(label.as_bytes().to_vec()), usage])
+        .map_err(pkcs11_error)?;
+    match keys[..] {
+        [key] => Ok(key),
+        [] => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no such PKCS#11 key",
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "several PKCS#11 keys have this label",
+        )),
+    }
+}
+
+/// Signs (or MACs) data with a key of the token, returning the signature.
+pub struct Sign<'a> {
+    pub token: &'a Token,
+    /// Label of the key.
+    pub key: &'a str,
+    pub mechanism: Mechanism<'a>,
+    pub data: &'a [u8],
+}
+
+/// Encrypts data with a key of the token, returning the ciphertext.
+pub struct Encrypt<'a> {
+    pub token: &'a Token,
+    /// Label of the key.
+    pub key: &'a str,
+    pub mechanism: Mechanism<'a>,
+    pub data: &'a [u8],
+}
+
+/// Decrypts data with a key of the token, returning the plaintext.
+pub struct Decrypt<'a> {
+    pub token: &'a Token,
+    /// Label of the key.
+    pub key: &'a str,
+    pub mechanism: Mechanism<'a>,
+    pub data: &'a [u8],
+}
+
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for Sign<'_> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let session = self.token.login(sec)?;
+        let key = find_key(&session, self.key, Attribute::Sign(true))?;
+        session
+            .sign(&self.mechanism, key, self.data)
+            .map_err(pkcs11_error)
+    }
+}
+
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for Encrypt<'_> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let session = self.token.login(sec)?;
+        let key = find_key(&session, self.key, Attribute::Encrypt(true))?;
+        session
+            .encrypt(&self.mechanism, key, self.data)
+            .map_err(pkcs11_error)
+    }
+}
+
+/// The plaintext is returned in clear: decrypt secrets with
+/// [`Secret::read_with_scratch`](crate::api::Secret::read_with_scratch) or store them
+/// in another secret right away.
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<u8>>> for Decrypt<'_> {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let session = self.token.login(sec)?;
+        let key = find_key(&session, self.key, Attribute::Decrypt(true))?;
+        session
+            .decrypt(&self.mechanism, key, self.data)
+            .map_err(pkcs11_error)
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+};
+
+use zeroize::Zeroize;
+
+use crate::api::SecretUpdater;
+
+/// Reads a passphrase typed on the terminal, with echo disabled, after writing the prompt.
+///
+/// The terminal is used directly (`/dev/tty` on Unix, the console on Windows), even when the
+/// standard input and output are redirected. The passphrase ends at the end of the line, that
+/// is not part of it.
+///
+/// Since a `String` cannot grow through the `&mut str` lent to updaters, the passphrase
+/// is written (UTF-8 encoded) into a byte array: use [`str::from_utf8`] when reading it.
+///
+/// # Security
+///
+/// Typed bytes are written directly into the secret, without any intermediate line buffer
+/// on Unix. On Windows, characters are read in a small buffer, zeroized afterwards.
+/// Echo is restored even on failure.
+pub struct UpdateSecretFromPrompt(pub String);
+
+/// Returns the length of the passphrase.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the passphrase is larger than the secret, and
+/// with [`io::ErrorKind::UnexpectedEof`] if the terminal is closed before the end of the line.
+/// On failure, the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromPrompt {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = prompt(&self.0, sec);
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Writes characters of the passphrase into the secret, until the end of the line.
+pub(super) struct Line<'a> {
+    sec: &'a mut [u8],
+    len: usize,
+    overflow: bool,
+}
+
+impl<'a> Line<'a> {
+    pub(super) fn new(sec: &'a mut [u8]) -> Self {
+        Line {
+            sec,
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    pub(super) fn push(&mut self, bytes: &[u8]) {
+        match self.sec.get_mut(self.len..self.len + bytes.len()) {
+            Some(dst) if !self.overflow => {
+                dst.copy_from_slice(bytes);
+                self.len += bytes.len();
+            }
+            // Keep reading until the end of the line, not to leave the rest on the terminal.
+            _ => self.overflow = true,
+        }
+    }
+
+    pub(super) fn finish(self) -> io::Result<usize> {
+        match self.overflow {
+            true => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "passphrase is larger than its buffer",
+            )),
+            false => Ok(self.len),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn prompt(prompt: &str, sec: &mut [u8]) -> io::Result<usize> {
+    use std::{io::Read, os::fd::AsRawFd};
+
+    /// Restores the terminal settings, even on failure.
+    struct Restore<'a>(&'a File, libc::termios);
+    impl Drop for Restore<'_> {
+        fn drop(&mut self) {
+            unsafe { libc::tcsetattr(self.0.as_raw_fd(), libc::TCSAFLUSH, &self.1) };
+        }
+    }
+
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    tty.write_all(prompt.as_bytes())?;
+    tty.flush()?;
+    let restore = Restore(&tty, termios);
+    termios.c_lflag &= !(libc::ECHO | libc::ECHONL);
+    if unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut line = Line::new(sec);
+    let mut byte = [0u8];
+    loop {
+        match (&tty).read(&mut byte)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ if byte[0] == b'\n' => break,
+            _ => line.push(&byte),
+        }
+    }
+    byte.zeroize();
+    drop(restore);
+    tty.write_all(b"\n")?;
+    line.finish()
+}
+
+#[cfg(windows)]
+fn prompt(prompt: &str, sec: &mut [u8]) -> io::Result<usize> {
+    use std::os::windows::io::AsRawHandle;
+
+    use windows_sys::Win32::System::Console::{
+        ENABLE_ECHO_INPUT, GetConsoleMode, ReadConsoleW, SetConsoleMode,
+    };
+
+    /// Restores the console mode, even on failure.
+    struct Restore<'a>(&'a File, u32);
+    impl Drop for Restore<'_> {
+        fn drop(&mut self) {
+            unsafe { SetConsoleMode(self.0.as_raw_handle(), self.1) };
+        }
+    }
+
+    let input = OpenOptions::new().read(true).write(true).open("CONIN$")?;
+    let mut output = OpenOptions::new().write(true).open("CONOUT$")?;
+    let mut mode = 0;
+    if unsafe { GetConsoleMode(input.as_raw_handle(), &mut mode) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    output.write_all(prompt.as_bytes())?;
+    let restore = Restore(&input, mode);
+    if unsafe { SetConsoleMode(input.as_raw_handle(), mode & !ENABLE_ECHO_INPUT) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut line = Line::new(sec);
+    let mut units = [0u16; 2];
+    let mut pending = 0;
+    let mut utf8 = [0u8; 4];
+    let result = loop {
+        let mut read = 0;
+        let ok = unsafe {
+            ReadConsoleW(
+                input.as_raw_handle(),
+                units[pending..].as_mut_ptr().cast(),
+                1,
+                &mut read,
+                std::ptr::null(),
+            )
+        };
+        if ok == 0 {
+            break Err(io::Error::last_os_error());
+        }
+        if read == 0 {
+            break Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        pending += 1;
+        match char::decode_utf16(units[..pending].iter().copied()).next() {
+            Some(Ok('\r')) => pending = 0,
+            Some(Ok('\n')) => break Ok(()),
+            Some(Ok(c)) => {
+                line.push(c.encode_utf8(&mut utf8).as_bytes());
+                pending = 0;
+            }
+            // Leading half of a surrogate pair, read the other one.
+            Some(Err(_)) if pending == 1 => {}
+            _ => {
+                break Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid UTF-16 input",
+                ));
+            }
+        }
+    };
+    units.zeroize();
+    utf8.zeroize();
+    drop(restore);
+    output.write_all(b"\n")?;
+    result.and_then(|()| line.finish())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn prompt(_prompt: &str, _sec: &mut [u8]) -> io::Result<usize> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "terminal prompts are not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::Line;
+
+    #[test]
+    fn test_line_overflow() {
+        let mut sec = [0u8; 4];
+        let mut line = Line {
+            sec: &mut sec,
+            len: 0,
+            overflow: false,
+        };
+        for byte in b"pass" {
+            line.push(&[*byte]);
+        }
+        assert_eq!(line.finish().unwrap(), 4);
+        let mut line = Line {
+            sec: &mut sec,
+            len: 0,
+            overflow: false,
+        };
+        line.push("pässe".as_bytes());
+        assert!(line.finish().is_err());
+    }
+}
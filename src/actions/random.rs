@@ -0,0 +1,38 @@
+use std::io;
+
+use crate::api::SecretUpdater;
+
+/// Fills the whole secret with random bytes from the OS CSPRNG, to generate a fresh key
+/// in place.
+///
+/// # Security
+///
+/// The bytes are written directly into the secret, without any intermediate copy.
+pub struct GenerateRandom;
+
+/// Fails if the OS CSPRNG cannot be used, in which case the secret is left unchanged.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<()>> for GenerateRandom {
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        getrandom::getrandom(sec).map_err(io::Error::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_generate_random() {
+        let mut first = pin!(Secret::<[u8; 32]>::new());
+        let mut second = pin!(Secret::<[u8; 32]>::new());
+        first.as_mut().update_with(&GenerateRandom).unwrap();
+        second.as_mut().update_with(&GenerateRandom).unwrap();
+        let first = first.as_ref().read_with(&|sec: &[u8]| sec.to_vec());
+        assert_ne!(first, [0; 32]);
+        assert!(second.as_ref().read_with(&|sec: &[u8]| sec != first));
+    }
+}
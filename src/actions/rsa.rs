@@ -0,0 +1,182 @@
+use std::{fmt, io};
+
+use ::rsa::{
+    Oaep, Pkcs1v15Sign, Pss, RsaPrivateKey,
+    pkcs1::{
+        self, DecodeRsaPrivateKey, EncodeRsaPublicKey,
+        der::{Decode, Reader, SliceReader},
+    },
+};
+use aes_gcm::aead::OsRng;
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::api::{SecretReader, Unsizeable};
+
+/// Capacity of an [`RsaKey`], enough for the private key of a 4096-bit modulus.
+pub const RSA_KEY_CAPACITY: usize = 2400;
+
+/// Data of a secret holding an RSA private key, as its `RSAPrivateKey` structure (PKCS#1)
+/// in DER, followed by zeros.
+///
+/// It is loaded, as any raw private key, with [`super::UpdateSecretFromPkcs8`] or
+/// [`super::UpdateSecretFromEncryptedPkcs8`].
+pub struct RsaKey([u8; RSA_KEY_CAPACITY]);
+
+impl Default for RsaKey {
+    fn default() -> Self {
+        RsaKey([0; RSA_KEY_CAPACITY])
+    }
+}
+
+impl Zeroize for RsaKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Unsizeable for RsaKey {
+    type Unsized = [u8];
+
+    fn get_unsized(&self) -> &Self::Unsized {
+        &self.0
+    }
+
+    fn get_unsized_mut(&mut self) -> &mut Self::Unsized {
+        &mut self.0
+    }
+}
+
+/// Signature schemes of RSA, with SHA-256 (RFC 8017).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RsaScheme {
+    /// RSASSA-PKCS1-v1_5, as RS256 of JOSE.
+    Pkcs1v15,
+    /// RSASSA-PSS, with MGF1-SHA256 and a 32-byte salt, as PS256 of JOSE.
+    Pss,
+}
+
+/// Signs a message with the RSA key held in the secret.
+pub struct RsaSign {
+    pub message: Vec<u8>,
+    pub scheme: RsaScheme,
+}
+
+/// Decrypts a ciphertext with the RSA key held in the secret, with RSAES-OAEP (with SHA-256
+/// and MGF1-SHA256, and no label).
+///
+/// # Security
+///
+/// The private key operation is blinded, but the `rsa` crate is not fully constant-time
+/// (RUSTSEC-2023-0071): do not expose decryption to an attacker able to time it precisely.
+pub struct RsaDecrypt(pub Vec<u8>);
+
+/// Returns the public key of the RSA key held in the secret, as its `RSAPublicKey`
+/// structure (PKCS#1) in DER.
+pub struct RsaPublicKey;
+
+fn invalid_data(err: impl fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// Parses the private key at the start of `sec`, ignoring the padding.
+fn private_key(sec: &[u8]) -> io::Result<RsaPrivateKey> {
+    let mut reader = SliceReader::new(sec).map_err(invalid_data)?;
+    pkcs1::RsaPrivateKey::decode(&mut reader).map_err(invalid_data)?;
+    let len = usize::try_from(reader.position()).map_err(invalid_data)?;
+    RsaPrivateKey::from_pkcs1_der(&sec[..len]).map_err(invalid_data)
+}
+
+/// Returns the signature.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the secret is not a valid RSA private key.
+impl SecretReader<RsaKey, io::Result<Vec<u8>>> for RsaSign {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let key = private_key(sec)?;
+        let digest = Sha256::digest(&self.message);
+        let signature = match self.scheme {
+            RsaScheme::Pkcs1v15 => {
+                key.sign_with_rng(&mut OsRng, Pkcs1v15Sign::new::<Sha256>(), &digest)
+            }
+            RsaScheme::Pss => key.sign_with_rng(&mut OsRng, Pss::new::<Sha256>(), &digest),
+        };
+        signature.map_err(invalid_data)
+    }
+}
+
+/// Returns the plaintext.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the secret is not a valid RSA private key,
+/// or if the ciphertext was not encrypted under this key, or was altered.
+impl SecretReader<RsaKey, io::Result<Zeroizing<Vec<u8>>>> for RsaDecrypt {
+    fn read(&self, sec: &[u8]) -> io::Result<Zeroizing<Vec<u8>>> {
+        let key = private_key(sec)?;
+        key.decrypt_blinded(&mut OsRng, Oaep::new::<Sha256>(), &self.0)
+            .map(Zeroizing::new)
+            .map_err(invalid_data)
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the secret is not a valid RSA private key.
+impl SecretReader<RsaKey, io::Result<Vec<u8>>> for RsaPublicKey {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let key = private_key(sec)?;
+        let der = key.to_public_key().to_pkcs1_der().map_err(invalid_data)?;
+        Ok(der.into_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use ::rsa::{pkcs1::DecodeRsaPublicKey, pkcs1::EncodeRsaPrivateKey, traits::PublicKeyParts};
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_rsa() {
+        let key = RsaPrivateKey::new(&mut OsRng, 1024).unwrap();
+        let der = key.to_pkcs1_der().unwrap();
+        let mut secret = pin!(Secret::<RsaKey>::new());
+        secret.as_mut().update_with(&|sec: &mut [u8]| {
+            sec[..der.as_bytes().len()].copy_from_slice(der.as_bytes())
+        });
+
+        let public = secret.as_ref().read_with(&RsaPublicKey).unwrap();
+        let public = ::rsa::RsaPublicKey::from_pkcs1_der(&public).unwrap();
+        assert_eq!(public.n(), key.n());
+
+        let sign = |scheme| {
+            secret.as_ref().read_with(&RsaSign {
+                message: b"secret message".to_vec(),
+                scheme,
+            })
+        };
+        let digest = Sha256::digest(b"secret message");
+        let signature = sign(RsaScheme::Pkcs1v15).unwrap();
+        assert!(
+            public
+                .verify(Pkcs1v15Sign::new::<Sha256>(), &digest, &signature)
+                .is_ok()
+        );
+        let signature = sign(RsaScheme::Pss).unwrap();
+        assert!(
+            public
+                .verify(Pss::new::<Sha256>(), &digest, &signature)
+                .is_ok()
+        );
+
+        let ciphertext = public
+            .encrypt(&mut OsRng, Oaep::new::<Sha256>(), b"wrapped key")
+            .unwrap();
+        let plaintext = secret.as_ref().read_with(&RsaDecrypt(ciphertext)).unwrap();
+        assert_eq!(**plaintext, *b"wrapped key");
+
+        secret.as_mut().update_with(&|sec: &mut [u8]| sec.zeroize());
+        let err = secret.as_ref().read_with(&RsaPublicKey).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
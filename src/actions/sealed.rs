@@ -0,0 +1,158 @@
+//! Files encrypted with AES-256-GCM under the key held in a secret, written atomically.
+//!
+//! A sealed file is made of an 8-byte header (the magic `SECRUST` followed by the version
+//! of the format, 1), the random 12-byte nonce, then the ciphertext and its 16-byte tag.
+//! The header is authenticated as associated data, so that it cannot be altered.
+
+use std::{fs, io, path::PathBuf};
+
+use aes_gcm::{
+    AeadCore, Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, OsRng, Payload},
+};
+
+use crate::api::SecretReader;
+
+/// Magic starting sealed files.
+const MAGIC: &[u8; 7] = b"SECRUST";
+/// Version of the format of sealed files.
+const VERSION: u8 = 1;
+/// Length of the nonce following the header.
+const NONCE_LEN: usize = 12;
+
+/// Encrypts a payload into a sealed file, replacing it atomically (written to a temporary
+/// file, synced, then renamed), so that it is never left half-written.
+///
+/// Uses of the key are not counted, as with [`super::Cipher`]: past 2³² files, nonces may
+/// collide.
+pub struct EncryptToFile {
+    pub path: PathBuf,
+    pub payload: Vec<u8>,
+}
+
+/// Decrypts a sealed file, as written by [`EncryptToFile`], returning its payload.
+pub struct DecryptFile(pub PathBuf);
+
+fn header() -> [u8; 8] {
+    let mut header = [VERSION; 8];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the payload is too large for AES-GCM, and
+/// with the errors of the file system.
+impl SecretReader<[u8; 32], io::Result<()>> for EncryptToFile {
+    fn read(&self, sec: &[u8]) -> io::Result<()> {
+        let header = header();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: &self.payload,
+            aad: &header,
+        };
+        let ciphertext = Aes256Gcm::new(sec.into())
+            .encrypt(&nonce, payload)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "payload is too large for AES-GCM",
+                )
+            })?;
+        super::write_atomically(&self.path, &[&header[..], &nonce, &ciphertext].concat())
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the file is not a sealed file, is of an
+/// unsupported version, was not encrypted under this key, or was altered, and with the
+/// errors of the file system.
+impl SecretReader<[u8; 32], io::Result<Vec<u8>>> for DecryptFile {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<u8>> {
+        let invalid_data = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let content = fs::read(&self.0)?;
+        let header = header();
+        let (file_header, rest) = match content.split_at_checked(header.len()) {
+            Some((file_header, rest)) if file_header.starts_with(MAGIC) => (file_header, rest),
+            _ => return Err(invalid_data("file is not a sealed file")),
+        };
+        if file_header != header {
+            return Err(invalid_data("sealed file is of an unsupported version"));
+        }
+        let (nonce, ciphertext) = rest
+            .split_at_checked(NONCE_LEN)
+            .ok_or_else(|| invalid_data("sealed file is truncated"))?;
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &header,
+        };
+        Aes256Gcm::new(sec.into())
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                invalid_data("sealed file was not encrypted under this key, or was altered")
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_sealed_file() {
+        let path = std::env::temp_dir().join(format!("secrust-sealed-{}", std::process::id()));
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let encrypt = |payload: &[u8]| {
+            secret.as_ref().read_with(&EncryptToFile {
+                path: path.clone(),
+                payload: payload.to_vec(),
+            })
+        };
+        let decrypt = || secret.as_ref().read_with(&DecryptFile(path.clone()));
+
+        encrypt(b"secret payload").unwrap();
+        let content = fs::read(&path).unwrap();
+        assert_eq!(content[..8], *b"SECRUST\x01");
+        assert_eq!(content.len(), 8 + NONCE_LEN + 14 + 16);
+        assert_eq!(decrypt().unwrap(), b"secret payload");
+        // Replaced as a whole, without leaving the temporary file behind, and private.
+        encrypt(b"other payload").unwrap();
+        assert_eq!(decrypt().unwrap(), b"other payload");
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let leftovers = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(|entry| entry.unwrap().file_name().into_string().ok())
+            .filter(|entry| entry.starts_with(name) && entry != name);
+        assert_eq!(leftovers.count(), 0);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let content = fs::read(&path).unwrap();
+        for (i, msg) in [
+            (0, "file is not a sealed file"),
+            (7, "sealed file is of an unsupported version"),
+            (
+                20,
+                "sealed file was not encrypted under this key, or was altered",
+            ),
+        ] {
+            let mut altered = content.clone();
+            altered[i] ^= 1;
+            fs::write(&path, altered).unwrap();
+            let err = decrypt().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), msg);
+        }
+        fs::write(&path, &content[..10]).unwrap();
+        assert_eq!(decrypt().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+//! Secrets kept by the Freedesktop Secret Service (GNOME Keyring, KWallet, KeePassXC…), over
+//! D-Bus.
+//!
+//! Items are found by their attributes, as with `secret-tool` or libsecret. Secrets are
+//! transferred on the bus encrypted with a session key negotiated by Diffie-Hellman
+//! (`dh-ietf1024-sha256-aes128-cbc-pkcs7`), so that other clients of the bus, and the bus
+//! itself, never see them in clear.
+
+use std::{collections::HashMap, io};
+
+use aes::{
+    Aes128,
+    cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, block_padding::Pkcs7},
+};
+use crypto_bigint::{
+    Encoding, U1024, const_residue, impl_modulus, modular::constant_mod::ResidueParams,
+};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Type, Value},
+};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::api::{SecretReader, SecretUpdater};
+
+const DESTINATION: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const ALGORITHM: &str = "dh-ietf1024-sha256-aes128-cbc-pkcs7";
+
+// Second Oakley group of RFC 2409, required by the Secret Service specification.
+impl_modulus!(
+    Oakley2,
+    U1024,
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7EDEE386BFB5A899FA5AE9F24117C4B1FE649286651ECE65381FFFFFFFFFFFFFFFF"
+);
+
+/// Loads the secret of the first item with these attributes, unlocking its collection if
+/// needed, which may prompt the user.
+///
+/// # Security
+///
+/// The secret is decrypted in place in a zeroized buffer, then copied into the secret.
+pub struct UpdateSecretFromSecretService {
+    pub attributes: HashMap<String, String>,
+}
+
+/// Stores the secret as an item of the default collection with these label and attributes,
+/// replacing any item with the same attributes.
+///
+/// # Security
+///
+/// The item can be read by any client of the session bus while the collection is unlocked.
+pub struct StoreInSecretService {
+    pub label: String,
+    pub attributes: HashMap<String, String>,
+}
+
+/// Secret as transferred on the bus.
+#[derive(Deserialize, Serialize, Type)]
+struct EncryptedSecret {
+    session: OwnedObjectPath,
+    /// Initialization vector.
+    parameters: Vec<u8>,
+    value: Vec<u8>,
+    content_type: String,
+}
+
+fn dbus_error(err: zbus::Error) -> io::Error {
+    match err {
+        zbus::Error::InputOutput(err) => io::Error::new(err.kind(), err.to_string()),
+        zbus::Error::MethodError(name, description, _) => {
+            let kind = match name.as_str() {
+                "org.freedesktop.Secret.Error.NoSuchObject" => io::ErrorKind::NotFound,
+                "org.freedesktop.Secret.Error.IsLocked" => io::ErrorKind::PermissionDenied,
+                "org.freedesktop.DBus.Error.ServiceUnknown" => io::ErrorKind::NotFound,
+                _ => io::ErrorKind::Other,
+            };
+            io::Error::new(kind, format!("{name}: {}", description.unwrap_or_default()))
+        }
+        err => io::Error::other(err),
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Generates a Diffie-Hellman key pair, returning the private and public keys.
+fn key_pair() -> io::Result<(Zeroizing<U1024>, [u8; 128])> {
+    let mut bytes = Zeroizing::new([0u8; 128]);
+    getrandom::getrandom(&mut *bytes)?;
+    let private = Zeroizing::new(U1024::from_be_bytes(*bytes));
+    let generator = U1024::from_u8(2);
+    let public = const_residue!(generator, Oakley2).pow(&*private).retrieve();
+    Ok((private, public.to_be_bytes()))
+}
+
+/// Derives the AES-128 session key from the public key of the service.
+fn session_key(private: &U1024, public: &[u8]) -> io::Result<Zeroizing<[u8; 16]>> {
+    let mut padded = [0u8; 128];
+    match public.len() {
+        len @ 1..=128 => padded[128 - len..].copy_from_slice(public),
+        _ => return Err(invalid_data("invalid Diffie-Hellman public key")),
+    }
+    let public = U1024::from_be_bytes(padded);
+    let max = Oakley2::MODULUS.wrapping_sub(&U1024::ONE);
+    if public <= U1024::ONE || public >= max {
+        return Err(invalid_data("invalid Diffie-Hellman public key"));
+    }
+    let shared = Zeroizing::new(const_residue!(public, Oakley2).pow(private).retrieve());
+    // Padded to the length of the prime, as libsecret does.
+    let shared = Zeroizing::new(shared.to_be_bytes());
+    let mut key = Zeroizing::new([0u8; 16]);
+    Hkdf::<Sha256>::new(None, &*shared)
+        .expand(&[], &mut *key)
+        .expect("16 bytes is a valid length");
+    Ok(key)
+}
+
+/// Encrypts `sec` with the session key, returning the initialization vector and ciphertext.
+fn encrypt(key: &[u8; 16], sec: &[u8]) -> io::Result<([u8; 16], Vec<u8>)> {
+    let mut iv = [0u8; 16];
+    getrandom::getrandom(&mut iv)?;
+    let mut buffer = Zeroizing::new(vec![0u8; (sec.len() / 16 + 1) * 16]);
+    buffer[..sec.len()].copy_from_slice(sec);
+    let ciphertext = cbc::Encryptor::<Aes128>::new(key.into(), &iv.into())
+        .encrypt_padded_mut::<Pkcs7>(&mut buffer, sec.len())
+        .expect("buffer has room for the padding")
+        .to_vec();
+    Ok((iv, ciphertext))
+}
+
+/// Decrypts `ciphertext` with the session key into the start of `sec`.
+fn decrypt(key: &[u8; 16], iv: &[u8], ciphertext: &[u8], sec: &mut [u8]) -> io::Result<usize> {
+    let iv: [u8; 16] = iv
+        .try_into()
+        .map_err(|_| invalid_data("invalid initialization vector"))?;
+    let mut buffer = Zeroizing::new(ciphertext.to_vec());
+    let plaintext = cbc::Decryptor::<Aes128>::new(key.into(), &iv.into())
+        .decrypt_padded_mut::<Pkcs7>(&mut buffer)
+        .map_err(|_| invalid_data("invalid padding of the secret"))?;
+    match sec.get_mut(..plaintext.len()) {
+        Some(dst) => dst.copy_from_slice(plaintext),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "item secret is larger than the secret",
+            ));
+        }
+    }
+    Ok(plaintext.len())
+}
+
+/// Encrypted session with the Secret Service, closed when dropped.
+struct Session<'a> {
+    service: Proxy<'a>,
+    path: OwnedObjectPath,
+    key: Zeroizing<[u8; 16]>,
+}
+
+impl<'a> Session<'a> {
+    fn open(connection: &'a Connection) -> io::Result<Self> {
+        let service = Proxy::new(
+            connection,
+            DESTINATION,
+            SERVICE_PATH,
+            "org.freedesktop.Secret.Service",
+        )
+        .map_err(dbus_error)?;
+        let (private, public) = key_pair()?;
+        let (output, path): (OwnedValue, OwnedObjectPath) = service
+            .call("OpenSession", &(ALGORITHM, Value::from(&public[..])))
+            .map_err(dbus_error)?;
+        let public = <Vec<u8>>::try_from(output)
+            .map_err(|_| invalid_data("invalid Diffie-Hellman public key"))?;
+        let key = session_key(&private, &public)?;
+        Ok(Session { service, path, key })
+    }
+
+    /// Unlocks `objects` (items or collections), prompting the user if needed.
+    fn unlock(&self, objects: &[OwnedObjectPath]) -> io::Result<()> {
+        let (_, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) = self
+            .service
+            .call("Unlock", &(objects,))
+            .map_err(dbus_error)?;
+        self.prompt(prompt)
+    }
+
+    /// Shows `prompt`, if any, and waits for its completion.
+    fn prompt(&self, prompt: OwnedObjectPath) -> io::Result<()> {
+        if prompt.as_str() == "/" {
+            return Ok(());
+        }
+        let prompt = Proxy::new(
+            self.service.connection(),
+            DESTINATION,
+            prompt,
+            "org.freedesktop.Secret.Prompt",
+        )
+        .map_err(dbus_error)?;
+        let mut completed = prompt.receive_signal("Completed").map_err(dbus_error)?;
+        prompt.call_method("Prompt", &("",)).map_err(dbus_error)?;
+        let message = completed
+            .next()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let (dismissed, _): (bool, OwnedValue) =
+            message.body().deserialize().map_err(dbus_error)?;
+        match dismissed {
+            true => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the Secret Service prompt was dismissed",
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        if let Ok(session) = Proxy::new(
+            self.service.connection(),
+            DESTINATION,
+            &self.path,
+            "org.freedesktop.Secret.Session",
+        ) {
+            let _ = session.call_method("Close", &());
+        }
+    }
+}
+
+/// Returns the length of the secret of the item.
+///
+/// Fails with [`io::ErrorKind::NotFound`] if there is no such item (or no Secret Service),
+/// with [`io::ErrorKind::PermissionDenied`] if the user dismisses the unlock prompt, and with
+/// [`io::ErrorKind::InvalidInput`] if the item secret is larger than the secret. On failure,
+/// the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromSecretService {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = Connection::session()
+            .map_err(dbus_error)
+            .and_then(|connection| {
+                let session = Session::open(&connection)?;
+                let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = session
+                    .service
+                    .call("SearchItems", &(&self.attributes,))
+                    .map_err(dbus_error)?;
+                let item = match (unlocked.into_iter().next(), locked.into_iter().next()) {
+                    (Some(item), _) => item,
+                    (None, Some(item)) => {
+                        session.unlock(std::slice::from_ref(&item))?;
+                        item
+                    }
+                    (None, None) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            "no Secret Service item with these attributes",
+                        ));
+                    }
+                };
+                let item = Proxy::new(
+                    &connection,
+                    DESTINATION,
+                    item,
+                    "org.freedesktop.Secret.Item",
+                )
+                .map_err(dbus_error)?;
+                let (secret,): (EncryptedSecret,) = item
+                    .call("GetSecret", &(&session.path,))
+                    .map_err(dbus_error)?;
+                decrypt(&session.key, &secret.parameters, &secret.value, sec)
+            });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+/// Fails with [`io::ErrorKind::NotFound`] if there is no default collection (or no Secret
+/// Service), and with [`io::ErrorKind::PermissionDenied`] if the user dismisses the unlock
+/// prompt.
+impl<const N: usize> SecretReader<[u8; N], io::Result<()>> for StoreInSecretService {
+    fn read(&self, sec: &[u8]) -> io::Result<()> {
+        let connection = Connection::session().map_err(dbus_error)?;
+        let session = Session::open(&connection)?;
+        let (collection,): (OwnedObjectPath,) = session
+            .service
+            .call("ReadAlias", &("default",))
+            .map_err(dbus_error)?;
+        if collection.as_str() == "/" {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no default Secret Service collection",
+            ));
+        }
+        session.unlock(std::slice::from_ref(&collection))?;
+        let (iv, value) = encrypt(&session.key, sec)?;
+        let secret = EncryptedSecret {
+            session: session.path.clone(),
+            parameters: iv.to_vec(),
+            value,
+            content_type: "application/octet-stream".to_owned(),
+        };
+        let properties = HashMap::from([
+            (
+                "org.freedesktop.Secret.Item.Label",
+                Value::from(&self.label),
+            ),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                Value::from(self.attributes.clone()),
+            ),
+        ]);
+        let collection = Proxy::new(
+            &connection,
+            DESTINATION,
+            ObjectPath::from(&collection),
+            "org.freedesktop.Secret.Collection",
+        )
+        .map_err(dbus_error)?;
+        let (_, prompt): (OwnedObjectPath, OwnedObjectPath) = collection
+            .call("CreateItem", &(properties, secret, true))
+            .map_err(dbus_error)?;
+        session.prompt(prompt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_session_encryption() {
+        let (client_private, client_public) = key_pair().unwrap();
+        let (service_private, service_public) = key_pair().unwrap();
+        let key = session_key(&client_private, &service_public).unwrap();
+        assert_eq!(
+            *key,
+            *session_key(&service_private, &client_public).unwrap()
+        );
+        assert!(session_key(&client_private, &[1]).is_err());
+
+        let (iv, ciphertext) = encrypt(&key, b"service secret").unwrap();
+        assert_eq!(ciphertext.len(), 16);
+        let mut sec = [0u8; 16];
+        let len = decrypt(&key, &iv, &ciphertext, &mut sec).unwrap();
+        assert_eq!(&sec[..len], b"service secret");
+        let mut small = [0u8; 8];
+        let err = decrypt(&key, &iv, &ciphertext, &mut small).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,219 @@
+use std::io;
+
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::api::{SecretReader, SecretUpdater};
+
+/// Multiplies in GF(2⁸), modulo the polynomial of AES (x⁸ + x⁴ + x³ + x + 1), in constant
+/// time.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(a >> 7));
+        b >>= 1;
+    }
+    product
+}
+
+/// Inverts in GF(2⁸), as a²⁵⁴, in constant time. The inverse of 0 is 0.
+fn inv(a: u8) -> u8 {
+    // a^254 = a^(2 + 4 + 8 + … + 128)
+    let mut square = a;
+    let mut inverse = 1;
+    for _ in 1..8 {
+        square = mul(square, square);
+        inverse = mul(inverse, square);
+    }
+    inverse
+}
+
+/// Splits the secret into `n` shares with Shamir's secret sharing over GF(2⁸), so that any
+/// `k` of them rebuild it, while fewer reveal nothing about it.
+///
+/// Each share starts with its index (from 1 to `n`), followed by one byte for each byte of
+/// the secret.
+///
+/// # Security
+///
+/// The shares are as sensitive as the secret, when `k` of them are gathered: they are
+/// zeroized on drop, as are the random coefficients of the polynomials.
+pub struct ShamirSplit {
+    pub k: u8,
+    pub n: u8,
+}
+
+/// Returns the `n` shares.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if `k` is 0 or larger than `n`, and with the
+/// errors of the OS CSPRNG.
+impl<const N: usize> SecretReader<[u8; N], io::Result<Vec<Zeroizing<Vec<u8>>>>> for ShamirSplit {
+    fn read(&self, sec: &[u8]) -> io::Result<Vec<Zeroizing<Vec<u8>>>> {
+        if self.k == 0 || self.k > self.n {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "threshold must be between 1 and the number of shares",
+            ));
+        }
+        let mut shares: Vec<_> = (1..=self.n)
+            .map(|x| {
+                let mut share = Zeroizing::new(Vec::with_capacity(1 + sec.len()));
+                share.push(x);
+                share
+            })
+            .collect();
+        let mut coefficients = Zeroizing::new(vec![0; usize::from(self.k)]);
+        for &byte in sec {
+            // The polynomial of each byte is the byte, plus k - 1 random terms.
+            coefficients[0] = byte;
+            getrandom::getrandom(&mut coefficients[1..])?;
+            for share in &mut shares {
+                let x = share[0];
+                // Horner's method.
+                let y = coefficients
+                    .iter()
+                    .rev()
+                    .fold(0, |y, &coefficient| mul(y, x) ^ coefficient);
+                share.push(y);
+            }
+        }
+        coefficients.zeroize();
+        Ok(shares)
+    }
+}
+
+/// Rebuilds a secret split by [`ShamirSplit`] from `k` of its shares, writing it directly
+/// into the secret.
+///
+/// The shares are zeroized on drop.
+///
+/// # Security
+///
+/// With fewer than `k` shares, a wrong secret is rebuilt without any error: check it, for
+/// instance against a digest or by decrypting with it.
+pub struct ShamirCombine(pub Vec<Zeroizing<Vec<u8>>>);
+
+/// Returns the length of the secret.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the shares are not of the same length, or
+/// have invalid or duplicated indexes, and with [`io::ErrorKind::InvalidInput`] if there is
+/// no share, or if the split secret is larger than the secret. On failure, the secret is
+/// zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for ShamirCombine {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = self.combine(sec);
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+impl ShamirCombine {
+    fn combine(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let invalid_data = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let len = match self.0.first() {
+            Some(share) => share
+                .len()
+                .checked_sub(1)
+                .ok_or_else(|| invalid_data("empty share"))?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "at least one share is needed",
+                ));
+            }
+        };
+        let sec = sec.get_mut(..len).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "split secret is larger than the secret",
+            )
+        })?;
+        for (i, share) in self.0.iter().enumerate() {
+            if share.len() != 1 + len {
+                return Err(invalid_data("shares are not of the same length"));
+            }
+            if share[0] == 0 || self.0[..i].iter().any(|other| other[0] == share[0]) {
+                return Err(invalid_data("shares have invalid or duplicated indexes"));
+            }
+        }
+        sec.fill(0);
+        for share in &self.0 {
+            // Lagrange basis polynomial of the share, at 0.
+            let x = share[0];
+            let basis = self
+                .0
+                .iter()
+                .filter(|other| other[0] != x)
+                .fold(1, |basis, other| {
+                    mul(basis, mul(other[0], inv(other[0] ^ x)))
+                });
+            for (byte, &y) in sec.iter_mut().zip(&share[1..]) {
+                *byte ^= mul(basis, y);
+            }
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_shamir() {
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert_eq!(mul(0x57, 0x13), 0xfe);
+        assert!((1..=255).all(|a| mul(a, inv(a)) == 1));
+
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"master key bytes"));
+        let split = |k, n| secret.as_ref().read_with(&ShamirSplit { k, n });
+
+        // With a threshold of 1, each share is the secret.
+        for (share, x) in split(1, 3).unwrap().iter().zip(1..) {
+            assert_eq!(share[0], x);
+            assert_eq!(share[1..], *b"master key bytes");
+        }
+        let shares = split(3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|share| share.len() == 17));
+        assert_ne!(shares[0][1..], *b"master key bytes");
+
+        let mut combined = pin!(Secret::<[u8; 16]>::new());
+        let mut combine = |shares: &[&Zeroizing<Vec<u8>>]| {
+            let shares = shares.iter().map(|&share| share.clone()).collect();
+            let len = combined.as_mut().update_with(&ShamirCombine(shares))?;
+            Ok::<_, io::Error>((len, combined.as_ref().read_with(&|sec: &[u8]| sec.to_vec())))
+        };
+        for picked in [[0, 1, 2], [4, 0, 3], [1, 3, 4]] {
+            let picked = picked.map(|i| &shares[i]);
+            assert_eq!(
+                combine(&picked).unwrap(),
+                (16, b"master key bytes".to_vec())
+            );
+        }
+        // Too few shares rebuild another secret.
+        let (_, wrong) = combine(&[&shares[0], &shares[1]]).unwrap();
+        assert_ne!(wrong, b"master key bytes");
+        let err = combine(&[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = combine(&[&shares[0], &shares[1], &shares[0]]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        combined
+            .as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 16]));
+
+        for (k, n) in [(0, 3), (4, 3)] {
+            let err = split(k, n).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
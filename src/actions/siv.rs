@@ -0,0 +1,75 @@
+use aes_gcm_siv::{
+    AeadCore, Aes256GcmSiv, KeyInit, Nonce,
+    aead::{Aead, OsRng},
+};
+
+use crate::api::SecretReader;
+
+/// Encrypts a message with AES-256-GCM-SIV, under a random nonce returned with the
+/// ciphertext.
+///
+/// Unlike with [`super::Cipher`], reusing a nonce (as when a nonce counter is not persisted
+/// properly) does not leak the authentication key: it only reveals whether the same
+/// message was encrypted twice.
+pub struct SivCipher(pub Vec<u8>);
+
+/// Decrypts a ciphertext and its nonce, as returned by [`SivCipher`].
+pub struct SivDecipher(pub (Vec<u8>, Nonce));
+
+impl SecretReader<[u8; 32], Result<(Vec<u8>, Nonce), aes_gcm_siv::Error>> for SivCipher {
+    fn read(&self, sec: &[u8]) -> Result<(Vec<u8>, Nonce), aes_gcm_siv::Error> {
+        let cipher = Aes256GcmSiv::new(sec.into());
+        let nonce = Aes256GcmSiv::generate_nonce(&mut OsRng);
+        let enc = cipher.encrypt(&nonce, &*self.0)?;
+        Ok((enc, nonce))
+    }
+}
+
+/// Fails if the ciphertext was not encrypted under this key, or was altered.
+impl SecretReader<[u8; 32], Result<Vec<u8>, aes_gcm_siv::Error>> for SivDecipher {
+    fn read(&self, sec: &[u8]) -> Result<Vec<u8>, aes_gcm_siv::Error> {
+        let cipher = Aes256GcmSiv::new(sec.into());
+        let (enc, nonce) = &self.0;
+        cipher.decrypt(nonce, &**enc)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_siv() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let (mut enc, nonce) = secret
+            .as_ref()
+            .read_with(&SivCipher(b"secret message".to_vec()))
+            .unwrap();
+        let dec = secret
+            .as_ref()
+            .read_with(&SivDecipher((enc.clone(), nonce)))
+            .unwrap();
+        assert_eq!(dec, b"secret message");
+
+        // Deterministic under a given nonce.
+        let again = secret.as_ref().read_with(&|sec: &[u8]| {
+            Aes256GcmSiv::new(sec.into()).encrypt(&nonce, &b"secret message"[..])
+        });
+        assert_eq!(again.unwrap(), enc);
+
+        enc[0] ^= 1;
+        assert!(
+            secret
+                .as_ref()
+                .read_with(&SivDecipher((enc, nonce)))
+                .is_err()
+        );
+    }
+}
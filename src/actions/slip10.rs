@@ -0,0 +1,211 @@
+//! Hierarchical deterministic key derivation with BIP32 and SLIP-10, along hardened paths.
+//!
+//! Each derived private key lands in a fresh secret. The chain codes, needed to derive
+//! further, stay within the derivation: to derive several keys under a common path, derive
+//! each of them from the seed.
+
+use std::{io, pin::Pin};
+
+use hmac::{Hmac, Mac};
+use k256::{Scalar, elliptic_curve::PrimeField};
+use sha2::Sha512;
+use zeroize::{Zeroize, Zeroizing};
+
+#[cfg(feature = "bip39")]
+use super::Bip39Seed;
+use crate::api::{Secret, SecretReader};
+
+/// Index of the first hardened child.
+const HARDENED: u32 = 1 << 31;
+
+/// Curves of SLIP-10.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slip10Curve {
+    /// secp256k1, as BIP32 derives it for Bitcoin and Ethereum wallets.
+    Secp256k1,
+    /// Ed25519, for which SLIP-10 only derives hardened children.
+    Ed25519,
+}
+
+/// Derives the private key at a hardened path from the seed held in the secret, with
+/// BIP32 for secp256k1 or SLIP-10 for Ed25519, as in `m/44'/0'/0'`.
+///
+/// The seed is a BIP39 seed, held in a `Bip39Seed`, or random bytes held in an array of 16
+/// to 32 bytes.
+///
+/// # Security
+///
+/// The intermediate keys and chain codes are zeroized, but not the states of HMAC-SHA512.
+pub struct DeriveSlip10Key {
+    pub curve: Slip10Curve,
+    /// Indexes of the path, without the hardening bit, as `[44, 0, 0]` for `m/44'/0'/0'`.
+    pub path: Vec<u32>,
+}
+
+/// Computes HMAC-SHA512 of the parts, keyed with `key`, into `output`.
+fn hmac(key: &[u8], parts: &[&[u8]], output: &mut [u8; 64]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    output.copy_from_slice(&mac.finalize().into_bytes());
+}
+
+fn invalid_key() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "derived key is invalid, use another index",
+    )
+}
+
+/// Parses a secp256k1 private key, that must be lower than the order of the curve and not
+/// zero.
+fn secp256k1_scalar(bytes: &[u8]) -> io::Result<Scalar> {
+    let bytes: [u8; 32] = bytes.try_into().expect("private keys are 32 bytes long");
+    Option::from(Scalar::from_repr(bytes.into()))
+        .filter(|scalar: &Scalar| !bool::from(scalar.is_zero()))
+        .ok_or_else(invalid_key)
+}
+
+impl DeriveSlip10Key {
+    fn derive(&self, seed: &[u8]) -> io::Result<Pin<Box<Secret<[u8; 32]>>>> {
+        if seed.len() < 16 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seed is shorter than 16 bytes",
+            ));
+        }
+        if self.path.iter().any(|&index| index >= HARDENED) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path indexes must be lower than 2^31",
+            ));
+        }
+        let curve_key: &[u8] = match self.curve {
+            Slip10Curve::Secp256k1 => b"Bitcoin seed",
+            Slip10Curve::Ed25519 => b"ed25519 seed",
+        };
+        // The private key, followed by the chain code.
+        let mut extended = Zeroizing::new([0; 64]);
+        hmac(curve_key, &[seed], &mut extended);
+        if self.curve == Slip10Curve::Secp256k1 {
+            secp256k1_scalar(&extended[..32])?;
+        }
+        let mut derived = Zeroizing::new([0; 64]);
+        for &index in &self.path {
+            let (key, chain_code) = extended.split_at(32);
+            let index = (HARDENED | index).to_be_bytes();
+            hmac(chain_code, &[&[0], key, &index], &mut derived);
+            match self.curve {
+                Slip10Curve::Secp256k1 => {
+                    let mut tweak = secp256k1_scalar(&derived[..32])?;
+                    let mut parent = secp256k1_scalar(key)?;
+                    let mut child = tweak + parent;
+                    tweak.zeroize();
+                    parent.zeroize();
+                    let valid = !bool::from(child.is_zero());
+                    extended[..32].copy_from_slice(&child.to_bytes());
+                    child.zeroize();
+                    if !valid {
+                        return Err(invalid_key());
+                    }
+                }
+                Slip10Curve::Ed25519 => extended[..32].copy_from_slice(&derived[..32]),
+            }
+            extended[32..].copy_from_slice(&derived[32..]);
+        }
+        let (key, ()) = Secret::boxed_with(&|key: &mut [u8]| key.copy_from_slice(&extended[..32]));
+        Ok(key)
+    }
+}
+
+/// Returns a fresh secret holding the derived private key.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the seed is shorter than 16 bytes, or if
+/// an index of the path is not lower than 2³¹, and, with secp256k1, with
+/// [`io::ErrorKind::InvalidData`] if a derived key is invalid (with a probability lower
+/// than 2⁻¹²⁷).
+impl<const N: usize> SecretReader<[u8; N], io::Result<Pin<Box<Secret<[u8; 32]>>>>>
+    for DeriveSlip10Key
+{
+    fn read(&self, sec: &[u8]) -> io::Result<Pin<Box<Secret<[u8; 32]>>>> {
+        self.derive(sec)
+    }
+}
+
+/// Returns a fresh secret holding the derived private key, and fails as for arrays.
+#[cfg(feature = "bip39")]
+impl SecretReader<Bip39Seed, io::Result<Pin<Box<Secret<[u8; 32]>>>>> for DeriveSlip10Key {
+    fn read(&self, sec: &[u8]) -> io::Result<Pin<Box<Secret<[u8; 32]>>>> {
+        self.derive(sec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+
+    /// Test vector 1 of BIP32 and of SLIP-10.
+    #[test]
+    fn test_derive_slip10_key() {
+        let mut seed = pin!(Secret::<[u8; 16]>::new());
+        seed.as_mut()
+            .update_with(&|sec: &mut [u8]| sec.iter_mut().zip(0..).for_each(|(byte, i)| *byte = i));
+        let derive = |curve, path: &[u32]| {
+            let key = seed.as_ref().read_with(&DeriveSlip10Key {
+                curve,
+                path: path.to_vec(),
+            })?;
+            Ok::<_, io::Error>(key.as_ref().read_with(&|sec: &[u8]| {
+                sec.iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<String>()
+            }))
+        };
+
+        for (path, expected) in [
+            (
+                &[][..],
+                "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+            ),
+            (
+                &[0],
+                "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+            ),
+        ] {
+            assert_eq!(derive(Slip10Curve::Secp256k1, path).unwrap(), expected);
+        }
+        for (path, expected) in [
+            (
+                &[][..],
+                "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+            ),
+            (
+                &[0],
+                "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+            ),
+            (
+                &[0, 1, 2, 2, 1000000000],
+                "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+            ),
+        ] {
+            assert_eq!(derive(Slip10Curve::Ed25519, path).unwrap(), expected);
+        }
+
+        let err = derive(Slip10Curve::Ed25519, &[HARDENED]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let short = pin!(Secret::<[u8; 8]>::new());
+        let err = short
+            .as_ref()
+            .read_with(&DeriveSlip10Key {
+                curve: Slip10Curve::Secp256k1,
+                path: vec![],
+            })
+            .map(drop)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
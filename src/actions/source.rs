@@ -0,0 +1,37 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use zeroize::Zeroizing;
+
+use super::{Encoding, UpdateSecretFromEnv};
+
+/// Where an encoded secret is read from.
+pub enum Source {
+    /// The whole content of a file.
+    File(PathBuf),
+    /// An environment variable, removed once read (see [`UpdateSecretFromEnv`]).
+    Env(std::ffi::OsString),
+    /// A string given by the caller (a command line argument…), zeroized on drop.
+    Text(Zeroizing<String>),
+}
+
+impl Source {
+    /// Returns the (encoded) content of the source, zeroized on drop.
+    pub(crate) fn load(&self) -> io::Result<Zeroizing<Vec<u8>>> {
+        match self {
+            Source::File(path) => {
+                let mut file = File::open(path)?;
+                // Sized upfront, so that no copy is left behind by reallocations.
+                let len = file.metadata()?.len() as usize;
+                let mut content = Zeroizing::new(Vec::with_capacity(len + 1));
+                file.read_to_end(&mut content)?;
+                Ok(content)
+            }
+            Source::Env(name) => UpdateSecretFromEnv::new(name, Encoding::Raw).take(),
+            Source::Text(text) => Ok(Zeroizing::new(text.as_bytes().to_vec())),
+        }
+    }
+}
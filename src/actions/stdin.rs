@@ -0,0 +1,119 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    mem::ManuallyDrop,
+};
+
+use zeroize::Zeroize;
+
+use super::read_to_end;
+use crate::api::SecretUpdater;
+
+/// Where a secret read from the standard input ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StdinEnd {
+    /// At the end of the input, once the writer closed it.
+    #[default]
+    Eof,
+    /// At the end of the first line (or of the input), the line ending being left out. The
+    /// rest of the input is not read.
+    Newline,
+}
+
+/// Reads a secret piped on the standard input by an orchestrator, at most as large as the
+/// secret, without temporary files or command line arguments.
+///
+/// # Security
+///
+/// The standard input is read directly into the secret, bypassing the buffer of
+/// [`io::Stdin`], which would otherwise keep a copy of it.
+pub struct UpdateSecretFromStdin {
+    pub end: StdinEnd,
+}
+
+/// The standard input, unbuffered and not closed once read.
+fn stdin() -> io::Result<ManuallyDrop<File>> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::FromRawFd;
+
+        Ok(ManuallyDrop::new(unsafe { File::from_raw_fd(0) }))
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::{AsRawHandle, FromRawHandle};
+
+        let handle = io::stdin().as_raw_handle();
+        if handle.is_null() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no standard input"));
+        }
+        Ok(ManuallyDrop::new(unsafe { File::from_raw_handle(handle) }))
+    }
+}
+
+/// Reads the first line of `reader` into the start of `sec`, one byte at a time so that
+/// nothing past the line is consumed.
+fn read_line(mut reader: impl Read, sec: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    let mut byte = [0u8];
+    let result = loop {
+        match reader.read(&mut byte) {
+            Ok(0) => break Ok(len),
+            Ok(_) if byte[0] == b'\n' => break Ok(len),
+            Ok(_) => match sec.get_mut(len) {
+                Some(dst) => {
+                    *dst = byte[0];
+                    len += 1;
+                }
+                None => {
+                    break Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "input is larger than the secret",
+                    ));
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => break Err(err),
+        }
+    };
+    byte.zeroize();
+    if len > 0 && sec[len - 1] == b'\r' {
+        sec[len - 1] = 0;
+        len -= 1;
+    }
+    result.map(|_| len)
+}
+
+/// Returns the length of the secret.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the input is larger than the secret. On
+/// failure, the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromStdin {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let result = stdin().and_then(|stdin| match self.end {
+            StdinEnd::Eof => read_to_end(&*stdin, sec),
+            StdinEnd::Newline => read_line(&*stdin, sec),
+        });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_read_line() {
+        let mut input = &b"pass\r\nrest"[..];
+        let mut sec = [0u8; 8];
+        assert_eq!(read_line(&mut input, &mut sec).unwrap(), 4);
+        assert_eq!(sec, *b"pass\0\0\0\0");
+        assert_eq!(input, b"rest");
+        assert_eq!(read_line(&mut input, &mut sec).unwrap(), 4);
+        assert!(read_line(&b"password!\n"[..], &mut sec).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! Encryption of large streams (files, backups…) with the STREAM construction over
+//! AES-256-GCM, by chunks of bounded size, so that the plaintext is never held whole in
+//! memory.
+//!
+//! The ciphertext starts with a random 7-byte nonce prefix, followed by the chunks of
+//! [`CHUNK_LEN`] bytes of plaintext, each followed by its 16-byte tag. The last chunk
+//! (shorter, possibly empty) is flagged as such, so that a truncated ciphertext does not
+//! decrypt, and chunks cannot be reordered.
+//!
+//! # Security
+//!
+//! Each chunk is authenticated on its own and written as soon as decrypted: if decryption
+//! fails, the plaintext already written must be discarded. Chunks are encrypted in place
+//! in a buffer zeroized once done.
+
+use std::io::{self, Read, Write};
+
+use aes_gcm::{
+    Aes256Gcm, KeyInit,
+    aead::{
+        OsRng,
+        generic_array::GenericArray,
+        rand_core::RngCore,
+        stream::{DecryptorBE32, EncryptorBE32, Nonce, StreamBE32},
+    },
+};
+use zeroize::Zeroizing;
+
+use crate::api::SecretReaderMut;
+
+/// Length of the plaintext of each chunk but the last.
+pub const CHUNK_LEN: usize = 64 << 10;
+
+const TAG_LEN: usize = 16;
+
+/// Encrypts everything read from `reader` to `writer`, returning the length of the
+/// plaintext.
+pub struct EncryptStream<R, W> {
+    reader: R,
+    writer: W,
+}
+
+/// Decrypts everything read from `reader` to `writer`, returning the length of the
+/// plaintext.
+pub struct DecryptStream<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> EncryptStream<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        EncryptStream { reader, writer }
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: Read, W: Write> DecryptStream<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        DecryptStream { reader, writer }
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+fn aead_error(_: aes_gcm::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "stream chunk is altered, truncated or out of order",
+    )
+}
+
+/// Appends to `buffer` what can be read from `reader`, up to `len` bytes. The capacity of
+/// `buffer` must be large enough, so that it is never reallocated.
+fn fill(reader: &mut impl Read, buffer: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    while buffer.len() < len {
+        let start = buffer.len();
+        buffer.resize(len, 0);
+        match reader.read(&mut buffer[start..]) {
+            Ok(0) => {
+                buffer.truncate(start);
+                break;
+            }
+            Ok(read) => buffer.truncate(start + read),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => buffer.truncate(start),
+            Err(err) => {
+                buffer.truncate(start);
+                return Err(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fails with the errors of the reader and the writer.
+impl<R: Read, W: Write> SecretReaderMut<[u8; 32], io::Result<u64>> for EncryptStream<R, W> {
+    fn read_mut(&mut self, sec: &[u8]) -> io::Result<u64> {
+        let (reader, writer) = (&mut self.reader, &mut self.writer);
+        let mut prefix: Nonce<Aes256Gcm, StreamBE32<Aes256Gcm>> = GenericArray::default();
+        OsRng.fill_bytes(&mut prefix);
+        let mut encryptor = EncryptorBE32::from_aead(Aes256Gcm::new(sec.into()), &prefix);
+        writer.write_all(&prefix)?;
+        // One more byte is read to know whether a chunk is the last one.
+        let mut buffer = Zeroizing::new(Vec::with_capacity(CHUNK_LEN + 1 + TAG_LEN));
+        let mut len = 0;
+        loop {
+            fill(reader, &mut buffer, CHUNK_LEN + 1)?;
+            if buffer.len() <= CHUNK_LEN {
+                len += buffer.len() as u64;
+                encryptor
+                    .encrypt_last_in_place(b"", &mut *buffer)
+                    .map_err(aead_error)?;
+                writer.write_all(&buffer)?;
+                writer.flush()?;
+                return Ok(len);
+            }
+            let next = buffer[CHUNK_LEN];
+            buffer.truncate(CHUNK_LEN);
+            encryptor
+                .encrypt_next_in_place(b"", &mut *buffer)
+                .map_err(aead_error)?;
+            writer.write_all(&buffer)?;
+            buffer.clear();
+            buffer.push(next);
+            len += CHUNK_LEN as u64;
+        }
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidData`] if the ciphertext was not encrypted under
+/// this key, or was altered or truncated, and with the errors of the reader and the
+/// writer.
+impl<R: Read, W: Write> SecretReaderMut<[u8; 32], io::Result<u64>> for DecryptStream<R, W> {
+    fn read_mut(&mut self, sec: &[u8]) -> io::Result<u64> {
+        let (reader, writer) = (&mut self.reader, &mut self.writer);
+        let mut prefix: Nonce<Aes256Gcm, StreamBE32<Aes256Gcm>> = GenericArray::default();
+        reader
+            .read_exact(&mut prefix)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => {
+                    io::Error::new(io::ErrorKind::InvalidData, "stream is truncated")
+                }
+                _ => err,
+            })?;
+        let mut decryptor = DecryptorBE32::from_aead(Aes256Gcm::new(sec.into()), &prefix);
+        let mut buffer = Zeroizing::new(Vec::with_capacity(CHUNK_LEN + TAG_LEN + 1));
+        let mut len = 0;
+        loop {
+            fill(reader, &mut buffer, CHUNK_LEN + TAG_LEN + 1)?;
+            if buffer.len() <= CHUNK_LEN + TAG_LEN {
+                decryptor
+                    .decrypt_last_in_place(b"", &mut *buffer)
+                    .map_err(aead_error)?;
+                len += buffer.len() as u64;
+                writer.write_all(&buffer)?;
+                writer.flush()?;
+                return Ok(len);
+            }
+            let next = buffer[CHUNK_LEN + TAG_LEN];
+            buffer.truncate(CHUNK_LEN + TAG_LEN);
+            decryptor
+                .decrypt_next_in_place(b"", &mut *buffer)
+                .map_err(aead_error)?;
+            writer.write_all(&buffer)?;
+            buffer.clear();
+            buffer.push(next);
+            len += CHUNK_LEN as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_stream() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        for plain_len in [0, 5, CHUNK_LEN, 2 * CHUNK_LEN + 5] {
+            let plain: Vec<u8> = (0..plain_len).map(|i| i as u8).collect();
+            let mut encrypt = EncryptStream::new(&plain[..], Vec::new());
+            assert_eq!(
+                secret.as_ref().read_with_mut(&mut encrypt).unwrap(),
+                plain_len as u64
+            );
+            let (_, enc) = encrypt.into_inner();
+            let chunks = plain_len.div_ceil(CHUNK_LEN).max(1);
+            assert_eq!(enc.len(), 7 + plain_len + chunks * TAG_LEN);
+
+            let mut decrypt = DecryptStream::new(&enc[..], Vec::new());
+            assert_eq!(
+                secret.as_ref().read_with_mut(&mut decrypt).unwrap(),
+                plain_len as u64
+            );
+            assert_eq!(decrypt.into_inner().1, plain);
+
+            let truncated = &enc[..enc.len().min(7 + CHUNK_LEN + TAG_LEN)];
+            if truncated.len() < enc.len() {
+                let mut decrypt = DecryptStream::new(truncated, Vec::new());
+                let err = secret.as_ref().read_with_mut(&mut decrypt).unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            }
+            let mut altered = enc.clone();
+            *altered.last_mut().unwrap() ^= 1;
+            let mut decrypt = DecryptStream::new(&altered[..], Vec::new());
+            let err = secret.as_ref().read_with_mut(&mut decrypt).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use zeroize::Zeroize;
+
+use super::read_to_end;
+use crate::api::SecretUpdater;
+
+/// Loads a credential passed by systemd to the service (`LoadCredential=`,
+/// `LoadCredentialEncrypted=`, `SetCredential=` or `ImportCredential=`), from
+/// `$CREDENTIALS_DIRECTORY`.
+///
+/// Encrypted credentials are decrypted by systemd before the service starts, and the
+/// directory is only readable by the service user, not swapped out and never written to
+/// disk.
+pub struct UpdateSecretFromSystemdCredential {
+    pub name: String,
+}
+
+/// Decrypts a credential encrypted with `systemd-creds encrypt` (with the host key, the
+/// TPM, or both), that has not been passed by systemd, by running `systemd-creds decrypt`.
+///
+/// # Security
+///
+/// The plaintext is read from the pipe directly into the secret.
+pub struct UpdateSecretFromEncryptedCredential {
+    pub path: PathBuf,
+    /// Name embedded in the credential, checked when decrypting; the file name if `None`.
+    pub name: Option<String>,
+}
+
+/// Returns the length of the credential.
+///
+/// Fails with [`io::ErrorKind::NotFound`] if the service has no credentials, or not this one,
+/// and with [`io::ErrorKind::InvalidInput`] if it is larger than the secret, which is then
+/// zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>>
+    for UpdateSecretFromSystemdCredential
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        self.load(std::env::var_os("CREDENTIALS_DIRECTORY"), sec)
+    }
+}
+
+impl UpdateSecretFromSystemdCredential {
+    /// Loads the credential from `directory`, `$CREDENTIALS_DIRECTORY` if set.
+    fn load(&self, directory: Option<OsString>, sec: &mut [u8]) -> io::Result<usize> {
+        if self.name.is_empty() || self.name.contains('/') || self.name == "." || self.name == ".."
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid credential name",
+            ));
+        }
+        let directory = directory.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "CREDENTIALS_DIRECTORY is not set")
+        })?;
+        read_to_end(File::open(Path::new(&directory).join(&self.name))?, sec)
+    }
+}
+
+/// Returns the length of the credential.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if `systemd-creds` cannot decrypt the
+/// credential, and with [`io::ErrorKind::InvalidInput`] if it is larger than the secret. On
+/// failure, the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>>
+    for UpdateSecretFromEncryptedCredential
+{
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let mut name = OsString::from("--name=");
+        match &self.name {
+            Some(embedded) => name.push(embedded),
+            None => name.push(self.path.file_name().unwrap_or_default()),
+        }
+        let mut child = Command::new("systemd-creds")
+            .arg("decrypt")
+            .arg(name)
+            .arg(&self.path)
+            .arg("-")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let read = read_to_end(child.stdout.take().expect("piped stdout"), sec);
+        let status = child.wait()?;
+        let len = read?;
+        if !status.success() {
+            sec.zeroize();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("systemd-creds failed to decrypt the credential ({status})"),
+            ));
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_update_secret_from_systemd_credential() {
+        let directory =
+            std::env::temp_dir().join(format!("secrust-credentials-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("db-password"), b"hunter42").unwrap();
+        let credential = |name: &str| UpdateSecretFromSystemdCredential { name: name.into() };
+
+        let mut sec = [0u8; 16];
+        let len = credential("db-password")
+            .load(Some(directory.clone().into()), &mut sec)
+            .unwrap();
+        assert_eq!(&sec[..len], b"hunter42");
+        let err = credential("db-password").load(None, &mut sec).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = credential("other")
+            .load(Some(directory.clone().into()), &mut sec)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = credential("../db-password")
+            .load(Some(directory.clone().into()), &mut sec)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let mut short = [0u8; 4];
+        let err = credential("db-password")
+            .load(Some(directory.clone().into()), &mut short)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(short, [0; 4]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+//! Secrets sealed by a TPM 2.0, so that they are never stored in clear on disk.
+//!
+//! A sealed blob can only be unsealed by the TPM that sealed it, and while the selected PCRs
+//! (SHA-256 bank) hold the same values as when it was sealed, that is on the same platform
+//! booted in the same state. It is sealed under a primary key of the owner hierarchy,
+//! regenerated by the TPM on each use, so nothing has to be provisioned.
+//!
+//! The TPM is reached through the TCTI set in the `TPM2TOOLS_TCTI`, `TCTI` or `TEST_TCTI`
+//! environment variables, or else through the kernel resource manager (`/dev/tpmrm0`).
+
+use std::{io, str::FromStr};
+
+pub use tss_esapi::structures::PcrSlot;
+use tss_esapi::{
+    Context, TctiNameConf,
+    attributes::ObjectAttributesBuilder,
+    constants::SessionType,
+    handles::{KeyHandle, ObjectHandle, SessionHandle},
+    interface_types::{
+        algorithm::{HashingAlgorithm, PublicAlgorithm},
+        key_bits::RsaKeyBits,
+        resource_handles::Hierarchy,
+        session_handles::PolicySession,
+    },
+    structures::{
+        Digest, KeyedHashScheme, MaxBuffer, PcrSelectionList, PcrSelectionListBuilder, Private,
+        Public, PublicBuilder, PublicKeyedHashParameters, RsaExponent, SensitiveData,
+        SymmetricDefinition, SymmetricDefinitionObject,
+    },
+    tcti_ldr::DeviceConfig,
+    traits::{Marshall, UnMarshall},
+    utils::create_restricted_decryption_rsa_public,
+};
+use zeroize::Zeroize;
+
+use crate::api::{SecretReader, SecretUpdater};
+
+/// A secret sealed by a TPM, that can be stored as is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SealedBlob {
+    /// Marshalled public area of the sealed object.
+    pub public: Vec<u8>,
+    /// Private area of the sealed object, encrypted by the TPM.
+    pub private: Vec<u8>,
+    /// PCRs it is bound to.
+    pub pcrs: Vec<PcrSlot>,
+}
+
+/// Seals the secret to the current values of some PCRs (at most 8).
+pub struct SealToTpm {
+    pub pcrs: Vec<PcrSlot>,
+}
+
+/// Unseals a blob directly into the secret.
+///
+/// # Security
+///
+/// The plaintext only transits through a buffer of the TSS library, zeroized when dropped.
+pub struct UnsealFromTpm(pub SealedBlob);
+
+fn tpm_error(err: tss_esapi::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+fn context() -> io::Result<Context> {
+    let tcti = match TctiNameConf::from_environment_variable() {
+        Ok(tcti) => tcti,
+        Err(_) => TctiNameConf::Device(DeviceConfig::from_str("/dev/tpmrm0").map_err(tpm_error)?),
+    };
+    Context::new(tcti).map_err(tpm_error)
+}
+
+fn selection(pcrs: &[PcrSlot]) -> tss_esapi::Result<PcrSelectionList> {
+    PcrSelectionListBuilder::new()
+        .with_selection(HashingAlgorithm::Sha256, pcrs)
+        .build()
+}
+
+/// Storage primary key of the owner hierarchy, derived from its seed.
+fn primary(context: &mut Context) -> tss_esapi::Result<KeyHandle> {
+    let public = create_restricted_decryption_rsa_public(
+        SymmetricDefinitionObject::AES_128_CFB,
+        RsaKeyBits::Rsa2048,
+        RsaExponent::default(),
+    )?;
+    context
+        .execute_with_nullauth_session(|context| {
+            context.create_primary(Hierarchy::Owner, public, None, None, None, None)
+        })
+        .map(|primary| primary.key_handle)
+}
+
+/// Starts a policy session (or a trial one, to compute a policy digest) restricted to the
+/// values of the PCRs, which must be given for a trial session.
+fn pcr_policy(
+    context: &mut Context,
+    session_type: SessionType,
+    pcrs: &[PcrSlot],
+) -> tss_esapi::Result<PolicySession> {
+    let selection = selection(pcrs)?;
+    let pcr_digest = match session_type {
+        SessionType::Trial => {
+            let (_, _, values) = context.pcr_read(selection.clone())?;
+            let concatenated: Vec<u8> = values
+                .value()
+                .iter()
+                .flat_map(|digest| digest.value().to_vec())
+                .collect();
+            context
+                .hash(
+                    MaxBuffer::try_from(concatenated)?,
+                    HashingAlgorithm::Sha256,
+                    Hierarchy::Owner,
+                )?
+                .0
+        }
+        // Checked against the current values by the TPM.
+        _ => Digest::default(),
+    };
+    let session = context
+        .start_auth_session(
+            None,
+            None,
+            None,
+            session_type,
+            SymmetricDefinition::AES_128_CFB,
+            HashingAlgorithm::Sha256,
+        )?
+        .ok_or(tss_esapi::Error::WrapperError(
+            tss_esapi::WrapperErrorKind::WrongValueFromTpm,
+        ))?;
+    let session = PolicySession::try_from(session)?;
+    context.policy_pcr(session, pcr_digest, selection)?;
+    Ok(session)
+}
+
+fn flush(context: &mut Context, handle: impl Into<ObjectHandle>) {
+    // Transient objects are flushed anyway when the connection is closed.
+    let _ = context.flush_context(handle.into());
+}
+
+impl<const N: usize> SecretReader<[u8; N], io::Result<SealedBlob>> for SealToTpm {
+    fn read(&self, sec: &[u8]) -> io::Result<SealedBlob> {
+        let mut context = context()?;
+        let trial = pcr_policy(&mut context, SessionType::Trial, &self.pcrs).map_err(tpm_error)?;
+        let policy = context.policy_get_digest(trial);
+        flush(&mut context, SessionHandle::from(trial));
+        let attributes = ObjectAttributesBuilder::new()
+            .with_fixed_tpm(true)
+            .with_fixed_parent(true)
+            .with_no_da(true)
+            .build()
+            .map_err(tpm_error)?;
+        let public = PublicBuilder::new()
+            .with_public_algorithm(PublicAlgorithm::KeyedHash)
+            .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+            .with_object_attributes(attributes)
+            .with_auth_policy(policy.map_err(tpm_error)?)
+            .with_keyed_hash_parameters(PublicKeyedHashParameters::new(KeyedHashScheme::Null))
+            .with_keyed_hash_unique_identifier(Default::default())
+            .build()
+            .map_err(tpm_error)?;
+        let data = SensitiveData::try_from(sec).map_err(tpm_error)?;
+        let primary = primary(&mut context).map_err(tpm_error)?;
+        let sealed = context.execute_with_nullauth_session(|context| {
+            context.create(primary, public, None, Some(data), None, None)
+        });
+        flush(&mut context, primary);
+        let sealed = sealed.map_err(tpm_error)?;
+        Ok(SealedBlob {
+            public: sealed.out_public.marshall().map_err(tpm_error)?,
+            private: sealed.out_private.value().to_vec(),
+            pcrs: self.pcrs.clone(),
+        })
+    }
+}
+
+/// Returns the length of the secret.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the sealed secret is larger than the
+/// secret buffer, and with [`io::ErrorKind::Other`] (holding a [`tss_esapi::Error`]) if the
+/// TPM refuses to unseal it, as when the PCRs changed. On failure, the secret is zeroized.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UnsealFromTpm {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let mut context = context()?;
+        let public = Public::unmarshall(&self.0.public).map_err(tpm_error)?;
+        let private = Private::try_from(self.0.private.as_slice()).map_err(tpm_error)?;
+        let primary = primary(&mut context).map_err(tpm_error)?;
+        let object =
+            context.execute_with_nullauth_session(|context| context.load(primary, private, public));
+        flush(&mut context, primary);
+        let object = object.map_err(tpm_error)?;
+        let unsealed = match pcr_policy(&mut context, SessionType::Policy, &self.0.pcrs) {
+            Ok(session) => {
+                let unsealed = context.execute_with_session(Some(session.into()), |context| {
+                    context.unseal(object.into())
+                });
+                flush(&mut context, SessionHandle::from(session));
+                unsealed
+            }
+            Err(err) => Err(err),
+        };
+        flush(&mut context, object);
+        let result = unsealed.map_err(tpm_error).and_then(|unsealed| {
+            let data = unsealed.value();
+            let dst = sec.get_mut(..data.len()).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "sealed secret is larger than its buffer",
+                )
+            })?;
+            dst.copy_from_slice(data);
+            Ok(data.len())
+        });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
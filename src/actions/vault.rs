@@ -0,0 +1,181 @@
+use std::{borrow::Cow, collections::HashMap, io, time::Duration};
+
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{
+    Encoding,
+    http::{agent, http_error, json_error, read_body},
+};
+use crate::api::SecretUpdater;
+
+/// How to authenticate to Vault.
+pub enum VaultAuth {
+    /// A Vault token.
+    Token(Zeroizing<String>),
+    /// An AppRole, logged in for each update.
+    AppRole {
+        role_id: String,
+        secret_id: Zeroizing<String>,
+    },
+}
+
+/// Fetches a field of a secret of a KV version 2 secrets engine of HashiCorp Vault, then
+/// decodes it into the secret.
+///
+/// # Security
+///
+/// Use an `https://` address, so that the secret is sent over TLS.
+///
+/// The field is not streamed into the secret: the whole response body, with the other
+/// fields of the Vault secret, is first read in a buffer zeroized once decoded. The field is
+/// then decoded from that buffer into the secret, through a copy (zeroized as well) only if
+/// it holds JSON escapes. Buffers of the HTTP and TLS stacks are not wiped.
+pub struct UpdateSecretFromVault {
+    /// Address of the Vault server, such as `https://vault.example.com:8200`.
+    pub address: String,
+    pub auth: VaultAuth,
+    /// Mount path of the secrets engine, usually `secret`.
+    pub mount: String,
+    /// Path of the secret in the secrets engine.
+    pub path: String,
+    pub field: String,
+    pub encoding: Encoding,
+    pub timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct Login<'a> {
+    #[serde(borrow)]
+    auth: LoginAuth<'a>,
+}
+
+#[derive(Deserialize)]
+struct LoginAuth<'a> {
+    #[serde(borrow)]
+    client_token: Cow<'a, str>,
+}
+
+#[derive(Deserialize)]
+struct Kv<'a> {
+    #[serde(borrow)]
+    data: KvData<'a>,
+}
+
+#[derive(Deserialize)]
+struct KvData<'a> {
+    #[serde(borrow)]
+    data: HashMap<Cow<'a, str>, Cow<'a, str>>,
+}
+
+impl Drop for KvData<'_> {
+    fn drop(&mut self) {
+        // Values holding escapes have been unescaped in new buffers.
+        for value in self.data.values_mut() {
+            if let Cow::Owned(value) = value {
+                value.zeroize();
+            }
+        }
+    }
+}
+
+impl UpdateSecretFromVault {
+    fn token(&self, agent: &ureq::Agent) -> io::Result<Zeroizing<String>> {
+        let (role_id, secret_id) = match &self.auth {
+            VaultAuth::Token(token) => return Ok(token.clone()),
+            VaultAuth::AppRole { role_id, secret_id } => (role_id, secret_id),
+        };
+        let request = Zeroizing::new(
+            serde_json::to_vec(&serde_json::json!({
+                "role_id": role_id,
+                "secret_id": secret_id.as_str(),
+            }))
+            .map_err(json_error)?,
+        );
+        let url = format!("{}/v1/auth/approle/login", self.address);
+        let response = agent.post(url).send(&request[..]).map_err(http_error)?;
+        let body = read_body(response)?;
+        let login: Login = serde_json::from_slice(&body).map_err(json_error)?;
+        Ok(Zeroizing::new(login.auth.client_token.into_owned()))
+    }
+}
+
+/// Returns the decoded length.
+///
+/// Fails with [`io::ErrorKind::NotFound`] if there is no such secret or field, with
+/// [`io::ErrorKind::PermissionDenied`] if access is denied, and as [`Encoding`] does if
+/// the field cannot be decoded into the secret.
+impl<const N: usize> SecretUpdater<[u8; N], io::Result<usize>> for UpdateSecretFromVault {
+    fn update(&self, sec: &mut [u8]) -> io::Result<usize> {
+        let agent = agent(self.timeout);
+        let token = self.token(&agent)?;
+        let url = format!("{}/v1/{}/data/{}", self.address, self.mount, self.path);
+        let response = agent
+            .get(url)
+            .header("X-Vault-Token", token.as_str())
+            .call()
+            .map_err(http_error)?;
+        let body = read_body(response)?;
+        let kv: Kv = serde_json::from_slice(&body).map_err(json_error)?;
+        let value = kv.data.data.get(self.field.as_str()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no such field in the Vault secret")
+        })?;
+        self.encoding.decode(value.as_bytes(), sec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        pin::pin,
+    };
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_update_secret_from_vault() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut request = BufReader::new(stream);
+            let mut line = String::new();
+            let mut token = None;
+            while request.read_line(&mut line).unwrap() > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("x-vault-token: ") {
+                    token = Some(value.trim().to_owned());
+                }
+                line.clear();
+            }
+            assert_eq!(token.as_deref(), Some("s.test"));
+            let body = r#"{"data":{"data":{"key":"00ff10a5","other":"x"},"metadata":{}}}"#;
+            write!(
+                request.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+        let vault = UpdateSecretFromVault {
+            address,
+            auth: VaultAuth::Token(Zeroizing::new("s.test".into())),
+            mount: "secret".into(),
+            path: "app/key".into(),
+            field: "key".into(),
+            encoding: Encoding::Hex,
+            timeout: Duration::from_secs(5),
+        };
+        let mut secret = pin!(Secret::<[u8; 4]>::new());
+        assert_eq!(secret.as_mut().update_with(&vault).unwrap(), 4);
+        assert!(
+            secret
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec == [0, 0xff, 0x10, 0xa5])
+        );
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use std::{io, pin::Pin};
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroize;
+
+use crate::{
+    api::{Secret, SecretReader, SecretUpdater},
+    memory::{Global, SecureAlloc},
+};
+
+/// Returns the X25519 public key of the private key held in the secret, to be sent to peers.
+pub struct X25519PublicKey;
+
+/// Agrees on a shared secret (RFC 7748) between the X25519 private key held in another secret
+/// and the public key of a peer, writing it directly into the secret.
+///
+/// # Security
+///
+/// The raw output of X25519 is not uniformly random: unless a protocol uses it as is, it
+/// should be derived with HKDF-SHA256, by setting `hkdf`.
+pub struct X25519Agree<'a, Alloc: SecureAlloc = Global> {
+    pub private_key: Pin<&'a Secret<[u8; 32], Alloc>>,
+    pub peer: [u8; 32],
+    /// Salt and info of HKDF-SHA256, to derive the shared secret from the output of X25519.
+    pub hkdf: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl SecretReader<[u8; 32], [u8; 32]> for X25519PublicKey {
+    fn read(&self, sec: &[u8]) -> [u8; 32] {
+        let sec: [u8; 32] = sec.try_into().expect("keys are 32 bytes long");
+        PublicKey::from(&StaticSecret::from(sec)).to_bytes()
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] if the public key of the peer is of small
+/// order, so that the shared secret would not depend on the private key. On failure, the
+/// secret is zeroized.
+impl<Alloc: SecureAlloc> SecretUpdater<[u8; 32], io::Result<()>> for X25519Agree<'_, Alloc> {
+    fn update(&self, sec: &mut [u8]) -> io::Result<()> {
+        let result = self.private_key.read_with_mut(&mut |private_key: &[u8]| {
+            let private_key: [u8; 32] = private_key.try_into().expect("keys are 32 bytes long");
+            let shared = StaticSecret::from(private_key).diffie_hellman(&self.peer.into());
+            if !shared.was_contributory() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "X25519 public key is of small order",
+                ));
+            }
+            match &self.hkdf {
+                Some((salt, info)) => Hkdf::<Sha256>::new(Some(salt), shared.as_bytes())
+                    .expand(info, sec)
+                    .expect("32 bytes is a valid length"),
+                None => sec.copy_from_slice(shared.as_bytes()),
+            }
+            Ok(())
+        });
+        if result.is_err() {
+            sec.zeroize();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+
+    /// Test vector of RFC 7748, section 6.1.
+    #[test]
+    fn test_x25519_agree() {
+        let mut alice = pin!(Secret::<[u8; 32]>::new());
+        alice.as_mut().update_with(&|sec: &mut [u8]| {
+            sec.copy_from_slice(&[
+                0x77, 0x07, 0x6d, 0x0a, 0x73, 0x18, 0xa5, 0x7d, 0x3c, 0x16, 0xc1, 0x72, 0x51, 0xb2,
+                0x66, 0x45, 0xdf, 0x4c, 0x2f, 0x87, 0xeb, 0xc0, 0x99, 0x2a, 0xb1, 0x77, 0xfb, 0xa5,
+                0x1d, 0xb9, 0x2c, 0x2a,
+            ])
+        });
+        let bob = [
+            0xde, 0x9e, 0xdb, 0x7d, 0x7b, 0x7d, 0xc1, 0xb4, 0xd3, 0x5b, 0x61, 0xc2, 0xec, 0xe4,
+            0x35, 0x37, 0x3f, 0x83, 0x43, 0xc8, 0x5b, 0x78, 0x67, 0x4d, 0xad, 0xfc, 0x7e, 0x14,
+            0x6f, 0x88, 0x2b, 0x4f,
+        ];
+        assert_eq!(
+            alice.as_ref().read_with(&X25519PublicKey),
+            [
+                0x85, 0x20, 0xf0, 0x09, 0x89, 0x30, 0xa7, 0x54, 0x74, 0x8b, 0x7d, 0xdc, 0xb4, 0x3e,
+                0xf7, 0x5a, 0x0d, 0xbf, 0x3a, 0x0d, 0x26, 0x38, 0x1a, 0xf4, 0xeb, 0xa4, 0xa9, 0x8e,
+                0xaa, 0x9b, 0x4e, 0x6a,
+            ]
+        );
+        let shared = [
+            0x4a, 0x5d, 0x9d, 0x5b, 0xa4, 0xce, 0x2d, 0xe1, 0x72, 0x8e, 0x3b, 0xf4, 0x80, 0x35,
+            0x0f, 0x25, 0xe0, 0x7e, 0x21, 0xc9, 0x47, 0xd1, 0x9e, 0x33, 0x76, 0xf0, 0x9b, 0x3c,
+            0x1e, 0x16, 0x17, 0x42,
+        ];
+
+        let mut key = pin!(Secret::<[u8; 32]>::new());
+        let agree = |peer, hkdf| X25519Agree {
+            private_key: alice.as_ref(),
+            peer,
+            hkdf,
+        };
+        key.as_mut().update_with(&agree(bob, None)).unwrap();
+        key.as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, shared));
+
+        let hkdf = Some((b"salt".to_vec(), b"info".to_vec()));
+        key.as_mut().update_with(&agree(bob, hkdf)).unwrap();
+        let mut expected = [0; 32];
+        Hkdf::<Sha256>::new(Some(b"salt"), &shared)
+            .expand(b"info", &mut expected)
+            .unwrap();
+        key.as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, expected));
+
+        let err = key.as_mut().update_with(&agree([0; 32], None)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        key.as_ref()
+            .read_with(&|sec: &[u8]| assert_eq!(sec, [0; 32]));
+    }
+}
@@ -0,0 +1,69 @@
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng},
+};
+
+use crate::api::SecretReader;
+
+/// Encrypts a message with XChaCha20-Poly1305, under a random nonce returned with the
+/// ciphertext.
+///
+/// Nonces are 192-bit long, so that random ones do not collide however many messages are
+/// encrypted under a key, unlike the 96-bit nonces of [`super::Cipher`].
+pub struct XChaChaCipher(pub Vec<u8>);
+
+/// Decrypts a ciphertext and its nonce, as returned by [`XChaChaCipher`].
+pub struct XChaChaDecipher(pub (Vec<u8>, XNonce));
+
+impl SecretReader<[u8; 32], Result<(Vec<u8>, XNonce), chacha20poly1305::Error>> for XChaChaCipher {
+    fn read(&self, sec: &[u8]) -> Result<(Vec<u8>, XNonce), chacha20poly1305::Error> {
+        let cipher = XChaCha20Poly1305::new(sec.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let enc = cipher.encrypt(&nonce, &*self.0)?;
+        Ok((enc, nonce))
+    }
+}
+
+/// Fails if the ciphertext was not encrypted under this key, or was altered.
+impl SecretReader<[u8; 32], Result<Vec<u8>, chacha20poly1305::Error>> for XChaChaDecipher {
+    fn read(&self, sec: &[u8]) -> Result<Vec<u8>, chacha20poly1305::Error> {
+        let cipher = XChaCha20Poly1305::new(sec.into());
+        let (enc, nonce) = &self.0;
+        cipher.decrypt(nonce, &**enc)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_xchacha() {
+        let mut secret = pin!(Secret::<[u8; 32]>::new());
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let (mut enc, nonce) = secret
+            .as_ref()
+            .read_with(&XChaChaCipher(b"secret message".to_vec()))
+            .unwrap();
+        assert_eq!(nonce.len(), 24);
+        let dec = secret
+            .as_ref()
+            .read_with(&XChaChaDecipher((enc.clone(), nonce)))
+            .unwrap();
+        assert_eq!(dec, b"secret message");
+
+        enc[0] ^= 1;
+        assert!(
+            secret
+                .as_ref()
+                .read_with(&XChaChaDecipher((enc, nonce)))
+                .is_err()
+        );
+    }
+}
@@ -1,11 +1,29 @@
-use std::{
+//! Without the `std` feature, only inline secrets ([`Secret::new`]) are available, and
+//! without the `alloc` feature, only fixed size arrays are [`Unsizeable`].
+
+mod poison;
+
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{
+    future::Future,
     marker::PhantomPinned,
     ops::{Deref, DerefMut},
     pin::Pin,
 };
+#[cfg(feature = "std")]
+use std::{
+    io,
+    panic::{self, AssertUnwindSafe},
+};
 
 use zeroize::{Zeroize, Zeroizing};
 
+use crate::memory::{self, Global, SecureAlloc, SecureBox};
+#[cfg(feature = "std")]
+use crate::memory::{Locked, Protection, SecretMem};
+use poison::Poison;
+
 #[derive(Clone)]
 /// Secret structure automatically zeroing its content after use
 ///
@@ -33,54 +51,461 @@ use zeroize::{Zeroize, Zeroizing};
 /// # Access
 ///
 /// Specific types can be designated as reader and updater of a [`Secret`] by implementing [`SecretReader`]
-/// and [`SecretUpdater`].
-pub struct Secret<Data: Zeroize> {
+/// and [`SecretUpdater`], [`SecretReaderMut`] and [`SecretUpdaterMut`] when they keep a state
+/// (a counter, a streaming hash…), or [`AsyncSecretReader`] and [`AsyncSecretUpdater`] when
+/// they await (a KMS, a vault, an HSM…).
+///
+/// In debug builds, accessing a dropped secret (through a dangling reference created by unsafe
+/// code) panics, reporting where it was created if backtraces are enabled.
+///
+/// # Dedicated memory
+///
+/// By default, the data lives inside the [`Secret`] itself. It can instead live in dedicated
+/// pages provided by a [`SecureAlloc`] (see [`Secret::new_in`]):
+///
+/// * [`Secret::new_locked`] uses [`Locked`] pages, that can never be swapped to disk,
+/// * [`Secret::new_secret_mem`] goes further on Linux by using `memfd_secret` ([`SecretMem`]),
+/// * [`Secret::new_no_access`] additionally makes the pages inaccessible outside of
+///   [`Secret::read_with`] and [`Secret::update_with`],
+/// * [`Secret::new_masked`] instead keeps them masked, so their plaintext only appears
+///   during accesses (see [`Protection`]).
+///
+/// Reading and updating is done the exact same way.
+///
+/// Dedicated memory requires the `std` feature.
+///
+/// Data in dedicated pages is surrounded by random canaries, checked on every access and on drop,
+/// to detect buffer overflows into (or out of) the secret (see [`OnCorruption`](crate::memory::OnCorruption)).
+pub struct Secret<Data: Zeroize, Alloc: SecureAlloc = Global> {
     /// Private field only accessible after pinning the secret
-    data: Zeroizing<Data>,
+    data: Storage<Data, Alloc>,
     /// Force the type to be `!Unpin`, preventing escaping from a pin.
     /// This is necessary to ensure that a [`Secret`] with sensible value inside
     /// cannot live out of a pin.
     _pin: PhantomPinned,
+    /// Detects accesses to the secret once dropped, in debug builds
+    poison: Poison,
+}
+
+/// Where the data of a [`Secret`] lives.
+enum Storage<Data: Zeroize, Alloc: SecureAlloc> {
+    /// Inside the [`Secret`] itself
+    Inline(Zeroizing<Data>),
+    /// In dedicated pages
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    Boxed(SecureBox<Data, Alloc>),
+}
+
+impl<Data: Zeroize + Clone, Alloc: SecureAlloc + Clone> Clone for Storage<Data, Alloc> {
+    /// # Panics
+    ///
+    /// Panics if dedicated pages cannot be obtained for the clone of a secret.
+    fn clone(&self) -> Self {
+        match self {
+            Storage::Inline(data) => Storage::Inline(data.clone()),
+            Storage::Boxed(data) => Storage::Boxed(
+                data.try_clone()
+                    .expect("failed to allocate memory for the cloned secret"),
+            ),
+        }
+    }
+}
+
+impl<Data: Zeroize, Alloc: SecureAlloc> Storage<Data, Alloc> {
+    fn read<A>(&self, f: impl FnOnce(&Data) -> A) -> A {
+        match self {
+            Storage::Inline(data) => f(data.deref()),
+            Storage::Boxed(data) => data.read(f),
+        }
+    }
+
+    fn write<A>(&mut self, f: impl FnOnce(&mut Data) -> A) -> A {
+        match self {
+            Storage::Inline(data) => f(data.deref_mut()),
+            Storage::Boxed(data) => data.write(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Data: Zeroize + Default, Alloc: SecureAlloc> Secret<Data, Alloc> {
+    /// Creates a secret whose data lives in dedicated pages obtained from `alloc`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `alloc` cannot provide the pages.
+    pub fn new_in(alloc: Alloc) -> Result<Self, Alloc::Error> {
+        Self::new_protected_in(alloc, Protection::new())
+    }
+
+    /// Same as [`Secret::new_in`], with pages concealed according to `protection` outside of
+    /// [`Secret::read_with`] and [`Secret::update_with`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if `alloc` cannot provide the pages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `protection` cannot be applied to the pages.
+    pub fn new_protected_in(alloc: Alloc, protection: Protection) -> Result<Self, Alloc::Error> {
+        Ok(Secret {
+            data: Storage::Boxed(SecureBox::new_in(Data::default, alloc, protection)?),
+            _pin: PhantomPinned,
+            poison: Poison::new(),
+        })
+    }
+
+    /// Same as [`Secret::new_in`], but pages are only accessible for the duration of
+    /// [`Secret::read_with`] (read only) and [`Secret::update_with`] (read and write).
+    ///
+    /// Outside of those, any access to the data, for instance through a stray pointer,
+    /// faults. Each access costs one or two `mprotect` calls.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `alloc` cannot provide the pages, or if their protection cannot be changed.
+    pub fn new_no_access_in(alloc: Alloc) -> Result<Self, Alloc::Error> {
+        Self::new_protected_in(alloc, Protection::new().no_access())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Data: Zeroize + Default> Secret<Data, Locked> {
+    /// Creates a secret whose data lives in [`Locked`] pages (`mlock` on Unix, `VirtualLock` on
+    /// Windows), preventing it from being swapped.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the platform does not support memory locking, or if the process is not
+    /// allowed to lock more memory (see `RLIMIT_MEMLOCK`).
+    pub fn new_locked() -> io::Result<Self> {
+        Self::new_in(Locked)
+    }
+
+    /// Same as [`Secret::new_locked`], but with the no access protection of [`Secret::new_no_access_in`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Secret::new_locked`].
+    pub fn new_no_access() -> io::Result<Self> {
+        Self::new_no_access_in(Locked)
+    }
+
+    /// Same as [`Secret::new_locked`], but pages are kept masked (see [`Protection::masked`])
+    /// outside of [`Secret::read_with`] and [`Secret::update_with`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Secret::new_locked`].
+    pub fn new_masked() -> io::Result<Self> {
+        Self::new_protected_in(Locked, Protection::new().masked())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Data: Zeroize + Default> Secret<Data, SecretMem> {
+    /// Creates a secret whose data lives in `memfd_secret` pages, removed from the kernel
+    /// direct map, when [`secret_mem_available`](crate::memory::secret_mem_available).
+    /// Otherwise, falls back to the locked pages of [`Secret::new_locked`] (see [`SecretMem`]).
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pages cannot be mapped, or on fallback, if they cannot be locked.
+    pub fn new_secret_mem() -> io::Result<Self> {
+        Self::new_in(SecretMem)
+    }
 }
 
 impl<Data: Zeroize + Default> Secret<Data> {
     pub fn new() -> Self {
         Secret {
-            data: Zeroizing::new(Data::default()),
+            data: Storage::Inline(Zeroizing::new(Data::default())),
             _pin: PhantomPinned,
+            poison: Poison::new(),
         }
     }
+
+    /// Creates a secret directly pinned on the heap.
+    ///
+    /// Unlike a [`Secret`] pinned on the stack, it can be moved around freely (only the [`Box`]
+    /// moves) and returned from functions. Secrets with dedicated pages do not need this:
+    /// their data already lives out of the [`Secret`] itself.
+    #[cfg(feature = "alloc")]
+    pub fn boxed() -> Pin<Box<Self>> {
+        Box::pin(Self::new())
+    }
+
+    /// Creates a secret directly pinned on the heap, and initializes it in place with `updater`.
+    ///
+    /// The secret value is only ever written in its final location, no temporary copy
+    /// of it exists on the stack.
+    #[cfg(feature = "alloc")]
+    pub fn boxed_with<A, Updater>(updater: &Updater) -> (Pin<Box<Self>>, A)
+    where
+        Updater: SecretUpdater<Data, A>,
+        Data: Unsizeable,
+    {
+        let mut secret = Self::boxed();
+        let result = secret.as_mut().update_with(updater);
+        (secret, result)
+    }
+
+    /// Same as [`Secret::boxed_with`], for updaters returning nothing, so that a secret is
+    /// created, pinned and initialized in a single expression.
+    #[cfg(feature = "alloc")]
+    pub fn init<Updater>(updater: &Updater) -> Pin<Box<Self>>
+    where
+        Updater: SecretUpdater<Data, ()>,
+        Data: Unsizeable,
+    {
+        let (secret, ()) = Self::boxed_with(updater);
+        secret
+    }
+
+    /// Same as [`Secret::init`], for fallible updaters. If `updater` fails, the secret is
+    /// dropped, hence zeroized, and its error returned. The value it returns otherwise is
+    /// discarded: use [`Secret::boxed_with`] to keep it.
+    #[cfg(feature = "alloc")]
+    pub fn try_init<A, E, Updater>(updater: &Updater) -> Result<Pin<Box<Self>>, E>
+    where
+        Updater: SecretUpdater<Data, Result<A, E>>,
+        Data: Unsizeable,
+    {
+        let (secret, result) = Self::boxed_with(updater);
+        result.map(|_| secret)
+    }
+}
+
+/// Creates a [`Secret`] pinned on the stack, and initializes it with an updater, in a single
+/// statement, binding the pinned secret to a name.
+///
+/// `secret!(let key: [u8; 32] = &updater);` stands for:
+///
+/// ```ignore
+/// let mut key = core::pin::pin!(Secret::<[u8; 32]>::new());
+/// let () = key.as_mut().update_with(&updater);
+/// ```
+///
+/// The result of a fallible updater is bound to a pattern after `=>`, as in
+/// `secret!(let key: [u8; 32] = &updater => result);`, to be checked right away.
+///
+/// Secrets to be returned from functions are created with [`Secret::init`] or
+/// [`Secret::try_init`] instead, pinned on the heap.
+#[macro_export]
+macro_rules! secret {
+    (let $name:ident: $data:ty = $updater:expr) => {
+        $crate::secret!(let $name: $data = $updater => ())
+    };
+    (let $name:ident: $data:ty = $updater:expr => $result:pat) => {
+        let mut $name = ::core::pin::pin!($crate::api::Secret::<$data>::new());
+        let $result = $name.as_mut().update_with($updater);
+    };
+}
+
+impl<Data: Zeroize + Default> Default for Secret<Data> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<Data: Zeroize> Secret<Data> {
-    fn _get(self: Pin<&Secret<Data>>) -> &Data {
-        self.get_ref().data.deref()
+impl<Data: Zeroize, Alloc: SecureAlloc> Secret<Data, Alloc> {
+    fn _read<A>(self: Pin<&Self>, f: impl FnOnce(&Data) -> A) -> A {
+        self.poison.check();
+        let result = self.get_ref().data.read(f);
+        memory::scrub_stack_after_access();
+        result
     }
 
-    fn _get_mut(self: Pin<&mut Secret<Data>>) -> &mut Data {
+    fn _write<A>(self: Pin<&mut Self>, f: impl FnOnce(&mut Data) -> A) -> A {
+        self.poison.check();
         // This is okay because `data` is *safe* (cannot produce *UB*) to move
         // More information on Rust [pin](https://doc.rust-lang.org/std/pin/index.html#choosing-pinning-not-to-be-structural-for-field) module
-        unsafe { self.get_unchecked_mut().data.deref_mut() }
+        let result = unsafe { self.get_unchecked_mut().data.write(f) };
+        memory::scrub_stack_after_access();
+        result
     }
 
     /// The only way to access self is by pinning it.
-    pub fn read_with<A, Reader>(self: Pin<&Secret<Data>>, reader: &Reader) -> A
+    pub fn read_with<A, Reader>(self: Pin<&Self>, reader: &Reader) -> A
     where
         Reader: SecretReader<Data, A>,
         Data: Unsizeable,
     {
-        reader.read(self._get().get_unsized())
+        self._read(|data| reader.read(data.get_unsized()))
     }
 
     /// The only way to access self is by pinning it.
-    pub fn update_with<A, Updater>(self: Pin<&mut Secret<Data>>, updater: &Updater) -> A
+    pub fn update_with<A, Updater>(self: Pin<&mut Self>, updater: &Updater) -> A
     where
         Updater: SecretUpdater<Data, A>,
         Data: Unsizeable,
     {
-        updater.update(self._get_mut().get_unsized_mut())
+        self._write(|data| updater.update(data.get_unsized_mut()))
+    }
+
+    /// Same as [`Secret::read_with`], for readers failing with an error convertible to an
+    /// [`Error`](crate::Error), such as the [`io::Error`]s of actions, so that accesses
+    /// compose with `?`.
+    #[cfg(feature = "std")]
+    pub fn try_read_with<A, E, Reader>(self: Pin<&Self>, reader: &Reader) -> Result<A, crate::Error>
+    where
+        Reader: SecretReader<Data, Result<A, E>>,
+        E: Into<crate::Error>,
+        Data: Unsizeable,
+    {
+        self.read_with(reader).map_err(Into::into)
+    }
+
+    /// Same as [`Secret::update_with`], for updaters failing with an error convertible to an
+    /// [`Error`](crate::Error), as [`Secret::try_read_with`].
+    ///
+    /// If `updater` fails, the secret is zeroized, so that it is never left partially
+    /// updated.
+    #[cfg(feature = "std")]
+    pub fn try_update_with<A, E, Updater>(
+        self: Pin<&mut Self>,
+        updater: &Updater,
+    ) -> Result<A, crate::Error>
+    where
+        Updater: SecretUpdater<Data, Result<A, E>>,
+        E: Into<crate::Error>,
+        Data: Unsizeable<Unsized: Zeroize>,
+    {
+        self._write(|data| {
+            let sec = data.get_unsized_mut();
+            updater.update(sec).map_err(|err| {
+                sec.zeroize();
+                err.into()
+            })
+        })
+    }
+
+    /// Same as [`Secret::read_with`], for readers keeping a state (see [`SecretReaderMut`]),
+    /// such as closures capturing mutable variables.
+    pub fn read_with_mut<A, Reader>(self: Pin<&Self>, reader: &mut Reader) -> A
+    where
+        Reader: SecretReaderMut<Data, A> + ?Sized,
+        Data: Unsizeable,
+    {
+        self._read(|data| reader.read_mut(data.get_unsized()))
+    }
+
+    /// Same as [`Secret::update_with`], for updaters keeping a state (see
+    /// [`SecretUpdaterMut`]).
+    pub fn update_with_mut<A, Updater>(self: Pin<&mut Self>, updater: &mut Updater) -> A
+    where
+        Updater: SecretUpdaterMut<Data, A> + ?Sized,
+        Data: Unsizeable,
+    {
+        self._write(|data| updater.update_mut(data.get_unsized_mut()))
+    }
+
+    /// Same as [`Secret::read_with`], for readers awaiting in between their accesses to the
+    /// secret (see [`AsyncSecretReader`]).
+    pub async fn read_with_async<A, Reader>(self: Pin<&Self>, reader: &Reader) -> A
+    where
+        Reader: AsyncSecretReader<Data, A, Alloc>,
+        Data: Unsizeable,
+    {
+        reader.read(self).await
+    }
+
+    /// Same as [`Secret::update_with`], for updaters awaiting in between their accesses to the
+    /// secret (see [`AsyncSecretUpdater`]).
+    pub async fn update_with_async<A, Updater>(self: Pin<&mut Self>, updater: &Updater) -> A
+    where
+        Updater: AsyncSecretUpdater<Data, A, Alloc>,
+        Data: Unsizeable,
+    {
+        updater.update(self).await
+    }
+
+    /// Compares the secret with another one in constant time, where `==` would exit on the
+    /// first differing byte. Only the lengths may leak.
+    pub fn ct_eq<Other, OtherAlloc: SecureAlloc>(
+        self: Pin<&Self>,
+        other: Pin<&Secret<Other, OtherAlloc>>,
+    ) -> bool
+    where
+        Data: Unsizeable<Unsized = [u8]>,
+        Other: Zeroize + Unsizeable<Unsized = [u8]>,
+    {
+        self.read_with(&|sec: &[u8]| other.read_with(&|other: &[u8]| constant_time_eq(sec, other)))
+    }
+
+    /// Same as [`Secret::read_with`], with a `scratch` area lent to `reader`, for its
+    /// intermediate values (derived keys, decrypted blocks…).
+    ///
+    /// If `reader` panics, `scratch` is zeroized before the panic is propagated, so
+    /// partially computed sensible values are not left behind.
+    #[cfg(feature = "std")]
+    pub fn read_with_scratch<A, Scratch, Reader>(
+        self: Pin<&Self>,
+        scratch: &mut Scratch,
+        reader: Reader,
+    ) -> A
+    where
+        Scratch: Zeroize + ?Sized,
+        Reader: FnOnce(&Data::Unsized, &mut Scratch) -> A,
+        Data: Unsizeable,
+    {
+        self._read(|data| {
+            panic::catch_unwind(AssertUnwindSafe(|| reader(data.get_unsized(), scratch)))
+                .unwrap_or_else(|payload| {
+                    scratch.zeroize();
+                    panic::resume_unwind(payload)
+                })
+        })
     }
+
+    /// Same as [`Secret::update_with`], with a `scratch` area lent to `updater`, for its
+    /// intermediate values (decoded input, key material being assembled…).
+    ///
+    /// If `updater` panics, `scratch` is zeroized before the panic is propagated. When
+    /// `wipe_secret` is set, the secret itself is zeroized too, rather than being left
+    /// partially updated.
+    #[cfg(feature = "std")]
+    pub fn update_with_scratch<A, Scratch, Updater>(
+        self: Pin<&mut Self>,
+        scratch: &mut Scratch,
+        wipe_secret: bool,
+        updater: Updater,
+    ) -> A
+    where
+        Scratch: Zeroize + ?Sized,
+        Updater: FnOnce(&mut Data::Unsized, &mut Scratch) -> A,
+        Data: Unsizeable,
+    {
+        self._write(|data| {
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                updater(data.get_unsized_mut(), scratch)
+            }))
+            .unwrap_or_else(|payload| {
+                scratch.zeroize();
+                if wipe_secret {
+                    data.zeroize();
+                }
+                panic::resume_unwind(payload)
+            })
+        })
+    }
+}
+
+/// Compares `a` and `b` without early exit, so that the time taken only depends on their
+/// lengths.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    // The accumulator is hidden from the optimizer, which could otherwise exit early.
+    let diff = a
+        .iter()
+        .zip(b)
+        .fold(0, |diff, (x, y)| core::hint::black_box(diff | (x ^ y)));
+    diff == 0
 }
+
 /// This trait makes it possible to work on unsized types instead of
 /// sized one. This prevent unattended copies of sensible data on the stack.
 pub trait Unsizeable {
@@ -101,6 +526,7 @@ impl<const N: usize> Unsizeable for [u8; N] {
     }
 }
 
+#[cfg(feature = "alloc")]
 impl Unsizeable for String {
     type Unsized = str;
 
@@ -113,6 +539,7 @@ impl Unsizeable for String {
     }
 }
 
+#[cfg(feature = "alloc")]
 impl<T> Unsizeable for Vec<T> {
     type Unsized = [T];
 
@@ -133,7 +560,8 @@ pub trait SecretReader<Data: Zeroize + Unsizeable, A> {
     ///
     /// * Returned array is unsized in order to prevent
     ///   unwanted copy on the stack by dereferencing it.
-    /// * `self` is *not* mutable, making it harder to leak secret
+    /// * `self` is *not* mutable, making it harder to leak secret (readers keeping a
+    ///   state implement [`SecretReaderMut`] instead)
     ///
     /// # Leaks
     ///
@@ -191,6 +619,67 @@ pub trait SecretUpdater<Data: Zeroize + Unsizeable, A> {
     fn update(&self, sec: &mut Data::Unsized) -> A;
 }
 
+/// A type reading a [`Secret`] while keeping a state must implement this trait, instead of
+/// [`SecretReader`] with interior mutability.
+///
+/// # Secrets leak mitigation
+///
+/// The state is mutable, so that the state a reader keeps is explicit in its type, to be
+/// audited: it must only hold what can outlive the access (a counter, a digest…), never the
+/// secret itself nor parts of it.
+pub trait SecretReaderMut<Data: Zeroize + Unsizeable, A> {
+    fn read_mut(&mut self, sec: &Data::Unsized) -> A;
+}
+
+impl<T, Data: Zeroize + Unsizeable, A> SecretReaderMut<Data, A> for T
+where
+    T: FnMut(&Data::Unsized) -> A,
+{
+    fn read_mut(&mut self, sec: &Data::Unsized) -> A {
+        self(sec)
+    }
+}
+
+/// A type updating a [`Secret`] while keeping a state must implement this trait, as
+/// [`SecretReaderMut`].
+pub trait SecretUpdaterMut<Data: Zeroize + Unsizeable, A> {
+    fn update_mut(&mut self, sec: &mut Data::Unsized) -> A;
+}
+
+impl<T, Data: Zeroize + Unsizeable, A> SecretUpdaterMut<Data, A> for T
+where
+    T: FnMut(&mut Data::Unsized) -> A,
+{
+    fn update_mut(&mut self, sec: &mut Data::Unsized) -> A {
+        self(sec)
+    }
+}
+
+/// A type reading a [`Secret`] asynchronously must implement this trait, with an `async fn`
+/// whose future is `Send`, so that it can be awaited by multithreaded runtimes.
+///
+/// # Secrets leak mitigation
+///
+/// The reader is lent the secret, not its data: it accesses it with [`Secret::read_with`],
+/// whose readers are synchronous. The data can thus not be held across an `.await`, and stays
+/// concealed (see [`Protection`]) while the task is suspended, whatever the other tasks do.
+/// The data copied out of the secret to be sent away, such as the plaintext of a request to
+/// a KMS, should be held in a [`Zeroizing`] buffer.
+pub trait AsyncSecretReader<Data: Zeroize + Unsizeable, A, Alloc: SecureAlloc = Global> {
+    fn read(&self, secret: Pin<&Secret<Data, Alloc>>) -> impl Future<Output = A> + Send;
+}
+
+/// A type updating a [`Secret`] asynchronously must implement this trait, as
+/// [`AsyncSecretReader`], the secret being only accessed with [`Secret::update_with`].
+///
+/// # Secrets leak mitigation
+///
+/// The updater should await all it needs (such as the response of a vault) before updating
+/// the secret, and zeroize it if it fails, so that it is never left partially updated.
+pub trait AsyncSecretUpdater<Data: Zeroize + Unsizeable, A, Alloc: SecureAlloc = Global> {
+    fn update(&self, secret: Pin<&mut Secret<Data, Alloc>>) -> impl Future<Output = A> + Send;
+}
+
 #[cfg(test)]
 mod test {
 
@@ -211,4 +700,173 @@ mod test {
 
         SecretByte::is_unpin(); // compile time error if Secret is unpin (because call to is_unpin is ambiguous)
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_scratch_is_wiped_on_panic() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let mut secret = Secret::<[u8; 4]>::boxed();
+        let mut scratch = [0u8; 8];
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            secret
+                .as_mut()
+                .update_with_scratch(&mut scratch, true, |sec, scratch: &mut [u8; 8]| {
+                    scratch.fill(0xff);
+                    sec.fill(0xff);
+                    panic!("updater failure")
+                })
+        }));
+        assert!(result.is_err());
+        assert_eq!(scratch, [0; 8]);
+        assert!(secret.as_ref().read_with(&|sec: &[u8]| sec == [0; 4]));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_stateful_access() {
+        let mut secret = Secret::<[u8; 8]>::boxed();
+        let mut next = 0;
+        let mut fill = |sec: &mut [u8]| {
+            for byte in sec {
+                *byte = next;
+                next += 1;
+            }
+        };
+        secret.as_mut().update_with_mut(&mut fill);
+        secret.as_mut().update_with_mut(&mut fill);
+        assert_eq!(next, 16);
+
+        let mut checksum = 0u32;
+        let mut reads = 0;
+        let mut accumulate = |sec: &[u8]| {
+            checksum += sec.iter().map(|&byte| u32::from(byte)).sum::<u32>();
+            reads += 1;
+        };
+        secret.as_ref().read_with_mut(&mut accumulate);
+        secret.as_ref().read_with_mut(&mut accumulate);
+        assert_eq!((checksum, reads), (2 * (8..16).sum::<u32>(), 2));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_async_access() {
+        use core::{
+            pin::{Pin, pin},
+            task::{Context, Poll, Waker},
+        };
+
+        use zeroize::Zeroizing;
+
+        use crate::api::{AsyncSecretReader, AsyncSecretUpdater};
+
+        /// Stands for a request to a remote service, pending on its first poll.
+        struct Remote(bool);
+        impl Future for Remote {
+            type Output = ();
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if core::mem::replace(&mut self.0, true) {
+                    return Poll::Ready(());
+                }
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+
+        /// Fetches a key, then writes it.
+        struct Fetch;
+        impl AsyncSecretUpdater<[u8; 4], ()> for Fetch {
+            async fn update(&self, secret: Pin<&mut Secret<[u8; 4]>>) {
+                Remote(false).await;
+                secret.update_with(&|sec: &mut [u8]| sec.copy_from_slice(b"key!"));
+            }
+        }
+
+        /// Sends a copy of the key away, then reads it again.
+        struct Forward;
+        impl AsyncSecretReader<[u8; 4], bool> for Forward {
+            async fn read(&self, secret: Pin<&Secret<[u8; 4]>>) -> bool {
+                let request = secret.read_with(&|sec: &[u8]| Zeroizing::new(sec.to_vec()));
+                Remote(false).await;
+                secret.read_with(&|sec: &[u8]| sec == &request[..])
+            }
+        }
+
+        fn block_on<F: Future + Send>(future: F) -> F::Output {
+            let mut future = pin!(future);
+            let mut cx = Context::from_waker(Waker::noop());
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+        }
+
+        let mut secret = Secret::<[u8; 4]>::boxed();
+        block_on(secret.as_mut().update_with_async(&Fetch));
+        assert!(block_on(secret.as_ref().read_with_async(&Forward)));
+        assert!(secret.as_ref().read_with(&|sec: &[u8]| sec == b"key!"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_init_secret() {
+        crate::secret!(let key: [u8; 4] = &|sec: &mut [u8]| sec.fill(1));
+        assert!(key.as_ref().read_with(&|sec: &[u8]| sec == [1; 4]));
+        crate::secret!(let counter: [u8; 4] = &|sec: &mut [u8]| sec.len() => len);
+        assert_eq!(len, 4);
+        assert!(counter.as_ref().read_with(&|sec: &[u8]| sec == [0; 4]));
+
+        let secret = Secret::<[u8; 4]>::init(&|sec: &mut [u8]| sec.fill(2));
+        assert!(secret.as_ref().read_with(&|sec: &[u8]| sec == [2; 4]));
+        let secret = Secret::<[u8; 4]>::try_init(&|sec: &mut [u8]| {
+            sec.fill(3);
+            Ok::<_, ()>(sec.len())
+        });
+        assert!(
+            secret
+                .unwrap()
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec == [3; 4])
+        );
+        let failed = Secret::<[u8; 4]>::try_init(&|_: &mut [u8]| Err::<(), _>("failure"));
+        assert_eq!(failed.err(), Some("failure"));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_boxed_secret() {
+        let (secret, ()) = Secret::<[u8; 4]>::boxed_with(&|sec: &mut [u8]| sec.fill(1));
+        let moved = secret;
+        assert!(moved.as_ref().read_with(&|sec: &[u8]| sec == [1; 4]));
+    }
+
+    #[cfg(all(debug_assertions, feature = "std"))]
+    #[test]
+    fn test_dropped_secret_access_is_detected() {
+        use std::{
+            panic::{self, AssertUnwindSafe},
+            pin::pin,
+        };
+
+        // Poisoned as on drop, but alive: what a dangling reference, obtained through unsafe
+        // code, would see.
+        let mut secret = pin!(Secret::<[u8; 4]>::new());
+        unsafe { secret.as_mut().get_unchecked_mut() }
+            .poison
+            .poison();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            secret.as_ref().read_with(&|sec: &[u8]| sec.len());
+        }));
+        let payload = result.unwrap_err();
+        let message = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap();
+        assert!(
+            message.starts_with("access to a dropped secret"),
+            "{message}"
+        );
+    }
 }
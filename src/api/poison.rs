@@ -0,0 +1,103 @@
+//! Detection of accesses to dropped secrets, in debug builds.
+//!
+//! Each [`Secret`](super::Secret) holds a sentinel, poisoned on drop. Accessing a secret
+//! whose sentinel is poisoned (through a dangling reference created by unsafe code) panics,
+//! reporting where the secret was created.
+
+#[cfg(all(debug_assertions, feature = "std"))]
+mod imp {
+    use std::{
+        backtrace::{Backtrace, BacktraceStatus},
+        collections::VecDeque,
+        sync::Mutex,
+    };
+
+    const ALIVE: u64 = 0x5ec2_e7a1_17e5_ec2e;
+    const POISONED: u64 = 0xdead_5ec2_e7de_ad00;
+
+    /// Number of dropped secrets whose creation backtrace is kept.
+    const KEPT: usize = 64;
+
+    /// Creation backtraces of the last dropped secrets, by address of their sentinel.
+    static DROPPED: Mutex<VecDeque<(usize, Backtrace)>> = Mutex::new(VecDeque::new());
+
+    pub(crate) struct Poison {
+        sentinel: u64,
+        /// Only captured if enabled, see [`Backtrace::capture`].
+        origin: Option<Backtrace>,
+    }
+
+    impl Poison {
+        pub(crate) fn new() -> Self {
+            let origin = Backtrace::capture();
+            Poison {
+                sentinel: ALIVE,
+                origin: (origin.status() == BacktraceStatus::Captured).then_some(origin),
+            }
+        }
+
+        fn addr(&self) -> usize {
+            &self.sentinel as *const u64 as usize
+        }
+
+        /// # Panics
+        ///
+        /// Panics if the secret has been dropped.
+        pub(crate) fn check(&self) {
+            // Read as if the memory could have been changed behind our back, as it has been
+            // released already when poisoned.
+            let sentinel = unsafe { std::ptr::read_volatile(&self.sentinel) };
+            if sentinel == ALIVE {
+                return;
+            }
+            let dropped = DROPPED.lock().unwrap_or_else(|err| err.into_inner());
+            match dropped.iter().rev().find(|(addr, _)| *addr == self.addr()) {
+                Some((_, origin)) => panic!("access to a dropped secret, created at:\n{origin}"),
+                None => panic!(
+                    "access to a dropped secret (run with RUST_BACKTRACE=1 to report its creation)"
+                ),
+            }
+        }
+
+        /// Poisons the sentinel, as done on drop. Only called directly by tests, to check
+        /// live secrets as if they had been dropped.
+        pub(crate) fn poison(&mut self) {
+            unsafe { std::ptr::write_volatile(&mut self.sentinel, POISONED) };
+            if let Some(origin) = self.origin.take() {
+                let mut dropped = DROPPED.lock().unwrap_or_else(|err| err.into_inner());
+                if dropped.len() == KEPT {
+                    dropped.pop_front();
+                }
+                dropped.push_back((self.addr(), origin));
+            }
+        }
+    }
+
+    impl Clone for Poison {
+        fn clone(&self) -> Self {
+            Poison::new()
+        }
+    }
+
+    impl Drop for Poison {
+        fn drop(&mut self) {
+            self.poison();
+        }
+    }
+}
+
+#[cfg(not(all(debug_assertions, feature = "std")))]
+mod imp {
+    #[derive(Clone)]
+    pub(crate) struct Poison;
+
+    impl Poison {
+        pub(crate) fn new() -> Self {
+            Poison
+        }
+
+        pub(crate) fn check(&self) {}
+    }
+}
+
+pub(crate) use imp::Poison;
@@ -0,0 +1,127 @@
+//! Error of fallible accesses to secrets, see [`Secret::try_read_with`] and
+//! [`Secret::try_update_with`].
+//!
+//! Actions fail with [`io::Error`]s, converted to [`Error::Io`], so that they compose with
+//! `?` with readers and updaters failing with an [`Error`] themselves.
+
+use std::{error, fmt, io};
+
+#[cfg(doc)]
+use crate::api::Secret;
+
+/// Errors of accesses to secrets.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Failure of an I/O operation or of an action, such as reading a file or decrypting.
+    Io(io::Error),
+    /// Failure of a cryptographic operation: a ciphertext, a tag or a signature that does not
+    /// verify, or an invalid key.
+    Crypto,
+    /// Data of an unexpected length, such as a key larger than the secret it is loaded into.
+    Length { expected: usize, actual: usize },
+    /// Failure of a secret store (an HSM, a vault, a keychain…).
+    Backend(Box<dyn error::Error + Send + Sync>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => err.fmt(f),
+            Error::Crypto => f.write_str("cryptographic operation failed"),
+            Error::Length { expected, actual } => {
+                write!(f, "expected {expected} bytes, got {actual} bytes")
+            }
+            Error::Backend(err) => write!(f, "secret store failed: {err}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Backend(err) => Some(&**err),
+            Error::Crypto | Error::Length { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// [`Error::Io`] is unwrapped, the other errors are held by errors of kind
+/// [`io::ErrorKind::InvalidData`] ([`Error::Crypto`]), [`io::ErrorKind::InvalidInput`]
+/// ([`Error::Length`]) or [`io::ErrorKind::Other`] ([`Error::Backend`]).
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match err {
+            Error::Io(err) => return err,
+            Error::Crypto => io::ErrorKind::InvalidData,
+            Error::Length { .. } => io::ErrorKind::InvalidInput,
+            Error::Backend(_) => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[cfg(feature = "impl")]
+impl From<aes_gcm::aead::Error> for Error {
+    fn from(_: aes_gcm::aead::Error) -> Self {
+        Error::Crypto
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::*;
+    use crate::api::Secret;
+
+    #[test]
+    fn test_try_access() {
+        let mut secret = pin!(Secret::<[u8; 16]>::new());
+        let load = |data: &'static [u8]| {
+            move |sec: &mut [u8]| {
+                sec.fill(0xff);
+                if data.len() > sec.len() {
+                    return Err(Error::Length {
+                        expected: sec.len(),
+                        actual: data.len(),
+                    });
+                }
+                sec[..data.len()].copy_from_slice(data);
+                Ok(data.len())
+            }
+        };
+        assert_eq!(secret.as_mut().try_update_with(&load(b"key")).unwrap(), 3);
+        let err = secret
+            .as_mut()
+            .try_update_with(&load(&[1; 17]))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Length {
+                expected: 16,
+                actual: 17
+            }
+        ));
+        // The failed update did not leave the secret partially written.
+        assert!(secret.as_ref().read_with(&|sec: &[u8]| sec == [0; 16]));
+
+        let err = secret
+            .as_ref()
+            .try_read_with(&|_: &[u8]| Err::<(), _>(io::Error::from(io::ErrorKind::NotFound)))
+            .unwrap_err();
+        let err = io::Error::from(err);
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = io::Error::from(Error::Crypto);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "cryptographic operation failed");
+    }
+}
@@ -0,0 +1,413 @@
+//! Process-wide hardening, complementary to the per-secret protections of [`memory`](crate::memory).
+//!
+//! These are meant to be applied once, early at startup, before any secret is created.
+
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Set once [`lock_all_memory`] succeeded, since it cannot be queried afterwards.
+static ALL_MEMORY_LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Set once [`restrict_ptrace`] succeeded, on platforms where it cannot be queried.
+#[cfg(target_os = "macos")]
+static PTRACE_RESTRICTED: AtomicBool = AtomicBool::new(false);
+
+/// Process-wide mitigations currently active, see [`mitigations`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Mitigations {
+    /// All the memory of the process is locked in RAM (see [`lock_all_memory`]).
+    pub all_memory_locked: bool,
+    /// No core dump of the process can be written (see [`disable_dumps`]).
+    pub dumps_disabled: bool,
+    /// Debuggers cannot attach to the process (see [`restrict_ptrace`]).
+    pub ptrace_restricted: bool,
+}
+
+/// Reports which of the mitigations of this module are active for the current process,
+/// whether they have been applied by this module or inherited.
+pub fn mitigations() -> Mitigations {
+    Mitigations {
+        all_memory_locked: ALL_MEMORY_LOCKED.load(Ordering::Relaxed),
+        dumps_disabled: dumps_disabled(),
+        ptrace_restricted: ptrace_restricted(),
+    }
+}
+
+/// Locks all the current and future memory of the process in RAM, so nothing
+/// (secrets included) can ever be swapped to disk.
+///
+/// This wraps `mlockall(MCL_CURRENT | MCL_FUTURE)`. Beforehand, the soft `RLIMIT_MEMLOCK`
+/// limit is raised up to the hard one, since the default soft limit is usually far too
+/// small for a whole process.
+///
+/// # Errors
+///
+/// Fails if the platform does not support it, or if the process memory exceeds the
+/// lockable amount (unless privileged with `CAP_IPC_LOCK` on Linux).
+///
+/// # Caveat
+///
+/// Once successful, any future allocation that cannot be locked fails, which usually
+/// means an out of memory abort. Keep `RLIMIT_MEMLOCK` large enough for the whole process.
+pub fn lock_all_memory() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        raise_soft_memlock_limit()?;
+        if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        ALL_MEMORY_LOCKED.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "locking all memory is not supported on this platform",
+    ))
+}
+
+/// Prevents core dumps of the process, which would contain every secret alive at the time.
+///
+/// This sets the `RLIMIT_CORE` limit to zero, and on Linux, also clears the dumpable flag
+/// (`prctl(PR_SET_DUMPABLE, 0)`), since core dumps piped to a handler (see `core_pattern`)
+/// ignore the limit.
+///
+/// # Errors
+///
+/// Fails if the platform does not support it.
+///
+/// # Caveat
+///
+/// On Linux, a non dumpable process cannot be traced by unprivileged debuggers either
+/// (see [`restrict_ptrace`]), and its `/proc/self` entries become owned by root.
+pub fn disable_dumps() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        clear_dumpable()?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "disabling core dumps is not supported on this platform",
+    ))
+}
+
+/// Prevents debuggers from attaching to the process, so that other processes of the
+/// same user cannot read its memory.
+///
+/// This clears the dumpable flag on Linux (`prctl(PR_SET_DUMPABLE, 0)`), disables tracing
+/// with `procctl(PROC_TRACE_CTL)` on FreeBSD, and uses `ptrace(PT_DENY_ATTACH)` on macOS.
+///
+/// # Errors
+///
+/// Fails if the platform does not support it.
+///
+/// # Caveat
+///
+/// Privileged processes (`CAP_SYS_PTRACE` on Linux, root elsewhere) are not restricted.
+pub fn restrict_ptrace() -> io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return clear_dumpable();
+    #[cfg(target_os = "freebsd")]
+    {
+        let mut disable = libc::PROC_TRACE_CTL_DISABLE;
+        let ret = unsafe {
+            libc::procctl(
+                libc::P_PID,
+                0,
+                libc::PROC_TRACE_CTL,
+                (&mut disable as *mut libc::c_int).cast(),
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(target_os = "macos")]
+    {
+        if unsafe { libc::ptrace(libc::PT_DENY_ATTACH, 0, std::ptr::null_mut(), 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        PTRACE_RESTRICTED.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "macos"
+    )))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "restricting ptrace is not supported on this platform",
+    ))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn clear_dumpable() -> io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_dumpable() -> bool {
+    unsafe { libc::prctl(libc::PR_GET_DUMPABLE) != 0 }
+}
+
+fn dumps_disabled() -> bool {
+    #[cfg(unix)]
+    {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        let no_core =
+            unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) } == 0 && limit.rlim_cur == 0;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        return no_core && !is_dumpable();
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        return no_core;
+    }
+    #[cfg(not(unix))]
+    false
+}
+
+fn ptrace_restricted() -> bool {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return !is_dumpable();
+    #[cfg(target_os = "freebsd")]
+    {
+        // -1 when tracing is disabled, the pid of the tracer otherwise.
+        let mut status: libc::c_int = 0;
+        let ret = unsafe {
+            libc::procctl(
+                libc::P_PID,
+                0,
+                libc::PROC_TRACE_STATUS,
+                (&mut status as *mut libc::c_int).cast(),
+            )
+        };
+        ret == 0 && status == -1
+    }
+    #[cfg(target_os = "macos")]
+    return PTRACE_RESTRICTED.load(Ordering::Relaxed);
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "macos"
+    )))]
+    false
+}
+
+/// Returns the soft `RLIMIT_MEMLOCK` limit, in bytes, or `None` if unlimited.
+///
+/// Locking more memory than this limit fails, unless privileged (`CAP_IPC_LOCK` on Linux).
+///
+/// # Errors
+///
+/// Fails if the platform does not have such a limit.
+pub fn memlock_limit() -> io::Result<Option<usize>> {
+    #[cfg(unix)]
+    return get_memlock_limit().map(|limit| from_rlim(limit.rlim_cur));
+    #[cfg(not(unix))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "there is no memory locking limit on this platform",
+    ))
+}
+
+/// Raises the soft `RLIMIT_MEMLOCK` limit to at least `bytes`, so that many bytes of secrets
+/// (and the rest of the process, see [`lock_all_memory`]) can be locked.
+///
+/// Raising it above the hard limit requires privileges (`CAP_SYS_RESOURCE` on Linux).
+///
+/// # Errors
+///
+/// Fails if the platform does not have such a limit, or if the process is not allowed to
+/// raise it that much.
+pub fn raise_memlock_limit(bytes: usize) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut limit = get_memlock_limit()?;
+        let bytes = bytes as libc::rlim_t;
+        if from_rlim(limit.rlim_cur).is_none_or(|cur| cur >= bytes as usize) {
+            return Ok(());
+        }
+        limit.rlim_cur = bytes;
+        if from_rlim(limit.rlim_max).is_some_and(|max| max < bytes as usize) {
+            limit.rlim_max = bytes;
+        }
+        set_memlock_limit(&limit)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = bytes;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "there is no memory locking limit on this platform",
+        ))
+    }
+}
+
+/// Returns how many more bytes can be locked before reaching [`memlock_limit`], or `None`
+/// if unlimited.
+///
+/// # Errors
+///
+/// Fails if the amount of memory already locked is unknown (it is only reported on Linux).
+pub fn remaining_memlock() -> io::Result<Option<usize>> {
+    #[cfg(target_os = "linux")]
+    {
+        let Some(limit) = memlock_limit()? else {
+            return Ok(None);
+        };
+        let status = std::fs::read_to_string("/proc/self/status")?;
+        let locked = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmLck:"))
+            .and_then(|kib| {
+                kib.trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no VmLck in status"))?;
+        Ok(Some(limit.saturating_sub(locked * 1024)))
+    }
+    #[cfg(not(target_os = "linux"))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "locked memory is not reported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn from_rlim(limit: libc::rlim_t) -> Option<usize> {
+    (limit != libc::RLIM_INFINITY).then(|| usize::try_from(limit).unwrap_or(usize::MAX))
+}
+
+#[cfg(unix)]
+fn get_memlock_limit() -> io::Result<libc::rlimit> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(limit)
+}
+
+#[cfg(unix)]
+fn set_memlock_limit(limit: &libc::rlimit) -> io::Result<()> {
+    if unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn raise_soft_memlock_limit() -> io::Result<()> {
+    let mut limit = get_memlock_limit()?;
+    if limit.rlim_cur != limit.rlim_max {
+        limit.rlim_cur = limit.rlim_max;
+        set_memlock_limit(&limit)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    /// Runs `f` in a forked child, since mitigations are process-wide, and asserts it succeeded.
+    #[cfg(target_os = "linux")]
+    fn in_child(f: impl FnOnce() -> i32) {
+        match unsafe { libc::fork() } {
+            0 => unsafe { libc::_exit(f()) },
+            pid => {
+                let mut status = 0;
+                assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+                assert!(libc::WIFEXITED(status));
+                assert_eq!(libc::WEXITSTATUS(status), 0);
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_lock_all_memory() {
+        in_child(|| match super::lock_all_memory() {
+            Ok(()) => {
+                let status = std::fs::read_to_string("/proc/self/status").unwrap();
+                let locked = status
+                    .lines()
+                    .find_map(|line| line.strip_prefix("VmLck:"))
+                    .map(|kib| kib.trim().trim_end_matches("kB").trim() != "0");
+                (locked != Some(true) || !super::mitigations().all_memory_locked) as i32
+            }
+            // Unprivileged, with a hard limit smaller than the process.
+            Err(err) if err.raw_os_error() == Some(libc::ENOMEM) => 0,
+            Err(_) => 2,
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_disable_dumps_and_restrict_ptrace() {
+        in_child(|| {
+            if super::restrict_ptrace().is_err() {
+                return 1;
+            }
+            if !super::mitigations().ptrace_restricted {
+                return 2;
+            }
+            if super::disable_dumps().is_err() || !super::mitigations().dumps_disabled {
+                return 3;
+            }
+            0
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_raise_memlock_limit() {
+        in_child(|| {
+            let Ok(Some(limit)) = super::memlock_limit() else {
+                return 0;
+            };
+            if super::raise_memlock_limit(limit / 2).is_err()
+                || super::memlock_limit().ok() != Some(Some(limit))
+            {
+                return 1;
+            }
+            // Raising the hard limit requires privileges.
+            let target = limit + (1 << 20);
+            if super::raise_memlock_limit(target).is_ok()
+                && super::memlock_limit().ok() != Some(Some(target))
+            {
+                return 2;
+            }
+            match super::remaining_memlock() {
+                Ok(Some(remaining)) if remaining <= target => 0,
+                _ => 3,
+            }
+        });
+    }
+}
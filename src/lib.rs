@@ -1,3 +1,22 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 #[cfg(feature = "impl")]
 pub mod actions;
 pub mod api;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+pub mod hardening;
+pub mod memory;
+#[cfg(feature = "std")]
+mod report;
+#[cfg(all(feature = "test-support", target_os = "linux"))]
+pub mod test_support;
+
+#[cfg(feature = "std")]
+pub use error::Error;
+#[cfg(feature = "std")]
+pub use report::{ProtectionReport, protection_report};
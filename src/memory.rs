@@ -0,0 +1,617 @@
+//! Dedicated memory backing secrets outside of the regular allocator.
+//!
+//! Dedicated memory (see [`SecureAlloc`]) requires the `std` feature.
+
+use core::{
+    fmt,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+#[cfg(feature = "std")]
+use std::io;
+
+use zeroize::Zeroize;
+
+#[cfg(all(feature = "std", not(any(unix, windows))))]
+mod other;
+#[cfg(feature = "std")]
+mod pkey;
+#[cfg(feature = "std")]
+mod secure_box;
+#[cfg(all(feature = "std", unix))]
+mod unix;
+#[cfg(all(feature = "std", windows))]
+mod windows;
+
+#[cfg(all(feature = "std", not(any(unix, windows))))]
+use other as sys;
+#[cfg(all(feature = "std", unix))]
+use unix as sys;
+#[cfg(all(feature = "std", windows))]
+use windows as sys;
+
+#[cfg(feature = "std")]
+pub(crate) use secure_box::SecureBox;
+#[cfg(feature = "std")]
+pub use secure_box::{OnCorruption, Protection, set_on_corruption};
+
+/// Without `std`, secrets cannot have dedicated pages: this type cannot be instantiated.
+#[cfg(not(feature = "std"))]
+pub(crate) struct SecureBox<T, Alloc>(
+    core::convert::Infallible,
+    core::marker::PhantomData<(T, Alloc)>,
+);
+
+#[cfg(not(feature = "std"))]
+impl<T, Alloc> SecureBox<T, Alloc> {
+    pub(crate) fn read<A>(&self, _f: impl FnOnce(&T) -> A) -> A {
+        match self.0 {}
+    }
+
+    pub(crate) fn write<A>(&mut self, _f: impl FnOnce(&mut T) -> A) -> A {
+        match self.0 {}
+    }
+
+    pub(crate) fn try_clone(&self) -> Result<Self, core::convert::Infallible> {
+        match self.0 {}
+    }
+}
+
+/// Access rights of the pages of a [`SecureBox`].
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+enum Access {
+    None,
+    Read,
+    ReadWrite,
+}
+
+/// Returns the size of a memory page.
+#[cfg(feature = "std")]
+pub fn page_size() -> usize {
+    sys::page_size()
+}
+
+/// Returns whether `memfd_secret` can be used to back secrets.
+///
+/// `memfd_secret` (Linux 5.14 and later) removes the pages from the kernel direct
+/// map, so they are invisible to other processes and to most of the kernel. It can
+/// be compiled out or disabled at boot, hence this runtime probe (done once).
+#[cfg(feature = "std")]
+pub fn secret_mem_available() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        static AVAILABLE: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+        *AVAILABLE.get_or_init(sys::probe_secret_mem)
+    }
+    #[cfg(not(target_os = "linux"))]
+    false
+}
+
+/// Returns whether [`Locked`] pages are tagged with the Memory Tagging Extension.
+///
+/// On `aarch64` Linux with MTE, the pages get a random tag, so accesses through any pointer
+/// not derived from the secret itself (a stray or forged pointer) fault. Tags are checked
+/// synchronously on the threads that allocated a secret, and on threads they spawn afterwards.
+#[cfg(feature = "std")]
+pub fn mte_available() -> bool {
+    sys::mte_available()
+}
+
+/// Returns whether secrets can be placed in a protection key domain (see [`Protection::pkey`]).
+///
+/// Protection keys require a CPU supporting them (Intel since Skylake server, AMD since Zen 3),
+/// and Linux 4.9 or later on `x86_64`. The key is allocated on the first call.
+#[cfg(feature = "std")]
+pub fn pkeys_available() -> bool {
+    pkey::available()
+}
+
+/// Overwrites (at least) `bytes` of the stack below the caller with zeroes.
+///
+/// Readers inevitably spill sensible values on the stack (key schedules, intermediate
+/// blocks…), which stay there once they return. Calling this right after accessing a
+/// secret wipes the stack region they used, provided `bytes` covers their stack usage.
+#[inline(never)]
+pub fn scrub_stack(bytes: usize) {
+    const CHUNK: usize = 1024;
+    let mut chunk = [0u8; CHUNK];
+    chunk.zeroize();
+    if bytes > CHUNK {
+        scrub_stack(bytes - CHUNK);
+    }
+    // Used after the recursive call, so that the frame cannot be reused by a tail call.
+    core::hint::black_box(&mut chunk);
+}
+
+static STACK_SCRUBBING: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes [`Secret::read_with`](crate::api::Secret::read_with) and
+/// [`Secret::update_with`](crate::api::Secret::update_with) (and their variants) call
+/// [`scrub_stack`] with `bytes` after each access, for the whole process.
+///
+/// Disabled by default (`0`).
+pub fn set_stack_scrubbing(bytes: usize) {
+    STACK_SCRUBBING.store(bytes, Ordering::Relaxed);
+}
+
+/// Scrubs the stack as configured by [`set_stack_scrubbing`].
+pub(crate) fn scrub_stack_after_access() {
+    let bytes = STACK_SCRUBBING.load(Ordering::Relaxed);
+    if bytes > 0 {
+        scrub_stack(bytes);
+    }
+}
+
+/// Error of the [`Global`] allocator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AllocError {}
+
+#[cfg(feature = "std")]
+impl From<AllocError> for io::Error {
+    fn from(err: AllocError) -> Self {
+        io::Error::new(io::ErrorKind::OutOfMemory, err)
+    }
+}
+
+/// Provider of the dedicated pages backing a [`Secret`](crate::api::Secret).
+///
+/// The allocator is responsible for the properties of the pages themselves, such as
+/// being locked in RAM or excluded from dumps. Whatever the allocator, secrets always
+/// zeroize their pages before giving them back (zero-on-free), and surround their data
+/// with canaries.
+///
+/// # Safety
+///
+/// [`SecureAlloc::allocate`] must return page-aligned memory, readable, writable and exclusively
+/// owned by the caller until given back to [`SecureAlloc::deallocate`]. Implementations must
+/// accept that the access rights of those pages are changed (see
+/// [`Secret::new_no_access_in`](crate::api::Secret::new_no_access_in)), and restored before
+/// deallocation.
+pub unsafe trait SecureAlloc {
+    type Error: fmt::Debug + fmt::Display;
+
+    /// Allocates `len` bytes, `len` being a non-zero multiple of the page size.
+    fn allocate(&self, len: usize) -> Result<NonNull<u8>, Self::Error>;
+
+    /// Gives back memory obtained from [`SecureAlloc::allocate`], already zeroized.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` and `len` must come from a successful call to [`SecureAlloc::allocate`] of this
+    /// allocator, and the memory must not be used anymore.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, len: usize);
+}
+
+/// Pages from the global allocator, without any additional protection.
+///
+/// Without `std`, allocation always fails.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Global;
+
+unsafe impl SecureAlloc for Global {
+    type Error = AllocError;
+
+    #[cfg(feature = "std")]
+    fn allocate(&self, len: usize) -> Result<NonNull<u8>, AllocError> {
+        let layout =
+            std::alloc::Layout::from_size_align(len, page_size()).map_err(|_| AllocError)?;
+        NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) }).ok_or(AllocError)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn allocate(&self, _len: usize) -> Result<NonNull<u8>, AllocError> {
+        Err(AllocError)
+    }
+
+    #[cfg(not(feature = "std"))]
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _len: usize) {
+        unreachable!("no allocation can exist without std")
+    }
+
+    #[cfg(feature = "std")]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, len: usize) {
+        let layout = std::alloc::Layout::from_size_align(len, page_size()).unwrap();
+        unsafe { std::alloc::dealloc(ptr.as_ptr(), layout) };
+    }
+}
+
+/// Dedicated pages, kept out of swap.
+///
+/// # Security
+///
+/// The pages are their own anonymous mapping, so locking (and later unlocking) them never
+/// affects unrelated heap allocations. They are locked in RAM (`mlock` on Unix,
+/// `VirtualLock` on Windows).
+///
+/// The pages are excluded from core dumps (`MADV_DONTDUMP` on Linux, `MAP_NOCORE` on
+/// FreeBSD, `MAP_CONCEAL` on OpenBSD, and `WerRegisterExcludedMemoryBlock` on Windows,
+/// for Windows Error Reporting dumps).
+///
+/// On Unix, forked children never see their content: the pages are wiped in the child
+/// (`MADV_WIPEONFORK` on Linux 4.14 and later, or a `pthread_atfork` handler
+/// replacing them with fresh zeroed pages otherwise). In the child, secrets are left with
+/// their default data, as if just created.
+///
+/// When [`mte_available`], the pages are tagged, so stray pointers cannot access them.
+///
+/// # Errors
+///
+/// Allocation fails if the pages cannot be locked (see `RLIMIT_MEMLOCK` on Unix, and the
+/// working set size on Windows).
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Locked;
+
+/// Applies the protections common to all dedicated pages, unmapping them on failure.
+#[cfg(feature = "std")]
+fn harden(ptr: NonNull<u8>, len: usize) -> io::Result<NonNull<u8>> {
+    if let Err(err) = sys::exclude_from_dumps(ptr, len).and_then(|()| sys::wipe_on_fork(ptr, len)) {
+        unsafe { sys::unmap(ptr, len) };
+        return Err(err);
+    }
+    Ok(ptr)
+}
+
+#[cfg(feature = "std")]
+unsafe impl SecureAlloc for Locked {
+    type Error = io::Error;
+
+    fn allocate(&self, len: usize) -> io::Result<NonNull<u8>> {
+        let ptr = sys::map(len)?;
+        if let Err(err) = sys::lock(ptr, len) {
+            unsafe { sys::unmap(ptr, len) };
+            return Err(err);
+        }
+        harden(ptr, len).map(|ptr| sys::tag(ptr, len))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, len: usize) {
+        let ptr = sys::untag(ptr);
+        unsafe {
+            sys::unlock(ptr, len);
+            sys::unmap(ptr, len);
+        }
+    }
+}
+
+/// `memfd_secret` pages, removed from the kernel direct map, when [`secret_mem_available`].
+/// Otherwise, falls back to [`Locked`] pages.
+///
+/// Secret memory pages are implicitly locked, and get the same protections as [`Locked`] ones.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SecretMem;
+
+#[cfg(feature = "std")]
+unsafe impl SecureAlloc for SecretMem {
+    type Error = io::Error;
+
+    fn allocate(&self, len: usize) -> io::Result<NonNull<u8>> {
+        #[cfg(target_os = "linux")]
+        if secret_mem_available() {
+            return harden(sys::map_secret_mem(len)?, len);
+        }
+        Locked.allocate(len)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, len: usize) {
+        // Unlocking secret memory pages is a no-op, both cases are handled the same way.
+        unsafe { Locked.deallocate(ptr, len) }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+
+    use std::pin::pin;
+
+    use crate::{
+        api::Secret,
+        memory::{Global, Locked, SecretMem, SecureAlloc},
+    };
+
+    #[test]
+    fn test_locked_secret() {
+        let secret: Secret<[u8; 32], Locked> = Secret::new_locked().unwrap();
+        let mut secret_pinned = pin!(secret);
+        secret_pinned
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let sum = secret_pinned
+            .as_ref()
+            .read_with(&|sec: &[u8]| sec.iter().map(|b| *b as usize).sum::<usize>());
+        assert_eq!(sum, 0x42 * 32);
+    }
+
+    /// Returns the `VmFlags` of the mapping containing `addr`, as reported by the kernel.
+    #[cfg(target_os = "linux")]
+    fn vm_flags(addr: usize) -> Vec<String> {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let mut in_mapping = false;
+        for line in smaps.lines() {
+            let range = line.split_whitespace().next().unwrap().split_once('-');
+            if let Some((Ok(start), Ok(end))) = range.map(|(start, end)| {
+                (
+                    usize::from_str_radix(start, 16),
+                    usize::from_str_radix(end, 16),
+                )
+            }) {
+                in_mapping = (start..end).contains(&addr);
+                continue;
+            }
+            if let Some(flags) = line.strip_prefix("VmFlags:").filter(|_| in_mapping) {
+                return flags.split_whitespace().map(String::from).collect();
+            }
+        }
+        panic!("no mapping found at {addr:#x}")
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_locked_secret_is_not_dumped() {
+        let secret: Secret<[u8; 32], Locked> = Secret::new_locked().unwrap();
+        let secret_pinned = pin!(secret);
+        let addr = secret_pinned
+            .as_ref()
+            .read_with(&|sec: &[u8]| sec.as_ptr() as usize);
+        let flags = vm_flags(addr);
+        assert!(flags.iter().any(|flag| flag == "dd"), "{flags:?}");
+        assert!(flags.iter().any(|flag| flag == "lo"), "{flags:?}");
+    }
+
+    #[cfg(target_os = "freebsd")]
+    #[test]
+    fn test_locked_secret_is_not_dumped() {
+        let secret: Secret<[u8; 32], Locked> = Secret::new_locked().unwrap();
+        let secret_pinned = pin!(secret);
+        let addr = secret_pinned
+            .as_ref()
+            .read_with(&|sec: &[u8]| sec.as_ptr() as u64);
+        let mut count = 0;
+        let entries = unsafe { libc::kinfo_getvmmap(libc::getpid(), &mut count) };
+        assert!(!entries.is_null());
+        let flags = unsafe { std::slice::from_raw_parts(entries, count as usize) }
+            .iter()
+            .find(|entry| (entry.kve_start..entry.kve_end).contains(&addr))
+            .map(|entry| entry.kve_flags);
+        unsafe { libc::free(entries.cast()) };
+        assert!(flags.is_some_and(|flags| flags & libc::KVME_FLAG_NOCOREDUMP != 0));
+    }
+
+    /// Forks, and returns the wait status of the child exiting with the code returned by `f`.
+    #[cfg(unix)]
+    fn status_in_child(f: impl FnOnce() -> libc::c_int) -> libc::c_int {
+        match unsafe { libc::fork() } {
+            0 => unsafe { libc::_exit(f()) },
+            pid => {
+                let mut status = 0;
+                assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+                status
+            }
+        }
+    }
+
+    /// Forks, and returns the wait status of the child exiting with the byte read at `addr`.
+    #[cfg(unix)]
+    fn read_in_child(addr: usize) -> libc::c_int {
+        status_in_child(|| unsafe { *(addr as *const u8) } as libc::c_int)
+    }
+
+    /// Forks, and returns the first byte of the secret as seen by the child.
+    #[cfg(unix)]
+    fn first_byte_in_child<Alloc: SecureAlloc>(
+        secret: std::pin::Pin<&Secret<[u8; 32], Alloc>>,
+    ) -> u8 {
+        let status = read_in_child(secret.read_with(&|sec: &[u8]| sec.as_ptr() as usize));
+        assert!(libc::WIFEXITED(status));
+        libc::WEXITSTATUS(status) as u8
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_no_access_secret_faults_when_idle() {
+        let secret: Secret<[u8; 32], Locked> = Secret::new_no_access().unwrap();
+        let mut secret_pinned = pin!(secret);
+        secret_pinned
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let addr = secret_pinned
+            .as_ref()
+            .read_with(&|sec: &[u8]| sec.as_ptr() as usize);
+        let status = read_in_child(addr);
+        assert!(libc::WIFSIGNALED(status) && libc::WTERMSIG(status) == libc::SIGSEGV);
+        let cloned = secret_pinned.clone();
+        assert!(
+            pin!(cloned)
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec == [0x42; 32])
+        );
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    #[test]
+    fn test_tagged_secret_faults_when_untagged() {
+        if !crate::memory::mte_available() {
+            return;
+        }
+        let secret: Secret<[u8; 32], Locked> = Secret::new_locked().unwrap();
+        let addr = pin!(secret)
+            .as_ref()
+            .read_with(&|sec: &[u8]| sec.as_ptr() as usize);
+        let status = read_in_child(addr & !(0xff << 56));
+        assert!(libc::WIFSIGNALED(status) && libc::WTERMSIG(status) == libc::SIGSEGV);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pkey_secret_faults_when_idle() {
+        if !crate::memory::pkeys_available() {
+            return;
+        }
+        let protection = crate::memory::Protection::new().pkey().masked();
+        let secret: Secret<[u8; 32], Locked> =
+            Secret::new_protected_in(Locked, protection).unwrap();
+        let mut secret_pinned = pin!(secret);
+        secret_pinned
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let cloned = pin!(secret_pinned.clone());
+        // Nested accesses keep the rights of the outer one.
+        let addr = cloned.as_ref().read_with(&|sec: &[u8]| {
+            assert!(
+                secret_pinned
+                    .as_ref()
+                    .read_with(&|other: &[u8]| sec == other)
+            );
+            assert_eq!(sec, [0x42; 32]);
+            sec.as_ptr() as usize
+        });
+        let status = read_in_child(addr);
+        assert!(libc::WIFSIGNALED(status) && libc::WTERMSIG(status) == libc::SIGSEGV);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_locked_secret_is_wiped_on_fork() {
+        fn check<Alloc: SecureAlloc>(secret: Secret<[u8; 32], Alloc>, readable: bool) {
+            let mut secret_pinned = Some(Box::pin(secret));
+            let secret = secret_pinned.as_mut().unwrap();
+            secret
+                .as_mut()
+                .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+            if readable {
+                assert_eq!(first_byte_in_child(secret.as_ref()), 0);
+            }
+            // The child accesses, then drops, an empty secret rather than a corrupted one.
+            let status = status_in_child(|| {
+                let mut secret = secret_pinned.take().unwrap();
+                let empty = secret.as_ref().read_with(&|sec: &[u8]| sec == [0; 32]);
+                secret
+                    .as_mut()
+                    .update_with(&|sec: &mut [u8]| sec.fill(0x43));
+                drop(secret);
+                libc::c_int::from(!empty)
+            });
+            assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+            assert!(
+                secret_pinned
+                    .unwrap()
+                    .as_ref()
+                    .read_with(&|sec: &[u8]| sec == [0x42; 32])
+            );
+        }
+
+        check(Secret::new_locked().unwrap(), true);
+        check(Secret::new_secret_mem().unwrap(), true);
+        check(Secret::new_no_access().unwrap(), false);
+        check(Secret::new_masked().unwrap(), false);
+    }
+
+    /// Run in a child, not to change the process-wide policy of the other tests.
+    #[cfg(unix)]
+    #[test]
+    fn test_canary_corruption_is_detected() {
+        let status = status_in_child(|| {
+            crate::memory::set_on_corruption(crate::memory::OnCorruption::Panic);
+            let result = std::panic::catch_unwind(|| {
+                let secret: Secret<[u8; 32], Locked> = Secret::new_locked().unwrap();
+                let mut secret_pinned = pin!(secret);
+                secret_pinned
+                    .as_mut()
+                    .update_with(&|sec: &mut [u8]| unsafe { *sec.as_mut_ptr().add(32) ^= 1 });
+            });
+            libc::c_int::from(result.is_ok())
+        });
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+    }
+
+    #[test]
+    fn test_custom_allocator() {
+        use std::{
+            ptr::NonNull,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        /// Counts live allocations, on top of the global allocator.
+        #[derive(Clone)]
+        struct Counting<'a>(&'a AtomicUsize);
+
+        unsafe impl SecureAlloc for Counting<'_> {
+            type Error = crate::memory::AllocError;
+
+            fn allocate(&self, len: usize) -> Result<NonNull<u8>, Self::Error> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Global.allocate(len)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, len: usize) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+                unsafe { Global.deallocate(ptr, len) }
+            }
+        }
+
+        let live = AtomicUsize::new(0);
+        {
+            let secret = Secret::<[u8; 32], _>::new_in(Counting(&live)).unwrap();
+            let mut secret_pinned = pin!(secret);
+            secret_pinned
+                .as_mut()
+                .update_with(&|sec: &mut [u8]| sec.fill(3));
+            let cloned = secret_pinned.clone();
+            assert_eq!(live.load(Ordering::Relaxed), 2);
+            assert!(
+                pin!(cloned)
+                    .as_ref()
+                    .read_with(&|sec: &[u8]| sec == [3; 32])
+            );
+        }
+        assert_eq!(live.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_scrub_stack() {
+        crate::memory::scrub_stack(0);
+        crate::memory::scrub_stack(64 * 1024);
+    }
+
+    #[test]
+    fn test_secret_mem_secret() {
+        let secret: Secret<[u8; 32], SecretMem> = Secret::new_secret_mem().unwrap();
+        let mut secret_pinned = pin!(secret);
+        secret_pinned
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(&[7; 32]));
+        assert!(
+            secret_pinned
+                .as_ref()
+                .read_with(&|sec: &[u8]| sec == [7; 32])
+        );
+    }
+
+    #[test]
+    fn test_masked_secret() {
+        let secret: Secret<[u8; 32], Locked> = Secret::new_masked().unwrap();
+        let mut secret_pinned = pin!(secret);
+        secret_pinned
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.fill(0x42));
+        let addr = secret_pinned
+            .as_ref()
+            .read_with(&|sec: &[u8]| sec.as_ptr() as usize);
+        // Pages stay mapped, but hold no plaintext between accesses.
+        let idle = unsafe { std::slice::from_raw_parts(addr as *const u8, 32) };
+        assert_ne!(idle, [0x42; 32]);
+        let cloned = pin!(secret_pinned.clone());
+        assert!(cloned.as_ref().read_with(&|sec: &[u8]| sec == [0x42; 32]));
+    }
+}
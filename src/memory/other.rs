@@ -0,0 +1,57 @@
+use std::{io, ptr::NonNull};
+
+pub(super) fn page_size() -> usize {
+    4096
+}
+
+pub(super) fn map(_len: usize) -> io::Result<NonNull<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "dedicated pages are not supported on this platform",
+    ))
+}
+
+pub(super) fn lock(_ptr: NonNull<u8>, _len: usize) -> io::Result<()> {
+    unreachable!("no mapping can exist on this platform")
+}
+
+pub(super) unsafe fn unlock(_ptr: NonNull<u8>, _len: usize) {
+    unreachable!("no mapping can exist on this platform")
+}
+
+pub(super) fn mte_available() -> bool {
+    false
+}
+
+pub(super) fn tag(_ptr: NonNull<u8>, _len: usize) -> NonNull<u8> {
+    unreachable!("no mapping can exist on this platform")
+}
+
+pub(super) fn untag(ptr: NonNull<u8>) -> NonNull<u8> {
+    ptr
+}
+
+pub(super) fn exclude_from_dumps(_ptr: NonNull<u8>, _len: usize) -> io::Result<()> {
+    unreachable!("no mapping can exist on this platform")
+}
+
+pub(super) fn wipe_on_fork(_ptr: NonNull<u8>, _len: usize) -> io::Result<()> {
+    unreachable!("no mapping can exist on this platform")
+}
+
+pub(super) fn fork_generation() -> usize {
+    0
+}
+
+/// Only reachable with a custom [`SecureAlloc`](super::SecureAlloc), that pages may not be
+/// protected on this platform.
+pub(super) fn protect(_ptr: NonNull<u8>, _len: usize, _access: super::Access) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "page protection is not supported on this platform",
+    ))
+}
+
+pub(super) unsafe fn unmap(_ptr: NonNull<u8>, _len: usize) {
+    unreachable!("no mapping can exist on this platform")
+}
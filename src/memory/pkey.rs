@@ -0,0 +1,140 @@
+//! Protection key domain shared by all the secrets, on `x86_64` Linux.
+//!
+//! Pages in the domain are tagged with a dedicated protection key, whose access rights
+//! are held per thread in the `PKRU` register. Threads are denied access by default, and
+//! only granted it for the duration of an access, without any system call.
+
+use std::ptr::NonNull;
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod imp {
+    use std::{arch::asm, cell::Cell, io, ptr::NonNull, sync::OnceLock};
+
+    const PKEY_DISABLE_ACCESS: libc::c_ulong = 1;
+
+    /// The key of the domain, allocated once per process.
+    pub(super) fn key() -> Option<libc::c_int> {
+        static KEY: OnceLock<Option<libc::c_int>> = OnceLock::new();
+        *KEY.get_or_init(|| {
+            match unsafe { libc::syscall(libc::SYS_pkey_alloc, 0, PKEY_DISABLE_ACCESS) } {
+                -1 => None,
+                key => Some(key as libc::c_int),
+            }
+        })
+    }
+
+    pub(super) fn assign(ptr: NonNull<u8>, len: usize, key: libc::c_int) -> io::Result<()> {
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        match unsafe { libc::syscall(libc::SYS_pkey_mprotect, ptr.as_ptr(), len, prot, key) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    thread_local! {
+        /// Ongoing `(reads, writes)` of the calling thread, accesses may be nested.
+        static ACCESSES: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+    }
+
+    fn read_pkru() -> u32 {
+        let pkru: u32;
+        unsafe {
+            asm!("rdpkru", in("ecx") 0, out("eax") pkru, out("edx") _, options(nomem, nostack, preserves_flags))
+        };
+        pkru
+    }
+
+    fn write_pkru(pkru: u32) {
+        unsafe {
+            asm!("wrpkru", in("eax") pkru, in("ecx") 0, in("edx") 0, options(nostack, preserves_flags))
+        };
+    }
+
+    /// Updates the rights of the calling thread on the domain, for its `(reads, writes)`.
+    fn update(key: libc::c_int, (reads, writes): (usize, usize)) {
+        ACCESSES.with(|accesses| accesses.set((reads, writes)));
+        // Two bits per key: access disable, then write disable.
+        let rights = match (reads, writes) {
+            (_, 1..) => 0b00,
+            (1.., 0) => 0b10,
+            (0, 0) => 0b01,
+        };
+        let shift = 2 * key as u32;
+        write_pkru(read_pkru() & !(0b11 << shift) | rights << shift);
+    }
+
+    pub(super) fn enter(key: libc::c_int, write: bool) {
+        let (reads, writes) = ACCESSES.with(Cell::get);
+        match write {
+            true => update(key, (reads, writes + 1)),
+            false => update(key, (reads + 1, writes)),
+        }
+    }
+
+    pub(super) fn leave(key: libc::c_int, write: bool) {
+        let (reads, writes) = ACCESSES.with(Cell::get);
+        match write {
+            true => update(key, (reads, writes - 1)),
+            false => update(key, (reads - 1, writes)),
+        }
+    }
+}
+
+/// Returns whether the protection key domain can be used.
+pub(super) fn available() -> bool {
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    return imp::key().is_some();
+    #[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+    false
+}
+
+/// Moves the (read and write) pages into the domain, or back to the default key, when
+/// [`available`].
+///
+/// # Panics
+///
+/// Panics if the key of the pages cannot be changed.
+pub(super) fn assign(ptr: NonNull<u8>, len: usize, in_domain: bool) {
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    if let Some(key) = imp::key()
+        && let Err(err) = imp::assign(ptr, len, if in_domain { key } else { 0 })
+    {
+        panic!("failed to change secret pages protection key: {err}");
+    }
+    let _ = (ptr, len, in_domain);
+}
+
+/// Grants the calling thread access to the domain, until dropped.
+pub(super) struct Access {
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    key: Option<libc::c_int>,
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    write: bool,
+}
+
+impl Access {
+    pub(super) fn new(write: bool) -> Self {
+        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+        {
+            let key = imp::key();
+            if let Some(key) = key {
+                imp::enter(key, write);
+            }
+            Access { key, write }
+        }
+        #[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+        {
+            let _ = write;
+            Access {}
+        }
+    }
+}
+
+impl Drop for Access {
+    fn drop(&mut self) {
+        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+        if let Some(key) = self.key {
+            imp::leave(key, self.write);
+        }
+    }
+}
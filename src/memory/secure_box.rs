@@ -0,0 +1,418 @@
+#[cfg(not(windows))]
+use std::sync::OnceLock;
+use std::{
+    ptr::NonNull,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+use zeroize::Zeroize;
+
+use super::{Access, SecureAlloc, page_size, pkey};
+
+/// What to do when the canaries around a secret are found corrupted.
+///
+/// A corrupted canary means something wrote out of bounds right next to key material,
+/// so the process state cannot be trusted anymore.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnCorruption {
+    /// Abort the process immediately (default).
+    Abort,
+    /// Panic, letting the application unwind.
+    Panic,
+}
+
+static ON_CORRUPTION: AtomicBool = AtomicBool::new(false);
+
+/// Sets what happens when a canary corruption is detected, for the whole process.
+pub fn set_on_corruption(policy: OnCorruption) {
+    ON_CORRUPTION.store(policy == OnCorruption::Panic, Ordering::Relaxed);
+}
+
+/// Serializes the recovery of pages wiped in forked children.
+static FORK_RECOVERY: Mutex<()> = Mutex::new(());
+
+/// Length of the canaries placed before and after a value.
+const CANARY_LEN: usize = 16;
+
+/// Protections applied to dedicated pages between accesses, on top of those of their
+/// [`SecureAlloc`].
+///
+/// Each one adds a cost to every access.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Protection {
+    no_access: bool,
+    masked: bool,
+    pkey: bool,
+}
+
+impl Protection {
+    /// No additional protection.
+    pub const fn new() -> Self {
+        Protection {
+            no_access: false,
+            masked: false,
+            pkey: false,
+        }
+    }
+
+    /// Pages are not accessible (`PROT_NONE` on Unix, `PAGE_NOACCESS` on Windows), so
+    /// stray pointer reads fault. Costs one or two `mprotect` calls per access.
+    pub const fn no_access(self) -> Self {
+        Protection {
+            no_access: true,
+            ..self
+        }
+    }
+
+    /// Pages are kept encrypted (`CryptProtectMemory` on Windows) or XORed with a random
+    /// per-process mask (elsewhere), so scraping the memory of the process does not reveal
+    /// the plaintext. Costs a pass over the pages per access.
+    pub const fn masked(self) -> Self {
+        Protection {
+            masked: true,
+            ..self
+        }
+    }
+
+    /// Pages are placed in a protection key domain shared by all secrets (`pkey_mprotect` on
+    /// `x86_64` Linux), that threads can only access for the duration of their own accesses,
+    /// so stray pointer reads fault. Costs two `wrpkru` instructions per access, but no
+    /// system call. Ignored unless [`pkeys_available`](super::pkeys_available).
+    pub const fn pkey(self) -> Self {
+        Protection { pkey: true, ..self }
+    }
+
+    /// Whether something has to be undone for each access, for all threads.
+    fn is_concealed(&self) -> bool {
+        self.no_access || self.masked
+    }
+}
+
+/// XORs `len` bytes at `ptr` with a random mask, drawn once per process.
+#[cfg(not(windows))]
+fn xor_mask(ptr: NonNull<u8>, len: usize) {
+    static MASK: OnceLock<Box<[u8]>> = OnceLock::new();
+    let mask = MASK.get_or_init(|| {
+        let mut mask = vec![0; page_size()].into_boxed_slice();
+        getrandom::getrandom(&mut mask).expect("failed to get randomness for the mask");
+        mask
+    });
+    let pages = unsafe { std::slice::from_raw_parts_mut(ptr.as_ptr(), len) };
+    for chunk in pages.chunks_mut(mask.len()) {
+        chunk
+            .iter_mut()
+            .zip(mask.iter())
+            .for_each(|(byte, mask)| *byte ^= mask);
+    }
+}
+
+/// Single value living in dedicated pages, obtained from a [`SecureAlloc`].
+///
+/// # Security
+///
+/// Outside of [`SecureBox::read`] and [`SecureBox::write`], the pages are concealed
+/// according to a [`Protection`].
+///
+/// The value is surrounded by random canaries, checked on each access and on drop
+/// (see [`OnCorruption`]).
+///
+/// On drop, the value is zeroized, then the whole pages are wiped before
+/// being given back to the allocator.
+///
+/// In forked children, pages wiped by their allocator (see [`Locked`](super::Locked)) are
+/// given a fresh value and canaries on first access, so the child sees an empty value.
+pub(crate) struct SecureBox<T: Zeroize, Alloc: SecureAlloc> {
+    /// Start of the pages
+    base: NonNull<u8>,
+    len: usize,
+    /// The value, right after the leading canary
+    ptr: NonNull<T>,
+    canary: [u8; CANARY_LEN],
+    alloc: Alloc,
+    protection: Protection,
+    /// Number of ongoing reads, when pages are concealed while idle.
+    readers: Option<Mutex<usize>>,
+    /// Makes the value given to pages wiped in a forked child.
+    init: fn() -> T,
+    /// Fork generation of the process the pages were last checked in.
+    generation: AtomicUsize,
+}
+
+// `SecureBox` uniquely owns its value, exactly like a `Box` would.
+unsafe impl<T: Zeroize + Send, Alloc: SecureAlloc + Send> Send for SecureBox<T, Alloc> {}
+unsafe impl<T: Zeroize + Sync, Alloc: SecureAlloc + Sync> Sync for SecureBox<T, Alloc> {}
+
+impl<T: Zeroize, Alloc: SecureAlloc> SecureBox<T, Alloc> {
+    /// Allocates pages from `alloc` and moves the value made by `init` into them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no randomness can be obtained for the canaries, or if `protection`
+    /// cannot be applied.
+    pub(crate) fn new_in(
+        init: fn() -> T,
+        alloc: Alloc,
+        protection: Protection,
+    ) -> Result<Self, Alloc::Error> {
+        Self::new_with(init(), init, alloc, protection)
+    }
+
+    /// Same as [`SecureBox::new_in`], moving `value` into the pages instead.
+    fn new_with(
+        value: T,
+        init: fn() -> T,
+        alloc: Alloc,
+        protection: Protection,
+    ) -> Result<Self, Alloc::Error> {
+        assert!(
+            align_of::<T>() <= page_size(),
+            "secret alignment exceeds the page size"
+        );
+        let mut canary = [0; CANARY_LEN];
+        getrandom::getrandom(&mut canary).expect("failed to get randomness for canaries");
+        let offset = CANARY_LEN.next_multiple_of(align_of::<T>());
+        let len = (offset + size_of::<T>() + CANARY_LEN).next_multiple_of(page_size());
+        let base = alloc.allocate(len)?;
+        // Pages are page-aligned, and the offset is a multiple of the alignment of `T`.
+        let ptr = unsafe { base.add(offset) }.cast::<T>();
+        let secure_box = SecureBox {
+            base,
+            len,
+            ptr,
+            canary,
+            alloc,
+            protection,
+            readers: protection.is_concealed().then(|| Mutex::new(0)),
+            init,
+            generation: AtomicUsize::new(super::sys::fork_generation()),
+        };
+        if protection.pkey {
+            pkey::assign(base, len, true);
+        }
+        let key = secure_box.key_access(true);
+        unsafe {
+            ptr.write(value);
+            secure_box.leading_canary().copy_from_slice(&canary);
+            secure_box.trailing_canary().copy_from_slice(&canary);
+        }
+        secure_box.conceal();
+        drop(key);
+        Ok(secure_box)
+    }
+
+    /// # Safety
+    ///
+    /// Pages must be accessible, and no other reference to the canary may exist.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn leading_canary(&self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.ptr.cast::<u8>().sub(CANARY_LEN).as_ptr(),
+                CANARY_LEN,
+            )
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Pages must be accessible, and no other reference to the canary may exist.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn trailing_canary(&self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.add(1).cast::<u8>().as_ptr(), CANARY_LEN) }
+    }
+
+    /// Checks both canaries, pages must be accessible.
+    fn check_canaries(&self) {
+        let intact = unsafe {
+            *self.leading_canary() == self.canary && *self.trailing_canary() == self.canary
+        };
+        if intact {
+            return;
+        }
+        if ON_CORRUPTION.load(Ordering::Relaxed) {
+            // Do not turn a panic into an abort by panicking again, the
+            // corruption has been reported by the ongoing one anyway.
+            if !std::thread::panicking() {
+                panic!("memory corruption detected around a secret");
+            }
+        } else {
+            eprintln!("memory corruption detected around a secret, aborting");
+            std::process::abort();
+        }
+    }
+
+    /// Gives a fresh value and canaries to pages wiped in a forked child, which hold zeroes
+    /// instead, so that they are not mistaken for corrupted ones.
+    fn recover_from_fork(&self) {
+        let generation = super::sys::fork_generation();
+        if self.generation.load(Ordering::Acquire) == generation {
+            return;
+        }
+        let _lock = FORK_RECOVERY.lock().unwrap_or_else(|err| err.into_inner());
+        if self.generation.load(Ordering::Relaxed) == generation {
+            return;
+        }
+        let _key = self.key_access(true);
+        if self.protection.no_access {
+            self.protect(Access::ReadWrite);
+        }
+        let pages = unsafe { std::slice::from_raw_parts(self.base.as_ptr(), self.len) };
+        // Canaries are random, whereas the pages of allocators not wiping them are left as is.
+        if pages.iter().all(|&byte| byte == 0) {
+            unsafe {
+                self.ptr.write((self.init)());
+                self.leading_canary().copy_from_slice(&self.canary);
+                self.trailing_canary().copy_from_slice(&self.canary);
+            }
+            if self.protection.masked {
+                self.mask();
+            }
+        }
+        if self.protection.no_access {
+            self.protect(Access::None);
+        }
+        self.generation.store(generation, Ordering::Release);
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the access rights of the pages cannot be changed, since the value
+    /// would either be unreachable or left exposed.
+    fn protect(&self, access: Access) {
+        if let Err(err) = super::sys::protect(self.base, self.len, access) {
+            panic!("failed to change secret pages protection: {err}");
+        }
+    }
+
+    /// Grants the calling thread access to the pages, when in the protection key domain.
+    fn key_access(&self, write: bool) -> Option<pkey::Access> {
+        self.protection.pkey.then(|| pkey::Access::new(write))
+    }
+
+    /// Makes the pages readable (or writable), undoing [`SecureBox::conceal`].
+    fn reveal(&self, access: Access) {
+        if self.protection.no_access {
+            // Unmasking writes to the pages.
+            self.protect(match self.protection.masked {
+                true => Access::ReadWrite,
+                false => access,
+            });
+        }
+        if self.protection.masked {
+            self.unmask();
+        }
+    }
+
+    /// Applies the [`Protection`] of the pages, which must be accessible.
+    fn conceal(&self) {
+        if self.protection.masked {
+            self.mask();
+        }
+        if self.protection.no_access {
+            self.protect(Access::None);
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn mask(&self) {
+        xor_mask(self.base, self.len);
+    }
+
+    #[cfg(not(windows))]
+    fn unmask(&self) {
+        xor_mask(self.base, self.len);
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the pages cannot be encrypted, rather than leaving them in clear.
+    #[cfg(windows)]
+    fn mask(&self) {
+        if let Err(err) = super::sys::mask(self.base, self.len) {
+            panic!("failed to mask secret pages: {err}");
+        }
+    }
+
+    #[cfg(windows)]
+    fn unmask(&self) {
+        if let Err(err) = super::sys::unmask(self.base, self.len) {
+            panic!("failed to unmask secret pages: {err}");
+        }
+    }
+
+    pub(crate) fn read<A>(&self, f: impl FnOnce(&T) -> A) -> A {
+        self.recover_from_fork();
+        // Unmasking writes to the pages.
+        let _key = self.key_access(self.protection.masked);
+        /// Conceals pages again after the last ongoing read, even on panic.
+        struct Guard<'a, T: Zeroize, Alloc: SecureAlloc>(&'a SecureBox<T, Alloc>, &'a Mutex<usize>);
+        impl<T: Zeroize, Alloc: SecureAlloc> Drop for Guard<'_, T, Alloc> {
+            fn drop(&mut self) {
+                let mut readers = self.1.lock().unwrap_or_else(|err| err.into_inner());
+                *readers -= 1;
+                if *readers == 0 {
+                    self.0.conceal();
+                }
+            }
+        }
+
+        let _guard = self.readers.as_ref().map(|readers| {
+            let mut count = readers.lock().unwrap_or_else(|err| err.into_inner());
+            if *count == 0 {
+                self.reveal(Access::Read);
+            }
+            *count += 1;
+            Guard(self, readers)
+        });
+        self.check_canaries();
+        f(unsafe { self.ptr.as_ref() })
+    }
+
+    pub(crate) fn write<A>(&mut self, f: impl FnOnce(&mut T) -> A) -> A {
+        /// Checks canaries and conceals pages again, even on panic.
+        struct Guard<'a, T: Zeroize, Alloc: SecureAlloc>(&'a SecureBox<T, Alloc>);
+        impl<T: Zeroize, Alloc: SecureAlloc> Drop for Guard<'_, T, Alloc> {
+            fn drop(&mut self) {
+                self.0.check_canaries();
+                self.0.conceal();
+            }
+        }
+
+        self.recover_from_fork();
+        let _key = self.key_access(true);
+        let mut ptr = self.ptr;
+        self.reveal(Access::ReadWrite);
+        self.check_canaries();
+        let _guard = Guard(self);
+        f(unsafe { ptr.as_mut() })
+    }
+}
+
+impl<T: Zeroize + Clone, Alloc: SecureAlloc + Clone> SecureBox<T, Alloc> {
+    pub(crate) fn try_clone(&self) -> Result<Self, Alloc::Error> {
+        let value = self.read(T::clone);
+        SecureBox::new_with(value, self.init, self.alloc.clone(), self.protection)
+    }
+}
+
+impl<T: Zeroize, Alloc: SecureAlloc> Drop for SecureBox<T, Alloc> {
+    fn drop(&mut self) {
+        self.recover_from_fork();
+        let _key = self.key_access(true);
+        self.reveal(Access::ReadWrite);
+        if self.protection.pkey {
+            // The allocator may reuse the pages for anything else.
+            pkey::assign(self.base, self.len, false);
+        }
+        self.check_canaries();
+        unsafe {
+            self.ptr.as_mut().zeroize();
+            self.ptr.drop_in_place();
+            std::slice::from_raw_parts_mut(self.base.as_ptr(), self.len).zeroize();
+            self.alloc.deallocate(self.base, self.len);
+        }
+    }
+}
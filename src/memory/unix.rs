@@ -0,0 +1,372 @@
+use std::{io, ptr::NonNull, sync::OnceLock};
+
+/// Flags excluding mappings from core dumps from their creation on, where supported
+/// (`MAP_CONCEAL` on OpenBSD, `MAP_NOCORE` on FreeBSD and DragonFly).
+#[cfg(target_os = "openbsd")]
+const MAP_NO_DUMP: libc::c_int = libc::MAP_CONCEAL;
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+const MAP_NO_DUMP: libc::c_int = libc::MAP_NOCORE;
+#[cfg(not(any(target_os = "openbsd", target_os = "freebsd", target_os = "dragonfly")))]
+const MAP_NO_DUMP: libc::c_int = 0;
+
+/// Fallback wiping secrets in forked children when `MADV_WIPEONFORK` cannot be used.
+///
+/// Registered mappings are replaced with fresh anonymous pages in the child, which is
+/// also correct for shared mappings (writing zeroes would wipe the parent's ones).
+mod fork {
+    use std::{
+        cell::UnsafeCell,
+        sync::{
+            Once,
+            atomic::{AtomicBool, Ordering},
+        },
+    };
+
+    /// Registered `(address, length)` mappings.
+    ///
+    /// A spin lock is used because it must be taken in the `prepare` handler
+    /// and released in the `parent` and `child` ones.
+    struct Registry {
+        locked: AtomicBool,
+        mappings: UnsafeCell<Vec<(usize, usize)>>,
+    }
+
+    unsafe impl Sync for Registry {}
+
+    static REGISTRY: Registry = Registry {
+        locked: AtomicBool::new(false),
+        mappings: UnsafeCell::new(Vec::new()),
+    };
+
+    fn lock() {
+        while REGISTRY
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+    }
+
+    fn unlock() {
+        REGISTRY.locked.store(false, Ordering::Release);
+    }
+
+    fn with_mappings(f: impl FnOnce(&mut Vec<(usize, usize)>)) {
+        lock();
+        f(unsafe { &mut *REGISTRY.mappings.get() });
+        unlock();
+    }
+
+    extern "C" fn prepare() {
+        lock();
+    }
+
+    extern "C" fn parent() {
+        unlock();
+    }
+
+    extern "C" fn child() {
+        for &(addr, len) in unsafe { &*REGISTRY.mappings.get() } {
+            unsafe {
+                libc::mmap(
+                    addr as *mut libc::c_void,
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED | super::MAP_NO_DUMP,
+                    -1,
+                    0,
+                )
+            };
+        }
+        unlock();
+    }
+
+    pub(super) fn register(addr: usize, len: usize) {
+        static HANDLERS: Once = Once::new();
+        HANDLERS.call_once(|| {
+            let ret = unsafe { libc::pthread_atfork(Some(prepare), Some(parent), Some(child)) };
+            assert_eq!(ret, 0, "failed to register fork handlers");
+        });
+        with_mappings(|mappings| mappings.push((addr, len)));
+    }
+
+    pub(super) fn unregister(addr: usize) {
+        with_mappings(|mappings| mappings.retain(|&(start, _)| start != addr));
+    }
+}
+
+/// Number of `fork`s separating the current process from the one of the first call.
+///
+/// Lets secrets tell that their pages were wiped by [`wipe_on_fork`], rather than corrupted.
+pub(super) fn fork_generation() -> usize {
+    use std::sync::{
+        Once,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    static GENERATION: AtomicUsize = AtomicUsize::new(0);
+    static HANDLER: Once = Once::new();
+
+    extern "C" fn child() {
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    HANDLER.call_once(|| {
+        let ret = unsafe { libc::pthread_atfork(None, None, Some(child)) };
+        assert_eq!(ret, 0, "failed to register fork handlers");
+    });
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Memory Tagging Extension, on `aarch64` Linux.
+///
+/// Dedicated pages are mapped with `PROT_MTE` and tagged with a random (non-zero) tag, so
+/// only pointers derived from the one returned by the allocator can access them.
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+mod mte {
+    use std::{arch::asm, cell::Cell, ptr::NonNull, sync::OnceLock};
+
+    // Not all exposed by `libc` yet.
+    const HWCAP2_MTE: libc::c_ulong = 1 << 18;
+    pub(super) const PROT_MTE: libc::c_int = 0x20;
+    const PR_SET_TAGGED_ADDR_CTRL: libc::c_int = 55;
+    const PR_TAGGED_ADDR_ENABLE: libc::c_ulong = 1;
+    const PR_MTE_TCF_SYNC: libc::c_ulong = 1 << 1;
+    const PR_MTE_TAG_SHIFT: libc::c_ulong = 3;
+    /// Granule sharing a same tag.
+    const GRANULE: usize = 16;
+    const TAG_SHIFT: u32 = 56;
+
+    pub(super) fn available() -> bool {
+        static AVAILABLE: OnceLock<bool> = OnceLock::new();
+        *AVAILABLE.get_or_init(|| unsafe { libc::getauxval(libc::AT_HWCAP2) } & HWCAP2_MTE != 0)
+    }
+
+    /// Enables synchronous tag checks and random non-zero tags for the calling thread
+    /// (threads it spawns afterwards inherit them).
+    fn enable_checks() -> bool {
+        thread_local! {
+            static ENABLED: Cell<Option<bool>> = const { Cell::new(None) };
+        }
+        ENABLED.with(|enabled| match enabled.get() {
+            Some(ret) => ret,
+            None => {
+                let ctrl = PR_TAGGED_ADDR_ENABLE | PR_MTE_TCF_SYNC | (0xfffe << PR_MTE_TAG_SHIFT);
+                let ret = unsafe { libc::prctl(PR_SET_TAGGED_ADDR_CTRL, ctrl, 0, 0, 0) } == 0;
+                enabled.set(Some(ret));
+                ret
+            }
+        })
+    }
+
+    /// Tags the `len` bytes at `ptr`, mapped with [`PROT_MTE`], returning the tagged pointer.
+    pub(super) fn tag(ptr: NonNull<u8>, len: usize) -> NonNull<u8> {
+        if !enable_checks() {
+            return ptr;
+        }
+        let tagged_addr: usize;
+        unsafe {
+            asm!(
+                ".arch_extension memtag",
+                "irg {tagged}, {addr}",
+                addr = in(reg) ptr.addr().get(),
+                tagged = lateout(reg) tagged_addr,
+                options(nomem, nostack, preserves_flags),
+            );
+            let tagged = ptr.as_ptr().with_addr(tagged_addr);
+            for offset in (0..len).step_by(GRANULE) {
+                asm!(
+                    ".arch_extension memtag",
+                    "stg {granule}, [{granule}]",
+                    granule = in(reg) tagged.add(offset),
+                    options(nostack, preserves_flags),
+                );
+            }
+            NonNull::new_unchecked(tagged)
+        }
+    }
+
+    pub(super) fn is_tagged(ptr: NonNull<u8>) -> bool {
+        ptr.addr().get() >> TAG_SHIFT != 0
+    }
+
+    pub(super) fn untag(ptr: NonNull<u8>) -> NonNull<u8> {
+        ptr.map_addr(|addr| std::num::NonZeroUsize::new(addr.get() & !(0xff << TAG_SHIFT)).unwrap())
+    }
+}
+
+/// Returns whether the Memory Tagging Extension is used for [`map`]ped pages.
+pub(super) fn mte_available() -> bool {
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    return mte::available();
+    #[cfg(not(all(target_os = "linux", target_arch = "aarch64")))]
+    false
+}
+
+/// Tags pages from [`map`] when [`mte_available`], returning the only pointer able to
+/// access them. The pages must not be used through `ptr` anymore.
+pub(super) fn tag(ptr: NonNull<u8>, len: usize) -> NonNull<u8> {
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    if mte::available() {
+        return mte::tag(ptr, len);
+    }
+    let _ = len;
+    ptr
+}
+
+/// Gets back the pointer given to [`tag`], for system calls.
+pub(super) fn untag(ptr: NonNull<u8>) -> NonNull<u8> {
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    return mte::untag(ptr);
+    #[cfg(not(all(target_os = "linux", target_arch = "aarch64")))]
+    ptr
+}
+
+pub(super) fn page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize)
+}
+
+pub(super) fn map(len: usize) -> io::Result<NonNull<u8>> {
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    let prot = match mte::available() {
+        true => prot | mte::PROT_MTE,
+        false => prot,
+    };
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            prot,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | MAP_NO_DUMP,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { NonNull::new_unchecked(ptr.cast()) })
+}
+
+/// On failure because of `RLIMIT_MEMLOCK`, the error tells how much memory remains lockable.
+pub(super) fn lock(ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+    if unsafe { libc::mlock(ptr.as_ptr().cast(), len) } == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    if !matches!(
+        err.raw_os_error(),
+        Some(libc::ENOMEM | libc::EAGAIN | libc::EPERM)
+    ) {
+        return Err(err);
+    }
+    let limit = match crate::hardening::memlock_limit() {
+        Ok(Some(limit)) => format!("{limit} bytes"),
+        Ok(None) => "unlimited".into(),
+        Err(_) => return Err(err),
+    };
+    let remaining = match crate::hardening::remaining_memlock() {
+        Ok(Some(remaining)) => format!("{remaining} bytes remaining"),
+        _ => "remaining unknown".into(),
+    };
+    Err(io::Error::new(
+        err.kind(),
+        format!("{err}: cannot lock {len} bytes, RLIMIT_MEMLOCK is {limit}, {remaining}"),
+    ))
+}
+
+/// # Safety
+///
+/// `ptr` and `len` must have been successfully locked with [`lock`].
+pub(super) unsafe fn unlock(ptr: NonNull<u8>, len: usize) {
+    unsafe { libc::munlock(ptr.as_ptr().cast(), len) };
+}
+
+/// On the BSDs, pages from [`map`] are already excluded through [`MAP_NO_DUMP`].
+pub(super) fn exclude_from_dumps(ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if unsafe { libc::madvise(ptr.as_ptr().cast(), len, libc::MADV_DONTDUMP) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let _ = (ptr, len);
+    Ok(())
+}
+
+pub(super) fn protect(ptr: NonNull<u8>, len: usize, access: super::Access) -> io::Result<()> {
+    let prot = match access {
+        super::Access::None => libc::PROT_NONE,
+        super::Access::Read => libc::PROT_READ,
+        super::Access::ReadWrite => libc::PROT_READ | libc::PROT_WRITE,
+    };
+    // Tags must be kept, and system calls take the untagged pointer.
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    let (ptr, prot) = match mte::is_tagged(ptr) {
+        true => (mte::untag(ptr), prot | mte::PROT_MTE),
+        false => (ptr, prot),
+    };
+    if unsafe { libc::mprotect(ptr.as_ptr().cast(), len, prot) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub(super) fn wipe_on_fork(ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+    // Fails on kernels older than 4.14, and on shared mappings (as `memfd_secret` ones).
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if unsafe { libc::madvise(ptr.as_ptr().cast(), len, libc::MADV_WIPEONFORK) } == 0 {
+        return Ok(());
+    }
+    fork::register(ptr.as_ptr() as usize, len);
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn memfd_secret() -> io::Result<libc::c_int> {
+    match unsafe { libc::syscall(libc::SYS_memfd_secret, libc::O_CLOEXEC) } {
+        -1 => Err(io::Error::last_os_error()),
+        fd => Ok(fd as libc::c_int),
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn probe_secret_mem() -> bool {
+    memfd_secret().is_ok_and(|fd| unsafe { libc::close(fd) } == 0)
+}
+
+/// Secret memory pages are implicitly locked, there is no need to `mlock` them.
+#[cfg(target_os = "linux")]
+pub(super) fn map_secret_mem(len: usize) -> io::Result<NonNull<u8>> {
+    let fd = memfd_secret()?;
+    let ptr = unsafe {
+        if libc::ftruncate(fd, len as libc::off_t) != 0 {
+            libc::MAP_FAILED
+        } else {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        }
+    };
+    // The mapping keeps the memory alive, the descriptor is not needed anymore.
+    let err = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+    if ptr == libc::MAP_FAILED {
+        return Err(err);
+    }
+    Ok(unsafe { NonNull::new_unchecked(ptr.cast()) })
+}
+
+/// # Safety
+///
+/// `ptr` and `len` must come from a successful call to [`map`] or `map_secret_mem`.
+pub(super) unsafe fn unmap(ptr: NonNull<u8>, len: usize) {
+    fork::unregister(ptr.as_ptr() as usize);
+    unsafe { libc::munmap(ptr.as_ptr().cast(), len) };
+}
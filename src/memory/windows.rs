@@ -0,0 +1,143 @@
+use std::{io, ptr::NonNull, sync::OnceLock};
+
+use windows_sys::Win32::Security::Cryptography::{
+    CRYPTPROTECTMEMORY_SAME_PROCESS, CryptProtectMemory, CryptUnprotectMemory,
+};
+use windows_sys::Win32::System::{
+    ErrorReporting::{WerRegisterExcludedMemoryBlock, WerUnregisterExcludedMemoryBlock},
+    Memory::{
+        MEM_COMMIT, MEM_RELEASE, MEM_RESERVE, PAGE_NOACCESS, PAGE_READONLY, PAGE_READWRITE,
+        VirtualAlloc, VirtualFree, VirtualLock, VirtualProtect, VirtualUnlock,
+    },
+    SystemInformation::{GetSystemInfo, SYSTEM_INFO},
+    Threading::{GetCurrentProcess, GetProcessWorkingSetSize, SetProcessWorkingSetSize},
+};
+
+pub(super) fn page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        let mut info: SYSTEM_INFO = unsafe { std::mem::zeroed() };
+        unsafe { GetSystemInfo(&mut info) };
+        info.dwPageSize as usize
+    })
+}
+
+/// `VirtualLock` is bounded by the minimum working set size of the process,
+/// which is small by default. Grow it by `len` bytes so the lock can be retried.
+fn grow_working_set(len: usize) -> io::Result<()> {
+    let (mut min, mut max) = (0, 0);
+    unsafe {
+        let process = GetCurrentProcess();
+        if GetProcessWorkingSetSize(process, &mut min, &mut max) == 0
+            || SetProcessWorkingSetSize(process, min + len, max.max(min + len)) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Windows Error Reporting accepts a limited number of excluded blocks per process,
+/// so the exclusion is best effort: a secret never fails to allocate because of it.
+pub(super) fn exclude_from_dumps(ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+    if let Ok(len) = u32::try_from(len) {
+        unsafe { WerRegisterExcludedMemoryBlock(ptr.as_ptr().cast(), len) };
+    }
+    Ok(())
+}
+
+pub(super) fn protect(ptr: NonNull<u8>, len: usize, access: super::Access) -> io::Result<()> {
+    let prot = match access {
+        super::Access::None => PAGE_NOACCESS,
+        super::Access::Read => PAGE_READONLY,
+        super::Access::ReadWrite => PAGE_READWRITE,
+    };
+    let mut old = 0;
+    if unsafe { VirtualProtect(ptr.as_ptr().cast(), len, prot, &mut old) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Encrypts the pages in place, with a key only known to the current process.
+///
+/// `len` is a multiple of the page size, hence of `CRYPTPROTECTMEMORY_BLOCK_SIZE`.
+pub(super) fn mask(ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    if unsafe { CryptProtectMemory(ptr.as_ptr().cast(), len, CRYPTPROTECTMEMORY_SAME_PROCESS) } == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Decrypts pages encrypted by [`mask`].
+pub(super) fn unmask(ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    if unsafe { CryptUnprotectMemory(ptr.as_ptr().cast(), len, CRYPTPROTECTMEMORY_SAME_PROCESS) }
+        == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// There is no memory tagging on Windows.
+pub(super) fn mte_available() -> bool {
+    false
+}
+
+pub(super) fn tag(ptr: NonNull<u8>, _len: usize) -> NonNull<u8> {
+    ptr
+}
+
+pub(super) fn untag(ptr: NonNull<u8>) -> NonNull<u8> {
+    ptr
+}
+
+/// There is no `fork` on Windows.
+pub(super) fn wipe_on_fork(_ptr: NonNull<u8>, _len: usize) -> io::Result<()> {
+    Ok(())
+}
+
+/// There is no `fork` on Windows.
+pub(super) fn fork_generation() -> usize {
+    0
+}
+
+pub(super) fn map(len: usize) -> io::Result<NonNull<u8>> {
+    let ptr = unsafe {
+        VirtualAlloc(
+            std::ptr::null(),
+            len,
+            MEM_COMMIT | MEM_RESERVE,
+            PAGE_READWRITE,
+        )
+    };
+    NonNull::new(ptr.cast::<u8>()).ok_or_else(io::Error::last_os_error)
+}
+
+pub(super) fn lock(ptr: NonNull<u8>, len: usize) -> io::Result<()> {
+    let lock = || unsafe { VirtualLock(ptr.as_ptr().cast(), len) } != 0;
+    if !lock() && (grow_working_set(len).is_err() || !lock()) {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// # Safety
+///
+/// `ptr` and `len` must have been successfully locked with [`lock`].
+pub(super) unsafe fn unlock(ptr: NonNull<u8>, len: usize) {
+    unsafe { VirtualUnlock(ptr.as_ptr().cast(), len) };
+}
+
+/// # Safety
+///
+/// `ptr` and `len` must come from a successful call to [`map`].
+pub(super) unsafe fn unmap(ptr: NonNull<u8>, _len: usize) {
+    unsafe {
+        WerUnregisterExcludedMemoryBlock(ptr.as_ptr().cast());
+        VirtualFree(ptr.as_ptr().cast(), 0, MEM_RELEASE);
+    }
+}
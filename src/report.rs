@@ -0,0 +1,77 @@
+//! Report of the protections available to secrets, and of the process-wide mitigations in
+//! place, for operators to assert a required hardening baseline at startup.
+
+use crate::{
+    hardening::{self, Mitigations},
+    memory::{self, Locked, SecureAlloc},
+};
+
+/// Protections available to secrets on the current platform and process, see
+/// [`protection_report`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ProtectionReport {
+    /// [`Locked`] pages can be allocated (`mlock` on Unix, `VirtualLock` on Windows), within
+    /// the current limits of the process.
+    pub memory_locking: bool,
+    /// Dedicated pages are excluded from core dumps (`MADV_DONTDUMP` on Linux, `MAP_NOCORE` on
+    /// FreeBSD, `MAP_CONCEAL` on OpenBSD, Windows Error Reporting dumps on Windows).
+    pub dump_exclusion: bool,
+    /// Dedicated pages are wiped in forked children (always on Unix).
+    pub fork_wiping: bool,
+    /// Dedicated pages can be made inaccessible between accesses
+    /// (see [`Protection::no_access`](memory::Protection::no_access)).
+    pub page_protection: bool,
+    /// `memfd_secret` is available (see [`memory::secret_mem_available`]).
+    pub secret_mem: bool,
+    /// [`Locked`] pages are tagged (see [`memory::mte_available`]).
+    pub memory_tagging: bool,
+    /// Protection keys are available (see [`memory::pkeys_available`]).
+    pub protection_keys: bool,
+    /// Process-wide mitigations currently active.
+    pub mitigations: Mitigations,
+}
+
+/// Reports which protections are available to secrets, and which process-wide mitigations
+/// are active.
+///
+/// Probing memory locking allocates (and releases) a locked page.
+pub fn protection_report() -> ProtectionReport {
+    let page_size = memory::page_size();
+    let memory_locking = match Locked.allocate(page_size) {
+        Ok(ptr) => {
+            unsafe { Locked.deallocate(ptr, page_size) };
+            true
+        }
+        Err(_) => false,
+    };
+    ProtectionReport {
+        memory_locking,
+        dump_exclusion: cfg!(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "openbsd",
+            windows
+        )),
+        fork_wiping: cfg!(unix),
+        page_protection: cfg!(any(unix, windows)),
+        secret_mem: memory::secret_mem_available(),
+        memory_tagging: memory::mte_available(),
+        protection_keys: memory::pkeys_available(),
+        mitigations: hardening::mitigations(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_protection_report() {
+        let report = crate::protection_report();
+        assert!(report.memory_locking && report.dump_exclusion && report.fork_wiping);
+        assert_eq!(report.secret_mem, crate::memory::secret_mem_available());
+    }
+}
@@ -0,0 +1,185 @@
+//! Helpers for regression tests asserting that secrets are actually wiped, for instance
+//! that a [`SecretReader`](crate::api::SecretReader) does not leave copies behind.
+//!
+//! Memory is read through `/proc/self/mem`, so reading regions that have been unmapped
+//! since never faults, hence this module is only available on Linux.
+
+use std::{fs::File, io, os::unix::fs::FileExt, pin::Pin};
+
+use zeroize::Zeroize;
+
+use crate::{
+    api::{Secret, Unsizeable},
+    memory::SecureAlloc,
+};
+
+/// Size of the chunks memory is read by.
+const CHUNK_LEN: usize = 1 << 20;
+
+/// Location of the data of a [`Secret`], to be checked once the secret has been dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Footprint {
+    addr: usize,
+    len: usize,
+}
+
+impl Footprint {
+    /// Records where the data of `secret` lives.
+    pub fn of<Data, Alloc>(secret: Pin<&Secret<Data, Alloc>>) -> Self
+    where
+        Data: Zeroize + Unsizeable,
+        Alloc: SecureAlloc,
+    {
+        secret.read_with(&|sec: &Data::Unsized| Footprint {
+            addr: (sec as *const Data::Unsized).cast::<u8>() as usize,
+            len: size_of_val(sec),
+        })
+    }
+
+    /// Returns the address and the length of the recorded data.
+    pub fn region(&self) -> (usize, usize) {
+        (self.addr, self.len)
+    }
+
+    /// Panics if the recorded region still contains `pattern`.
+    ///
+    /// A region that cannot be read anymore (dedicated pages given back to the system)
+    /// is considered wiped.
+    pub fn assert_wiped(&self, pattern: &[u8]) {
+        let mut buf = vec![0; self.len];
+        if read_memory(&open_memory(), self.addr, &mut buf).is_err() {
+            return;
+        }
+        if let Some(offset) = find(&buf, pattern) {
+            panic!(
+                "secret pattern found at {:#x}, {offset} bytes into the dropped secret",
+                self.addr + offset
+            );
+        }
+    }
+}
+
+/// Returns the addresses of all the copies of `pattern` in the writable anonymous memory of
+/// the process (heap, allocator arenas, thread stacks and dedicated pages).
+///
+/// Pages that are not readable (see [`Protection`](crate::memory::Protection)) are skipped.
+/// The `pattern` argument itself is ignored, but any other copy of it is reported,
+/// including in variables of the caller: keep a single copy of the expected pattern.
+///
+/// # Errors
+///
+/// Fails if `/proc/self/maps` or `/proc/self/mem` cannot be read, for instance when the
+/// process is not dumpable (see [`disable_dumps`](crate::hardening::disable_dumps)).
+pub fn find_in_memory(pattern: &[u8]) -> io::Result<Vec<usize>> {
+    assert!(!pattern.is_empty(), "empty pattern");
+    let maps = std::fs::read_to_string("/proc/self/maps")?;
+    let mem = open_memory();
+    let mut buf = vec![0; CHUNK_LEN + pattern.len() - 1];
+    let ignored = [address_range(pattern), address_range(&buf)];
+    let mut found = Vec::new();
+    for (start, end) in maps.lines().filter_map(anonymous_writable_region) {
+        // Chunks overlap, so that no copy is missed across their boundaries.
+        let mut addr = start;
+        while addr + pattern.len() <= end {
+            let len = buf.len().min(end - addr);
+            if read_memory(&mem, addr, &mut buf[..len]).is_err() {
+                break;
+            }
+            let mut offset = 0;
+            while let Some(found_at) = find(&buf[offset..len], pattern) {
+                let copy = addr + offset + found_at;
+                if !ignored.iter().any(|range| range.contains(&copy)) {
+                    found.push(copy);
+                }
+                offset += found_at + 1;
+            }
+            addr += CHUNK_LEN;
+        }
+    }
+    buf.zeroize();
+    Ok(found)
+}
+
+/// Panics if [`find_in_memory`] finds any copy of `pattern`.
+pub fn assert_not_in_memory(pattern: &[u8]) {
+    let found = find_in_memory(pattern).expect("failed to scan the process memory");
+    assert!(
+        found.is_empty(),
+        "secret pattern found in memory at {found:#x?}"
+    );
+}
+
+fn open_memory() -> File {
+    File::open("/proc/self/mem").expect("failed to open /proc/self/mem")
+}
+
+fn read_memory(mem: &File, addr: usize, buf: &mut [u8]) -> io::Result<()> {
+    mem.read_exact_at(buf, addr as u64)
+}
+
+fn address_range(slice: &[u8]) -> std::ops::Range<usize> {
+    let start = slice.as_ptr() as usize;
+    start..start + slice.len()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parses a line of `/proc/self/maps`, keeping writable mappings not backed by a file.
+fn anonymous_writable_region(line: &str) -> Option<(usize, usize)> {
+    let mut fields = line.split_whitespace();
+    let (start, end) = fields.next()?.split_once('-')?;
+    let writable = fields.next()?.as_bytes().get(1) == Some(&b'w');
+    let path = fields.nth(3).unwrap_or("");
+    let anonymous = path.is_empty() || path == "[heap]" || path.starts_with("[stack");
+    (writable && anonymous).then_some(())?;
+    Some((
+        usize::from_str_radix(start, 16).ok()?,
+        usize::from_str_radix(end, 16).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::pin::pin;
+
+    use super::{Footprint, find_in_memory};
+    use crate::{api::Secret, memory::Locked};
+
+    #[test]
+    fn test_dropped_secrets_are_wiped() {
+        let pattern: Vec<u8> = (0..32).map(|i| 0xa0 ^ i).collect();
+        let fill = |sec: &mut [u8]| sec.copy_from_slice(&pattern);
+
+        let mut boxed = Secret::<[u8; 32]>::boxed();
+        boxed.as_mut().update_with(&fill);
+        let footprint = Footprint::of(boxed.as_ref());
+        drop(boxed);
+        footprint.assert_wiped(&pattern);
+
+        let secret: Secret<[u8; 32], Locked> = Secret::new_locked().unwrap();
+        let mut secret_pinned = pin!(secret);
+        secret_pinned.as_mut().update_with(&fill);
+        let footprint = Footprint::of(secret_pinned.as_ref());
+        let (addr, _) = footprint.region();
+        assert_eq!(find_in_memory(&pattern).unwrap(), [addr]);
+    }
+
+    #[test]
+    fn test_leaked_copy_is_found() {
+        let pattern: Vec<u8> = (0..32).map(|i| 0x50 ^ i).collect();
+        let mut secret = Secret::<[u8; 32]>::boxed();
+        secret
+            .as_mut()
+            .update_with(&|sec: &mut [u8]| sec.copy_from_slice(&pattern));
+        // A careless reader copying the secret to the heap.
+        let leaked = secret.as_ref().read_with(&|sec: &[u8]| sec.to_vec());
+        drop(secret);
+        let found = find_in_memory(&pattern).unwrap();
+        assert_eq!(found, [leaked.as_ptr() as usize]);
+    }
+}